@@ -0,0 +1,20 @@
+// Scanlines over slightly softened pixels, for use with `--shader`.
+#version 110
+
+uniform sampler2D screen;
+uniform vec2 source_size;
+uniform vec2 resolution;
+uniform float time;
+varying vec2 uv;
+
+void main() {
+    vec3 color = texture2D(screen, uv).rgb;
+    // Where this fragment falls within its row of the screen, from 0 to 1
+    float row = fract(uv.y * source_size.y);
+    // Darkest between rows, and only when rows are tall enough to show it
+    float depth = clamp(resolution.y / source_size.y / 8.0, 0.0, 1.0) * 0.45;
+    float scanline = 1.0 - depth * pow(abs(row - 0.5) * 2.0, 2.0);
+    // A faint flicker, like an old tube
+    float flicker = 1.0 - 0.015 * sin(time * 60.0);
+    gl_FragColor = vec4(color * scanline * flicker, 1.0);
+}
@@ -0,0 +1,100 @@
+use crate::io::Status;
+use core::time::Duration;
+use futures::StreamExt;
+use smol::Timer;
+use std::io::Write;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// One line per row, `#` for lit pixels and `.` for dark ones.
+pub fn render(vram: &[bool], width: usize) -> String {
+    let mut out = String::with_capacity(vram.len() + vram.len() / width);
+    for row in vram.chunks(width) {
+        out.extend(row.iter().map(|pix| if *pix { '#' } else { '.' }));
+        out.push('\n');
+    }
+    out
+}
+
+/// The screen as printed by [`mirror`], under its frame number.
+fn frame_text(frame: u64, vram: &[bool]) -> String {
+    format!("Frame {frame}\n{}", render(vram, 64))
+}
+
+/// Prints the screen to stdout every `interval`.
+pub async fn mirror(
+    vram: Arc<Mutex<[bool; 64 * 32]>>,
+    status: Arc<Status>,
+    interval: Duration,
+) -> ! {
+    let mut ticks = Timer::interval(interval);
+    loop {
+        ticks.next().await;
+        let frame = status.frames.load(Ordering::Relaxed);
+        // One write for the whole frame so log lines can't end up in the middle
+        let text = frame_text(frame, &*vram.lock().unwrap());
+        let mut stdout = std::io::stdout().lock();
+        if stdout
+            .write_all(text.as_bytes())
+            .and_then(|()| stdout.flush())
+            .is_err()
+        {
+            // Nobody is reading any more, don't spin on the error
+            Timer::never().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 64x32 screen with the given pixels lit.
+    fn screen(lit: &[(usize, usize)]) -> Vec<bool> {
+        let mut vram = vec![false; 64 * 32];
+        for (x, y) in lit {
+            vram[y * 64 + x] = true;
+        }
+        vram
+    }
+
+    #[test]
+    fn renders_the_golden_string() {
+        let vram = screen(&[(0, 0), (1, 0), (63, 0), (2, 1), (0, 31), (63, 31)]);
+        let dark = ".".repeat(64);
+        let mut expected = format!("##{}#\n..#{}\n", ".".repeat(61), ".".repeat(61));
+        for _ in 2..31 {
+            expected += &format!("{dark}\n");
+        }
+        expected += &format!("#{}#\n", ".".repeat(62));
+        assert_eq!(render(&vram, 64), expected);
+    }
+
+    #[test]
+    fn frames_are_numbered() {
+        let text = frame_text(42, &screen(&[(3, 0)]));
+        let mut lines = text.lines();
+        assert_eq!(lines.next(), Some("Frame 42"));
+        assert_eq!(
+            lines.next(),
+            Some(format!("...#{}", ".".repeat(60)).as_str())
+        );
+        assert_eq!(lines.count(), 31);
+        assert!(text.ends_with('\n'));
+    }
+
+    /// Hires screens are just wider rows.
+    #[test]
+    fn renders_any_width() {
+        let mut vram = vec![false; 128 * 64];
+        vram[127] = true;
+        vram[128 * 63] = true;
+        let text = render(&vram, 128);
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines.len(), 64);
+        assert!(lines.iter().all(|line| line.len() == 128));
+        assert_eq!(lines[0], format!("{}#", ".".repeat(127)));
+        assert_eq!(lines[63], format!("#{}", ".".repeat(127)));
+    }
+}
@@ -0,0 +1,184 @@
+//! Addresses to stop at before running the instruction there, and memory and
+//! registers to stop after changing.
+
+use crate::condition::Condition;
+use crate::trace::Registers;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+#[derive(Debug, Default)]
+pub struct Breakpoints {
+    /// Only stopping when the condition holds, if there is one
+    addresses: BTreeMap<u16, Option<Condition>>,
+    /// Removed the first time they are hit, e.g. to step over a call
+    temporary: BTreeSet<u16>,
+    /// Memory addresses
+    watches: BTreeSet<u16>,
+    /// Conditions on the registers, and whether each held after the last instruction
+    register_watches: Vec<(Condition, bool)>,
+}
+
+impl Breakpoints {
+    pub fn add(&mut self, addr: u16, condition: Option<Condition>) {
+        self.addresses.insert(addr, condition);
+    }
+
+    pub fn add_temporary(&mut self, addr: u16) {
+        self.temporary.insert(addr);
+    }
+
+    /// Removes either kind of breakpoint at `addr`, returning whether there was one.
+    pub fn remove(&mut self, addr: u16) -> bool {
+        self.addresses.remove(&addr).is_some() | self.temporary.remove(&addr)
+    }
+
+    pub fn watch(&mut self, addr: u16) {
+        self.watches.insert(addr);
+    }
+
+    pub fn is_watched(&self, addr: u16) -> bool {
+        self.watches.contains(&addr)
+    }
+
+    pub fn watch_registers(&mut self, condition: Condition) {
+        self.register_watches.push((condition, false));
+    }
+
+    /// The first condition that has just become true.
+    pub fn check_registers(&mut self, registers: &Registers) -> Option<&Condition> {
+        let mut triggered = None;
+        for (idx, (condition, held)) in self.register_watches.iter_mut().enumerate() {
+            let holds = condition.eval(registers);
+            if holds && !*held && triggered.is_none() {
+                triggered = Some(idx);
+            }
+            *held = holds;
+        }
+        triggered.map(|idx| &self.register_watches[idx].0)
+    }
+
+    /// Whether there are no breakpoints, ignoring watches.
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty() && self.temporary.is_empty()
+    }
+
+    /// Whether to stop before running `addr`, which uses up a temporary breakpoint.
+    pub fn hit(&mut self, addr: u16, registers: &Registers) -> bool {
+        self.temporary.remove(&addr)
+            || self
+                .addresses
+                .get(&addr)
+                .is_some_and(|condition| condition.as_ref().is_none_or(|c| c.eval(registers)))
+    }
+}
+
+/// Parses an address like `0x228`, `228` (hex either way) or `$228`.
+pub fn parse_address(s: &str) -> Result<u16, String> {
+    let digits = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .or_else(|| s.strip_prefix('$'))
+        .unwrap_or(s);
+    u16::from_str_radix(digits, 16)
+        .ok()
+        .filter(|addr| *addr <= 0xFFF)
+        .ok_or_else(|| format!("Expected an address between 0x000 and 0xFFF, got {s:?}"))
+}
+
+/// Parses a breakpoint like `0x2A0`, or `0x2A0 if v0 > 5` to only stop when the
+/// condition holds.
+pub fn parse_breakpoint(s: &str) -> Result<(u16, Option<Condition>), String> {
+    match s.split_once(" if ") {
+        Some((addr, condition)) => Ok((parse_address(addr.trim())?, Some(condition.parse()?))),
+        None => Ok((parse_address(s.trim())?, None)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::Headless;
+    use crate::quirks::Quirks;
+
+    fn registers(v0: u8) -> Registers {
+        let mut v = [0; 16];
+        v[0] = v0;
+        (v, 0)
+    }
+
+    #[test]
+    fn parses_breakpoints() {
+        assert_eq!(parse_breakpoint("0x2A0"), Ok((0x2A0, None)));
+        assert_eq!(parse_breakpoint("$2a0"), Ok((0x2A0, None)));
+        let (addr, condition) = parse_breakpoint("2A0 if v0 > 5").unwrap();
+        assert_eq!(addr, 0x2A0);
+        assert_eq!(condition.unwrap().to_string(), "V0 > 0x5");
+        assert!(parse_breakpoint("0x1000").is_err());
+        assert!(parse_breakpoint("0x2A0 if v0").is_err());
+    }
+
+    #[test]
+    fn conditional_breakpoints_only_stop_while_the_condition_holds() {
+        let mut breakpoints = Breakpoints::default();
+        breakpoints.add(0x2A0, Some("v0 > 5".parse().unwrap()));
+        assert!(!breakpoints.hit(0x2A0, &registers(5)));
+        assert!(breakpoints.hit(0x2A0, &registers(6)));
+        assert!(breakpoints.hit(0x2A0, &registers(6)));
+        assert!(!breakpoints.hit(0x2A2, &registers(6)));
+    }
+
+    #[test]
+    fn temporary_breakpoints_go_away_once_hit() {
+        let mut breakpoints = Breakpoints::default();
+        breakpoints.add_temporary(0x204);
+        assert!(!breakpoints.is_empty());
+        assert!(breakpoints.hit(0x204, &registers(0)));
+        assert!(!breakpoints.hit(0x204, &registers(0)));
+        assert!(breakpoints.is_empty());
+    }
+
+    /// A watch goes off when its condition becomes true, not for as long as it stays
+    /// true, and again after it has been false.
+    #[test]
+    fn register_watches_trigger_on_becoming_true() {
+        let mut breakpoints = Breakpoints::default();
+        breakpoints.watch_registers("v0 >= 2".parse().unwrap());
+        let fired: Vec<bool> = [0, 1, 2, 3, 1, 2]
+            .into_iter()
+            .map(|v0| breakpoints.check_registers(&registers(v0)).is_some())
+            .collect();
+        assert_eq!(fired, [false, false, true, false, false, true]);
+    }
+
+    #[test]
+    fn first_watch_to_trigger_wins() {
+        let mut breakpoints = Breakpoints::default();
+        breakpoints.watch_registers("v0 == 9".parse().unwrap());
+        breakpoints.watch_registers("v0 > 3".parse().unwrap());
+        breakpoints.watch_registers("v0 > 4".parse().unwrap());
+        let triggered = breakpoints.check_registers(&registers(5)).cloned();
+        assert_eq!(triggered.unwrap().to_string(), "V0 > 0x3");
+        // Both were noted as holding, so neither goes off again
+        assert!(breakpoints.check_registers(&registers(6)).is_none());
+    }
+
+    /// Checked after every instruction, a watch goes off on the one that makes it true.
+    #[test]
+    fn register_watch_stops_after_the_instruction_that_sets_it_off() {
+        // ADD V3, 4; JP 200
+        let mut core = Headless::new(vec![0x73, 0x04, 0x12, 0x00], Quirks::default(), 10);
+        let mut breakpoints = Breakpoints::default();
+        breakpoints.watch_registers("v3 == 0x20".parse().unwrap());
+        let mut fired = vec![];
+        for count in 1..=40 {
+            let pc = core.state.pc;
+            let _ = core.step();
+            let registers = (core.state.registers.0, core.state.vi);
+            if breakpoints.check_registers(&registers).is_some() {
+                fired.push((count, pc));
+            }
+        }
+        // The eighth ADD, which is the fifteenth instruction
+        assert_eq!(fired, [(15, 0x200)]);
+    }
+}
@@ -0,0 +1,264 @@
+//! `chip8 callgraph`: which subroutines call which, found by following the code
+//! from the entry point without running it.
+use crate::cli::Exit;
+use crate::instruction::decode;
+use crate::instruction::disassemble;
+use crate::instruction::DecodedInstr;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fmt::Write;
+use std::path::PathBuf;
+
+const START: u16 = 0x200;
+
+pub const USAGE: &str = "\
+Usage: chip8 callgraph [--dot] ROM
+
+Options:
+    --dot              Print a Graphviz DOT graph instead of a summary";
+
+#[derive(Debug, Default)]
+pub struct CallGraph {
+    /// Subroutine entry points, with the entry point of the program first
+    pub routines: BTreeSet<u16>,
+    /// Caller routine to callee routine, with the addresses of the calls
+    pub calls: BTreeMap<(u16, u16), Vec<u16>>,
+    /// BNNN jumps, whose target depends on V0, by routine
+    pub indirect: Vec<(u16, u16)>,
+    /// Addresses reached as the start of an instruction
+    pub reached: BTreeSet<u16>,
+}
+
+/// Follows every path through `rom`, loaded at 0x200, attributing each instruction to
+/// the routine it was reached from.
+pub fn analyze(rom: &[u8]) -> CallGraph {
+    let end = START + rom.len() as u16;
+    let mut graph = CallGraph::default();
+    graph.routines.insert(START);
+    let mut visited = BTreeSet::new();
+    let mut pending = vec![(START, START)];
+    while let Some((routine, addr)) = pending.pop() {
+        if addr < START || addr + 1 >= end || !visited.insert((routine, addr)) {
+            continue;
+        }
+        graph.reached.insert(addr);
+        let idx = usize::from(addr - START);
+        let opcode = u16::from_be_bytes([rom[idx], rom[idx + 1]]);
+        match decode(opcode) {
+            DecodedInstr::Return | DecodedInstr::IllegalInstruction(_) => {}
+            DecodedInstr::Jump { address } => pending.push((routine, u16::from(address))),
+            DecodedInstr::JumpWithOffset { .. } => graph.indirect.push((routine, addr)),
+            DecodedInstr::Call { address } => {
+                let callee = u16::from(address);
+                graph.routines.insert(callee);
+                graph.calls.entry((routine, callee)).or_default().push(addr);
+                pending.push((callee, callee));
+                pending.push((routine, addr + 2));
+            }
+            DecodedInstr::SkipIfEqual { .. }
+            | DecodedInstr::SkipIfNotEqual { .. }
+            | DecodedInstr::SkipIfRegisterEqual { .. }
+            | DecodedInstr::SkipIfRegisterNotEqual { .. }
+            | DecodedInstr::SkipIfPressed { .. }
+            | DecodedInstr::SkipIfNotPressed { .. } => {
+                pending.push((routine, addr + 2));
+                pending.push((routine, addr + 4));
+            }
+            _ => pending.push((routine, addr + 2)),
+        }
+    }
+    graph
+}
+
+impl CallGraph {
+    /// Ranges of the ROM that no path reaches, which may well be sprites or other data.
+    pub fn unreachable(&self, rom_len: usize) -> Vec<(u16, u16)> {
+        let end = START + rom_len as u16;
+        let mut covered = vec![false; rom_len];
+        for addr in &self.reached {
+            let idx = usize::from(addr - START);
+            covered[idx] = true;
+            if let Some(next) = covered.get_mut(idx + 1) {
+                *next = true;
+            }
+        }
+        let mut ranges = Vec::new();
+        let mut addr = START;
+        while addr < end {
+            if covered[usize::from(addr - START)] {
+                addr += 1;
+                continue;
+            }
+            let start = addr;
+            while addr < end && !covered[usize::from(addr - START)] {
+                addr += 1;
+            }
+            ranges.push((start, addr - 1));
+        }
+        ranges
+    }
+
+    pub fn summary(&self, rom: &[u8]) -> String {
+        let mut out = String::new();
+        for routine in &self.routines {
+            let kind = if *routine == START { "entry" } else { "sub" };
+            let _ = writeln!(out, "{kind} {routine:03X}");
+            for ((_, callee), sites) in self.calls.range((*routine, 0)..=(*routine, u16::MAX)) {
+                let sites: Vec<_> = sites.iter().map(|site| format!("{site:03X}")).collect();
+                let _ = writeln!(out, "  calls {callee:03X} from {}", sites.join(", "));
+            }
+            for (_, site) in self.indirect.iter().filter(|(r, _)| r == routine) {
+                let _ = writeln!(out, "  indirect jump at {site:03X}, not followed");
+            }
+        }
+        for (start, end) in self.unreachable(rom.len()) {
+            let idx = usize::from(start - START);
+            let first = u16::from_be_bytes([rom[idx], *rom.get(idx + 1).unwrap_or(&0)]);
+            let _ = writeln!(
+                out,
+                "unreachable {start:03X}-{end:03X} (starts with {first:04X} {})",
+                disassemble(first)
+            );
+        }
+        out
+    }
+
+    pub fn dot(&self) -> String {
+        let mut out = String::from("digraph callgraph {\n");
+        for routine in &self.routines {
+            let shape = if *routine == START {
+                "doublecircle"
+            } else {
+                "box"
+            };
+            let _ = writeln!(out, "    \"{routine:03X}\" [shape={shape}];");
+        }
+        for ((caller, callee), sites) in &self.calls {
+            let _ = writeln!(
+                out,
+                "    \"{caller:03X}\" -> \"{callee:03X}\" [label=\"{}\"];",
+                sites.len()
+            );
+        }
+        for (routine, site) in &self.indirect {
+            let _ = writeln!(
+                out,
+                "    \"BNNN@{site:03X}\" [shape=diamond, style=dashed];"
+            );
+            let _ = writeln!(
+                out,
+                "    \"{routine:03X}\" -> \"BNNN@{site:03X}\" [style=dashed];"
+            );
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+/// Runs the subcommand with the arguments after `callgraph`.
+pub fn run(args: impl IntoIterator<Item = String>) -> Result<(), Exit> {
+    let mut dot = false;
+    let mut rom = None;
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            "--dot" => dot = true,
+            _ if arg.starts_with('-') => return Err(usage_error(format!("Unknown option {arg}"))),
+            _ if rom.is_none() => rom = Some(PathBuf::from(arg)),
+            _ => return Err(usage_error(format!("Unexpected argument {arg}"))),
+        }
+    }
+    let rom = rom.ok_or_else(|| usage_error("No ROM given"))?;
+    let rom = crate::io::rom::read(&rom).map_err(Exit::failed)?;
+    let graph = analyze(&rom);
+    if dot {
+        print!("{}", graph.dot());
+    } else {
+        print!("{}", graph.summary(&rom));
+    }
+    Ok(())
+}
+
+fn usage_error(message: impl fmt::Display) -> Exit {
+    Exit::usage(message, USAGE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The entry point calls 208 twice, which calls 20C, with data in between
+    const NESTED: &[u8] = &[
+        0x22, 0x08, // 200: CALL 208
+        0x22, 0x08, // 202: CALL 208
+        0x12, 0x04, // 204: JP 204
+        0xAB, 0xCD, // 206: data
+        0x22, 0x0C, // 208: CALL 20C
+        0x00, 0xEE, // 20A: RET
+        0x00, 0xEE, // 20C: RET
+    ];
+
+    #[test]
+    fn finds_nested_calls() {
+        let graph = analyze(NESTED);
+        assert_eq!(
+            graph.routines.into_iter().collect::<Vec<_>>(),
+            [0x200, 0x208, 0x20C]
+        );
+        assert_eq!(
+            graph.calls.into_iter().collect::<Vec<_>>(),
+            [
+                ((0x200, 0x208), vec![0x200, 0x202]),
+                ((0x208, 0x20C), vec![0x208]),
+            ]
+        );
+        assert!(graph.indirect.is_empty());
+    }
+
+    #[test]
+    fn summarizes_calls_and_unreachable_code() {
+        assert_eq!(
+            analyze(NESTED).summary(NESTED),
+            format!(
+                "entry 200\n  calls 208 from 200, 202\nsub 208\n  calls 20C from 208\nsub 20C\n\
+                 unreachable 206-207 (starts with ABCD {})\n",
+                disassemble(0xABCD)
+            )
+        );
+    }
+
+    #[test]
+    fn draws_calls_and_indirect_jumps() {
+        // 200: CALL 204, 202: JP 202, 204: JP V0, 206
+        let rom = [0x22, 0x04, 0x12, 0x02, 0xB2, 0x06];
+        let graph = analyze(&rom);
+        assert_eq!(graph.indirect, [(0x204, 0x204)]);
+        assert_eq!(
+            graph.dot(),
+            "digraph callgraph {\n    \
+             \"200\" [shape=doublecircle];\n    \
+             \"204\" [shape=box];\n    \
+             \"200\" -> \"204\" [label=\"1\"];\n    \
+             \"BNNN@204\" [shape=diamond, style=dashed];\n    \
+             \"204\" -> \"BNNN@204\" [style=dashed];\n\
+             }\n"
+        );
+    }
+
+    /// Mistakes come back for `main` to report instead of exiting from here.
+    #[test]
+    fn returns_usage_and_read_errors() {
+        let run = |args: &[&str]| run(args.iter().map(|arg| arg.to_string()));
+        assert_eq!(run(&[]), Err(usage_error("No ROM given")));
+        assert_eq!(run(&["--svg"]), Err(usage_error("Unknown option --svg")));
+        let missing =
+            std::env::temp_dir().join(format!("chip8-{}-missing.ch8", std::process::id()));
+        let err = run(&[missing.to_str().unwrap()]).unwrap_err();
+        assert!(matches!(err, Exit::Failed(_)), "{err:?}");
+        assert_eq!(err.code(), 1);
+    }
+}
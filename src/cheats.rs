@@ -0,0 +1,266 @@
+//! Cheats that pin a byte of memory to a value, e.g. to never lose a life.
+//!
+//! Each ROM's cheats are kept next to the config file in `cheats/<rom hash>.txt`, one
+//! `<addr>=<value>` line per cheat in hex, followed by ` off` if it is disabled.
+
+use crate::breakpoints::parse_address;
+use crate::cli::Exit;
+use crate::hash;
+use core::fmt;
+use core::str::FromStr;
+use std::path::PathBuf;
+use tracing::*;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Cheat {
+    pub addr: u16,
+    pub value: u8,
+    pub enabled: bool,
+}
+
+impl FromStr for Cheat {
+    type Err = String;
+
+    /// Parses `0x3A2=0x09`, where both sides are hex with or without the `0x`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((addr, value)) = s.split_once('=') else {
+            return Err(format!("Expected a cheat like 0x3A2=0x09, got {s:?}"));
+        };
+        let addr = parse_address(addr.trim())?;
+        if addr < 0x200 {
+            return Err(format!(
+                "Cheats can only change the ROM's memory, from 0x200, got {addr:#X}"
+            ));
+        }
+        let value = value.trim();
+        let digits = value
+            .strip_prefix("0x")
+            .or_else(|| value.strip_prefix("0X"))
+            .unwrap_or(value);
+        let value = u8::from_str_radix(digits, 16)
+            .map_err(|_| format!("Expected a byte in hex, got {value:?}"))?;
+        Ok(Cheat {
+            addr,
+            value,
+            enabled: true,
+        })
+    }
+}
+
+impl fmt::Display for Cheat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:03X}={:02X}", self.addr, self.value)
+    }
+}
+
+/// The cheats for one ROM.
+#[derive(Clone, Debug, Default)]
+pub struct Cheats {
+    path: Option<PathBuf>,
+    list: Vec<Cheat>,
+}
+
+impl Cheats {
+    /// Reads the cheats saved for `rom`, skipping lines that don't parse. None saved
+    /// is just empty.
+    pub fn load(rom: &[u8]) -> Cheats {
+        let path = default_path(rom);
+        let text = match path.as_deref().map(std::fs::read_to_string) {
+            Some(Ok(text)) => text,
+            Some(Err(err)) if err.kind() != std::io::ErrorKind::NotFound => {
+                warn!("Could not read the cheats: {err}");
+                String::new()
+            }
+            _ => String::new(),
+        };
+        let mut list = vec![];
+        for line in text.lines() {
+            let (cheat, enabled) = match line.trim().strip_suffix(" off") {
+                Some(cheat) => (cheat, false),
+                None => (line.trim(), true),
+            };
+            match cheat.parse::<Cheat>() {
+                Ok(cheat) => list.push(Cheat { enabled, ..cheat }),
+                Err(err) => warn!("Skipping cheat {line:?}: {err}"),
+            }
+        }
+        Cheats { path, list }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let text: String = self
+            .list
+            .iter()
+            .map(|cheat| match cheat.enabled {
+                true => format!("{cheat}\n"),
+                false => format!("{cheat} off\n"),
+            })
+            .collect();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, text)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Cheat> {
+        self.list.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// Adds a cheat, replacing any other one for the same address.
+    pub fn add(&mut self, cheat: Cheat) {
+        match self.list.iter_mut().find(|other| other.addr == cheat.addr) {
+            Some(other) => *other = cheat,
+            None => self.list.push(cheat),
+        }
+    }
+
+    /// Cheat `n`, counting from 1 like the printed list.
+    pub fn get_mut(&mut self, n: usize) -> Option<&mut Cheat> {
+        self.list.get_mut(n.checked_sub(1)?)
+    }
+
+    pub fn remove(&mut self, n: usize) -> Option<Cheat> {
+        let idx = n.checked_sub(1).filter(|idx| *idx < self.list.len())?;
+        Some(self.list.remove(idx))
+    }
+}
+
+fn default_path(rom: &[u8]) -> Option<PathBuf> {
+    let file = format!("{:016x}.txt", hash::fnv1a(rom));
+    Some(
+        crate::config::default_path()?
+            .with_file_name("cheats")
+            .join(file),
+    )
+}
+
+pub const USAGE: &str = "\
+Usage: chip8 cheats ROM [COMMAND]
+
+Lists or changes the cheats saved for a ROM, which are on whenever it is played.
+
+Commands:
+    list               Number and show each cheat [default]
+    add <ADDR=VALUE>   Pin the byte at ADDR to VALUE, both in hex
+    enable <N>         Turn cheat N back on
+    disable <N>        Keep cheat N but stop applying it
+    remove <N>         Forget cheat N";
+
+pub fn run(args: impl IntoIterator<Item = String>) -> Result<(), Exit> {
+    let mut args = args.into_iter();
+    let rom = match args.next().as_deref() {
+        None => return Err(usage_error("No ROM given")),
+        Some("-h" | "--help") => {
+            println!("{USAGE}");
+            return Ok(());
+        }
+        Some(rom) => PathBuf::from(rom),
+    };
+    let rom = crate::io::rom::read(&rom).map_err(Exit::failed)?;
+    let mut cheats = Cheats::load(&rom);
+    let command = args.next().unwrap_or_else(|| "list".into());
+    let mut argument = || {
+        args.next()
+            .ok_or_else(|| usage_error(format!("{command} expects a value")))
+    };
+    let mut number = || {
+        let value = argument()?;
+        value
+            .parse::<usize>()
+            .map_err(|_| usage_error(format!("Expected a cheat number, got {value:?}")))
+    };
+    let missing = |n: usize| Exit::failed(format!("There is no cheat {n}"));
+    match command.as_str() {
+        "list" => {
+            if cheats.is_empty() {
+                println!("No cheats");
+            }
+            for (n, cheat) in cheats.iter().enumerate() {
+                let off = if cheat.enabled { "" } else { " (off)" };
+                println!("{:>2}. {cheat}{off}", n + 1);
+            }
+            return Ok(());
+        }
+        "add" => {
+            let cheat = argument()?
+                .parse()
+                .map_err(|err: String| usage_error(err))?;
+            cheats.add(cheat);
+        }
+        "enable" | "disable" => {
+            let n = number()?;
+            let cheat = cheats.get_mut(n).ok_or_else(|| missing(n))?;
+            cheat.enabled = command == "enable";
+        }
+        "remove" => {
+            let n = number()?;
+            if cheats.remove(n).is_none() {
+                return Err(missing(n));
+            }
+        }
+        _ => return Err(usage_error(format!("Unknown command {command:?}"))),
+    }
+    cheats
+        .save()
+        .map_err(|err| Exit::failed(format!("Could not save the cheats: {err}")))
+}
+
+fn usage_error(message: impl fmt::Display) -> Exit {
+    Exit::usage(message, USAGE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::Headless;
+    use crate::quirks::Quirks;
+
+    #[test]
+    fn parses_cheats() {
+        let cheat = Cheat {
+            addr: 0x3A2,
+            value: 9,
+            enabled: true,
+        };
+        assert_eq!("0x3A2=0x09".parse(), Ok(cheat));
+        assert_eq!("3a2 = 9".parse(), Ok(cheat));
+        assert_eq!(cheat.to_string(), "3A2=09");
+        assert!("0x3A2".parse::<Cheat>().is_err());
+        assert!("0x100=1".parse::<Cheat>().is_err());
+        assert!("0x3A2=100".parse::<Cheat>().is_err());
+    }
+
+    /// A cheat pins the counter a loop keeps decrementing, until cheats are turned
+    /// off.
+    #[test]
+    fn freezes_a_counter() {
+        // Loads the byte at 0x300 into V0, decrements it and stores it back, forever
+        let mut rom = vec![0; 0x101];
+        rom[..12].copy_from_slice(&[
+            0xA3, 0x00, 0xF0, 0x65, 0x70, 0xFF, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x00,
+        ]);
+        rom[0x100] = 9;
+        let mut core = Headless::new(rom, Quirks::default(), 10);
+        let mut cheats = Cheats::default();
+        cheats.add("300=9".parse().unwrap());
+        core.state.status.set_cheats(cheats);
+        let run_loops = |core: &mut Headless, loops| {
+            for _ in 0..loops * 6 {
+                let _ = core.step();
+            }
+            core.state.memory[0x300]
+        };
+        assert_eq!(run_loops(&mut core, 10), 9);
+        assert_eq!(core.state.status.toggle_cheats(), "Cheats off");
+        assert_eq!(run_loops(&mut core, 3), 6);
+        core.state.status.toggle_cheats();
+        assert_eq!(run_loops(&mut core, 1), 9);
+    }
+}
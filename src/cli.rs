@@ -0,0 +1,666 @@
+use crate::breakpoints::parse_address;
+use crate::breakpoints::parse_breakpoint;
+use crate::cheats::Cheat;
+use crate::condition::Condition;
+use crate::io::check_duty;
+use crate::io::check_hz;
+use crate::io::check_speed;
+use crate::io::parse_color;
+use crate::io::parse_lowpass;
+use crate::io::parse_position;
+use crate::io::Borderless;
+use crate::io::Filter;
+use crate::io::Filtering;
+use crate::io::Layout;
+use crate::io::Palette;
+use crate::io::Rotation;
+use crate::io::Vsync;
+use crate::io::Waveform;
+use crate::io::MAX_SCALE;
+use crate::io::MAX_VOLUME;
+use crate::logging;
+use crate::quirks::Profile;
+use crate::recent::Recent;
+use crate::trace::TraceFormat;
+use sdl2::pixels::Color;
+use std::fmt;
+use std::path::PathBuf;
+use tracing::level_filters::LevelFilter;
+
+pub const USAGE: &str = "\
+Usage: chip8 [OPTIONS] [ROM]
+       chip8 callgraph [--dot] ROM
+       chip8 compare [--quirks A,B] ROM [ROM]
+       chip8 diff-quirks [--frames N] [--profiles A,B] ROM
+       chip8 trace [--instructions N] [--quirks PROFILE] ROM
+       chip8 info ROM
+       chip8 inspect-dump FILE
+       chip8 cheats ROM [list|add|enable|disable|remove]
+
+Options:
+    --layout <LAYOUT>  Keypad layout: classic, right-hand or current-default
+    --virtual-keypad   Show a clickable keypad below the game (toggle with F4)
+    --sticky-keys      Keys toggle on each press instead of having to be held
+    --confirm-quit     Require pressing the quit key twice within a second
+    --no-pause-on-focus-loss
+                       Keep running while the window is in the background
+    --integer-scale    Only scale the image by whole multiples (toggle with F6)
+    --scale <N>        Initial window size as a multiple of 64x32 [default: 10]
+    --fullscreen       Start in fullscreen (toggle with F11)
+    --monitor <N>      Open the window on display N, counting from 0 [default: 0]
+    --window-pos <X,Y> Open the window here on its display instead of centered
+    --borderless       Open the window without decorations (toggle with Shift+F11)
+    --borderless-desktop
+                       Open the window without decorations, covering its display
+    --palette <NAME>   Colors: mono, octo, gameboy, amber or paper [default: mono]
+    --fg <COLOR>       Color of lit pixels: #RGB, #RRGGBB or a name
+    --bg <COLOR>       Color of unlit pixels
+    --filter <FILTER>  Display filter: none or crt (toggle with F8) [default: none]
+    --filtering <MODE> Scaling to the window: nearest or linear [default: nearest]
+    --shader <FILE>    Draw the game through a GLSL fragment shader (needs the shader feature)
+    --decay <MS>       Let pixels fade out over this many milliseconds [default: 0]
+    --rotate <DEGREES> Rotate the screen clockwise: 0, 90, 180 or 270 [default: 0]
+    --vsync <MODE>     Sync to the display: on, off or adaptive [default: off]
+    --waveform <WAVE>  Buzzer sound: square, sine, triangle, sawtooth or noise
+    --beep-sample <WAV> Play a mono WAV clip instead of the waveform
+    --tone-hz <HZ>     Buzzer frequency [default: 880]
+    --volume <0-100>   Buzzer volume [default: 25]
+    --audio-device <NAME>
+                       Play sound on the device with NAME in its name
+    --list-audio-devices
+                       List the devices --audio-device picks from
+    --no-audio         Don't play sound (lights the visual bell instead)
+    --lowpass <HZ>     Soften the buzzer above this frequency, or off [default: 5000]
+    --mute             Start with the buzzer muted (toggle with M)
+    --duty <FRACTION>  Fraction of each period the buzzer wave is high [default: 0.5]
+    --visual-bell      Light a border around the screen while the buzzer sounds
+    --record <FILE>    Record keypad input to a file
+    --replay <FILE>    Play back recorded keypad input
+    --script <FILE>    Press keys and check pixels at given frames, for automated runs
+    --print-frame-hash-every <N>
+                       Print a hash of the screen every N frames
+    --exit-after-frames <N>
+                       Exit with status 3 after showing N frames
+    --exit-after-instructions <N>
+                       Exit with status 3 after running N instructions
+    --dump-frame <FILE>
+                       Save the last frame as a PNG (or .ppm) on exit
+    --dump-state-on-exit <FILE>
+                       Save the registers, memory and screen as JSON on exit, - for
+                       stdout
+    --record-gif <FILE>
+                       Record the game to an animated GIF (toggle with Ctrl+G)
+    --record-video <FILE>
+                       Write raw 64x32 RGB24 frames at 60fps, - for stdout
+    --emit-trace <FILE>
+                       Write the state before every instruction to a file
+    --compare-trace <FILE>
+                       Stop at the first instruction that differs from a trace
+    --trace-file <FILE>
+                       Log every instruction to a file
+    --trace-format <FORMAT>
+                       Trace log lines: compact or full [default: compact]
+    --coverage <FILE>  Report which parts of the ROM never ran on exit
+    --heatmap <FILE>   Draw how often each instruction ran as a PNG on exit
+    --quirks <PROFILE> Instruction behaviour: chip8, schip, xochip or amiga
+    --speed <N>        Run N times as fast, timers included, from 0.25 to 8 [default: 1]
+    --start-paused     Load the ROM but wait for Ctrl+P before running anything
+    --break-at-start   Like --start-paused, but stop as if at a breakpoint at 0x200
+    --break <ADDR>     Pause before running the instruction at ADDR (resume with Ctrl+P),
+                       or with \"ADDR if v0 > 5\" only when that holds, can be repeated
+    --watch-reg <COND> Pause once an instruction makes e.g. \"v3 == 0x20\" true
+    --watch <ADDR>     Pause after anything writes to memory at ADDR, can be repeated
+    --cheat <ADDR=VALUE>
+                       Keep the byte at ADDR at VALUE (toggle with Ctrl+H), saved
+                       for next time, can be repeated
+    --dump-on-error <DIR>
+                       Save a crash dump to DIR if the program dies
+    --ascii-mirror     Print the screen to stdout every second
+    --listen <ADDR>    Accept remote keypads and screen viewers, e.g. on 0.0.0.0:5508
+    --listen-token <TOKEN>
+                       Only let in remote clients that give this token
+    --stream <URL>     Serve a page to watch and play in a browser, e.g. on
+                       ws://0.0.0.0:8080
+    --debug-http <ADDR>
+                       Serve the machine's state and controls over HTTP for
+                       scripts, on 127.0.0.1 unless given a host, e.g. 9000
+    --recent [N]       List recently played ROMs, or play entry N
+    --rom-dir <DIR>    Where Ctrl+R looks for ROMs to pick from
+    --no-cache         Download a ROM given as a URL again, even if it was before
+    --log-level <LEVEL>
+                       Print messages down to error, warn, info, debug or trace
+    --log-filter <FILTER>
+                       Levels by module like RUST_LOG, e.g. warn,chip8::io=debug
+    --log-format <FORMAT>
+                       Log lines as text or json [default: text]
+    -h, --help         Print this help";
+
+/// Why the program stops early, for `main` to print before it exits with
+/// [`Exit::code`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum Exit {
+    /// A mistake on the command line, with the usage to put it right
+    Usage(String),
+    /// Something went wrong along the way, e.g. a ROM that won't open
+    Failed(String),
+    /// Everything has been said already, only the code is left
+    Code(i32),
+}
+
+impl Exit {
+    /// `message` followed by a subcommand's `usage`.
+    pub fn usage(message: impl fmt::Display, usage: &str) -> Exit {
+        Exit::Usage(format!("{message}\n\n{usage}"))
+    }
+
+    pub fn failed(err: impl fmt::Display) -> Exit {
+        Exit::Failed(err.to_string())
+    }
+
+    pub fn code(&self) -> i32 {
+        match self {
+            Exit::Usage(_) => 2,
+            Exit::Failed(_) => 1,
+            Exit::Code(code) => *code,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Options {
+    /// The splash screen is shown when missing
+    pub rom: Option<PathBuf>,
+    pub layout: Option<Layout>,
+    pub virtual_keypad: bool,
+    pub sticky_keys: bool,
+    pub confirm_quit: bool,
+    pub integer_scale: bool,
+    pub scale: Option<u32>,
+    pub fullscreen: bool,
+    pub monitor: Option<i32>,
+    pub window_pos: Option<(i32, i32)>,
+    pub borderless: Option<Borderless>,
+    pub palette: Option<Palette>,
+    pub foreground: Option<Color>,
+    pub background: Option<Color>,
+    pub filter: Option<Filter>,
+    pub filtering: Option<Filtering>,
+    pub shader: Option<PathBuf>,
+    pub decay: Option<u64>,
+    pub rotation: Option<Rotation>,
+    pub vsync: Option<Vsync>,
+    pub waveform: Option<Waveform>,
+    pub beep_sample: Option<PathBuf>,
+    pub tone_hz: Option<f32>,
+    pub volume: Option<u8>,
+    pub audio_device: Option<String>,
+    pub list_audio_devices: bool,
+    pub no_audio: bool,
+    /// `Some(None)` turns the filter off
+    pub lowpass: Option<Option<f32>>,
+    pub mute: bool,
+    pub visual_bell: bool,
+    pub duty: Option<f32>,
+    pub record: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+    pub script: Option<PathBuf>,
+    pub frame_hash_every: Option<u64>,
+    pub exit_after_frames: Option<u64>,
+    pub exit_after_instructions: Option<u64>,
+    pub dump_frame: Option<PathBuf>,
+    pub dump_state_on_exit: Option<PathBuf>,
+    pub record_gif: Option<PathBuf>,
+    pub record_video: Option<PathBuf>,
+    pub emit_trace: Option<PathBuf>,
+    pub compare_trace: Option<PathBuf>,
+    pub trace_file: Option<PathBuf>,
+    pub trace_format: Option<TraceFormat>,
+    pub log_level: Option<LevelFilter>,
+    pub log_filter: Option<logging::Filter>,
+    pub log_format: Option<logging::Format>,
+    pub coverage: Option<PathBuf>,
+    pub heatmap: Option<PathBuf>,
+    pub quirks: Option<Profile>,
+    pub speed: Option<f32>,
+    pub start_paused: bool,
+    pub break_at_start: bool,
+    pub breakpoints: Vec<(u16, Option<Condition>)>,
+    pub register_watches: Vec<Condition>,
+    pub watches: Vec<u16>,
+    pub cheats: Vec<Cheat>,
+    pub dump_on_error: Option<PathBuf>,
+    pub ascii_mirror: bool,
+    pub listen: Option<String>,
+    pub listen_token: Option<String>,
+    pub stream: Option<String>,
+    pub debug_http: Option<String>,
+    pub list_recent: bool,
+    pub recent: Option<usize>,
+    pub rom_dir: Option<PathBuf>,
+    /// Refetch a ROM given as a URL instead of using the cached copy
+    pub no_cache: bool,
+    pub no_pause_on_focus_loss: bool,
+}
+
+impl Options {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+        let mut args = args.into_iter().peekable();
+        let mut rom = None;
+        let mut layout = None;
+        let mut virtual_keypad = false;
+        let mut sticky_keys = false;
+        let mut confirm_quit = false;
+        let mut integer_scale = false;
+        let mut scale = None;
+        let mut fullscreen = false;
+        let mut monitor = None;
+        let mut window_pos = None;
+        let mut borderless = None;
+        let mut palette = None;
+        let mut foreground = None;
+        let mut background = None;
+        let mut filter = None;
+        let mut filtering = None;
+        let mut shader = None;
+        let mut decay = None;
+        let mut rotation = None;
+        let mut vsync = None;
+        let mut waveform = None;
+        let mut beep_sample = None;
+        let mut tone_hz = None;
+        let mut volume = None;
+        let mut audio_device = None;
+        let mut list_audio_devices = false;
+        let mut no_audio = false;
+        let mut lowpass = None;
+        let mut mute = false;
+        let mut visual_bell = false;
+        let mut duty = None;
+        let mut record = None;
+        let mut replay = None;
+        let mut script = None;
+        let mut frame_hash_every = None;
+        let mut exit_after_frames = None;
+        let mut exit_after_instructions = None;
+        let mut dump_frame = None;
+        let mut dump_state_on_exit = None;
+        let mut record_gif = None;
+        let mut record_video = None;
+        let mut emit_trace = None;
+        let mut compare_trace = None;
+        let mut trace_file = None;
+        let mut trace_format = None;
+        let mut log_level = None;
+        let mut log_filter = None;
+        let mut log_format = None;
+        let mut coverage = None;
+        let mut heatmap = None;
+        let mut quirks = None;
+        let mut speed = None;
+        let mut start_paused = false;
+        let mut break_at_start = false;
+        let mut breakpoints = Vec::new();
+        let mut watches = Vec::new();
+        let mut register_watches = Vec::new();
+        let mut cheats = Vec::new();
+        let mut dump_on_error = None;
+        let mut ascii_mirror = false;
+        let mut listen = None;
+        let mut listen_token = None;
+        let mut stream = None;
+        let mut debug_http = None;
+        let mut list_recent = false;
+        let mut recent = None;
+        let mut rom_dir = None;
+        let mut no_cache = false;
+        let mut no_pause_on_focus_loss = false;
+        while let Some(arg) = args.next() {
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_owned())),
+                _ => (arg.as_str(), None),
+            };
+            let mut value = || {
+                inline
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("{flag} expects a value"))
+            };
+            match flag {
+                "-h" | "--help" => {
+                    println!("{USAGE}");
+                    std::process::exit(0);
+                }
+                "--layout" => layout = Some(value()?.parse()?),
+                "--virtual-keypad" => virtual_keypad = true,
+                "--sticky-keys" => sticky_keys = true,
+                "--confirm-quit" => confirm_quit = true,
+                "--no-pause-on-focus-loss" => no_pause_on_focus_loss = true,
+                "--integer-scale" => integer_scale = true,
+                "--scale" => {
+                    let value = value()?;
+                    scale = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|scale| (1..=MAX_SCALE).contains(scale))
+                            .ok_or_else(|| {
+                                format!("--scale expects a number between 1 and {MAX_SCALE}, got {value:?}")
+                            })?,
+                    );
+                }
+                "--fullscreen" => fullscreen = true,
+                "--monitor" => {
+                    let value = value()?;
+                    monitor = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|monitor| *monitor >= 0)
+                            .ok_or_else(|| {
+                                format!("--monitor expects a display number, got {value:?}")
+                            })?,
+                    );
+                }
+                "--window-pos" => window_pos = Some(parse_position(&value()?)?),
+                "--borderless" => borderless = Some(Borderless::Window),
+                "--borderless-desktop" => borderless = Some(Borderless::Desktop),
+                "--palette" => palette = Some(value()?.parse()?),
+                "--fg" => foreground = Some(parse_color(&value()?)?),
+                "--bg" => background = Some(parse_color(&value()?)?),
+                "--filter" => filter = Some(value()?.parse()?),
+                "--filtering" => filtering = Some(value()?.parse()?),
+                "--shader" => shader = Some(PathBuf::from(value()?)),
+                "--decay" => {
+                    let value = value()?;
+                    decay = Some(value.parse().map_err(|_| {
+                        format!("--decay expects a number of milliseconds, got {value:?}")
+                    })?);
+                }
+                "--rotate" => rotation = Some(value()?.parse()?),
+                "--vsync" => vsync = Some(value()?.parse()?),
+                "--waveform" => waveform = Some(value()?.parse()?),
+                "--beep-sample" => beep_sample = Some(PathBuf::from(value()?)),
+                "--tone-hz" => {
+                    let value = value()?;
+                    let hz = value
+                        .parse()
+                        .map_err(|_| format!("--tone-hz expects a frequency, got {value:?}"))?;
+                    tone_hz = Some(check_hz(hz)?);
+                }
+                "--audio-device" => audio_device = Some(value()?),
+                "--list-audio-devices" => list_audio_devices = true,
+                "--no-audio" => no_audio = true,
+                "--volume" => {
+                    let value = value()?;
+                    let parsed: u8 = value.parse().map_err(|_| {
+                        format!("--volume expects a number from 0 to {MAX_VOLUME}, got {value:?}")
+                    })?;
+                    volume = Some(parsed.min(MAX_VOLUME));
+                }
+                "--lowpass" => lowpass = Some(parse_lowpass(&value()?)?),
+                "--mute" => mute = true,
+                "--duty" => {
+                    let value = value()?;
+                    let parsed = value
+                        .parse()
+                        .map_err(|_| format!("--duty expects a fraction, got {value:?}"))?;
+                    duty = Some(check_duty(parsed)?);
+                }
+                "--visual-bell" => visual_bell = true,
+                "--record" => record = Some(PathBuf::from(value()?)),
+                "--replay" => replay = Some(PathBuf::from(value()?)),
+                "--script" => script = Some(PathBuf::from(value()?)),
+                "--print-frame-hash-every" => {
+                    let value = value()?;
+                    frame_hash_every = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|n| *n > 0)
+                            .ok_or_else(|| {
+                                format!("--print-frame-hash-every expects a number of frames, got {value:?}")
+                            })?,
+                    );
+                }
+                "--exit-after-frames" => {
+                    let value = value()?;
+                    exit_after_frames = Some(value.parse().map_err(|_| {
+                        format!("--exit-after-frames expects a number of frames, got {value:?}")
+                    })?);
+                }
+                "--exit-after-instructions" => {
+                    let value = value()?;
+                    exit_after_instructions = Some(value.parse().map_err(|_| {
+                        format!("--exit-after-instructions expects a number, got {value:?}")
+                    })?);
+                }
+                "--dump-frame" => dump_frame = Some(PathBuf::from(value()?)),
+                "--dump-state-on-exit" => dump_state_on_exit = Some(PathBuf::from(value()?)),
+                "--record-gif" => record_gif = Some(PathBuf::from(value()?)),
+                "--record-video" => record_video = Some(PathBuf::from(value()?)),
+                "--emit-trace" => emit_trace = Some(PathBuf::from(value()?)),
+                "--compare-trace" => compare_trace = Some(PathBuf::from(value()?)),
+                "--trace-file" => trace_file = Some(PathBuf::from(value()?)),
+                "--trace-format" => trace_format = Some(value()?.parse()?),
+                "--log-level" => log_level = Some(logging::parse_level(&value()?)?),
+                "--log-filter" => log_filter = Some(value()?.parse()?),
+                "--log-format" => log_format = Some(value()?.parse()?),
+                "--coverage" => coverage = Some(PathBuf::from(value()?)),
+                "--heatmap" => heatmap = Some(PathBuf::from(value()?)),
+                "--quirks" => quirks = Some(value()?.parse()?),
+                "--speed" => {
+                    let value = value()?;
+                    let parsed = value
+                        .parse()
+                        .map_err(|_| format!("--speed expects a number, got {value:?}"))?;
+                    speed = Some(check_speed(parsed)?);
+                }
+                "--start-paused" => start_paused = true,
+                "--break-at-start" => break_at_start = true,
+                "--break" => breakpoints.push(parse_breakpoint(&value()?)?),
+                "--watch-reg" => register_watches.push(value()?.parse()?),
+                "--watch" => watches.push(parse_address(&value()?)?),
+                "--cheat" => cheats.push(value()?.parse()?),
+                "--dump-on-error" => dump_on_error = Some(PathBuf::from(value()?)),
+                "--ascii-mirror" => ascii_mirror = true,
+                "--listen" => listen = Some(value()?),
+                "--listen-token" => listen_token = Some(value()?),
+                "--stream" => stream = Some(value()?),
+                "--debug-http" => debug_http = Some(value()?),
+                "--no-cache" => no_cache = true,
+                "--rom-dir" => rom_dir = Some(PathBuf::from(value()?)),
+                "--recent" => {
+                    // The entry number is optional, so only take the next argument
+                    // if it is one
+                    let n = match inline {
+                        Some(n) => Some(n),
+                        None => args.next_if(|arg| arg.parse::<usize>().is_ok()),
+                    };
+                    match n {
+                        Some(n) => {
+                            recent = Some(n.parse().map_err(|_| {
+                                format!("--recent expects an entry number, got {n:?}")
+                            })?)
+                        }
+                        None => list_recent = true,
+                    }
+                }
+                _ if flag.starts_with('-') => return Err(format!("Unknown option {flag}")),
+                _ if rom.is_none() => rom = Some(PathBuf::from(&arg)),
+                _ => return Err(format!("Unexpected argument {arg}")),
+            }
+        }
+        Ok(Options {
+            rom,
+            layout,
+            virtual_keypad,
+            sticky_keys,
+            confirm_quit,
+            integer_scale,
+            scale,
+            fullscreen,
+            monitor,
+            window_pos,
+            borderless,
+            palette,
+            foreground,
+            background,
+            filter,
+            filtering,
+            shader,
+            decay,
+            rotation,
+            vsync,
+            waveform,
+            beep_sample,
+            tone_hz,
+            volume,
+            audio_device,
+            list_audio_devices,
+            no_audio,
+            lowpass,
+            mute,
+            duty,
+            visual_bell,
+            record,
+            replay,
+            script,
+            frame_hash_every,
+            exit_after_frames,
+            exit_after_instructions,
+            dump_frame,
+            dump_state_on_exit,
+            record_gif,
+            record_video,
+            emit_trace,
+            compare_trace,
+            trace_file,
+            trace_format,
+            log_level,
+            log_filter,
+            log_format,
+            coverage,
+            heatmap,
+            quirks,
+            speed,
+            start_paused,
+            break_at_start,
+            breakpoints,
+            watches,
+            register_watches,
+            cheats,
+            dump_on_error,
+            ascii_mirror,
+            listen,
+            listen_token,
+            stream,
+            debug_http,
+            list_recent,
+            recent,
+            rom_dir,
+            no_cache,
+            no_pause_on_focus_loss,
+        })
+    }
+
+    /// The ROM to start with: the one given, or one from `recent` with `--recent`.
+    /// `None` shows the splash screen, which a ROM can be dropped on or opened
+    /// from with Ctrl+O.
+    pub fn rom_path(&self, recent: impl FnOnce() -> Recent) -> Result<Option<PathBuf>, String> {
+        match self.recent {
+            Some(n) => match recent().get(n) {
+                Some(path) => Ok(Some(path.to_owned())),
+                None => Err(format!(
+                    "There is no recent ROM number {n}, see `chip8 --recent`"
+                )),
+            },
+            None => Ok(self.rom.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    /// A temp list of recent ROMs holding `roms`, newest first, which exist.
+    fn recent(name: &str, roms: &[&str]) -> Recent {
+        let dir = std::env::temp_dir().join(format!("chip8-{}-{name}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut text = String::new();
+        for rom in roms {
+            std::fs::write(dir.join(rom), [0x12, 0x00]).unwrap();
+            text += &format!("0\t{}\n", dir.join(rom).display());
+        }
+        std::fs::write(dir.join("recent.txt"), text).unwrap();
+        Recent::load(Some(dir.join("recent.txt")))
+    }
+
+    #[test]
+    fn rom_from_the_arguments_or_recent_list() {
+        let unused = || -> Recent { panic!("only --recent reads the list") };
+        assert_eq!(parse(&[]).unwrap().rom_path(unused), Ok(None));
+        assert_eq!(
+            parse(&["pong.ch8"]).unwrap().rom_path(unused),
+            Ok(Some(PathBuf::from("pong.ch8")))
+        );
+
+        let list = recent("rom-path", &["tetris.ch8", "pong.ch8"]);
+        let pick = |args: &[&str]| parse(args).unwrap().rom_path(|| list.clone());
+        let second = list.get(2).unwrap().to_owned();
+        assert!(second.ends_with("pong.ch8"));
+        assert_eq!(pick(&["--recent", "2"]), Ok(Some(second)));
+        assert_eq!(
+            pick(&["--recent", "3"]),
+            Err("There is no recent ROM number 3, see `chip8 --recent`".into())
+        );
+        assert!(pick(&["--recent", "0"]).is_err());
+        std::fs::remove_dir_all(list.get(1).unwrap().parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn buzzer_options() {
+        let options = parse(&["--tone-hz", "440", "--volume", "40", "--duty", "0.25"]).unwrap();
+        assert_eq!(options.tone_hz, Some(440.0));
+        assert_eq!(options.volume, Some(40));
+        assert_eq!(options.duty, Some(0.25));
+        // Too loud is turned down rather than refused
+        assert_eq!(
+            parse(&["--volume", "250"]).unwrap().volume,
+            Some(MAX_VOLUME)
+        );
+        assert!(parse(&["--volume", "-1"]).is_err());
+        assert!(parse(&["--tone-hz", "0"]).is_err());
+        assert!(parse(&["--tone-hz", "loud"]).is_err());
+        assert!(parse(&["--duty", "1"]).is_err());
+    }
+
+    #[test]
+    fn breakpoint_options() {
+        let options = parse(&[
+            "--break",
+            "0x2A0 if v0 > 5",
+            "--break",
+            "2B0",
+            "--watch-reg",
+            "v3 == 0x20",
+        ])
+        .unwrap();
+        let breakpoints: Vec<_> = options
+            .breakpoints
+            .iter()
+            .map(|(addr, condition)| (*addr, condition.as_ref().map(ToString::to_string)))
+            .collect();
+        assert_eq!(
+            breakpoints,
+            [(0x2A0, Some("V0 > 0x5".into())), (0x2B0, None)]
+        );
+        assert_eq!(options.register_watches[0].to_string(), "V3 == 0x20");
+        assert!(parse(&["--break", "0x2A0 if"]).is_err());
+        assert!(parse(&["--watch-reg", "v3 = 0x20"]).is_err());
+    }
+}
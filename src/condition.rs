@@ -0,0 +1,314 @@
+//! Conditions on the registers for breakpoints, like `v0 > 5 and v1 != vf`.
+//!
+//! Operands are the registers `v0`-`vf`, `i`, and numbers, which are decimal unless
+//! they start with `0x`. Comparisons are `==`, `!=`, `<`, `<=`, `>` and `>=`, joined
+//! with `and` (or `&&`), which binds tighter than `or` (or `||`).
+
+use crate::trace::Registers;
+use core::fmt;
+use core::iter::Peekable;
+use core::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Condition {
+    Compare(Operand, Comparison, Operand),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Operand {
+    Register(u8),
+    I,
+    Value(u16),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Condition {
+    pub fn eval(&self, registers: &Registers) -> bool {
+        match self {
+            Condition::Compare(left, comparison, right) => {
+                let (left, right) = (left.eval(registers), right.eval(registers));
+                match comparison {
+                    Comparison::Eq => left == right,
+                    Comparison::Ne => left != right,
+                    Comparison::Lt => left < right,
+                    Comparison::Le => left <= right,
+                    Comparison::Gt => left > right,
+                    Comparison::Ge => left >= right,
+                }
+            }
+            Condition::And(left, right) => left.eval(registers) && right.eval(registers),
+            Condition::Or(left, right) => left.eval(registers) || right.eval(registers),
+        }
+    }
+}
+
+impl Operand {
+    fn eval(self, (v, i): &Registers) -> u16 {
+        match self {
+            Operand::Register(x) => u16::from(v[usize::from(x)]),
+            Operand::I => *i,
+            Operand::Value(value) => value,
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s)?;
+        let mut tokens = tokens.iter().map(String::as_str).peekable();
+        let condition = parse_or(&mut tokens)?;
+        match tokens.next() {
+            None => Ok(condition),
+            Some(token) => Err(format!("Unexpected {token:?} in condition {s:?}")),
+        }
+    }
+}
+
+fn parse_or<'a>(tokens: &mut Peekable<impl Iterator<Item = &'a str>>) -> Result<Condition, String> {
+    let mut condition = parse_and(tokens)?;
+    while tokens
+        .next_if(|token| matches!(*token, "or" | "||"))
+        .is_some()
+    {
+        condition = Condition::Or(Box::new(condition), Box::new(parse_and(tokens)?));
+    }
+    Ok(condition)
+}
+
+fn parse_and<'a>(
+    tokens: &mut Peekable<impl Iterator<Item = &'a str>>,
+) -> Result<Condition, String> {
+    let mut condition = parse_comparison(tokens)?;
+    while tokens
+        .next_if(|token| matches!(*token, "and" | "&&"))
+        .is_some()
+    {
+        condition = Condition::And(Box::new(condition), Box::new(parse_comparison(tokens)?));
+    }
+    Ok(condition)
+}
+
+fn parse_comparison<'a>(
+    tokens: &mut Peekable<impl Iterator<Item = &'a str>>,
+) -> Result<Condition, String> {
+    let left = parse_operand(tokens.next())?;
+    let comparison = match tokens.next() {
+        Some("==") => Comparison::Eq,
+        Some("!=") => Comparison::Ne,
+        Some("<") => Comparison::Lt,
+        Some("<=") => Comparison::Le,
+        Some(">") => Comparison::Gt,
+        Some(">=") => Comparison::Ge,
+        Some(token) => return Err(format!("Expected a comparison, got {token:?}")),
+        None => return Err("Expected a comparison".into()),
+    };
+    let right = parse_operand(tokens.next())?;
+    Ok(Condition::Compare(left, comparison, right))
+}
+
+fn parse_operand(token: Option<&str>) -> Result<Operand, String> {
+    let Some(token) = token else {
+        return Err("Expected a register or a number".into());
+    };
+    let lower = token.to_ascii_lowercase();
+    if lower == "i" {
+        return Ok(Operand::I);
+    }
+    if let Some(x) = lower.strip_prefix('v') {
+        if x.len() == 1 {
+            if let Ok(x) = u8::from_str_radix(x, 16) {
+                return Ok(Operand::Register(x));
+            }
+        }
+    }
+    let value = match lower.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => lower.parse(),
+    };
+    value
+        .map(Operand::Value)
+        .map_err(|_| format!("Expected a register or a number, got {token:?}"))
+}
+
+/// Splits on whitespace and around comparisons, so `v0>5` works too.
+fn tokenize(s: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            _ if c.is_whitespace() => {}
+            '=' | '!' | '<' | '>' | '&' | '|' => {
+                let mut token = c.to_string();
+                if let Some(next) = chars.next_if(|next| matches!(next, '=' | '&' | '|')) {
+                    token.push(next);
+                }
+                if matches!(token.as_str(), "=" | "!" | "&" | "|") {
+                    return Err(format!("Unexpected {token:?} in condition {s:?}"));
+                }
+                tokens.push(token);
+            }
+            _ => {
+                let mut token = c.to_string();
+                while let Some(next) = chars.next_if(|next| next.is_ascii_alphanumeric()) {
+                    token.push(next);
+                }
+                tokens.push(token);
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Condition::Compare(left, comparison, right) => {
+                let comparison = match comparison {
+                    Comparison::Eq => "==",
+                    Comparison::Ne => "!=",
+                    Comparison::Lt => "<",
+                    Comparison::Le => "<=",
+                    Comparison::Gt => ">",
+                    Comparison::Ge => ">=",
+                };
+                write!(f, "{left} {comparison} {right}")
+            }
+            Condition::And(left, right) => write!(f, "{left} and {right}"),
+            Condition::Or(left, right) => write!(f, "{left} or {right}"),
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Register(x) => write!(f, "V{x:X}"),
+            Operand::I => f.write_str("I"),
+            Operand::Value(value) => write!(f, "{value:#X}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Condition {
+        s.parse().unwrap_or_else(|err| panic!("{s:?}: {err}"))
+    }
+
+    fn compare(left: Operand, comparison: Comparison, right: Operand) -> Condition {
+        Condition::Compare(left, comparison, right)
+    }
+
+    #[test]
+    fn parses_comparisons() {
+        use Comparison::*;
+        use Operand::*;
+        for (text, comparison) in [
+            ("==", Eq),
+            ("!=", Ne),
+            ("<", Lt),
+            ("<=", Le),
+            (">", Gt),
+            (">=", Ge),
+        ] {
+            let expected = compare(Register(0), comparison, Value(5));
+            assert_eq!(parse(&format!("v0 {text} 5")), expected);
+            assert_eq!(parse(&format!("v0{text}5")), expected);
+        }
+        assert_eq!(parse("VF == 0x20"), compare(Register(15), Eq, Value(0x20)));
+        assert_eq!(parse("v3 != va"), compare(Register(3), Ne, Register(10)));
+        assert_eq!(parse("I >= 0xFFF"), compare(I, Ge, Value(0xFFF)));
+        assert_eq!(parse("  i<10 "), compare(I, Lt, Value(10)));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let a = || parse("v0 == 1");
+        let b = || parse("v1 == 2");
+        let c = || parse("v2 == 3");
+        let and = |l, r| Condition::And(Box::new(l), Box::new(r));
+        let or = |l, r| Condition::Or(Box::new(l), Box::new(r));
+        assert_eq!(
+            parse("v0 == 1 or v1 == 2 and v2 == 3"),
+            or(a(), and(b(), c()))
+        );
+        assert_eq!(
+            parse("v0 == 1 && v1 == 2 || v2 == 3"),
+            or(and(a(), b()), c())
+        );
+        assert_eq!(parse("v0==1&&v1==2&&v2==3"), and(and(a(), b()), c()));
+    }
+
+    #[test]
+    fn rejects_malformed_conditions() {
+        for text in [
+            "",
+            "v0",
+            "v0 ==",
+            "v0 = 5",
+            "v0 ! 5",
+            "v0 => 5",
+            "v0 == 5 v1",
+            "v0 == 5 and",
+            "or v0 == 5",
+            "vg == 1",
+            "v10 == 1",
+            "v0 == 0x10000",
+            "v0 == -1",
+            "v0 & 1",
+        ] {
+            assert!(text.parse::<Condition>().is_err(), "{text:?}");
+        }
+    }
+
+    #[test]
+    fn evaluates_against_the_registers() {
+        let mut v = [0; 16];
+        v[0] = 6;
+        v[1] = 6;
+        v[0xF] = 1;
+        let registers = (v, 0x300);
+        for (text, holds) in [
+            ("v0 > 5", true),
+            ("v0 > 6", false),
+            ("v0 == v1", true),
+            ("v0 != v1", false),
+            ("vf <= 1", true),
+            ("i == 0x300", true),
+            ("i < 768", false),
+            ("v2 == 0 and v0 == 7", false),
+            ("v2 == 1 or v0 == 6", true),
+            ("v2 == 1 or v0 == 6 and v1 == 0", false),
+        ] {
+            assert_eq!(parse(text).eval(&registers), holds, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn display_parses_back() {
+        for text in [
+            "v0 > 5",
+            "VF == 0x20 and i != 512",
+            "v1 < v2 or v3 >= 0x0 and v4 <= 255",
+        ] {
+            let condition = parse(text);
+            assert_eq!(parse(&condition.to_string()), condition, "{condition}");
+        }
+        assert_eq!(parse("vf==32").to_string(), "VF == 0x20");
+    }
+}
@@ -0,0 +1,524 @@
+use core::any::type_name;
+use core::str::FromStr;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::path::PathBuf;
+use tracing::*;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(PathBuf, std::io::Error),
+    Parse { line: usize, message: String },
+    Invalid { key: String, message: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(path, err) => write!(f, "{}: {err}", path.display()),
+            Error::Parse { line, message } => write!(f, "Config line {line}: {message}"),
+            Error::Invalid { key, message } => write!(f, "Config key `{key}`: {message}"),
+        }
+    }
+}
+
+/// A small subset of TOML: `key = value` pairs, `[section]` headers and `#` comments.
+/// Section names are folded into the keys, so `[keypad]` followed by `1 = "Num4"`
+/// is stored as `keypad.1`.
+/// The original lines are kept around so [`Config::set`] can write values back without
+/// disturbing comments or unrelated entries.
+///
+/// Once a ROM is picked with [`Config::select_rom`], a value is looked up in order:
+/// 1. the ROM's own section, `[rom."sha1:<hash>"]` with the SHA-1 `chip8 info` shows,
+/// 2. the settings the ROM came with, from its sidecar file,
+/// 3. the rest of the file.
+///
+/// Command line options are checked by the caller before any of these.
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    path: Option<PathBuf>,
+    lines: Vec<String>,
+    sections: Vec<(String, usize)>,
+    entries: BTreeMap<String, Entry>,
+    /// `rom.sha1:<hash>` of the selected ROM
+    rom: Option<String>,
+    /// Settings from the selected ROM's sidecar
+    suggested: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    value: String,
+    line: usize,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, Error> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => {
+                info!("Loading config from {}", path.display());
+                let config = Config::parse(&text)?;
+                Ok(Config {
+                    path: Some(path.to_owned()),
+                    ..config
+                })
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                debug!("No config file at {}", path.display());
+                Ok(Config {
+                    path: Some(path.to_owned()),
+                    ..Config::default()
+                })
+            }
+            Err(err) => Err(Error::Io(path.to_owned(), err)),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Config, Error> {
+        let mut entries = BTreeMap::new();
+        let mut sections = Vec::new();
+        let mut section = String::new();
+        for (idx, line) in text.lines().enumerate() {
+            let line_no = idx + 1;
+            let parse_err = |message: &str| Error::Parse {
+                line: line_no,
+                message: message.to_owned(),
+            };
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[') {
+                let header = header
+                    .strip_suffix(']')
+                    .ok_or_else(|| parse_err("Unterminated section header"))?;
+                section = parse_key(header).ok_or_else(|| parse_err("Invalid section name"))?;
+                sections.push((section.clone(), idx));
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| parse_err("Expected `key = value`"))?;
+            let key = parse_key(key).ok_or_else(|| parse_err("Invalid key"))?;
+            let value = parse_value(value.trim()).ok_or_else(|| parse_err("Invalid value"))?;
+            let key = if section.is_empty() {
+                key
+            } else {
+                format!("{section}.{key}")
+            };
+            let entry = Entry { value, line: idx };
+            if entries.insert(key.clone(), entry).is_some() {
+                return Err(parse_err(&format!("Duplicate key `{key}`")));
+            }
+        }
+        Ok(Config {
+            path: None,
+            lines: text.lines().map(str::to_owned).collect(),
+            sections,
+            entries,
+            rom: None,
+            suggested: BTreeMap::new(),
+        })
+    }
+
+    /// Makes the values for `rom` take precedence, followed by the ones its sidecar
+    /// `suggested`.
+    pub fn select_rom(&mut self, rom: &[u8], suggested: Vec<(String, String)>) {
+        self.rom = Some(format!("rom.sha1:{}", crate::hash::sha1_hex(rom)));
+        self.suggested = suggested.into_iter().collect();
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        let own = self
+            .rom
+            .as_ref()
+            .and_then(|rom| self.entries.get(&format!("{rom}.{key}")));
+        own.map(|entry| entry.value.as_str())
+            .or_else(|| self.suggested.get(key).map(String::as_str))
+            .or_else(|| self.entries.get(key).map(|entry| entry.value.as_str()))
+    }
+
+    /// Parses the value of `key`, e.g. as a bool or a number.
+    pub fn get_as<T: FromStr>(&self, key: &str) -> Result<Option<T>, Error> {
+        let Some(value) = self.get(key) else {
+            return Ok(None);
+        };
+        value.parse().map(Some).map_err(|_| Error::Invalid {
+            key: key.to_owned(),
+            message: format!("Expected a {}, got {value:?}", type_name::<T>()),
+        })
+    }
+
+    /// Every entry, with section names folded into the keys.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|(key, entry)| (key.as_str(), entry.value.as_str()))
+    }
+
+    /// All entries below `prefix.`, with the prefix stripped from the keys, each with
+    /// the value [`Config::get`] would give it.
+    pub fn section<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = (&'a str, &'a str)> {
+        let below = |key: &'a str| key.strip_prefix(prefix)?.strip_prefix('.');
+        let mut merged: BTreeMap<&str, &str> = BTreeMap::new();
+        for (key, entry) in &self.entries {
+            if let Some(rest) = below(key) {
+                merged.insert(rest, &entry.value);
+            }
+        }
+        for (key, value) in &self.suggested {
+            if let Some(rest) = below(key) {
+                merged.insert(rest, value);
+            }
+        }
+        if let Some(rom) = &self.rom {
+            for (key, entry) in &self.entries {
+                let own = key
+                    .strip_prefix(rom.as_str())
+                    .and_then(|key| key.strip_prefix('.'));
+                if let Some(rest) = own.and_then(below) {
+                    merged.insert(rest, &entry.value);
+                }
+            }
+        }
+        merged.into_iter()
+    }
+
+    /// Updates `key` in place if it exists, otherwise adds it to the end of the
+    /// most specific matching section (or before the first section).
+    pub fn set(&mut self, key: &str, value: &str) {
+        let formatted = format_value(value);
+        if let Some(entry) = self.entries.get_mut(key) {
+            let line = &mut self.lines[entry.line];
+            let comment = line.split_off(strip_comment(line).len());
+            let eq = line.find('=').unwrap();
+            line.truncate(eq + 1);
+            line.push(' ');
+            line.push_str(&formatted);
+            if !comment.is_empty() {
+                line.push(' ');
+                line.push_str(&comment);
+            }
+            entry.value = value.to_owned();
+            return;
+        }
+
+        let section = self
+            .sections
+            .iter()
+            .filter(|(name, _)| {
+                key.strip_prefix(name.as_str())
+                    .is_some_and(|rest| rest.starts_with('.'))
+            })
+            .max_by_key(|(name, _)| name.len());
+        let (local_key, pos) = match section {
+            Some((name, header)) => {
+                let end = self
+                    .sections
+                    .iter()
+                    .map(|(_, line)| *line)
+                    .find(|line| line > header)
+                    .unwrap_or(self.lines.len());
+                (
+                    &key[name.len() + 1..],
+                    self.last_content_line(*header, end) + 1,
+                )
+            }
+            None => {
+                let end = self.sections.first().map_or(self.lines.len(), |s| s.1);
+                let pos = match end {
+                    0 => 0,
+                    _ => self.last_content_line(0, end) + 1,
+                };
+                (key, pos)
+            }
+        };
+        self.lines
+            .insert(pos, format!("{} = {formatted}", format_key(local_key)));
+        for line in self
+            .entries
+            .values_mut()
+            .map(|entry| &mut entry.line)
+            .chain(self.sections.iter_mut().map(|section| &mut section.1))
+        {
+            if *line >= pos {
+                *line += 1;
+            }
+        }
+        self.entries.insert(
+            key.to_owned(),
+            Entry {
+                value: value.to_owned(),
+                line: pos,
+            },
+        );
+    }
+
+    /// Sets `key` for the selected ROM only, in its own section, which is added to the
+    /// end of the file if it isn't there yet. Returns false, having done nothing, if no
+    /// ROM is selected.
+    pub fn set_for_rom(&mut self, key: &str, value: &str) -> bool {
+        let Some(rom) = self.rom.clone() else {
+            return false;
+        };
+        if !self.sections.iter().any(|(name, _)| *name == rom) {
+            if self
+                .lines
+                .last()
+                .is_some_and(|line| !line.trim().is_empty())
+            {
+                self.lines.push(String::new());
+            }
+            self.sections.push((rom.clone(), self.lines.len()));
+            self.lines.push(format!("[{}]", format_key(&rom)));
+        }
+        self.set(&format!("{rom}.{key}"), value);
+        true
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        let Some(path) = &self.path else {
+            warn!("No config file location, not saving");
+            return Ok(());
+        };
+        info!("Saving config to {}", path.display());
+        let mut text = self.lines.join("\n");
+        text.push('\n');
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|err| Error::Io(dir.to_owned(), err))?;
+        }
+        std::fs::write(path, text).map_err(|err| Error::Io(path.clone(), err))
+    }
+
+    fn last_content_line(&self, start: usize, end: usize) -> usize {
+        (start..end)
+            .rev()
+            .find(|idx| !self.lines[*idx].trim().is_empty())
+            .unwrap_or(start)
+    }
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    let dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(dir.join("chip8").join("config.toml"))
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (idx, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..idx],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_key(key: &str) -> Option<String> {
+    let parts = key
+        .split('.')
+        .map(|part| {
+            let part = part.trim();
+            if let Some(quoted) = part.strip_prefix('"') {
+                quoted.strip_suffix('"').map(str::to_owned)
+            } else if is_bare_key(part) {
+                Some(part.to_owned())
+            } else {
+                None
+            }
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(parts.join("."))
+}
+
+fn is_bare_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn format_key(key: &str) -> String {
+    key.split('.')
+        .map(|part| {
+            if is_bare_key(part) {
+                part.to_owned()
+            } else {
+                format_value(part)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+fn format_value(value: &str) -> String {
+    let mut out = String::from('"');
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn parse_value(value: &str) -> Option<String> {
+    let Some(quoted) = value.strip_prefix('"') else {
+        return (!value.is_empty()).then(|| value.to_owned());
+    };
+    let mut out = String::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return chars.as_str().trim().is_empty().then_some(out),
+            '\\' => match chars.next()? {
+                'n' => out.push('\n'),
+                't' => out.push('\t'),
+                c @ ('"' | '\\') => out.push(c),
+                _ => return None,
+            },
+            c => out.push(c),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROM: &[u8] = &[0x12, 0x00];
+    const OTHER: &[u8] = &[0x00, 0xE0];
+
+    fn section_name(rom: &[u8]) -> String {
+        format!("[rom.\"sha1:{}\"]", crate::hash::sha1_hex(rom))
+    }
+
+    fn config(text: &str) -> Config {
+        Config::parse(text).unwrap()
+    }
+
+    #[test]
+    fn rom_section_then_sidecar_then_the_rest() {
+        let text = format!(
+            "speed = 1\nvideo.palette = \"amber\"\ninput.sticky = true\n\n{}\nspeed = 3\n",
+            section_name(ROM)
+        );
+        let mut config = config(&text);
+        let suggested = vec![
+            ("speed".to_owned(), "2".to_owned()),
+            ("video.palette".to_owned(), "octo".to_owned()),
+        ];
+        // Nothing selected yet, so only the rest of the file counts
+        assert_eq!(config.get("speed"), Some("1"));
+        config.select_rom(ROM, suggested.clone());
+        assert_eq!(config.get("speed"), Some("3"));
+        assert_eq!(config.get("video.palette"), Some("octo"));
+        assert_eq!(config.get("input.sticky"), Some("true"));
+        // Left to the caller's default
+        assert_eq!(config.get("video.filter"), None);
+        assert_eq!(config.get_as::<f32>("speed").unwrap(), Some(3.0));
+        // Another ROM doesn't see this one's section
+        config.select_rom(OTHER, suggested);
+        assert_eq!(config.get("speed"), Some("2"));
+        config.select_rom(OTHER, vec![]);
+        assert_eq!(config.get("speed"), Some("1"));
+        assert_eq!(config.get("video.palette"), Some("amber"));
+    }
+
+    #[test]
+    fn sections_merge_in_the_same_order() {
+        let text = format!(
+            "[keypad]\n1 = \"Num1\"\n2 = \"Num2\"\n{}\nkeypad.2 = \"W\"\n",
+            section_name(ROM)
+        );
+        let mut config = config(&text);
+        config.select_rom(ROM, vec![("keypad.3".into(), "E".into())]);
+        let keypad: Vec<(&str, &str)> = config.section("keypad").collect();
+        assert_eq!(keypad, [("1", "Num1"), ("2", "W"), ("3", "E")]);
+    }
+
+    #[test]
+    fn rom_sections_are_named_after_the_sha1() {
+        let mut config = config("");
+        config.select_rom(b"abc", vec![]);
+        assert!(config.set_for_rom("speed", "2"));
+        assert_eq!(
+            config.lines,
+            [
+                "[rom.\"sha1:a9993e364706816aba3e25717850c26c9cd0d89d\"]",
+                "speed = \"2\""
+            ]
+        );
+        // Nothing to put it under without a ROM
+        let mut config = Config::default();
+        assert!(!config.set_for_rom("speed", "2"));
+        assert!(config.lines.is_empty());
+    }
+
+    #[test]
+    fn writing_back_keeps_the_rest_of_the_file() {
+        let text = format!(
+            "# My settings\nspeed = 1 # everywhere\n\n[video]\npalette = \"amber\"  # easy on the eyes\n\n{}\n# this one is too fast\nspeed = 4 # really\n\n[keypad]\n1 = \"Num1\"",
+            section_name(OTHER)
+        );
+        let mut config = config(&text);
+        config.select_rom(OTHER, vec![]);
+        config.set_for_rom("speed", "2");
+        let mut expected: Vec<String> = text.lines().map(str::to_owned).collect();
+        expected[8] = "speed = \"2\" # really".into();
+        assert_eq!(config.lines, expected);
+
+        // A new ROM gets a new section at the end, and everything before stays put
+        config.select_rom(ROM, vec![]);
+        config.set_for_rom("video.filter", "crt");
+        config.set_for_rom("speed", "0.5");
+        expected.push(String::new());
+        expected.push(section_name(ROM));
+        expected.push("video.filter = \"crt\"".into());
+        expected.push("speed = \"0.5\"".into());
+        assert_eq!(config.lines, expected);
+
+        // And it all reads back the same
+        let mut again = Config::parse(&config.lines.join("\n")).unwrap();
+        again.select_rom(ROM, vec![]);
+        assert_eq!(again.get("speed"), Some("0.5"));
+        assert_eq!(again.get("video.filter"), Some("crt"));
+        assert_eq!(again.get("video.palette"), Some("amber"));
+        again.select_rom(OTHER, vec![]);
+        assert_eq!(again.get("speed"), Some("2"));
+        assert_eq!(again.get("keypad.1"), Some("Num1"));
+    }
+
+    #[test]
+    fn saves_to_the_file_it_came_from() {
+        let path = std::env::temp_dir().join(format!("chip8-{}-config.toml", std::process::id()));
+        std::fs::write(&path, "# kept\nspeed = 1\n").unwrap();
+        let mut config = Config::load(&path).unwrap();
+        config.select_rom(ROM, vec![]);
+        config.set_for_rom("speed", "2");
+        config.save().unwrap();
+        let saved = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            saved,
+            format!(
+                "# kept\nspeed = 1\n\n{}\nspeed = \"2\"\n",
+                section_name(ROM)
+            )
+        );
+    }
+}
@@ -0,0 +1,134 @@
+//! Which parts of a ROM have run, to see what testing hasn't reached.
+use crate::instruction::disassemble;
+use bitvec::prelude::*;
+use std::fmt::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+const START: usize = 0x200;
+
+#[derive(Debug)]
+pub struct Coverage {
+    path: PathBuf,
+    state: Mutex<(Vec<u8>, BitVec)>,
+}
+
+impl Coverage {
+    /// Call [`Coverage::reset`] once the ROM is loaded.
+    pub fn new(path: &Path) -> Coverage {
+        Coverage {
+            path: path.to_owned(),
+            state: Mutex::new((Vec::new(), BitVec::new())),
+        }
+    }
+
+    /// Starts over for a newly loaded ROM.
+    pub fn reset(&self, rom: &[u8]) {
+        *self.state.lock().unwrap() = (rom.to_vec(), bitvec![0; rom.len()]);
+    }
+
+    /// Marks both bytes of the instruction at `addr` as run.
+    pub fn hit(&self, addr: u16) {
+        let mut state = self.state.lock().unwrap();
+        let executed = &mut state.1;
+        for offset in [0, 1] {
+            if let Some(mut bit) = (usize::from(addr) + offset)
+                .checked_sub(START)
+                .and_then(|idx| executed.get_mut(idx))
+            {
+                *bit = true;
+            }
+        }
+    }
+
+    /// How much of the ROM ran, followed by each range of bytes that never did along
+    /// with its disassembly. Data in the ROM counts as never run.
+    pub fn report(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let (rom, executed) = &*state;
+        let covered = executed.count_ones();
+        let percent = if rom.is_empty() {
+            100.0
+        } else {
+            covered as f64 * 100.0 / rom.len() as f64
+        };
+        let mut report = format!("Covered {covered} of {} bytes ({percent:.1}%)\n", rom.len());
+        let mut idx = 0;
+        while let Some(start) = executed[idx..].first_zero().map(|offset| idx + offset) {
+            let end = executed[start..]
+                .first_one()
+                .map_or(rom.len(), |offset| start + offset);
+            let _ = writeln!(
+                report,
+                "\nNever ran {:03X}-{:03X}:",
+                START + start,
+                START + end - 1
+            );
+            for (offset, word) in rom[start..end].chunks(2).enumerate() {
+                let addr = START + start + offset * 2;
+                let opcode = u16::from_be_bytes([word[0], *word.get(1).unwrap_or(&0)]);
+                let _ = writeln!(
+                    report,
+                    "  {addr:03X}: {opcode:04X}  {}",
+                    disassemble(opcode)
+                );
+            }
+            idx = end;
+        }
+        report
+    }
+
+    pub fn write(&self) {
+        match std::fs::write(&self.path, self.report()) {
+            Ok(()) => tracing::info!("Saved coverage to {}", self.path.display()),
+            Err(err) => tracing::error!("Could not save coverage: {}: {err}", self.path.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::Headless;
+    use crate::quirks::Quirks;
+    use std::sync::Arc;
+
+    #[test]
+    fn reports_the_branch_that_never_ran() {
+        // V0=1, skip the next instruction since V0 is 1, V1=2, loop forever
+        let rom = vec![0x60, 0x01, 0x30, 0x01, 0x61, 0x02, 0x12, 0x06];
+        let mut core = Headless::new(rom.clone(), Quirks::default(), 10);
+        let coverage = Coverage::new(Path::new("unused"));
+        coverage.reset(&rom);
+        Arc::get_mut(&mut core.state.status).unwrap().coverage = Some(coverage);
+        for _ in 0..10 {
+            let _ = core.step();
+        }
+        let report = core.state.status.coverage.as_ref().unwrap().report();
+        assert_eq!(
+            report,
+            format!(
+                "Covered 6 of 8 bytes (75.0%)\n\nNever ran 204-205:\n  204: 6102  {}\n",
+                disassemble(0x6102)
+            )
+        );
+    }
+
+    #[test]
+    fn counts_trailing_data_as_never_run() {
+        let coverage = Coverage::new(Path::new("unused"));
+        coverage.reset(&[0x12, 0x00, 0xAB]);
+        coverage.hit(0x200);
+        // Outside the ROM
+        coverage.hit(0x100);
+        coverage.hit(0x300);
+        assert_eq!(
+            coverage.report(),
+            format!(
+                "Covered 2 of 3 bytes (66.7%)\n\nNever ran 202-202:\n  202: AB00  {}\n",
+                disassemble(0xAB00)
+            )
+        );
+    }
+}
@@ -0,0 +1,297 @@
+//! A small HTTP server for poking at a running game from scripts, e.g. with curl.
+//! It only listens on this machine unless told otherwise, as anyone who can reach it
+//! can control the emulator.
+//!
+//! - `GET /state`: the machine as JSON, see [`crate::export`]
+//! - `GET /framebuffer`: the screen as a 64x32 PNG in the current palette, or
+//!   `/framebuffer?format=bits` for the pixels packed eight to a byte with the
+//!   leftmost in the high bit, row by row
+//! - `GET /disasm?addr=0x200&count=16`: one `ADDR OPCODE MNEMONIC` line per
+//!   instruction
+//! - `POST /key/<k>/down` and `POST /key/<k>/up`: press and release keypad key `k`,
+//!   in hex
+//! - `POST /pause`, `POST /resume` and `POST /step`, which runs one instruction
+//!   while paused
+//! - `POST /loadstate`: not implemented yet, as there are no save states to load
+//!
+//! Snapshots are taken by the core between two instructions, like exports.
+
+use crate::breakpoints::parse_address;
+use crate::dump::Dump;
+use crate::io::Keypad;
+use crate::io::Status;
+use core::time::Duration;
+use futures::FutureExt;
+use smol::io::AsyncBufReadExt;
+use smol::io::AsyncWriteExt;
+use smol::io::BufReader;
+use smol::net::TcpListener;
+use smol::net::TcpStream;
+use smol::Async;
+use smol::Timer;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use tracing::*;
+
+/// How long to wait for the core to hand over a snapshot.
+const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(1);
+/// The most instructions `/disasm` lists at once.
+const MAX_DISASM: u16 = 256;
+
+pub struct Server {
+    listener: TcpListener,
+}
+
+/// What the handlers work with.
+struct Machine {
+    keypad: Arc<Mutex<Keypad>>,
+    vram: Arc<Mutex<[bool; 64 * 32]>>,
+    status: Arc<Status>,
+    paused: Arc<Mutex<bool>>,
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn ok(content_type: &'static str, body: impl Into<Vec<u8>>) -> Response {
+        Response {
+            status: "200 OK",
+            content_type,
+            body: body.into(),
+        }
+    }
+
+    fn error(status: &'static str, message: impl Into<String>) -> Response {
+        let mut body = message.into();
+        body.push('\n');
+        Response {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: body.into(),
+        }
+    }
+}
+
+impl Server {
+    /// Starts listening on an address like `127.0.0.1:9000`, or on this machine only
+    /// given just a port.
+    pub fn bind(addr: &str) -> Result<Server, String> {
+        let addr: SocketAddr = match addr.parse::<u16>() {
+            Ok(port) => SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port),
+            Err(_) => addr
+                .parse()
+                .map_err(|_| format!("Expected an address like 127.0.0.1:9000, got {addr:?}"))?,
+        };
+        if !addr.ip().is_loopback() {
+            warn!("The debug server on {addr} lets anyone who can reach it control the game");
+        }
+        let listener = Async::<std::net::TcpListener>::bind(addr)
+            .map_err(|err| format!("Could not listen on {addr}: {err}"))?;
+        info!("Debug server at http://{addr}/");
+        Ok(Server {
+            listener: listener.into(),
+        })
+    }
+
+    /// Where it ended up listening, which is needed for port 0.
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Answers each connection in a task of its own.
+    pub async fn serve(
+        self,
+        keypad: Arc<Mutex<Keypad>>,
+        vram: Arc<Mutex<[bool; 64 * 32]>>,
+        status: Arc<Status>,
+        paused: Arc<Mutex<bool>>,
+    ) -> ! {
+        let machine = Arc::new(Machine {
+            keypad,
+            vram,
+            status,
+            paused,
+        });
+        loop {
+            match self.listener.accept().await {
+                Ok((stream, peer)) => {
+                    let machine = machine.clone();
+                    smol::spawn(async move {
+                        if let Err(err) = connection(stream, &machine).await {
+                            debug!("Debug request from {peer} failed: {err}");
+                        }
+                    })
+                    .detach();
+                }
+                Err(err) => {
+                    warn!("Could not accept a connection: {err}");
+                    Timer::after(Duration::from_millis(100)).await;
+                }
+            }
+        }
+    }
+}
+
+/// Answers one request and closes the connection.
+async fn connection(stream: TcpStream, machine: &Machine) -> std::io::Result<()> {
+    let mut writer = stream.clone();
+    let mut reader = BufReader::new(stream);
+    let mut request = String::new();
+    reader.read_line(&mut request).await?;
+    // Nothing takes a body, so the headers are only read past
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+    let mut parts = request.split_whitespace();
+    let (method, target) = (
+        parts.next().unwrap_or_default(),
+        parts.next().unwrap_or("/"),
+    );
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    debug!("Debug request {method} {target}");
+    let response = handle(method, path, query, machine).await;
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    writer.write_all(head.as_bytes()).await?;
+    writer.write_all(&response.body).await
+}
+
+async fn handle(method: &str, path: &str, query: &str, machine: &Machine) -> Response {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match (method, &segments[..]) {
+        ("GET", ["state"]) => match snapshot(&machine.status).await {
+            Ok(dump) => Response::ok("application/json", crate::export::to_json(&dump)),
+            Err(response) => response,
+        },
+        ("GET", ["framebuffer"]) => framebuffer(query, machine),
+        ("GET", ["disasm"]) => disasm(query, &machine.status).await,
+        ("POST", ["key", key, action @ ("down" | "up")]) => {
+            let Some(key) = u8::from_str_radix(key, 16).ok().filter(|key| *key < 16) else {
+                return Response::error("400 Bad Request", "Expected a key between 0 and F");
+            };
+            let mut keypad = machine.keypad.lock().unwrap();
+            match *action {
+                "down" => keypad.press(key),
+                _ => keypad.release(key),
+            }
+            Response::ok("text/plain", "ok\n")
+        }
+        ("POST", ["pause"]) => {
+            *machine.paused.lock().unwrap() = true;
+            Response::ok("text/plain", "ok\n")
+        }
+        ("POST", ["resume"]) => {
+            *machine.paused.lock().unwrap() = false;
+            Response::ok("text/plain", "ok\n")
+        }
+        ("POST", ["step"]) => {
+            if !*machine.paused.lock().unwrap() {
+                return Response::error("409 Conflict", "Only a paused game can be stepped");
+            }
+            machine.status.step.store(true, Ordering::Relaxed);
+            Response::ok("text/plain", "ok\n")
+        }
+        ("POST", ["loadstate"]) => Response::error(
+            "501 Not Implemented",
+            "There are no save states to load yet",
+        ),
+        (_, ["state" | "framebuffer" | "disasm" | "pause" | "resume" | "step" | "loadstate"])
+        | (_, ["key", _, _]) => Response::error("405 Method Not Allowed", "Wrong method"),
+        _ => Response::error("404 Not Found", format!("Nothing at {path}")),
+    }
+}
+
+/// A snapshot from the core, which only answers between two instructions.
+async fn snapshot(status: &Status) -> Result<Dump, Response> {
+    let receiver = status.request_state();
+    let timeout = Timer::after(SNAPSHOT_TIMEOUT).map(|_| None);
+    match futures::future::select(Box::pin(receiver.recv().map(Result::ok)), timeout)
+        .await
+        .factor_first()
+        .0
+    {
+        Some(dump) => Ok(dump),
+        None => Err(Response::error(
+            "503 Service Unavailable",
+            "The core didn't answer",
+        )),
+    }
+}
+
+fn framebuffer(query: &str, machine: &Machine) -> Response {
+    let vram = *machine.vram.lock().unwrap();
+    match param(query, "format").unwrap_or("png") {
+        "png" => {
+            let palette = *machine.status.palette.lock().unwrap();
+            let rgb = crate::io::to_rgb24(&crate::io::pixel_colors(&vram, &palette));
+            Response::ok("image/png", crate::io::encode_png(64, 32, &rgb))
+        }
+        "bits" => {
+            let packed: Vec<u8> = vram
+                .chunks(8)
+                .map(|byte| byte.iter().fold(0, |acc, on| acc << 1 | u8::from(*on)))
+                .collect();
+            Response::ok("application/octet-stream", packed)
+        }
+        format => Response::error(
+            "400 Bad Request",
+            format!("Unknown format {format:?}, expected png or bits"),
+        ),
+    }
+}
+
+async fn disasm(query: &str, status: &Status) -> Response {
+    let addr = match param(query, "addr").map(parse_address).transpose() {
+        Ok(addr) => addr,
+        Err(err) => return Response::error("400 Bad Request", err),
+    };
+    let count = match param(query, "count").map(str::parse::<u16>).transpose() {
+        Ok(count) => count.unwrap_or(16).min(MAX_DISASM),
+        Err(_) => return Response::error("400 Bad Request", "count has to be a number"),
+    };
+    let dump = match snapshot(status).await {
+        Ok(dump) => dump,
+        Err(response) => return response,
+    };
+    let mut text = String::new();
+    let mut addr = addr.unwrap_or(dump.pc);
+    for _ in 0..count {
+        let (Some(high), Some(low)) = (
+            dump.memory.get(addr as usize),
+            dump.memory.get(addr as usize + 1),
+        ) else {
+            break;
+        };
+        let opcode = u16::from_be_bytes([*high, *low]);
+        text.push_str(&format!(
+            "{addr:03X} {opcode:04X} {}\n",
+            crate::instruction::disassemble(opcode)
+        ));
+        addr += 2;
+    }
+    Response::ok("text/plain; charset=utf-8", text)
+}
+
+/// The value of `name` in a query string like `addr=0x200&count=16`.
+fn param<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
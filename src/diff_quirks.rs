@@ -0,0 +1,244 @@
+//! `chip8 diff-quirks`: runs a ROM under several quirk profiles side by side to find
+//! out which quirk it is sensitive to.
+
+use crate::cli::Exit;
+use crate::hash::fnv1a;
+use crate::headless::Headless;
+use crate::quirks::Profile;
+use crate::ExitReason;
+use crate::State;
+use core::fmt;
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: chip8 diff-quirks [OPTIONS] ROM
+
+Runs the ROM without a window under each profile in lockstep, with no keys pressed,
+and reports the first frame where they stop agreeing.
+
+Options:
+    --frames <N>       How many frames to run for [default: 600]
+    --ipf <N>          Instructions per frame [default: 10]
+    --profiles <A,B>   Profiles to compare, the first against each of the others:
+                       chip8, schip, xochip, amiga or all [default: chip8,schip]";
+
+/// How one profile compared with the first.
+#[derive(Debug)]
+pub enum Verdict {
+    Same {
+        frames: u64,
+    },
+    Diverges {
+        frame: u64,
+        pc: u16,
+        opcode: u16,
+        /// The quirk that decides what `opcode` does, if the profiles disagree on one
+        quirk: Option<&'static str>,
+    },
+    /// Both stopped, e.g. on an infinite loop, without disagreeing first
+    Stopped {
+        frame: u64,
+        reason: ExitReason,
+    },
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Verdict::Same { frames } => write!(f, "agrees for all {frames} frames"),
+            Verdict::Stopped { frame, reason } => {
+                write!(f, "agrees until both stop at frame {frame} ({reason:?})")
+            }
+            Verdict::Diverges {
+                frame,
+                quirk: Some(quirk),
+                ..
+            } => write!(f, "diverges at frame {frame} due to {quirk}"),
+            Verdict::Diverges {
+                frame,
+                pc,
+                opcode,
+                quirk: None,
+            } => write!(f, "diverges at frame {frame} on {opcode:04X} at {pc:03X}"),
+        }
+    }
+}
+
+impl State {
+    /// Everything a program can observe, so equal hashes mean the runs can't tell
+    /// each other apart.
+    fn state_hash(&self) -> u64 {
+        // Writing zeroes past the end of the program grows it without changing
+        // what reading memory gives
+        let rom = &self.memory.rom;
+        let used = rom
+            .iter()
+            .rposition(|byte| *byte != 0)
+            .map_or(0, |idx| idx + 1);
+        let stack = self.stack.iter().flat_map(|addr| addr.to_le_bytes());
+        let vram = crate::hash::pack(&*self.vram.lock().unwrap());
+        let delay = *self.delay_timer.lock().unwrap();
+        let sound = *self.sound_timer.lock().unwrap();
+        let bytes: Vec<u8> = self
+            .pc
+            .to_le_bytes()
+            .into_iter()
+            .chain(self.vi.to_le_bytes())
+            .chain(self.registers.0)
+            .chain([delay, sound])
+            .chain(stack)
+            .chain(vram)
+            .chain(rom[..used].iter().copied())
+            .collect();
+        fnv1a(&bytes)
+    }
+}
+
+/// Runs `rom` under `base` and `other` together for up to `frames` frames.
+pub fn compare(rom: &[u8], base: Profile, other: Profile, frames: u64, ipf: u64) -> Verdict {
+    let mut a = Headless::new(rom.to_vec(), base.quirks(), ipf);
+    let mut b = Headless::new(rom.to_vec(), other.quirks(), ipf);
+    while a.frame() < frames {
+        let frame = a.frame();
+        let (pc, opcode) = (a.state.pc, a.state.fetch().opcode());
+        let flow = a.step();
+        // While the states match, so does how the step ended
+        let _ = b.step();
+        if a.state.state_hash() != b.state.state_hash() {
+            return Verdict::Diverges {
+                frame,
+                pc,
+                opcode,
+                quirk: base.quirks().attribute(&other.quirks(), opcode),
+            };
+        }
+        match flow {
+            // Stuck on FX0A with the timers run down, so later frames are all the same
+            ControlFlow::Break(ExitReason::WaitingForKeyPress) if !a.timers_stopped() => {}
+            ControlFlow::Break(reason) => return Verdict::Stopped { frame, reason },
+            ControlFlow::Continue(()) => {}
+        }
+    }
+    Verdict::Same { frames }
+}
+
+pub fn run(args: impl IntoIterator<Item = String>) -> Result<(), Exit> {
+    let mut frames = 600;
+    let mut ipf = 10;
+    let mut profiles = vec![Profile::Chip8, Profile::Schip];
+    let mut rom = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_owned())),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| usage_error(format!("{flag} expects a value")))
+        };
+        match flag {
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            "--frames" => frames = number(flag, &value()?)?,
+            "--ipf" => ipf = number(flag, &value()?)?.max(1),
+            "--profiles" => {
+                let value = value()?;
+                profiles = if value == "all" {
+                    Profile::ALL.to_vec()
+                } else {
+                    value
+                        .split(',')
+                        .map(|name| {
+                            name.trim()
+                                .parse::<Profile>()
+                                .map_err(|err: String| usage_error(err))
+                        })
+                        .collect::<Result<_, _>>()?
+                };
+                if profiles.len() < 2 {
+                    return Err(usage_error("--profiles needs at least two profiles"));
+                }
+            }
+            _ if flag.starts_with('-') => {
+                return Err(usage_error(format!("Unknown option {flag}")))
+            }
+            _ if rom.is_none() => rom = Some(PathBuf::from(&arg)),
+            _ => return Err(usage_error(format!("Unexpected argument {arg}"))),
+        }
+    }
+    let rom = rom.ok_or_else(|| usage_error("No ROM given"))?;
+    let rom = crate::io::rom::read(&rom).map_err(Exit::failed)?;
+    let base = profiles[0];
+    for &other in &profiles[1..] {
+        let verdict = compare(&rom, base, other, frames, ipf);
+        println!("{base} vs {other}: {verdict}");
+    }
+    Ok(())
+}
+
+fn number(flag: &str, value: &str) -> Result<u64, Exit> {
+    value
+        .parse()
+        .map_err(|_| usage_error(format!("{flag} expects a number, got {value:?}")))
+}
+
+fn usage_error(message: impl fmt::Display) -> Exit {
+    Exit::usage(message, USAGE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sets V0 and V1 over two frames, then shifts into V0, which only the shift
+    /// quirk decides the result of
+    const SHIFT: &[u8] = &[
+        0x60, 0x05, 0x61, 0x03, 0x62, 0x07, 0x63, 0x08, 0x80, 0x16, 0x12, 0x0A,
+    ];
+
+    #[test]
+    fn finds_the_shift_quirk() {
+        let verdict = compare(SHIFT, Profile::Chip8, Profile::Schip, 10, 2);
+        let Verdict::Diverges {
+            frame,
+            pc,
+            opcode,
+            quirk,
+        } = verdict
+        else {
+            panic!("expected a divergence, got {verdict:?}");
+        };
+        assert_eq!((frame, pc, opcode), (2, 0x208, 0x8016));
+        assert_eq!(quirk, Some("8XY6 shift source"));
+        assert_eq!(
+            verdict.to_string(),
+            "diverges at frame 2 due to 8XY6 shift source"
+        );
+    }
+
+    #[test]
+    fn agrees_when_only_other_quirks_differ() {
+        // XO-CHIP shifts like CHIP-8 but differs in VF reset and clipping
+        let verdict = compare(SHIFT, Profile::Chip8, Profile::XoChip, 10, 2);
+        assert!(
+            matches!(
+                verdict,
+                Verdict::Stopped {
+                    frame: 2,
+                    reason: ExitReason::InfiniteLoop
+                }
+            ),
+            "{verdict:?}"
+        );
+        assert_eq!(
+            verdict.to_string(),
+            "agrees until both stop at frame 2 (InfiniteLoop)"
+        );
+    }
+}
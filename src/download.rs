@@ -0,0 +1,169 @@
+//! ROMs given as an `http://` or `https://` URL, fetched with ureq. Like curl, it
+//! follows redirects and goes through the proxy in `HTTPS_PROXY` or `HTTP_PROXY`
+//! when there is one.
+//!
+//! Downloads are kept in `~/.cache/chip8/roms/<url hash>/<file name>`, so a ROM that
+//! was played once still starts without a network, and keeps its own file name for
+//! the title and screenshots.
+
+use crate::hash;
+use crate::io::rom;
+use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::*;
+
+/// How long a download can take before it's given up on.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The URL in `path`, if it is one.
+pub fn url(path: &Path) -> Option<&str> {
+    let path = path.to_str()?;
+    let scheme = path.split_once("://")?.0;
+    (scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https")).then_some(path)
+}
+
+/// Where the ROM at `url` is cached, whether or not it has been downloaded yet.
+pub fn cache_path(url: &str) -> Option<PathBuf> {
+    let dir = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(cache_path_in(&dir, url))
+}
+
+/// Where the ROM at `url` goes in the cache directory `dir`: a directory named after
+/// the whole URL's hash, so different URLs never share a file, holding the file
+/// named after the last part of its path.
+pub fn cache_path_in(dir: &Path, url: &str) -> PathBuf {
+    // Without the query or fragment, e.g. `pong.ch8` from `.../pong.ch8?raw=true`,
+    // and never the host's name
+    let file = url
+        .split(['?', '#'])
+        .next()
+        .and_then(|url| url.split_once("://"))
+        .and_then(|(_, rest)| rest.split_once('/'))
+        .and_then(|(_, path)| path.rsplit('/').next())
+        .filter(|file| !file.is_empty() && !file.contains(':'))
+        .unwrap_or("rom.ch8");
+    dir.join("chip8")
+        .join("roms")
+        .join(format!("{:016x}", hash::fnv1a(url.as_bytes())))
+        .join(file)
+}
+
+/// Downloads the ROM at `url` unless it is cached already, or always with `refresh`,
+/// and returns the file it is in.
+pub fn fetch(url: &str, refresh: bool) -> Result<PathBuf, String> {
+    let path = cache_path(url).ok_or("No cache directory to download ROMs to")?;
+    if !refresh && path.is_file() {
+        info!("Using {} for {url}", path.display());
+        return Ok(path);
+    }
+    info!("Downloading {url}");
+    let dir = path.parent().unwrap();
+    std::fs::create_dir_all(dir).map_err(|err| format!("{}: {err}", dir.display()))?;
+    let rom = download(url)?;
+    // Written next to it first, so a failed write doesn't replace a good copy
+    let partial = path.with_extension("part");
+    std::fs::write(&partial, &rom).map_err(|err| format!("{}: {err}", partial.display()))?;
+    std::fs::rename(&partial, &path).map_err(|err| format!("{}: {err}", path.display()))?;
+    Ok(path)
+}
+
+fn download(url: &str) -> Result<Vec<u8>, String> {
+    let too_large = |size: String| {
+        format!(
+            "{url} is too large for a ROM ({size} bytes, at most {})",
+            rom::MAX_SIZE
+        )
+    };
+    let agent = ureq::AgentBuilder::new()
+        .timeout(TIMEOUT)
+        .try_proxy_from_env(true)
+        .build();
+    let response = match agent.get(url).call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(code, response)) => {
+            return Err(format!(
+                "Could not download {url}: {code} {}",
+                response.status_text()
+            ))
+        }
+        Err(err) => return Err(format!("Could not download {url}: {err}")),
+    };
+    let length = response.header("Content-Length");
+    if let Some(length) =
+        length.filter(|length| length.parse().is_ok_and(|n: usize| n > rom::MAX_SIZE))
+    {
+        return Err(too_large(length.to_owned()));
+    }
+    // Checked again as not every server says how large a file is up front
+    let mut rom = vec![];
+    response
+        .into_reader()
+        .take(rom::MAX_SIZE as u64 + 1)
+        .read_to_end(&mut rom)
+        .map_err(|err| format!("Could not download {url}: {err}"))?;
+    if rom.len() > rom::MAX_SIZE {
+        return Err(too_large(format!("over {}", rom::MAX_SIZE)));
+    }
+    Ok(rom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_http_and_https_are_urls() {
+        for url in ["http://a/b.ch8", "HTTPS://a/b.ch8"] {
+            assert_eq!(super::url(Path::new(url)), Some(url));
+        }
+        for path in [
+            "roms/pong.ch8",
+            "ftp://a/b.ch8",
+            "file:///a.ch8",
+            "C:\\roms\\a.ch8",
+        ] {
+            assert_eq!(super::url(Path::new(path)), None, "{path}");
+        }
+    }
+
+    #[test]
+    fn cache_is_keyed_by_the_whole_url() {
+        let dir = Path::new("/cache");
+        let path = cache_path_in(dir, "https://example.com/games/pong.ch8");
+        assert_eq!(path.file_name().unwrap(), "pong.ch8");
+        assert!(path.starts_with("/cache/chip8/roms"));
+        assert_eq!(
+            path,
+            cache_path_in(dir, "https://example.com/games/pong.ch8")
+        );
+        // The same file name from somewhere else, or with another query, is another ROM
+        let others = [
+            "https://example.org/games/pong.ch8",
+            "http://example.com/games/pong.ch8",
+            "https://example.com/games/pong.ch8?v=2",
+        ];
+        for other in others {
+            let other = cache_path_in(dir, other);
+            assert_eq!(other.file_name().unwrap(), "pong.ch8");
+            assert_ne!(other.parent(), path.parent());
+        }
+    }
+
+    #[test]
+    fn cached_file_is_named_after_the_url_path() {
+        let name = |url| {
+            cache_path_in(Path::new("/cache"), url)
+                .file_name()
+                .unwrap()
+                .to_owned()
+        };
+        assert_eq!(name("https://a/pong.ch8?raw=true"), "pong.ch8");
+        assert_eq!(name("https://a/pong.ch8#top"), "pong.ch8");
+        assert_eq!(name("https://a/games/"), "rom.ch8");
+        assert_eq!(name("https://a"), "rom.ch8");
+    }
+}
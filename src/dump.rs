@@ -0,0 +1,542 @@
+//! Crash dumps: everything about the machine when a program dies, for bug reports.
+//!
+//! A dump is a text file, one field per line after a `chip8-dump 1` header:
+//!
+//! ```text
+//! chip8-dump 1
+//! reason IllegalInstruction
+//! fault 0206                    the instruction that stopped the program
+//! rom-hash 5d1e0c3a9f8b7c21     FNV-1a of the ROM as loaded
+//! quirks vf-reset memory clipping
+//! pc 0208
+//! i 0300
+//! v 05 00 00 00 00 00 00 00 00 00 00 00 00 00 00 01
+//! stack 0202                    return addresses, innermost last
+//! timers 00 00                  delay, then sound
+//! vram                          32 rows of 64 pixels, # for lit
+//! ................................................................
+//! memory                        256 rows of 16 bytes
+//! 0000 F0 90 90 90 F0 20 60 20 20 70 F0 10 F0 80 F0 F0
+//! history 3                     the last instructions run, oldest first, in
+//! 0200 6005 00 00 ...           --emit-trace lines
+//! ```
+//!
+//! `chip8 inspect-dump FILE` reads one back.
+
+use crate::cli::Exit;
+use crate::hash;
+use crate::instruction;
+use crate::quirks::Quirks;
+use crate::trace;
+use crate::trace::Registers;
+use std::collections::VecDeque;
+use std::fmt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+const HEADER: &str = "chip8-dump";
+const VERSION: u32 = 1;
+/// How many instructions a dump remembers.
+pub const HISTORY: usize = 200;
+
+/// The last [`HISTORY`] instructions and what they ran with, kept while a program
+/// runs with `--dump-on-error` so a dump can show how it got into trouble.
+#[derive(Debug)]
+pub struct Recorder {
+    dir: PathBuf,
+    rom_hash: u64,
+    history: VecDeque<(u16, u16, Registers)>,
+}
+
+impl Recorder {
+    pub fn new(dir: PathBuf, rom: &[u8]) -> Recorder {
+        Recorder {
+            dir,
+            rom_hash: hash::fnv1a(rom),
+            history: VecDeque::with_capacity(HISTORY),
+        }
+    }
+
+    /// Starts over for a new ROM.
+    pub fn reset(&mut self, rom: &[u8]) {
+        self.rom_hash = hash::fnv1a(rom);
+        self.history.clear();
+    }
+
+    /// Notes an instruction and the registers before it runs.
+    pub fn record(&mut self, pc: u16, opcode: u16, registers: &Registers) {
+        if self.history.len() == HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back((pc, opcode, *registers));
+    }
+
+    /// Fills in what was recorded and writes `dump` to a new file in the dump
+    /// directory, returning its path.
+    pub fn save(&self, mut dump: Dump) -> Result<PathBuf, String> {
+        dump.rom_hash = self.rom_hash;
+        dump.history = self.history.iter().copied().collect();
+        std::fs::create_dir_all(&self.dir)
+            .map_err(|err| format!("{}: {err}", self.dir.display()))?;
+        let secs = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        let path = self.dir.join(format!("chip8-{secs}.dump"));
+        std::fs::write(&path, dump.to_string())
+            .map_err(|err| format!("{}: {err}", path.display()))?;
+        Ok(path)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Dump {
+    pub reason: String,
+    pub fault: u16,
+    pub rom_hash: u64,
+    pub quirks: Quirks,
+    pub pc: u16,
+    pub i: u16,
+    pub registers: [u8; 16],
+    pub stack: Vec<u16>,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    /// 64x32, row by row
+    pub vram: Vec<bool>,
+    /// All 4K
+    pub memory: Vec<u8>,
+    pub history: Vec<(u16, u16, Registers)>,
+}
+
+/// The names quirks are saved under.
+pub fn quirk_names(quirks: &mut Quirks) -> [(&'static str, &mut bool); 6] {
+    [
+        ("vf-reset", &mut quirks.vf_reset),
+        ("memory", &mut quirks.memory),
+        ("shift", &mut quirks.shift),
+        ("jump", &mut quirks.jump),
+        ("clipping", &mut quirks.clipping),
+        ("index-overflow", &mut quirks.index_overflow),
+    ]
+}
+
+impl core::fmt::Display for Dump {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        writeln!(f, "{HEADER} {VERSION}")?;
+        writeln!(f, "reason {}", self.reason)?;
+        writeln!(f, "fault {:04X}", self.fault)?;
+        writeln!(f, "rom-hash {:016x}", self.rom_hash)?;
+        let mut quirks = self.quirks;
+        f.write_str("quirks")?;
+        for (name, _) in quirk_names(&mut quirks).into_iter().filter(|(_, on)| **on) {
+            write!(f, " {name}")?;
+        }
+        writeln!(f)?;
+        writeln!(f, "pc {:04X}", self.pc)?;
+        writeln!(f, "i {:04X}", self.i)?;
+        f.write_str("v")?;
+        for v in self.registers {
+            write!(f, " {v:02X}")?;
+        }
+        writeln!(f)?;
+        f.write_str("stack")?;
+        for addr in &self.stack {
+            write!(f, " {addr:04X}")?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "timers {:02X} {:02X}",
+            self.delay_timer, self.sound_timer
+        )?;
+        writeln!(f, "vram")?;
+        for row in self.vram.chunks(64) {
+            let row: String = row.iter().map(|&on| if on { '#' } else { '.' }).collect();
+            writeln!(f, "{row}")?;
+        }
+        writeln!(f, "memory")?;
+        for (n, row) in self.memory.chunks(16).enumerate() {
+            write!(f, "{:04X}", n * 16)?;
+            for byte in row {
+                write!(f, " {byte:02X}")?;
+            }
+            writeln!(f)?;
+        }
+        writeln!(f, "history {}", self.history.len())?;
+        for (pc, opcode, (registers, i)) in &self.history {
+            writeln!(f, "{}", trace::format_line(*pc, *opcode, registers, *i))?;
+        }
+        Ok(())
+    }
+}
+
+impl Dump {
+    pub fn load(path: &Path) -> Result<Dump, String> {
+        let text =
+            std::fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        Dump::parse(&text).map_err(|err| format!("{}: {err}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Dump, String> {
+        let mut lines = text.lines().enumerate().map(|(idx, line)| (idx + 1, line));
+        let (_, version) = field(&mut lines, HEADER).map_err(|_| "Not a crash dump".to_owned())?;
+        let version = version.join(" ");
+        if version != VERSION.to_string() {
+            return Err(format!("Unsupported dump version {version}"));
+        }
+        let hex = |word: &str| u16::from_str_radix(word, 16).ok();
+        let (_, reason) = field(&mut lines, "reason")?;
+        let reason = reason.join(" ");
+        let fault = one(field(&mut lines, "fault")?, hex)?;
+        let rom_hash = one(field(&mut lines, "rom-hash")?, |word| {
+            u64::from_str_radix(word, 16).ok()
+        })?;
+        let (number, names) = field(&mut lines, "quirks")?;
+        let mut quirks = Quirks {
+            vf_reset: false,
+            memory: false,
+            shift: false,
+            jump: false,
+            clipping: false,
+            index_overflow: false,
+        };
+        for name in names {
+            let (_, on) = quirk_names(&mut quirks)
+                .into_iter()
+                .find(|(known, _)| *known == name)
+                .ok_or_else(|| format!("Unknown quirk {name:?} on line {number}"))?;
+            *on = true;
+        }
+        let pc = one(field(&mut lines, "pc")?, hex)?;
+        let i = one(field(&mut lines, "i")?, hex)?;
+        let (number, words) = field(&mut lines, "v")?;
+        let registers = hex_bytes(number, &words)?
+            .try_into()
+            .map_err(|_| format!("Expected 16 registers on line {number}"))?;
+        let (number, words) = field(&mut lines, "stack")?;
+        let stack = words
+            .iter()
+            .map(|word| u16::from_str_radix(word, 16))
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("Invalid stack on line {number}"))?;
+        let (number, words) = field(&mut lines, "timers")?;
+        let [delay_timer, sound_timer] = hex_bytes(number, &words)?[..] else {
+            return Err(format!("Expected two timers on line {number}"));
+        };
+        field(&mut lines, "vram")?;
+        let mut vram = Vec::with_capacity(64 * 32);
+        for _ in 0..32 {
+            let (number, line) = lines.next().ok_or("Missing vram rows at the end")?;
+            if line.len() != 64 || !line.chars().all(|c| c == '#' || c == '.') {
+                return Err(format!("Invalid vram row on line {number}"));
+            }
+            vram.extend(line.chars().map(|c| c == '#'));
+        }
+        field(&mut lines, "memory")?;
+        let mut memory = Vec::with_capacity(0x1000);
+        for row in 0..0x100 {
+            let (number, line) = lines.next().ok_or("Missing memory rows at the end")?;
+            let words: Vec<_> = line.split_whitespace().collect();
+            let [addr, bytes @ ..] = &words[..] else {
+                return Err(format!("Invalid memory row on line {number}"));
+            };
+            if u16::from_str_radix(addr, 16) != Ok(row * 16) || bytes.len() != 16 {
+                return Err(format!("Invalid memory row on line {number}"));
+            }
+            memory.extend(hex_bytes(number, bytes)?);
+        }
+        let count = one(field(&mut lines, "history")?, |word| {
+            word.parse::<usize>().ok()
+        })?;
+        let mut history = Vec::with_capacity(count);
+        for _ in 0..count {
+            let (number, line) = lines.next().ok_or("Missing history at the end")?;
+            history.push(
+                parse_trace_line(line)
+                    .ok_or_else(|| format!("Invalid history on line {number}"))?,
+            );
+        }
+        Ok(Dump {
+            reason,
+            fault,
+            rom_hash,
+            quirks,
+            pc,
+            i,
+            registers,
+            stack,
+            delay_timer,
+            sound_timer,
+            vram,
+            memory,
+            history,
+        })
+    }
+
+    /// A readable summary, with the code around the faulting instruction.
+    pub fn report(&self) -> String {
+        let mut out = format!("{} at {:04X}\n", self.reason, self.fault);
+        out.push_str(&format!("ROM hash {:016x}\n", self.rom_hash));
+        let mut quirks = self.quirks;
+        let on: Vec<_> = quirk_names(&mut quirks)
+            .into_iter()
+            .filter(|(_, on)| **on)
+            .map(|(name, _)| name)
+            .collect();
+        let on = if on.is_empty() {
+            "none".into()
+        } else {
+            on.join(", ")
+        };
+        out.push_str(&format!("Quirks: {on}\n\n"));
+        out.push_str(&format!(
+            "PC {:04X}  I {:04X}  DT {:02X}  ST {:02X}\n",
+            self.pc, self.i, self.delay_timer, self.sound_timer
+        ));
+        for (row, values) in self.registers.chunks(8).enumerate() {
+            let line: Vec<_> = values
+                .iter()
+                .enumerate()
+                .map(|(idx, v)| format!("V{:X} {v:02X}", row * 8 + idx))
+                .collect();
+            out.push_str(&line.join("  "));
+            out.push('\n');
+        }
+        for ret in self.stack.iter().rev() {
+            let call = ret.wrapping_sub(2);
+            out.push_str(&format!(
+                "Called from {call:04X} {}\n",
+                instruction::disassemble(self.opcode_at(call))
+            ));
+        }
+        out.push_str("\nCode:\n");
+        let start = self.fault.saturating_sub(10);
+        for addr in (start..self.fault.saturating_add(12)).step_by(2) {
+            let marker = if addr == self.fault { '>' } else { ' ' };
+            let opcode = self.opcode_at(addr);
+            out.push_str(&format!(
+                "{marker} {addr:04X} {opcode:04X}  {}\n",
+                instruction::disassemble(opcode)
+            ));
+        }
+        if !self.history.is_empty() {
+            out.push_str(&format!("\nLast {} instructions:\n", self.history.len()));
+            out.push_str(trace::HEADER);
+            out.push('\n');
+            for (pc, opcode, (registers, i)) in &self.history {
+                out.push_str(&trace::format_line(*pc, *opcode, registers, *i));
+                out.push('\n');
+            }
+        }
+        out.push_str("\nScreen:\n");
+        for row in self.vram.chunks(64) {
+            out.extend(row.iter().map(|&on| if on { '#' } else { '.' }));
+            out.push('\n');
+        }
+        out
+    }
+
+    fn opcode_at(&self, addr: u16) -> u16 {
+        let byte = |addr: u16| self.memory.get(usize::from(addr)).copied().unwrap_or(0);
+        u16::from_be_bytes([byte(addr), byte(addr.wrapping_add(1))])
+    }
+}
+
+/// The words after `name` on the next line, and the line's number.
+fn field<'a>(
+    lines: &mut impl Iterator<Item = (usize, &'a str)>,
+    name: &str,
+) -> Result<(usize, Vec<&'a str>), String> {
+    let (number, line) = lines
+        .next()
+        .ok_or_else(|| format!("Missing {name} at the end"))?;
+    let mut words = line.split_whitespace();
+    if words.next() != Some(name) {
+        return Err(format!("Expected {name} on line {number}, got {line:?}"));
+    }
+    Ok((number, words.collect()))
+}
+
+/// The only word of a line, parsed.
+fn one<T>(
+    (number, words): (usize, Vec<&str>),
+    parse: impl Fn(&str) -> Option<T>,
+) -> Result<T, String> {
+    match words[..] {
+        [word] => parse(word).ok_or_else(|| format!("Invalid value {word:?} on line {number}")),
+        _ => Err(format!("Expected one value on line {number}")),
+    }
+}
+
+fn hex_bytes(number: usize, words: &[&str]) -> Result<Vec<u8>, String> {
+    words
+        .iter()
+        .map(|word| u8::from_str_radix(word, 16))
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("Invalid bytes on line {number}"))
+}
+
+/// Reads a line written by [`trace::format_line`].
+fn parse_trace_line(line: &str) -> Option<(u16, u16, Registers)> {
+    let words: Vec<_> = line.split_whitespace().collect();
+    let [pc, opcode, registers @ .., i] = &words[..] else {
+        return None;
+    };
+    let registers: Vec<u8> = registers
+        .iter()
+        .map(|word| u8::from_str_radix(word, 16).ok())
+        .collect::<Option<_>>()?;
+    Some((
+        u16::from_str_radix(pc, 16).ok()?,
+        u16::from_str_radix(opcode, 16).ok()?,
+        (registers.try_into().ok()?, u16::from_str_radix(i, 16).ok()?),
+    ))
+}
+
+pub const USAGE: &str = "\
+Usage: chip8 inspect-dump FILE
+
+Prints a crash dump written by --dump-on-error: why the program stopped, its
+registers and call stack, the code around the instruction that stopped it, the
+last instructions it ran and the screen.";
+
+pub fn run(args: impl IntoIterator<Item = String>) -> Result<(), Exit> {
+    let mut path = None;
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            _ if arg.starts_with('-') => return Err(usage_error(format!("Unknown option {arg}"))),
+            _ if path.is_some() => return Err(usage_error(format!("Unexpected argument {arg:?}"))),
+            _ => path = Some(PathBuf::from(arg)),
+        }
+    }
+    let path = path.ok_or_else(|| usage_error("No dump given"))?;
+    let dump = Dump::load(&path).map_err(Exit::failed)?;
+    print!("{}", dump.report());
+    Ok(())
+}
+
+fn usage_error(message: impl fmt::Display) -> Exit {
+    Exit::usage(message, USAGE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::Headless;
+    use crate::ExitReason;
+    use std::ops::ControlFlow;
+
+    /// Draws a 5, calls a subroutine and dies on the illegal FFFF in it.
+    const ROM: &[u8] = &[
+        0x60, 0x05, // 200: LD V0, 05
+        0xF0, 0x29, // 202: LD F, V0
+        0xD0, 0x15, // 204: DRW V0, V1, 5
+        0xA3, 0x00, // 206: LD I, 300
+        0x22, 0x0C, // 208: CALL 20C
+        0x00, 0x00, // 20A
+        0xFF, 0xFF, // 20C
+    ];
+
+    /// Runs `rom` until it stops, recording every instruction like `--dump-on-error`
+    /// does, and returns the dump it would save.
+    fn crash(rom: &[u8], dir: PathBuf) -> (Recorder, Dump) {
+        let mut core = Headless::new(rom.to_vec(), Quirks::default(), 10);
+        let mut recorder = Recorder::new(dir, rom);
+        let reason = loop {
+            let state = &core.state;
+            let registers = (state.registers.0, state.vi);
+            recorder.record(state.pc, state.fetch().opcode(), &registers);
+            if let ControlFlow::Break(reason) = core.step() {
+                break reason;
+            }
+        };
+        let dump = core.state.dump(reason, core.state.pc.wrapping_sub(2));
+        (recorder, dump)
+    }
+
+    #[test]
+    fn round_trips_through_a_file() {
+        let dir = std::env::temp_dir().join(format!("chip8-dump-{}", std::process::id()));
+        let (recorder, dump) = crash(ROM, dir.clone());
+        assert_eq!(dump.reason, format!("{:?}", ExitReason::IllegalInstruction));
+        let path = recorder.save(dump.clone()).unwrap();
+        let loaded = Dump::load(&path);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.rom_hash, hash::fnv1a(ROM));
+        assert_eq!(loaded.history.len(), 6);
+        assert_eq!(loaded.history[5].0, 0x20C);
+        assert_eq!(
+            Dump {
+                history: vec![],
+                ..loaded
+            },
+            dump
+        );
+    }
+
+    #[test]
+    fn text_parses_back() {
+        let (recorder, mut dump) = crash(ROM, PathBuf::new());
+        dump.rom_hash = recorder.rom_hash;
+        dump.history = recorder.history.into_iter().collect();
+        assert_eq!(Dump::parse(&dump.to_string()), Ok(dump));
+    }
+
+    #[test]
+    fn history_keeps_the_last_instructions() {
+        let mut recorder = Recorder::new(PathBuf::new(), &[]);
+        for n in 0..HISTORY as u16 + 50 {
+            recorder.record(n, 0x1200, &([0; 16], 0));
+        }
+        assert_eq!(recorder.history.len(), HISTORY);
+        assert_eq!(recorder.history.front().unwrap().0, 50);
+        recorder.reset(&[1]);
+        assert!(recorder.history.is_empty());
+        assert_eq!(recorder.rom_hash, hash::fnv1a(&[1]));
+    }
+
+    #[test]
+    fn report_points_at_the_fault() {
+        let (_, dump) = crash(ROM, PathBuf::new());
+        let report = dump.report();
+        assert!(
+            report.starts_with("IllegalInstruction at 020C\n"),
+            "{report}"
+        );
+        assert!(report.contains("> 020C FFFF  DW FFFF\n"), "{report}");
+        assert!(report.contains("Called from 0208 CALL 20C\n"), "{report}");
+        assert!(
+            report.contains("Quirks: vf-reset, memory, clipping\n"),
+            "{report}"
+        );
+        // The 5 drawn at the top
+        assert!(report.contains("\n.....####"), "{report}");
+    }
+
+    #[test]
+    fn rejects_broken_dumps() {
+        let (_, dump) = crash(ROM, PathBuf::new());
+        let text = dump.to_string();
+        assert_eq!(
+            Dump::parse("chip8-dump 2\n").unwrap_err(),
+            "Unsupported dump version 2"
+        );
+        assert_eq!(Dump::parse("hello").unwrap_err(), "Not a crash dump");
+        let truncated: String = text
+            .lines()
+            .take(20)
+            .map(|line| format!("{line}\n"))
+            .collect();
+        assert!(Dump::parse(&truncated).is_err());
+        let bad_quirk = text.replace("quirks vf-reset", "quirks vf-rest");
+        assert_eq!(
+            Dump::parse(&bad_quirk).unwrap_err(),
+            "Unknown quirk \"vf-rest\" on line 5"
+        );
+    }
+}
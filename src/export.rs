@@ -0,0 +1,98 @@
+//! The machine's state as JSON, for other tools. Saved between two instructions, from
+//! the same snapshot as crash dumps, so nothing in it is from halfway through one.
+//!
+//! The schema is versioned; fields are only ever added within a version:
+//!
+//! ```json
+//! {
+//!   "format": "chip8-state",
+//!   "version": 1,
+//!   "rom_hash": "5d1e0c3a9f8b7c21",
+//!   "quirks": { "vf-reset": true, "memory": true, "shift": false, ... },
+//!   "pc": 520,
+//!   "i": 768,
+//!   "v": [5, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+//!   "stack": [514],
+//!   "timers": { "delay": 0, "sound": 0 },
+//!   "screen": { "width": 64, "height": 32, "rows": ["....##..", ...] },
+//!   "memory": "F0909090F0..."
+//! }
+//! ```
+//!
+//! Numbers are plain JSON numbers. The stack holds return addresses, innermost
+//! last. Screen rows are strings with `#` for a lit pixel and `.` for a dark one.
+//! `memory` is all 4096 bytes in hex, two uppercase digits each.
+
+use crate::dump::quirk_names;
+use crate::dump::Dump;
+use std::fmt::Write as _;
+use std::path::Path;
+
+pub const FORMAT: &str = "chip8-state";
+pub const VERSION: u32 = 1;
+
+/// `dump` as pretty-printed JSON, ending in a newline.
+pub fn to_json(dump: &Dump) -> String {
+    let list = |values: &mut dyn Iterator<Item = String>| values.collect::<Vec<_>>().join(", ");
+    let mut quirks = dump.quirks;
+    let quirks = list(
+        &mut quirk_names(&mut quirks)
+            .into_iter()
+            .map(|(name, on)| format!("\"{name}\": {on}")),
+    );
+    let rows: Vec<String> = dump
+        .vram
+        .chunks(64)
+        .map(|row| {
+            let row: String = row.iter().map(|on| if *on { '#' } else { '.' }).collect();
+            format!("      \"{row}\"")
+        })
+        .collect();
+    let mut memory = String::with_capacity(dump.memory.len() * 2);
+    for byte in &dump.memory {
+        let _ = write!(memory, "{byte:02X}");
+    }
+    format!(
+        "{{
+  \"format\": \"{FORMAT}\",
+  \"version\": {VERSION},
+  \"rom_hash\": \"{:016x}\",
+  \"quirks\": {{ {quirks} }},
+  \"pc\": {},
+  \"i\": {},
+  \"v\": [{}],
+  \"stack\": [{}],
+  \"timers\": {{ \"delay\": {}, \"sound\": {} }},
+  \"screen\": {{
+    \"width\": 64,
+    \"height\": 32,
+    \"rows\": [
+{}
+    ]
+  }},
+  \"memory\": \"{memory}\"
+}}
+",
+        dump.rom_hash,
+        dump.pc,
+        dump.i,
+        list(&mut dump.registers.iter().map(u8::to_string)),
+        list(&mut dump.stack.iter().map(u16::to_string)),
+        dump.delay_timer,
+        dump.sound_timer,
+        rows.join(",\n"),
+    )
+}
+
+/// Writes `dump` to `path`, or to stdout for `-`.
+pub fn save(path: &Path, dump: &Dump) -> Result<(), String> {
+    let json = to_json(dump);
+    if crate::io::is_stdout(path) {
+        print!("{json}");
+        return Ok(());
+    }
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).map_err(|err| format!("{}: {err}", dir.display()))?;
+    }
+    std::fs::write(path, json).map_err(|err| format!("{}: {err}", path.display()))
+}
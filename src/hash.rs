@@ -0,0 +1,159 @@
+//! Fingerprints of the display, for spotting changes in what games draw, and of ROMs.
+
+/// Packs the display into bytes, eight pixels each with the leftmost in the high bit,
+/// so the hash doesn't depend on how the pixels are stored.
+pub fn pack(vram: &[bool]) -> Vec<u8> {
+    vram.chunks(8)
+        .map(|pixels| {
+            pixels
+                .iter()
+                .enumerate()
+                .fold(0, |byte, (idx, on)| byte | (u8::from(*on) << (7 - idx)))
+        })
+        .collect()
+}
+
+/// 64-bit FNV-1a over the dimensions and the packed pixels.
+pub fn framebuffer_hash(vram: &[bool], width: u16, height: u16) -> u64 {
+    let dimensions = [width.to_le_bytes(), height.to_le_bytes()].concat();
+    fnv1a(&[dimensions, pack(vram)].concat())
+}
+
+/// 64-bit FNV-1a.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    const OFFSET: u64 = 0xCBF2_9CE4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01B3;
+    bytes.iter().fold(OFFSET, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(PRIME)
+    })
+}
+
+/// SHA-1, as other tools show for a ROM, so it can be looked up elsewhere.
+pub fn sha1(bytes: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [
+        0x6745_2301,
+        0xEFCD_AB89,
+        0x98BA_DCFE,
+        0x1032_5476,
+        0xC3D2_E1F0,
+    ];
+    let mut message = bytes.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(bytes.len() as u64 * 8).to_be_bytes());
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (word, bytes) in w.iter_mut().zip(block.chunks(4)) {
+            *word = u32::from_be_bytes(bytes.try_into().unwrap());
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+                _ => (b ^ c ^ d, 0xCA62_C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            (e, d, c, b, a) = (d, c, b.rotate_left(30), a, temp);
+        }
+        for (total, add) in state.iter_mut().zip([a, b, c, d, e]) {
+            *total = total.wrapping_add(add);
+        }
+    }
+    let mut digest = [0; 20];
+    for (bytes, word) in digest.chunks_mut(4).zip(state) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+/// [`sha1`] in lowercase hex.
+pub fn sha1_hex(bytes: &[u8]) -> String {
+    sha1(bytes)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixels(bits: &str) -> Vec<bool> {
+        bits.chars().map(|bit| bit == '1').collect()
+    }
+
+    #[test]
+    fn packs_the_leftmost_pixel_into_the_high_bit() {
+        assert_eq!(pack(&pixels("10000000")), [0x80]);
+        assert_eq!(pack(&pixels("00000001")), [0x01]);
+        assert_eq!(pack(&pixels("1100000000000101")), [0xC0, 0x05]);
+    }
+
+    #[test]
+    fn pads_a_short_last_byte_on_the_right() {
+        assert_eq!(pack(&pixels("111")), [0xE0]);
+        assert_eq!(pack(&pixels("000000001")), [0x00, 0x80]);
+        assert_eq!(pack(&[]), [] as [u8; 0]);
+    }
+
+    /// Rows of a sprite, packed the way CHIP-8 stores sprites in memory
+    #[test]
+    fn packs_rows_like_sprites() {
+        let sprite = [0xF0, 0x90, 0x90, 0x90, 0xF0];
+        let vram: Vec<bool> = sprite
+            .iter()
+            .flat_map(|row| (0..8).map(move |bit| row & (0x80 >> bit) != 0))
+            .collect();
+        assert_eq!(pack(&vram), sprite);
+    }
+
+    #[test]
+    fn fnv1a_matches_the_reference() {
+        assert_eq!(fnv1a(b""), 0xCBF2_9CE4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xAF63_DC4C_8601_EC8C);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_F739_67E8);
+    }
+
+    #[test]
+    fn hash_covers_the_dimensions_and_every_pixel() {
+        let blank = vec![false; 64 * 32];
+        let hash = framebuffer_hash(&blank, 64, 32);
+        assert_ne!(hash, framebuffer_hash(&blank, 128, 16));
+        for idx in [0, 7, 8, 64 * 32 - 1] {
+            let mut vram = blank.clone();
+            vram[idx] = true;
+            assert_ne!(framebuffer_hash(&vram, 64, 32), hash, "pixel {idx}");
+        }
+        assert_eq!(
+            hash,
+            fnv1a(&[[64, 0, 32, 0].as_slice(), &[0; 256]].concat())
+        );
+    }
+
+    #[test]
+    fn sha1_reference_values() {
+        assert_eq!(sha1_hex(b""), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(sha1_hex(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        // Two blocks once padded
+        assert_eq!(
+            sha1_hex(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+        );
+        assert_eq!(
+            sha1_hex(&[b'a'; 1000]),
+            "291e9a6c66994949b57ba5e650361e98fc36b1ba"
+        );
+    }
+}
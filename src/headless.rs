@@ -0,0 +1,147 @@
+//! Running the core without a display, the same way every time.
+
+use crate::io::Keypad;
+use crate::io::Script;
+use crate::quirks::Quirks;
+use crate::ExitReason;
+use crate::State;
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// A core on a fixed schedule: every `ipf` instructions make a frame, after which the
+/// timers tick, and the random number generator is reseeded from the instruction
+/// count before each instruction.
+pub struct Headless {
+    pub state: State,
+    ipf: u64,
+    /// Instructions run so far
+    count: u64,
+}
+
+impl Headless {
+    pub fn new(rom: Vec<u8>, quirks: Quirks, ipf: u64) -> Headless {
+        Headless {
+            state: State::headless(rom, quirks),
+            ipf: ipf.max(1),
+            count: 0,
+        }
+    }
+
+    /// The frame the next instruction runs in.
+    pub fn frame(&self) -> u64 {
+        self.count / self.ipf
+    }
+
+    /// Runs one instruction, then ticks the timers if that finished a frame. Waiting
+    /// for a key counts as an instruction, so time still passes while nothing is
+    /// pressed.
+    pub fn step(&mut self) -> ControlFlow<ExitReason> {
+        fastrand::seed(self.count);
+        let flow = self.state.step();
+        self.count += 1;
+        if self.count.is_multiple_of(self.ipf) {
+            self.state.tick_timers();
+        }
+        flow
+    }
+
+    /// Plays `script` from the current frame until it quits, failing at the first
+    /// expectation that isn't met or if it hasn't quit by `max_frames`.
+    pub fn run_script(&mut self, script: &mut Script, max_frames: u64) -> Result<(), String> {
+        let keypad = self.keypad();
+        loop {
+            let frame = self.frame();
+            if frame > max_frames {
+                return Err(format!("The script didn't quit within {max_frames} frames"));
+            }
+            let vram = *self.state.vram.lock().unwrap();
+            let flow = script.play(frame, &vram, |key, down| {
+                let mut keypad = keypad.lock().unwrap();
+                if down {
+                    keypad.press(key);
+                } else {
+                    keypad.release(key);
+                }
+            })?;
+            if flow.is_break() {
+                return Ok(());
+            }
+            while self.frame() == frame {
+                let _ = self.step();
+            }
+        }
+    }
+
+    /// The keypad the program reads, for pressing keys from outside.
+    pub fn keypad(&self) -> Arc<Mutex<Keypad>> {
+        self.state.keypad.clone()
+    }
+
+    pub fn timers_stopped(&self) -> bool {
+        *self.state.delay_timer.lock().unwrap() == 0 && *self.state.sound_timer.lock().unwrap() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::Ordering;
+
+    /// For each tick after the sound timer is loaded with `value`, whether the buzzer
+    /// is sounding once it's done.
+    fn sounding_after_ticks(value: u8) -> Vec<bool> {
+        // LD V0, value; LD ST, V0; then jump in place
+        let rom = vec![0x60, value, 0xF0, 0x18, 0x12, 0x04];
+        let mut core = Headless::new(rom, Quirks::default(), 4);
+        let sounding = |core: &Headless| core.state.status.sounding.load(Ordering::Relaxed);
+        let _ = core.step();
+        let _ = core.step();
+        assert_eq!(sounding(&core), value > 0, "on loading {value}");
+        let mut ticks = vec![];
+        while ticks.len() <= usize::from(value) {
+            let frame = core.frame();
+            let _ = core.step();
+            if core.frame() != frame {
+                ticks.push(sounding(&core));
+            }
+        }
+        ticks
+    }
+
+    #[test]
+    fn sounds_until_the_timer_runs_out() {
+        for value in [1, 2, 60] {
+            let ticks = sounding_after_ticks(value);
+            // Cleared by the tick that takes the timer to zero, and not before
+            let cleared = ticks.iter().position(|sounding| !sounding).unwrap() + 1;
+            assert_eq!(cleared, usize::from(value), "{ticks:?}");
+            assert!(ticks[cleared..].iter().all(|sounding| !sounding));
+        }
+    }
+
+    #[test]
+    fn loading_zero_stays_silent() {
+        assert_eq!(sounding_after_ticks(0), [false]);
+    }
+
+    /// FX0A keeps waiting while a key is held, and takes it once let go of.
+    #[test]
+    fn waits_for_a_key_to_be_let_go() {
+        // LD V0, K; then jump in place
+        let rom = vec![0xF0, 0x0A, 0x12, 0x02];
+        let mut core = Headless::new(rom, Quirks::default(), 4);
+        let keypad = core.keypad();
+        assert!(core.step().is_break());
+        keypad.lock().unwrap().press(7);
+        for _ in 0..3 {
+            assert!(core.step().is_break());
+            assert_eq!(core.state.snapshot().pc, 0x200);
+        }
+        keypad.lock().unwrap().release(7);
+        assert!(core.step().is_break());
+        assert!(core.step().is_continue());
+        assert_eq!(core.state.snapshot().pc, 0x202);
+        assert_eq!(core.state.snapshot().registers[0], 7);
+    }
+}
@@ -0,0 +1,304 @@
+//! How often each instruction of a ROM ran, drawn as a PNG on exit to make hot loops
+//! stand out. Each 2-byte word of the ROM is a cell, 32 to a row in ROM order, with
+//! the address of the row on the left and the offset into it along the top. Cells
+//! go from dark blue through red and yellow to white as the count grows, on a log
+//! scale so a busy inner loop doesn't wash out everything else. Words that never ran
+//! are grey.
+//!
+//! The same counts feed the live overlay in the window, so they are also kept while
+//! that is shown.
+
+use crate::io::text_rects;
+use sdl2::rect::Point;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+const START: usize = 0x200;
+/// ROM words per row, so each row starts 0x40 bytes after the one above.
+const COLUMNS: usize = 32;
+/// Size of a cell in image pixels, including a pixel of grid to its right and below.
+const CELL: usize = 8;
+/// Room for the 3-digit row addresses on the left.
+const LEFT: usize = 32;
+/// Room for the column offsets along the top.
+const TOP: usize = 12;
+const MARGIN: usize = 8;
+/// Columns between two offsets along the top.
+const LABEL_EVERY: usize = 8;
+
+const BACKGROUND: [u8; 3] = [0x10, 0x10, 0x10];
+const LABEL: [u8; 3] = [0xC0, 0xC0, 0xC0];
+const NEVER_RAN: [u8; 3] = [0x40, 0x40, 0x40];
+/// Evenly spaced stops from the fewest runs to the most.
+const RAMP: [[u8; 3]; 5] = [
+    [0x10, 0x10, 0x80],
+    [0x90, 0x10, 0x90],
+    [0xF0, 0x20, 0x10],
+    [0xFF, 0xD0, 0x00],
+    [0xFF, 0xFF, 0xFF],
+];
+
+#[derive(Debug)]
+pub struct Heatmap {
+    /// Where to save the image on exit
+    path: Option<PathBuf>,
+    /// Whether anything wants the counts, so the core can skip locking them otherwise
+    counting: AtomicBool,
+    /// Runs of the instruction starting at each byte of the ROM
+    counts: Mutex<Vec<u64>>,
+}
+
+impl Heatmap {
+    /// Counts all along if the image is to be saved to `path`. Call
+    /// [`Heatmap::reset`] once the ROM is loaded.
+    pub fn new(path: Option<&Path>) -> Heatmap {
+        Heatmap {
+            path: path.map(Path::to_owned),
+            counting: AtomicBool::new(path.is_some()),
+            counts: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Keeps counting for the overlay while `on`, even without an image to save.
+    pub fn count(&self, on: bool) {
+        self.counting
+            .store(on || self.path.is_some(), Ordering::Relaxed);
+    }
+
+    /// Starts over for a newly loaded ROM.
+    pub fn reset(&self, rom: &[u8]) {
+        *self.counts.lock().unwrap() = vec![0; rom.len()];
+    }
+
+    /// Counts a run of the instruction at `addr`. Ones outside the ROM aren't kept.
+    pub fn hit(&self, addr: u16) {
+        if !self.counting.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut counts = self.counts.lock().unwrap();
+        if let Some(count) = usize::from(addr)
+            .checked_sub(START)
+            .and_then(|idx| counts.get_mut(idx))
+        {
+            *count += 1;
+        }
+    }
+
+    /// Copies the counts so far into `counts`.
+    pub fn sample(&self, counts: &mut Vec<u64>) {
+        counts.clone_from(&self.counts.lock().unwrap());
+    }
+
+    /// The image's width, height and pixels as 8-bit RGB.
+    pub fn render(&self) -> (usize, usize, Vec<u8>) {
+        // Instructions at odd addresses share a cell with the one they overlap
+        let words: Vec<u64> = self
+            .counts
+            .lock()
+            .unwrap()
+            .chunks(2)
+            .map(|pair| pair.iter().sum())
+            .collect();
+        let max = words.iter().copied().max().unwrap_or(0);
+        let rows = words.len().div_ceil(COLUMNS).max(1);
+        let width = LEFT + COLUMNS * CELL + MARGIN;
+        let height = TOP + rows * CELL + MARGIN;
+        let mut rgb = BACKGROUND.repeat(width * height);
+        let mut fill = |x: usize, y: usize, w: usize, h: usize, color: [u8; 3]| {
+            for row in y..y + h {
+                for col in x..x + w {
+                    let idx = (row * width + col) * 3;
+                    rgb[idx..idx + 3].copy_from_slice(&color);
+                }
+            }
+        };
+        for (idx, count) in words.iter().enumerate() {
+            let (x, y) = cell_origin(idx);
+            fill(x, y, CELL - 1, CELL - 1, color(*count, max));
+        }
+        let mut labels = vec![];
+        for row in 0..rows {
+            let text = format!("{:03X}", START + row * COLUMNS * 2);
+            labels.extend(text_rects(
+                &text,
+                Point::new(0, (TOP + row * CELL) as i32),
+                1,
+            ));
+        }
+        for col in (0..COLUMNS).step_by(LABEL_EVERY) {
+            let text = format!("+{:02X}", col * 2);
+            labels.extend(text_rects(
+                &text,
+                Point::new((LEFT + col * CELL) as i32, 0),
+                1,
+            ));
+        }
+        for rect in labels {
+            fill(
+                rect.x() as usize,
+                rect.y() as usize,
+                rect.width() as usize,
+                rect.height() as usize,
+                LABEL,
+            );
+        }
+        (width, height, rgb)
+    }
+
+    /// Saves the image, if asked to.
+    pub fn write(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let (width, height, rgb) = self.render();
+        let png = crate::io::encode_png(width as u32, height as u32, &rgb);
+        match std::fs::write(path, png) {
+            Ok(()) => tracing::info!("Saved the heatmap to {}", path.display()),
+            Err(err) => tracing::error!("Could not save the heatmap: {}: {err}", path.display()),
+        }
+    }
+}
+
+/// Top left corner of the cell for the `word`th word of the ROM.
+fn cell_origin(word: usize) -> (usize, usize) {
+    (LEFT + word % COLUMNS * CELL, TOP + word / COLUMNS * CELL)
+}
+
+/// The color for a word that ran `count` times, when the busiest one ran `max` times.
+fn color(count: u64, max: u64) -> [u8; 3] {
+    if count == 0 {
+        return NEVER_RAN;
+    }
+    // Anything that ran at all starts at the first stop
+    let scale = if max > 1 {
+        (count as f64).ln() / (max as f64).ln()
+    } else {
+        1.0
+    };
+    ramp(scale)
+}
+
+/// The color `heat` of the way along the ramp, from 0 for the first stop to 1 for
+/// the last.
+pub fn ramp(heat: f64) -> [u8; 3] {
+    let position = heat.clamp(0.0, 1.0) * (RAMP.len() - 1) as f64;
+    let stop = (position as usize).min(RAMP.len() - 2);
+    let along = position - stop as f64;
+    let (from, to) = (RAMP[stop], RAMP[stop + 1]);
+    std::array::from_fn(|channel| {
+        let (a, b) = (f64::from(from[channel]), f64::from(to[channel]));
+        (a + (b - a) * along).round() as u8
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads back a PNG as written by [`crate::io::encode_png`]: one IDAT chunk of
+    /// stored deflate blocks, with no filtering.
+    fn decode(png: &[u8]) -> (usize, usize, Vec<u8>) {
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+        let mut at = 8;
+        let (mut width, mut height, mut zlib) = (0, 0, vec![]);
+        while at < png.len() {
+            let len = u32::from_be_bytes(png[at..at + 4].try_into().unwrap()) as usize;
+            let data = &png[at + 8..at + 8 + len];
+            match &png[at + 4..at + 8] {
+                b"IHDR" => {
+                    width = u32::from_be_bytes(data[..4].try_into().unwrap()) as usize;
+                    height = u32::from_be_bytes(data[4..8].try_into().unwrap()) as usize;
+                    assert_eq!(data[8..10], [8, 2]);
+                }
+                b"IDAT" => zlib.extend_from_slice(data),
+                _ => {}
+            }
+            at += len + 12;
+        }
+        let mut raw = vec![];
+        let mut block = &zlib[2..];
+        loop {
+            let len = usize::from(u16::from_le_bytes([block[1], block[2]]));
+            raw.extend_from_slice(&block[5..5 + len]);
+            if block[0] & 1 == 1 {
+                break;
+            }
+            block = &block[5 + len..];
+        }
+        let rgb = raw
+            .chunks(width * 3 + 1)
+            .flat_map(|row| {
+                assert_eq!(row[0], 0);
+                row[1..].to_vec()
+            })
+            .collect();
+        (width, height, rgb)
+    }
+
+    #[test]
+    fn counts_go_up_the_ramp_on_a_log_scale() {
+        assert_eq!(color(0, 1000), NEVER_RAN);
+        assert_eq!(color(1, 1000), RAMP[0]);
+        assert_eq!(color(1000, 1000), RAMP[4]);
+        // Halfway in log terms is the middle stop
+        assert_eq!(color(100, 10_000), RAMP[2]);
+        assert_eq!(color(1, 1), RAMP[4]);
+        assert_eq!(ramp(0.25), RAMP[1]);
+        assert_eq!(ramp(0.125), [0x50, 0x10, 0x88]);
+        assert_eq!(ramp(-1.0), RAMP[0]);
+        assert_eq!(ramp(2.0), RAMP[4]);
+    }
+
+    #[test]
+    fn cells_are_laid_out_in_rom_order() {
+        assert_eq!(cell_origin(0), (LEFT, TOP));
+        assert_eq!(cell_origin(1), (LEFT + CELL, TOP));
+        assert_eq!(cell_origin(COLUMNS + 2), (LEFT + 2 * CELL, TOP + CELL));
+
+        let heatmap = Heatmap::new(None);
+        // 65 words need a third row
+        heatmap.reset(&[0; 130]);
+        let (width, height, rgb) = heatmap.render();
+        assert_eq!(width, LEFT + COLUMNS * CELL + MARGIN);
+        assert_eq!(height, TOP + 3 * CELL + MARGIN);
+        assert_eq!(rgb.len(), width * height * 3);
+    }
+
+    #[test]
+    fn saved_image_shows_the_counts() {
+        let path = std::env::temp_dir().join(format!("chip8-{}-heatmap.png", std::process::id()));
+        let heatmap = Heatmap::new(Some(&path));
+        heatmap.reset(&[0; 128]);
+        for _ in 0..100 {
+            heatmap.hit(0x200);
+        }
+        // Both halves of a word count for its cell
+        for _ in 0..5 {
+            heatmap.hit(0x202);
+            heatmap.hit(0x203);
+        }
+        // Outside the ROM
+        heatmap.hit(0x1FE);
+        heatmap.hit(0x280);
+        heatmap.write();
+        let png = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let (width, height, rgb) = decode(&png);
+        assert_eq!((width, height, rgb.clone()), heatmap.render());
+
+        let pixel = |(x, y): (usize, usize)| -> [u8; 3] {
+            let idx = (y * width + x) * 3;
+            rgb[idx..idx + 3].try_into().unwrap()
+        };
+        assert_eq!(pixel(cell_origin(0)), RAMP[4]);
+        assert_eq!(pixel(cell_origin(1)), color(10, 100));
+        assert_eq!(pixel(cell_origin(2)), NEVER_RAN);
+        // The grid line to the right of a cell, and the labels
+        assert_eq!(pixel((LEFT + CELL - 1, TOP)), BACKGROUND);
+        assert!((0..TOP * width).any(|idx| rgb[idx * 3..idx * 3 + 3] == LABEL));
+        assert!((TOP..height).any(|y| (0..LEFT).any(|x| pixel((x, y)) == LABEL)));
+    }
+}
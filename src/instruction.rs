@@ -1,2 +1,14 @@
 mod execute;
+mod mnemonic;
 mod raw;
+pub use execute::DecodedInstr;
+
+/// What `opcode` does, for looking at code without running it.
+pub fn decode(opcode: u16) -> DecodedInstr {
+    raw::Instr::new(opcode).decode()
+}
+
+/// The mnemonic of `opcode`, e.g. `LD V1, 20` for `0x6120`.
+pub fn disassemble(opcode: u16) -> String {
+    decode(opcode).to_string()
+}
@@ -1,9 +1,9 @@
 use crate::ExitReason;
 use bitvec::prelude::*;
 use core::cmp::min;
-use core::time::Duration;
-use log::*;
 use std::ops::ControlFlow;
+use std::sync::atomic::Ordering;
+use tracing::*;
 use ux::u12;
 use ux::u4;
 
@@ -42,6 +42,8 @@ pub enum DecodedInstr {
     BinaryCodedDecimal { register: u4 },
     StoreRegisters { register: u4 },
     LoadRegisters { register: u4 },
+    LoadAudioPattern,
+    SetPitch { register: u4 },
     IllegalInstruction(u16),
 }
 
@@ -121,21 +123,27 @@ impl crate::State {
                 let y = self.registers[y];
                 let x = &mut self.registers[x];
                 *x |= y;
-                self.registers[u4::new(0xF)] = 0;
+                if self.quirks.vf_reset {
+                    self.registers[u4::new(0xF)] = 0;
+                }
             }
             AndRegisters { x, y } => {
                 info!("Adding register {x} with register {y}");
                 let y = self.registers[y];
                 let x = &mut self.registers[x];
                 *x &= y;
-                self.registers[u4::new(0xF)] = 0;
+                if self.quirks.vf_reset {
+                    self.registers[u4::new(0xF)] = 0;
+                }
             }
             XorRegisters { x, y } => {
                 info!("Xoring register {x} with register {y}");
                 let y = self.registers[y];
                 let x = &mut self.registers[x];
                 *x ^= y;
-                self.registers[u4::new(0xF)] = 0;
+                if self.quirks.vf_reset {
+                    self.registers[u4::new(0xF)] = 0;
+                }
             }
             SkipIfRegisterNotEqual { x, y } => {
                 info!("Skipping if register {x} is not equal to register {y}");
@@ -146,6 +154,8 @@ impl crate::State {
                     self.pc += 2;
                 }
             }
+            // From here to ShiftLeft, VF is written after the result, so with x = F
+            // the register ends up holding the flag
             AddRegisters { x, y } => {
                 info!("Adding register {y} to register {x}");
                 let y = self.registers[y];
@@ -166,7 +176,7 @@ impl crate::State {
             }
             ShiftRight { x, y } => {
                 info!("Setting register {x} to shifted register {y}");
-                let y = self.registers[y];
+                let y = self.registers[if self.quirks.shift { x } else { y }];
                 let x = &mut self.registers[x];
                 let lsb = y & 0b1;
                 *x = y >> 1;
@@ -184,7 +194,7 @@ impl crate::State {
             }
             ShiftLeft { x, y } => {
                 info!("Setting register {x} to shifted register {y}");
-                let y = self.registers[y];
+                let y = self.registers[if self.quirks.shift { x } else { y }];
                 let x = &mut self.registers[x];
                 let msb = (y & 0b1000_0000) >> 7;
                 *x = y << 1;
@@ -196,8 +206,13 @@ impl crate::State {
                 self.vi = value.into();
             }
             JumpWithOffset { address } => {
-                info!("Jumping to address {address:04X} + V0");
-                let reg = self.registers[u4::new(0)];
+                let register = if self.quirks.jump {
+                    u4::new((u16::from(address) >> 8) as u8)
+                } else {
+                    u4::new(0)
+                };
+                info!("Jumping to address {address:04X} + V{register:X}");
+                let reg = self.registers[register];
                 self.pc = u16::from(address).wrapping_add(u16::from(reg));
             }
             LoadRandom { register, mask } => {
@@ -212,22 +227,22 @@ impl crate::State {
                 let x = x % 0x40;
                 let y = y % 0x20;
                 info!("Drawing sprite at {x},{y} with size {bytes}");
-                std::thread::sleep(Duration::from_secs_f32(1f32 / 60f32));
 
                 let mut vram = self.vram.lock().unwrap();
                 let mut collision = false;
                 for b in 0..bytes {
                     //Drawing past the bottom
-                    if y + b >= 32 {
+                    if y + b >= 32 && self.quirks.clipping {
                         debug!("Drawing past the bottom of the frame");
                         break;
                     }
                     let byte = self.memory[self.vi + u16::from(b)];
                     debug!("Drawing line {b}, value: {byte:X}");
                     let bits = byte.view_bits::<Msb0>();
-                    let start = usize::from(y + b) * 64 + usize::from(x);
-                    let end = usize::from(y + b) * 64 + min(usize::from(x) + 8, 63);
-                    {
+                    let row = usize::from((y + b) % 32) * 64;
+                    if self.quirks.clipping {
+                        let start = row + usize::from(x);
+                        let end = row + min(usize::from(x) + 8, 63);
                         let write_area = &mut vram[start..=end];
                         write_area.iter_mut().zip(bits).for_each(|(v, s)| {
                             if *v && *s {
@@ -235,6 +250,14 @@ impl crate::State {
                             }
                             *v ^= *s
                         });
+                    } else {
+                        for (col, s) in (0..).zip(bits) {
+                            let v = &mut vram[row + usize::from((x + col) % 64)];
+                            if *v && *s {
+                                collision = true;
+                            }
+                            *v ^= *s
+                        }
                     }
                 }
                 self.registers[u4::new(0xF)] = collision as u8;
@@ -242,7 +265,7 @@ impl crate::State {
             SkipIfPressed { key } => {
                 info!("Skipping if key in register {key} is pressed");
                 let key = self.registers[key];
-                debug!("Key: {key}");
+                debug!(key, "Checking key");
                 let pressed = self.keypad.lock().unwrap().is_pressed(key);
                 if pressed {
                     trace!("Skipped");
@@ -252,7 +275,7 @@ impl crate::State {
             SkipIfNotPressed { key } => {
                 info!("Skipping if key in register {key} is not pressed");
                 let key = self.registers[key];
-                debug!("Key: {key}");
+                debug!(key, "Checking key");
                 let pressed = self.keypad.lock().unwrap().is_pressed(key);
                 if !pressed {
                     trace!("Skipped");
@@ -267,7 +290,7 @@ impl crate::State {
                 info!("Waiting for keypress to put in register {register}");
 
                 if let Some(key) = self.last_key_press {
-                    debug!("Got key press: {key}");
+                    debug!(key, "Got key press");
                     self.registers[register] = key;
                     self.last_key_press = None;
                 } else {
@@ -281,15 +304,26 @@ impl crate::State {
             }
             SetSoundTimer { register } => {
                 info!("Setting sound timer to register {register}");
-                *self.sound_timer.lock().unwrap() = self.registers[register];
+                let value = self.registers[register];
+                *self.sound_timer.lock().unwrap() = value;
+                // Start the beep now rather than on the next tick, which then ends it
+                // after exactly `value` ticks
+                self.status.sounding.store(value > 0, Ordering::Relaxed);
+                if value > 0 {
+                    self.status.beep.store(true, Ordering::Relaxed);
+                }
             }
             AddToIRegister { register } => {
                 info!("Adding register {register} to I");
                 self.vi += u16::from(self.registers[register]);
+                if self.quirks.index_overflow {
+                    self.registers[u4::new(0xF)] = u8::from(self.vi > 0xFFF);
+                }
             }
             GetCharSprite { char } => {
-                info!("Loading location of sprite {char:X}");
-                self.vi = u16::from(char) * 5;
+                info!("Loading location of the sprite for V{char:X}");
+                // The digit comes from VX, and only the low one has a sprite
+                self.vi = u16::from(self.registers[char] & 0xF) * 5;
             }
             BinaryCodedDecimal { register } => {
                 info!("Converting register {register} to decimal");
@@ -299,22 +333,40 @@ impl crate::State {
                 for (idx, digit) in decimal.chars().take(3).enumerate() {
                     let idx = u16::try_from(idx).unwrap();
                     let digit = u8::try_from(digit.to_digit(10).unwrap()).unwrap();
-                    self.memory[self.vi + idx] = digit;
+                    self.store(self.vi + idx, digit);
                 }
             }
+            LoadAudioPattern => {
+                info!("Loading audio pattern from I");
+                let mut pattern = [0; 16];
+                for (idx, byte) in (0..).zip(&mut pattern) {
+                    *byte = self.memory[self.vi + idx];
+                }
+                *self.status.pattern.lock().unwrap() = Some(pattern);
+            }
+            SetPitch { register } => {
+                info!("Setting pitch to register {register}");
+                self.status
+                    .pitch
+                    .store(self.registers[register], Ordering::Relaxed);
+            }
             StoreRegisters { register } => {
                 info!("Storing registers 0 - {register}");
                 for x in 0..=u8::from(register) {
-                    self.memory[self.vi + u16::from(x)] = self.registers[u4::new(x)];
+                    self.store(self.vi + u16::from(x), self.registers[u4::new(x)]);
+                }
+                if self.quirks.memory {
+                    self.vi += u16::from(register) + 1;
                 }
-                self.vi += u16::from(register) + 1;
             }
             LoadRegisters { register } => {
                 info!("Loading registers 0 - {register}");
                 for x in 0..=u8::from(register) {
                     self.registers[u4::new(x)] = self.memory[self.vi + u16::from(x)];
                 }
-                self.vi += u16::from(register) + 1;
+                if self.quirks.memory {
+                    self.vi += u16::from(register) + 1;
+                }
             }
             DecodedInstr::IllegalInstruction(instr) => {
                 error!("Recieved illegal instruction: {instr:04X}");
@@ -324,3 +376,134 @@ impl crate::State {
         ControlFlow::Continue(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::quirks::Quirks;
+    use crate::State;
+    use proptest::prelude::*;
+
+    /// Runs `8XYn` through [`State::step`] with `registers` loaded, returning the
+    /// registers after.
+    fn run(op: u8, x: u8, y: u8, registers: [u8; 16], quirks: Quirks) -> [u8; 16] {
+        let rom = vec![0x80 | x, y << 4 | op];
+        let mut state = State::headless(rom, quirks);
+        state.registers.0 = registers;
+        assert!(state.step().is_continue());
+        state.registers.0
+    }
+
+    fn quirks() -> impl Strategy<Value = Quirks> {
+        (any::<bool>(), any::<bool>()).prop_map(|(shift, vf_reset)| Quirks {
+            shift,
+            vf_reset,
+            ..Quirks::default()
+        })
+    }
+
+    /// 8XY4, 8XY5, 8XY6, 8XY7 and 8XYE, with x = F turning up often enough.
+    fn case() -> impl Strategy<Value = (u8, u8, u8, [u8; 16], Quirks)> {
+        (
+            prop::sample::select(vec![0x4, 0x5, 0x6, 0x7, 0xE]),
+            prop_oneof![Just(0xF), 0..16u8],
+            prop_oneof![Just(0xF), 0..16u8],
+            any::<[u8; 16]>(),
+            quirks(),
+        )
+    }
+
+    /// The result and the flag each instruction should leave, from the registers
+    /// before it ran.
+    fn expected(op: u8, x: u8, y: u8, registers: &[u8; 16], quirks: Quirks) -> (u8, u8) {
+        let vx = registers[usize::from(x)];
+        let vy = registers[usize::from(y)];
+        let source = if quirks.shift { vx } else { vy };
+        match op {
+            0x4 => (
+                vx.wrapping_add(vy),
+                u8::from(u16::from(vx) + u16::from(vy) > 255),
+            ),
+            0x5 => (vx.wrapping_sub(vy), u8::from(vx >= vy)),
+            0x6 => (source >> 1, source & 1),
+            0x7 => (vy.wrapping_sub(vx), u8::from(vy >= vx)),
+            0xE => (source << 1, source >> 7),
+            _ => unreachable!(),
+        }
+    }
+
+    /// FX29 points I at the sprite for the digit held in VX, not for X itself.
+    #[test]
+    fn font_sprite_comes_from_the_register_value() {
+        let mut state = State::headless(vec![0xF5, 0x29, 0xF3, 0x29], Quirks::default());
+        state.registers.0[0x5] = 0x1A;
+        state.registers.0[0x3] = 0x7;
+        assert!(state.step().is_continue());
+        assert_eq!(state.vi, 0xA * 5);
+        assert!(state.step().is_continue());
+        assert_eq!(state.vi, 0x7 * 5);
+    }
+
+    proptest! {
+        #[test]
+        fn add_carries_past_255(
+            registers: [u8; 16],
+            x in 0..15u8,
+            y in 0..16u8,
+            quirks in quirks(),
+        ) {
+            let after = run(0x4, x, y, registers, quirks);
+            let (vx, vy) = (registers[usize::from(x)], registers[usize::from(y)]);
+            let sum = u16::from(vx) + u16::from(vy);
+            prop_assert_eq!(after[usize::from(x)], sum as u8);
+            prop_assert_eq!(after[0xF] == 1, sum > 255);
+        }
+
+        #[test]
+        fn sub_flags_are_the_complement_of_underflow(
+            registers: [u8; 16],
+            x in 0..15u8,
+            y in 0..16u8,
+            quirks in quirks(),
+        ) {
+            let (vx, vy) = (registers[usize::from(x)], registers[usize::from(y)]);
+            let after = run(0x5, x, y, registers, quirks);
+            prop_assert_eq!(after[usize::from(x)], vx.wrapping_sub(vy));
+            prop_assert_eq!(after[0xF] == 1, vx.checked_sub(vy).is_some());
+            let after = run(0x7, x, y, registers, quirks);
+            prop_assert_eq!(after[usize::from(x)], vy.wrapping_sub(vx));
+            prop_assert_eq!(after[0xF] == 1, vy.checked_sub(vx).is_some());
+        }
+
+        /// The bit shifted out ends up in VF, so the source can be put back together.
+        #[test]
+        fn shifts_keep_the_lost_bit_in_vf(
+            registers: [u8; 16],
+            x in 0..15u8,
+            y in 0..16u8,
+            quirks in quirks(),
+        ) {
+            let source = registers[usize::from(if quirks.shift { x } else { y })];
+            let after = run(0x6, x, y, registers, quirks);
+            prop_assert_eq!(after[0xF] <= 1, true);
+            prop_assert_eq!(after[usize::from(x)] << 1 | after[0xF], source);
+            let after = run(0xE, x, y, registers, quirks);
+            prop_assert_eq!(after[0xF] <= 1, true);
+            prop_assert_eq!(after[0xF] << 7 | after[usize::from(x)] >> 1, source);
+        }
+
+        /// With x = F the flag is written last and wins over the result, and nothing
+        /// else but VX and VF changes.
+        #[test]
+        fn vf_ends_up_holding_the_flag((op, x, y, registers, quirks) in case()) {
+            let after = run(op, x, y, registers, quirks);
+            let (result, flag) = expected(op, x, y, &registers, quirks);
+            prop_assert_eq!(after[0xF], flag);
+            if x != 0xF {
+                prop_assert_eq!(after[usize::from(x)], result);
+            }
+            for idx in (0..15).filter(|idx| *idx != usize::from(x)) {
+                prop_assert_eq!(after[idx], registers[idx], "V{:X}", idx);
+            }
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use super::execute::DecodedInstr;
+use core::fmt;
+
+/// Cowgod's mnemonics, e.g. `LD V1, 20` or `DRW V0, V1, 5`. Numbers are in hex.
+impl fmt::Display for DecodedInstr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use DecodedInstr::*;
+        let v = u8::from;
+        let addr = u16::from;
+        match *self {
+            ClearScreen => write!(f, "CLS"),
+            Return => write!(f, "RET"),
+            Jump { address } => write!(f, "JP {:03X}", addr(address)),
+            Call { address } => write!(f, "CALL {:03X}", addr(address)),
+            SkipIfEqual { register, value } => write!(f, "SE V{:X}, {value:02X}", v(register)),
+            SkipIfNotEqual { register, value } => {
+                write!(f, "SNE V{:X}, {value:02X}", v(register))
+            }
+            SkipIfRegisterEqual { x, y } => write!(f, "SE V{:X}, V{:X}", v(x), v(y)),
+            LoadRegister { register, value } => write!(f, "LD V{:X}, {value:02X}", v(register)),
+            CopyRegister { x, y } => write!(f, "LD V{:X}, V{:X}", v(x), v(y)),
+            OrRegisters { x, y } => write!(f, "OR V{:X}, V{:X}", v(x), v(y)),
+            AndRegisters { x, y } => write!(f, "AND V{:X}, V{:X}", v(x), v(y)),
+            XorRegisters { x, y } => write!(f, "XOR V{:X}, V{:X}", v(x), v(y)),
+            AddToRegister { register, value } => {
+                write!(f, "ADD V{:X}, {value:02X}", v(register))
+            }
+            SkipIfRegisterNotEqual { x, y } => write!(f, "SNE V{:X}, V{:X}", v(x), v(y)),
+            AddRegisters { x, y } => write!(f, "ADD V{:X}, V{:X}", v(x), v(y)),
+            SubtractRegisters { x, y } => write!(f, "SUB V{:X}, V{:X}", v(x), v(y)),
+            ShiftRight { x, y } => write!(f, "SHR V{:X}, V{:X}", v(x), v(y)),
+            SubtractRegistersReverse { x, y } => write!(f, "SUBN V{:X}, V{:X}", v(x), v(y)),
+            ShiftLeft { x, y } => write!(f, "SHL V{:X}, V{:X}", v(x), v(y)),
+            LoadIRegister { value } => write!(f, "LD I, {:03X}", addr(value)),
+            JumpWithOffset { address } => write!(f, "JP V0, {:03X}", addr(address)),
+            LoadRandom { register, mask } => write!(f, "RND V{:X}, {mask:02X}", v(register)),
+            DrawSprite { x, y, bytes } => {
+                write!(f, "DRW V{:X}, V{:X}, {:X}", v(x), v(y), v(bytes))
+            }
+            SkipIfPressed { key } => write!(f, "SKP V{:X}", v(key)),
+            SkipIfNotPressed { key } => write!(f, "SKNP V{:X}", v(key)),
+            StoreDelayTimer { register } => write!(f, "LD V{:X}, DT", v(register)),
+            WaitForKeyPress { register } => write!(f, "LD V{:X}, K", v(register)),
+            SetDelayTimer { register } => write!(f, "LD DT, V{:X}", v(register)),
+            SetSoundTimer { register } => write!(f, "LD ST, V{:X}", v(register)),
+            AddToIRegister { register } => write!(f, "ADD I, V{:X}", v(register)),
+            GetCharSprite { char } => write!(f, "LD F, V{:X}", v(char)),
+            BinaryCodedDecimal { register } => write!(f, "LD B, V{:X}", v(register)),
+            StoreRegisters { register } => write!(f, "LD [I], V{:X}", v(register)),
+            LoadRegisters { register } => write!(f, "LD V{:X}, [I]", v(register)),
+            LoadAudioPattern => write!(f, "AUDIO"),
+            SetPitch { register } => write!(f, "PITCH V{:X}", v(register)),
+            IllegalInstruction(opcode) => write!(f, "DW {opcode:04X}"),
+        }
+    }
+}
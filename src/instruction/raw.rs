@@ -5,6 +5,14 @@ use crate::State;
 pub struct Instr(u16);
 
 impl Instr {
+    pub fn new(opcode: u16) -> Instr {
+        Instr(opcode)
+    }
+
+    pub fn opcode(&self) -> u16 {
+        self.0
+    }
+
     pub fn decode(self) -> DecodedInstr {
         match self.0 {
             0x00E0 => DecodedInstr::ClearScreen,
@@ -104,6 +112,7 @@ impl Instr {
                 _ => DecodedInstr::IllegalInstruction(self.0),
             },
             0xF000..=0xFFFF => match u8::try_from(self.0 & 0xFF).unwrap() {
+                0x02 if self.0 == 0xF002 => DecodedInstr::LoadAudioPattern,
                 0x07 => DecodedInstr::StoreDelayTimer {
                     register: ((self.0 & 0x0F00) >> 8).try_into().unwrap(),
                 },
@@ -125,6 +134,9 @@ impl Instr {
                 0x33 => DecodedInstr::BinaryCodedDecimal {
                     register: ((self.0 & 0x0F00) >> 8).try_into().unwrap(),
                 },
+                0x3A => DecodedInstr::SetPitch {
+                    register: ((self.0 & 0x0F00) >> 8).try_into().unwrap(),
+                },
                 0x55 => DecodedInstr::StoreRegisters {
                     register: ((self.0 & 0x0F00) >> 8).try_into().unwrap(),
                 },
@@ -1,23 +1,596 @@
+pub use borderless::Borderless;
+pub use geometry::parse_position;
 use sdl2::event::Event;
+use sdl2::event::WindowEvent;
 use sdl2::keyboard::Keycode;
+use sdl2::keyboard::Mod;
+use sdl2::mouse::MouseButton;
+use sdl2::mouse::MouseUtil;
+use sdl2::pixels::Color;
 use sdl2::pixels::PixelFormatEnum;
+use sdl2::rect::Point;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::render::Texture;
+use sdl2::render::TextureCreator;
+use sdl2::video::FullscreenType;
+use sdl2::video::SwapInterval;
+use sdl2::video::Window;
+use sdl2::video::WindowContext;
+pub use viewport::Rotation;
+pub use viewport::Scaling;
+use viewport::Viewport;
+pub use vsync::Vsync;
 
+use core::fmt;
 use core::time::Duration;
-use log::*;
-use sdl2::audio::{AudioCallback, AudioSpecDesired};
+use sdl2::audio::AudioSpecDesired;
+use sdl2::audio::AudioStatus;
+use smol::channel::Receiver;
+use smol::channel::Sender;
 use smol::Timer;
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicI32;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::AtomicU8;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::Instant;
+use tracing::level_filters::LevelFilter;
+use tracing::*;
+
+mod activity;
+mod audio;
+mod audio_devices;
+mod bell;
+mod borderless;
+mod browser;
+mod capture;
+mod color;
+pub mod compare;
+mod controller;
+mod debug;
+#[cfg(feature = "file-dialog")]
+pub mod dialog;
+mod filter;
+mod focus;
+mod font;
+mod frameskip;
+mod geometry;
+mod gif;
+mod hotkeys;
+mod input;
+mod keymap;
+mod keynames;
+mod memview;
+mod osd;
+mod overlay;
+mod phosphor;
+mod png;
+mod poll;
+mod replay;
+pub mod rom;
+mod screenshot;
+pub mod script;
+#[cfg(feature = "shader")]
+mod shader;
+mod title;
+mod touch;
+mod video;
+mod viewport;
+mod visibility;
+mod vsync;
+mod wav;
+use crate::breakpoints::Breakpoints;
+use crate::cheats::Cheats;
+use crate::cli::Options;
+use crate::condition::Condition;
+use crate::config;
+use crate::config::Config;
+use crate::dump::Dump;
+use crate::logging;
+use crate::pace;
+use crate::pace::Pacer;
+pub use audio::check_duty;
+pub use audio::check_hz;
+pub use audio::parse_lowpass;
+pub use audio::Waveform;
+pub use audio::DEFAULT_PITCH;
+pub use audio::MAX_VOLUME;
+pub use color::parse_color;
+pub use color::pixel_colors;
+pub use color::to_rgb24;
+pub use color::Palette;
+use controller::Action;
+pub use controller::ControllerMap;
+pub use debug::Snapshot;
+pub use filter::Filter;
+pub use filter::Filtering;
+use focus::FocusPause;
+pub use font::text_rects;
+use hotkeys::Hotkey;
+pub use hotkeys::Hotkeys;
+use hotkeys::QuitConfirm;
+pub use input::Input;
+pub use input::StickyKeys;
+pub use keymap::Binding;
+pub use keymap::KeyMap;
+pub use keymap::KeyMaps;
+pub use keymap::Layout;
+pub use memview::PAGE as MEMORY_PAGE;
+pub use overlay::SPRITE_ROWS;
+pub use png::encode_rgb as encode_png;
+pub use replay::Recorder;
+pub use replay::Replay;
+pub use script::Script;
+pub use video::is_stdout;
+
+pub struct Settings {
+    pub keymaps: KeyMaps,
+    pub controller_map: ControllerMap,
+    pub virtual_keypad: bool,
+    /// Treat the whole game area as a 4x4 touch pad while the virtual keypad is hidden
+    pub touch_grid: bool,
+    pub sticky_keys: Option<StickyKeys>,
+    pub hotkeys: Hotkeys,
+    pub confirm_quit: bool,
+    pub scaling: Scaling,
+    /// Initial window size in window pixels per CHIP-8 pixel
+    pub scale: u32,
+    /// Whether `scale` came from the command line, which wins over the saved window size
+    pub explicit_scale: bool,
+    pub fullscreen: bool,
+    pub borderless: Borderless,
+    /// Save the window geometry on quitting, and open where it was last time
+    pub remember_window: bool,
+    pub window: Option<geometry::Geometry>,
+    /// Display to open the window on, which wins over the saved one
+    pub monitor: Option<i32>,
+    /// Where on that display to open the window, instead of centered
+    pub window_pos: Option<(i32, i32)>,
+    pub palette: Palette,
+    pub inverted: bool,
+    pub filter: Filter,
+    /// How the image is stretched at sizes that aren't whole multiples
+    pub filtering: Filtering,
+    /// GLSL fragment shader to draw the game through instead
+    pub shader: Option<PathBuf>,
+    /// How long pixels take to fade out after turning off, if at all
+    pub decay: Option<Duration>,
+    pub rotation: Rotation,
+    /// Remap the direction keys 2/4/6/8 to follow the rotation
+    pub rotate_directions: bool,
+    pub vsync: Vsync,
+    /// File name of the ROM, for the window title
+    pub rom_name: String,
+    /// Where the ROM browser looks for ROMs
+    pub rom_dir: Option<PathBuf>,
+    /// Show the frame rate in the window title
+    pub title_fps: bool,
+    /// Most frames in a row left undrawn when drawing falls behind, 0 to always draw
+    pub frame_skip: u32,
+    /// Require dropping a ROM twice to replace the running one
+    pub confirm_drop: bool,
+    pub screenshot_dir: Option<PathBuf>,
+    /// Start recording a GIF here right away
+    pub record_gif: Option<PathBuf>,
+    /// Where to write raw video, `-` for stdout
+    pub record_video: Option<PathBuf>,
+    /// Only record every this many frames
+    pub gif_divisor: u32,
+    pub waveform: Waveform,
+    /// Sample rate and samples of a clip to play instead of the waveform
+    pub beep_sample: Option<(u32, Vec<f32>)>,
+    pub tone_hz: f32,
+    /// Name, or part of the name, of the device to play sound on instead of the default
+    pub audio_device: Option<String>,
+    /// Whether to play sound at all
+    pub audio: bool,
+    /// 0-100
+    pub volume: u8,
+    pub muted: bool,
+    /// Set up by `main` from `--record` and `--replay`
+    pub recorder: Option<Recorder>,
+    pub replay: Option<Replay>,
+    /// Set up by `main` from `--script`
+    pub script: Option<Script>,
+    pub frame_hash_every: Option<u64>,
+    /// Exit once this many frames have been shown
+    pub exit_after_frames: Option<u64>,
+    /// Whether live input still counts during a replay
+    pub merge_replay: bool,
+    /// Low-pass cutoff in Hz
+    pub lowpass: Option<f32>,
+    /// Whether to light a border while the buzzer sounds, by default only without sound
+    pub visual_bell: Option<bool>,
+    pub duty: f32,
+    /// Show the bound keys in the key state overlay
+    pub key_state_labels: bool,
+    /// The least severe messages the console keeps
+    pub console_level: LevelFilter,
+    /// Pause while the window is in the background
+    pub pause_on_focus_loss: bool,
+}
+
+impl Settings {
+    pub fn new(options: &Options, config: &Config) -> Result<Settings, config::Error> {
+        let default_keymap = match options.layout {
+            Some(layout) => layout.keymap(),
+            None => KeyMap::from_config(config)?,
+        };
+        let sticky = options.sticky_keys || config.get_as("input.sticky")?.unwrap_or(false);
+        let sticky_keys = if sticky {
+            let timeout: f32 = config.get_as("input.sticky-timeout")?.unwrap_or(2.0);
+            Some(StickyKeys::new(
+                (timeout > 0.0).then(|| Duration::from_secs_f32(timeout)),
+            ))
+        } else {
+            None
+        };
+        let mut palette = match options.palette {
+            Some(palette) => palette,
+            None => match config.get("video.palette") {
+                Some(name) => name.parse().map_err(|message| config::Error::Invalid {
+                    key: "video.palette".into(),
+                    message,
+                })?,
+                None => Palette::default(),
+            },
+        };
+        if let Some(color) = options.background.or(config_color(config, "video.bg")?) {
+            palette.0[0] = color;
+        }
+        if let Some(color) = options.foreground.or(config_color(config, "video.fg")?) {
+            palette.0[1] = color;
+        }
+        let remember_window = config.get_as("video.remember-window")?.unwrap_or(true);
+        Ok(Settings {
+            keymaps: KeyMaps::from_config(config, default_keymap)?,
+            controller_map: ControllerMap::from_config(config)?,
+            virtual_keypad: options.virtual_keypad,
+            touch_grid: config.get_as("touch.grid")?.unwrap_or(true),
+            sticky_keys,
+            hotkeys: Hotkeys::from_config(config)?,
+            confirm_quit: options.confirm_quit
+                || config.get_as("hotkeys.confirm-quit")?.unwrap_or(false),
+            scaling: if options.integer_scale
+                || config.get_as("video.integer-scale")?.unwrap_or(false)
+            {
+                Scaling::Integer
+            } else {
+                Scaling::Fit
+            },
+            scale: match options.scale {
+                Some(scale) => scale,
+                None => match config.get_as("video.scale")? {
+                    Some(scale @ 1..=MAX_SCALE) => scale,
+                    Some(scale) => {
+                        return Err(config::Error::Invalid {
+                            key: "video.scale".into(),
+                            message: format!("{scale} is not between 1 and {MAX_SCALE}"),
+                        })
+                    }
+                    None => 10,
+                },
+            },
+            explicit_scale: options.scale.is_some(),
+            fullscreen: options.fullscreen || config.get_as("video.fullscreen")?.unwrap_or(false),
+            borderless: match options.borderless {
+                Some(borderless) => borderless,
+                None => match config.get("video.borderless") {
+                    Some(name) => name.parse().map_err(|message| config::Error::Invalid {
+                        key: "video.borderless".into(),
+                        message,
+                    })?,
+                    None => Borderless::Off,
+                },
+            },
+            remember_window,
+            window: remember_window.then(geometry::Geometry::load).flatten(),
+            monitor: match options.monitor {
+                Some(monitor) => Some(monitor),
+                None => config.get_as("video.monitor")?,
+            },
+            window_pos: match options.window_pos {
+                Some(position) => Some(position),
+                None => match config.get("video.window-pos") {
+                    Some(position) => Some(parse_position(position).map_err(|message| {
+                        config::Error::Invalid {
+                            key: "video.window-pos".into(),
+                            message,
+                        }
+                    })?),
+                    None => None,
+                },
+            },
+            palette,
+            inverted: config.get_as("video.invert")?.unwrap_or(false),
+            filter: match options.filter {
+                Some(filter) => filter,
+                None => match config.get("video.filter") {
+                    Some(name) => name.parse().map_err(|message| config::Error::Invalid {
+                        key: "video.filter".into(),
+                        message,
+                    })?,
+                    None => Filter::None,
+                },
+            },
+            filtering: match options.filtering {
+                Some(filtering) => filtering,
+                None => match config.get("video.filtering") {
+                    Some(name) => name.parse().map_err(|message| config::Error::Invalid {
+                        key: "video.filtering".into(),
+                        message,
+                    })?,
+                    None => Filtering::Nearest,
+                },
+            },
+            shader: options
+                .shader
+                .clone()
+                .or_else(|| config.get("video.shader").map(PathBuf::from)),
+            decay: Some(match options.decay {
+                Some(decay) => decay,
+                None => config.get_as("video.decay")?.unwrap_or(0),
+            })
+            .filter(|decay| *decay > 0)
+            .map(Duration::from_millis),
+            rotation: match options.rotation {
+                Some(rotation) => rotation,
+                None => match config.get("video.rotate") {
+                    Some(value) => value.parse().map_err(|message| config::Error::Invalid {
+                        key: "video.rotate".into(),
+                        message,
+                    })?,
+                    None => Rotation::None,
+                },
+            },
+            rotate_directions: config.get_as("input.rotate-directions")?.unwrap_or(false),
+            rom_name: options.rom.as_deref().map(rom::name).unwrap_or_default(),
+            rom_dir: options
+                .rom_dir
+                .clone()
+                .or_else(|| config.get("rom-dir").map(PathBuf::from)),
+            title_fps: config.get_as("video.title-fps")?.unwrap_or(false),
+            frame_skip: config.get_as("video.frame-skip")?.unwrap_or(0),
+            confirm_drop: config.get_as("input.confirm-drop")?.unwrap_or(false),
+            record_gif: options.record_gif.clone(),
+            record_video: options.record_video.clone(),
+            gif_divisor: config.get_as("gif.divisor")?.unwrap_or(1).max(1),
+            screenshot_dir: match config.get("screenshot.dir") {
+                Some(dir) => Some(PathBuf::from(dir)),
+                None => screenshot::default_dir(),
+            },
+            waveform: match options.waveform {
+                Some(waveform) => waveform,
+                None => match config.get("audio.waveform") {
+                    Some(name) => name.parse().map_err(|message| config::Error::Invalid {
+                        key: "audio.waveform".into(),
+                        message,
+                    })?,
+                    None => Waveform::Square,
+                },
+            },
+            beep_sample: match options
+                .beep_sample
+                .clone()
+                .or_else(|| config.get("audio.beep-sample").map(PathBuf::from))
+            {
+                Some(path) => Some(wav::load(&path).map_err(|message| config::Error::Invalid {
+                    key: "audio.beep-sample".into(),
+                    message,
+                })?),
+                None => None,
+            },
+            tone_hz: match options.tone_hz {
+                Some(hz) => hz,
+                None => config_audio(config, "audio.tone-hz", 880.0, audio::check_hz)?,
+            },
+            audio_device: options
+                .audio_device
+                .clone()
+                .or_else(|| config.get("audio.device").map(str::to_owned)),
+            audio: !options.no_audio && config.get_as("audio.enabled")?.unwrap_or(true),
+            volume: match options.volume {
+                Some(volume) => volume,
+                None => config.get_as::<u8>("audio.volume")?.unwrap_or(25),
+            }
+            .min(MAX_VOLUME),
+            lowpass: match options.lowpass {
+                Some(lowpass) => lowpass,
+                None => match config.get("audio.lowpass") {
+                    Some(value) => {
+                        audio::parse_lowpass(value).map_err(|message| config::Error::Invalid {
+                            key: "audio.lowpass".into(),
+                            message,
+                        })?
+                    }
+                    None => Some(5000.0),
+                },
+            },
+            recorder: None,
+            replay: None,
+            script: None,
+            frame_hash_every: options.frame_hash_every,
+            exit_after_frames: options.exit_after_frames,
+            merge_replay: config.get_as("input.replay-merge")?.unwrap_or(false),
+            muted: options.mute || config.get_as("audio.mute")?.unwrap_or(false),
+            visual_bell: if options.visual_bell {
+                Some(true)
+            } else {
+                config.get_as("video.visual-bell")?
+            },
+            duty: match options.duty {
+                Some(duty) => duty,
+                None => config_audio(config, "audio.duty", 0.5, audio::check_duty)?,
+            },
+            key_state_labels: config.get_as("overlay.key-labels")?.unwrap_or(true),
+            console_level: match config.get("console.level") {
+                Some(level) => {
+                    logging::parse_level(level).map_err(|message| config::Error::Invalid {
+                        key: "console.level".into(),
+                        message,
+                    })?
+                }
+                None => LevelFilter::WARN,
+            },
+            pause_on_focus_loss: !options.no_pause_on_focus_loss
+                && config.get_as("input.pause-on-focus-loss")?.unwrap_or(true),
+            vsync: match options.vsync {
+                Some(vsync) => vsync,
+                None => match config.get("video.vsync") {
+                    Some(value) => value.parse().map_err(|message| config::Error::Invalid {
+                        key: "video.vsync".into(),
+                        message,
+                    })?,
+                    None => Vsync::Off,
+                },
+            },
+        })
+    }
+}
+
+fn config_audio(
+    config: &Config,
+    key: &str,
+    default: f32,
+    check: fn(f32) -> Result<f32, String>,
+) -> Result<f32, config::Error> {
+    match config.get_as(key)? {
+        Some(value) => check(value).map_err(|message| config::Error::Invalid {
+            key: key.to_owned(),
+            message,
+        }),
+        None => Ok(default),
+    }
+}
+
+fn config_color(config: &Config, key: &str) -> Result<Option<Color>, config::Error> {
+    config
+        .get(key)
+        .map(|value| {
+            parse_color(value).map_err(|message| config::Error::Invalid {
+                key: key.to_owned(),
+                message,
+            })
+        })
+        .transpose()
+}
+
+/// Saves a setting changed while playing as a preference for the loaded ROM.
+fn remember_for_rom(config: &mut Config, osd: &mut osd::Osd, key: &str, value: &str) {
+    if !config.set_for_rom(key, value) {
+        return;
+    }
+    if let Err(err) = config.save() {
+        error!("Could not save {key} for this ROM: {err}");
+        osd.push("Could not save the setting for this ROM", Instant::now());
+    }
+}
 
 pub async fn sdl2(
     vram: Arc<Mutex<[bool; 64 * 32]>>,
     keypad: Arc<Mutex<Keypad>>,
-    sound_timer: Arc<Mutex<u8>>,
-) {
+    status: Arc<Status>,
+    paused: Arc<Mutex<bool>>,
+    settings: Settings,
+    mut config: Config,
+) -> Result<(), Error> {
+    let Settings {
+        mut keymaps,
+        controller_map,
+        mut virtual_keypad,
+        touch_grid,
+        sticky_keys,
+        hotkeys,
+        confirm_quit,
+        mut scaling,
+        scale,
+        explicit_scale,
+        fullscreen,
+        borderless,
+        remember_window,
+        window: saved_window,
+        monitor,
+        window_pos,
+        palette,
+        mut inverted,
+        mut filter,
+        filtering,
+        shader,
+        decay,
+        rotation,
+        rotate_directions,
+        vsync,
+        mut rom_name,
+        rom_dir,
+        title_fps,
+        frame_skip,
+        confirm_drop,
+        screenshot_dir,
+        record_gif,
+        record_video,
+        gif_divisor,
+        waveform,
+        beep_sample,
+        tone_hz,
+        audio_device,
+        audio,
+        volume,
+        muted,
+        recorder,
+        replay,
+        mut script,
+        frame_hash_every,
+        exit_after_frames,
+        merge_replay,
+        lowpass,
+        visual_bell,
+        duty,
+        key_state_labels,
+        console_level,
+        pause_on_focus_loss,
+    } = settings;
+    let direction = |key| {
+        if rotate_directions {
+            rotation.direction_key(key)
+        } else {
+            key
+        }
+    };
+    let mut phosphor = decay.map(phosphor::Phosphor::new);
+    status.volume.store(volume, Ordering::Relaxed);
+    status.muted.store(muted, Ordering::Relaxed);
+    let quit_key = hotkeys.binding(Hotkey::Quit);
+    let mut quit_confirm = confirm_quit.then(|| QuitConfirm::new(Duration::from_secs(1)));
+    // Scripted runs and recordings have to keep going whatever has focus
+    let mut focus_pause =
+        (pause_on_focus_loss && replay.is_none() && script.is_none() && record_video.is_none())
+            .then(|| FocusPause::new(FOCUS_GRACE));
+    let replaying = replay.is_some();
+    let mut input = input::Input::new(keypad.clone(), sticky_keys);
+    if let Some(recorder) = recorder {
+        input.record(recorder);
+    }
+    if let Some(replay) = replay {
+        input.replay(replay, merge_replay);
+    }
     info!("Warming up sdl system");
-    let sdl_context = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
-    let audio_subsystem = sdl_context.audio().unwrap();
+    // Ctrl+C is caught by crate::signals instead, which gets the same clean shutdown
+    // without racing SDL's own quit event
+    sdl2::hint::set("SDL_NO_SIGNAL_HANDLERS", "1");
+    let sdl_context = sdl2::init().map_err(Error::Sdl)?;
+    let video_subsystem = sdl_context.video().map_err(Error::Sdl)?;
+    let audio_subsystem = start_audio(&sdl_context, audio);
+    let mut controllers = controller::Controllers::new(
+        sdl_context.game_controller().map_err(Error::Sdl)?,
+        controller_map,
+    );
 
     let desired_audio_spec = AudioSpecDesired {
         freq: None,
@@ -25,182 +598,2240 @@ pub async fn sdl2(
         samples: None,
     };
 
-    let audio_device = audio_subsystem
-        .open_playback(None, &desired_audio_spec, |spec| {
-            // initialize the audio callback
-            SquareWave {
-                phase_inc: 880.0 / spec.freq as f32,
-                phase: 0.0,
-                volume: 0.25,
+    let device_name = match (&audio_subsystem, &audio_device) {
+        (Some(audio_subsystem), Some(wanted)) => {
+            let devices = audio_devices::playback_devices(audio_subsystem);
+            match audio_devices::find(&devices, wanted) {
+                Ok(name) => Some(name.to_owned()),
+                Err(err) => return Err(Error::Setting(err)),
             }
-        })
-        .unwrap();
+        }
+        _ => None,
+    };
+    let open_audio = |device: Option<&str>| {
+        let Some(audio_subsystem) = &audio_subsystem else {
+            return Err("audio is off".to_owned());
+        };
+        let audio_device = audio_subsystem.open_playback(device, &desired_audio_spec, |spec| {
+            // initialize the audio callback
+            let sample = beep_sample.as_ref().map(|(rate, samples)| {
+                let samples = wav::resample(samples, *rate, spec.freq as u32);
+                wav::loop_point(&samples).to_vec()
+            });
+            audio::Buzzer::new(
+                waveform,
+                tone_hz,
+                spec.freq,
+                duty,
+                status.clone(),
+                sample,
+                lowpass,
+            )
+        })?;
+        // The buzzer fades itself in and out, so the device keeps running
+        audio_device.resume();
+        Ok::<_, String>(audio_device)
+    };
+    if let Some(name) = &device_name {
+        info!("Playing sound on {name}");
+    }
+    // None without sound, or once the device is gone and the default one can't be
+    // opened either
+    let mut audio_output = match audio_subsystem {
+        Some(_) => open_audio(device_name.as_deref())
+            .map_err(|err| {
+                warn!("Could not open the audio device, carrying on without sound: {err}")
+            })
+            .ok(),
+        None => None,
+    };
 
-    let window = video_subsystem
-        .window("chip8", 640, 320)
-        .position_centered()
+    let mut keymap = keymaps.current().clone();
+    let title = title::format(
+        &rom_name,
+        &keymaps.describe(),
+        false,
+        muted,
+        status.speed(),
+        None,
+    );
+    let (width, height) = rotation.apply(layout_size(virtual_keypad));
+    let display = geometry::choose_display(&video_subsystem, monitor);
+    let scale = match video_subsystem.desktop_display_mode(display) {
+        Ok(desktop) => fit_scale((width, height), scale, (desktop.w as u32, desktop.h as u32)),
+        Err(err) => {
+            warn!("Could not query the desktop size: {err}");
+            scale
+        }
+    };
+    // Asking for a display or position by hand wins over where it was last time
+    let placed = monitor.is_some() || window_pos.is_some();
+    let restored = saved_window.as_ref().filter(|_| !placed).and_then(|saved| {
+        let size = explicit_scale.then_some((width * scale, height * scale));
+        let rect = saved.restore(&video_subsystem, (width, height), size)?;
+        Some((rect, saved.fullscreen))
+    });
+    let mut window = match restored {
+        Some((rect, _)) => {
+            debug!("Restoring the window at {rect:?}");
+            let mut window = video_subsystem.window(&title, rect.width(), rect.height());
+            window.position(rect.x(), rect.y());
+            window
+        }
+        None => {
+            let size = (width * scale, height * scale);
+            let mut window = video_subsystem.window(&title, size.0, size.1);
+            match geometry::place(&video_subsystem, display, window_pos, size) {
+                Some(rect) => window.position(rect.x(), rect.y()),
+                None => window.position_centered(),
+            };
+            window
+        }
+    };
+    #[cfg(feature = "shader")]
+    if shader.is_some() {
+        // The shader needs the GL renderer, and to draw in between SDL's own calls
+        sdl2::hint::set("SDL_RENDER_DRIVER", "opengl");
+        sdl2::hint::set("SDL_RENDER_BATCHING", "0");
+    }
+    #[cfg(not(feature = "shader"))]
+    if shader.is_some() {
+        error!("This build can't use shaders, it was built without the shader feature");
+    }
+    let window = window
+        .resizable()
+        .allow_highdpi()
         .build()
-        .unwrap();
+        .map_err(|err| Error::Window(err.to_string()))?;
+    let fullscreen = fullscreen || restored.is_some_and(|(_, fullscreen)| fullscreen);
 
-    let mut canvas = window.into_canvas().build().unwrap();
+    let canvas = match vsync {
+        Vsync::Off => window.into_canvas().build(),
+        Vsync::On | Vsync::Adaptive => window.into_canvas().present_vsync().build(),
+    };
+    let mut canvas = canvas.map_err(|err| Error::Window(err.to_string()))?;
+    if vsync == Vsync::Adaptive {
+        if let Err(err) = video_subsystem.gl_set_swap_interval(SwapInterval::LateSwapTearing) {
+            warn!("Adaptive vsync is not supported, using regular vsync: {err}");
+        }
+    }
+    #[cfg(feature = "shader")]
+    let shader =
+        shader.and_then(|path| load_shader(&canvas, &video_subsystem, &path, rotation, &status));
+    let mouse = sdl_context.mouse();
 
-    canvas.set_logical_size(64, 32).unwrap();
+    let mut viewport = set_layout(&mut canvas, false, virtual_keypad, scaling, rotation)?;
+    // Kept up to date while windowed, as a fullscreen window or one covering the
+    // display is the size of the display
+    let mut windowed = remember_window
+        .then(|| geometry::Geometry::of(canvas.window()))
+        .flatten();
+    let mut borderless_window = borderless::BorderlessWindow::default();
+    if borderless != Borderless::Off {
+        borderless_window.set(canvas.window_mut(), borderless);
+        viewport = fit_layout(&canvas, virtual_keypad, scaling, rotation)?;
+    }
+    if fullscreen {
+        set_fullscreen(&mut canvas, &mouse, true);
+        viewport = fit_layout(&canvas, virtual_keypad, scaling, rotation)?;
+    }
     canvas.clear();
 
     let texcreator = canvas.texture_creator();
-    let mut tex = texcreator
-        .create_texture_streaming(PixelFormatEnum::RGB332, 64, 32)
-        .unwrap();
+    let mut texture_filtering = filtering.with(scaling);
+    let (mut tex, mut crt_tex) = create_textures(&texcreator, texture_filtering)?;
     canvas.present();
-    let mut event_pump = sdl_context.event_pump().unwrap();
-    loop {
+    let mut event_pump = sdl_context.event_pump().map_err(Error::Sdl)?;
+    let mut rebind: Option<keymap::Rebind> = None;
+    let mut mouse_key: Option<u8> = None;
+    let mut touches = touch::Touches::default();
+    let game_area = Rect::new(0, 0, 64, 32);
+    let keypad_area = Rect::new(0, 32, 64, 32);
+    let mut last_frame = Instant::now();
+    let mut frame_counter = title::FrameCounter::new(last_frame);
+    let mut shown = overlay::Shown::default();
+    let mut activity: Option<activity::Activity> = None;
+    logging::capture(console_level);
+    let mut console_lines = vec![];
+    // How many lines the console is scrolled up from the newest
+    let mut console_scroll = 0;
+    let mut pending_drop = None;
+    let mut load_path: Option<PathBuf> = None;
+    // Along with whether the game was paused before the browser paused it
+    let mut rom_browser: Option<(browser::Browser, bool)> = None;
+    // Without a ROM there is only the splash screen, so offer the ROMs right away
+    if let (true, Some(dir)) = (rom_name.is_empty(), &rom_dir) {
+        match browser::Browser::open(dir) {
+            Ok(list) if !list.is_empty() => {
+                let mut paused = paused.lock().unwrap();
+                rom_browser = Some((list, *paused));
+                *paused = true;
+            }
+            Ok(_) => {}
+            Err(err) => warn!("{err}"),
+        }
+    }
+    let mut visibility = visibility::Visibility::default();
+    let mut frame_skip = (frame_skip > 0).then(|| frameskip::FrameSkip::new(frame_skip));
+    let mut pacer = Pacer::new(
+        60.0,
+        frame_skip
+            .as_ref()
+            .map_or(pace::MAX_BEHIND, frameskip::FrameSkip::behind),
+    );
+    let mut present_timing = vsync::PresentTiming::new();
+    let video = match record_video {
+        Some(target) => match video::VideoSink::open(&target) {
+            Ok(sink) => Some(sink),
+            Err(err) => return Err(Error::Setting(format!("Could not record video: {err}"))),
+        },
+        None => None,
+    };
+    let mut capture = capture::Capture::new(screenshot_dir, record_gif, gif_divisor, video);
+    let mut disassembly = (last_frame, String::new());
+    let mut memory_view = memview::MemoryView::default();
+    let mut osd = osd::Osd::default();
+    let mut fps = 0.0;
+    // Without sound the buzzer is shown instead, unless turned off on purpose
+    let mut bell =
+        bell::wanted(visual_bell, audio_output.is_some()).then(bell::VisualBell::default);
+    let mut ips = 0.0;
+    let mut last_count = (last_frame, 0);
+    let mut poller = poll::Poller::new(hotkeys.bindings());
+    let mut outcome = Ok(());
+    'running: loop {
+        if status.interrupted.load(Ordering::Relaxed) != 0 {
+            break 'running;
+        }
+        // Left before waiting for the next frame, while the core runs
+        let frame_span =
+            debug_span!("frame", number = status.frames.load(Ordering::Relaxed) + 1).entered();
         let start = std::time::Instant::now();
+        pacer.arrived(start);
+        let frame_time = start - last_frame;
+        last_frame = start;
+        let palette = palette.with_inversion(inverted);
+        *status.palette.lock().unwrap() = palette;
+        *status.memory_top.lock().unwrap() = shown.memory.then(|| memory_view.top());
+        if let Some(notice) = status.notice.lock().unwrap().take() {
+            osd.push(notice, Instant::now());
+        }
+        if let Some(focus_pause) = &mut focus_pause {
+            let mut paused = paused.lock().unwrap();
+            if focus_pause.resume(Instant::now(), *paused) {
+                // Whatever was pressed to switch back doesn't count
+                input.release_all();
+                *paused = false;
+                info!("Resumed after getting focus back");
+                osd.push("Resumed", Instant::now());
+            }
+        }
+        canvas.set_draw_color(palette.background());
         canvas.clear();
-        for event in event_pump.poll_iter() {
-            use Keycode::*;
+        // Anything picked up while waiting for this frame came first
+        for event in poller.take().into_iter().chain(event_pump.poll_iter()) {
+            if let Some(binding) = &mut rebind {
+                match event {
+                    Event::Quit { .. } => {
+                        info!("Recieved quit. Shutting down");
+                        break 'running;
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::Escape),
+                        ..
+                    } => {
+                        info!("Cancelled key rebinding");
+                        osd.push("Rebinding cancelled", Instant::now());
+                        rebind = None;
+                    }
+                    Event::KeyDown {
+                        scancode: Some(scancode),
+                        repeat: false,
+                        ..
+                    } => {
+                        if let Some(new_keymap) = binding.bind(scancode) {
+                            keymaps.replace_current(new_keymap, &mut config);
+                            keymap = keymaps.current().clone();
+                            if let Err(err) = config.save() {
+                                error!("Could not save key bindings: {err}");
+                                osd.push("Could not save key bindings", Instant::now());
+                            } else {
+                                osd.push("Key bindings saved", Instant::now());
+                            }
+                            rebind = None;
+                        }
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            if let Some((list, was_paused)) = &mut rom_browser {
+                match event {
+                    Event::Quit { .. } => {
+                        info!("Recieved quit. Shutting down");
+                        break 'running;
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::Escape),
+                        ..
+                    } => {
+                        *paused.lock().unwrap() = *was_paused;
+                        rom_browser = None;
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::Return | Keycode::KpEnter),
+                        ..
+                    } => {
+                        load_path = list.selected().map(Path::to_owned);
+                        *paused.lock().unwrap() = *was_paused;
+                        rom_browser = None;
+                    }
+                    Event::KeyDown {
+                        keycode: Some(keycode),
+                        ..
+                    } => match keycode {
+                        Keycode::Up => list.move_by(-1),
+                        Keycode::Down => list.move_by(1),
+                        Keycode::PageUp => list.move_pages(-1),
+                        Keycode::PageDown => list.move_pages(1),
+                        Keycode::Home => list.first(),
+                        Keycode::End => list.last(),
+                        _ => {}
+                    },
+                    _ => {}
+                }
+                continue;
+            }
+            if memory_view.prompt().is_some() {
+                match event {
+                    Event::Quit { .. } => {
+                        info!("Recieved quit. Shutting down");
+                        break 'running;
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::Escape),
+                        ..
+                    } => memory_view.cancel_prompt(),
+                    Event::KeyDown {
+                        keycode: Some(Keycode::Return | Keycode::KpEnter),
+                        ..
+                    } => memory_view.finish_prompt(),
+                    Event::KeyDown {
+                        keycode: Some(Keycode::Backspace),
+                        ..
+                    } => memory_view.backspace(),
+                    Event::TextInput { text, .. } => {
+                        text.chars().for_each(|c| memory_view.type_char(c));
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            // Any other key means the first press of the quit key was a slip
+            if let (
+                Event::KeyDown {
+                    keycode,
+                    scancode,
+                    repeat: false,
+                    ..
+                },
+                Some(confirm),
+            ) = (&event, &mut quit_confirm)
+            {
+                if !quit_key.matches(*scancode, *keycode) {
+                    confirm.cancel();
+                }
+            }
+            let hotkey = hotkeys
+                .find(&event)
+                .filter(|hotkey| match (hotkey, &event) {
+                    // Layouts that use the key for the keypad keep it
+                    (
+                        Hotkey::Mute,
+                        Event::KeyDown {
+                            scancode, keycode, ..
+                        },
+                    ) => keymap.key(*scancode, *keycode).is_none(),
+                    (Hotkey::PageUp | Hotkey::PageDown, _) => shown.memory || shown.console,
+                    (Hotkey::Home | Hotkey::GoTo, _) => shown.memory,
+                    #[cfg(not(feature = "file-dialog"))]
+                    (Hotkey::OpenRom, _) => false,
+                    _ => true,
+                });
+            if let Some(hotkey) = hotkey {
+                match hotkey {
+                    Hotkey::Quit => {
+                        let confirmed = match &mut quit_confirm {
+                            Some(confirm) => confirm.press(Instant::now()),
+                            None => true,
+                        };
+                        if confirmed {
+                            info!("Recieved quit. Shutting down");
+                            break 'running;
+                        }
+                        info!("Press {quit_key} again to quit");
+                        osd.push(format!("Press {quit_key} again to quit"), Instant::now());
+                    }
+                    Hotkey::Borderless => {
+                        let mode = borderless_window.mode().toggled(borderless);
+                        info!("Borderless: {mode:?}");
+                        borderless_window.set(canvas.window_mut(), mode);
+                        viewport = fit_layout(&canvas, virtual_keypad, scaling, rotation)?;
+                    }
+                    Hotkey::Fullscreen => {
+                        let fullscreen = canvas.window().fullscreen_state() == FullscreenType::Off;
+                        info!("Fullscreen: {fullscreen}");
+                        set_fullscreen(&mut canvas, &mouse, fullscreen);
+                        viewport = fit_layout(&canvas, virtual_keypad, scaling, rotation)?;
+                    }
+                    Hotkey::Mute => {
+                        let muted = audio::toggle_mute(&status);
+                        info!("Muted: {muted}");
+                        osd.push(
+                            if muted { "Sound muted" } else { "Sound on" },
+                            Instant::now(),
+                        );
+                        if matches!(event, Event::KeyDown { keymod, .. }
+                            if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD))
+                        {
+                            if let Err(err) = audio::save_mute(&mut config, muted) {
+                                error!("Could not save the mute setting: {err}");
+                                osd.push("Could not save the mute setting", Instant::now());
+                            }
+                        }
+                        let paused = *paused.lock().unwrap();
+                        let title = title::format(
+                            &rom_name,
+                            &keymaps.describe(),
+                            paused,
+                            muted,
+                            status.speed(),
+                            title_fps.then_some(fps),
+                        );
+                        set_title(&mut canvas, &title)?;
+                    }
+                    Hotkey::Invert => {
+                        inverted = !inverted;
+                        info!("Inverted colors: {inverted}");
+                        remember_for_rom(
+                            &mut config,
+                            &mut osd,
+                            "video.invert",
+                            &inverted.to_string(),
+                        );
+                        osd.push(
+                            if inverted {
+                                "Colors inverted"
+                            } else {
+                                "Colors normal"
+                            },
+                            Instant::now(),
+                        );
+                    }
+                    Hotkey::Filter => {
+                        filter = match filter {
+                            Filter::None => Filter::Crt,
+                            Filter::Crt => Filter::None,
+                        };
+                        info!("Filter: {filter:?}");
+                        remember_for_rom(
+                            &mut config,
+                            &mut osd,
+                            "video.filter",
+                            &filter.to_string(),
+                        );
+                        osd.push(format!("Filter: {filter:?}"), Instant::now());
+                    }
+                    Hotkey::Stats => shown.stats = !shown.stats,
+                    Hotkey::KeyState => shown.key_state = !shown.key_state,
+                    Hotkey::Console => {
+                        shown.console = !shown.console;
+                        console_scroll = 0;
+                    }
+                    Hotkey::Heatmap => {
+                        activity = match activity {
+                            Some(_) => None,
+                            None => Some(activity::Activity::new(
+                                &*vram.lock().unwrap(),
+                                &status.heatmap,
+                            )),
+                        };
+                        status.heatmap.count(activity.is_some());
+                    }
+                    Hotkey::Export => match capture.dir() {
+                        // The core saves it once the current instruction is done
+                        Some(dir) => {
+                            status.request_export(screenshot::state_path(dir, &rom_name));
+                        }
+                        None => osd.push(
+                            "No directory to save the state to, set screenshot.dir",
+                            Instant::now(),
+                        ),
+                    },
+                    Hotkey::Registers => shown.registers = !shown.registers,
+                    Hotkey::Screenshot => capture.screenshot(),
+                    Hotkey::Disassembly => shown.disassembly = !shown.disassembly,
+                    Hotkey::Memory => shown.memory = !shown.memory,
+                    Hotkey::Slower | Hotkey::Faster => {
+                        let speed = status.change_speed(hotkey == Hotkey::Faster);
+                        osd.push(format!("Speed {speed}x"), Instant::now());
+                        remember_for_rom(&mut config, &mut osd, "speed", &speed.to_string());
+                    }
+                    Hotkey::FastForward => {
+                        let held = matches!(event, Event::KeyDown { .. });
+                        status.fast_forward.store(held, Ordering::Relaxed);
+                        if held {
+                            osd.push(">> Fast forward", Instant::now());
+                        }
+                    }
+                    Hotkey::Rebind => {
+                        info!("Rebinding keys");
+                        osd.push("Press a key for each keypad key", Instant::now());
+                        input.release_all();
+                        rebind = Some(keymap::Rebind::default());
+                    }
+                    Hotkey::VirtualKeypad => {
+                        virtual_keypad = !virtual_keypad;
+                        info!("Virtual keypad: {virtual_keypad}");
+                        viewport = set_layout(
+                            &mut canvas,
+                            borderless_window.covering(),
+                            virtual_keypad,
+                            scaling,
+                            rotation,
+                        )?;
+                    }
+                    Hotkey::Scaling => {
+                        scaling = match scaling {
+                            Scaling::Fit => Scaling::Integer,
+                            Scaling::Integer => Scaling::Fit,
+                        };
+                        info!("Scaling: {scaling:?}");
+                        osd.push(format!("Scaling: {scaling:?}"), Instant::now());
+                        if filtering.with(scaling) != texture_filtering {
+                            texture_filtering = filtering.with(scaling);
+                            (tex, crt_tex) = create_textures(&texcreator, texture_filtering)?;
+                        }
+                        viewport = fit_layout(&canvas, virtual_keypad, scaling, rotation)?;
+                    }
+                    Hotkey::PageUp if shown.memory => memory_view.scroll(-1),
+                    Hotkey::PageDown if shown.memory => memory_view.scroll(1),
+                    Hotkey::PageUp => {
+                        console_scroll = (console_scroll + CONSOLE_PAGE).min(console_lines.len());
+                    }
+                    Hotkey::PageDown => {
+                        console_scroll = console_scroll.saturating_sub(CONSOLE_PAGE);
+                    }
+                    Hotkey::Home => memory_view.go_to(status.snapshot.lock().unwrap().pc),
+                    Hotkey::Sprite => shown.sprite = !shown.sprite,
+                    Hotkey::GoTo => {
+                        input.release_all();
+                        memory_view.start_prompt();
+                    }
+                    Hotkey::OpenRom => {
+                        input.release_all();
+                        #[cfg(feature = "file-dialog")]
+                        match dialog::pick_rom() {
+                            Ok(path) => load_path = path,
+                            Err(err) => {
+                                warn!("{err}");
+                                osd.push(err, Instant::now());
+                            }
+                        }
+                    }
+                    Hotkey::Pause => {
+                        let mut paused = paused.lock().unwrap();
+                        *paused = !*paused;
+                        info!("Paused: {paused}");
+                        osd.push(if *paused { "Paused" } else { "Resumed" }, Instant::now());
+                        if let Some(focus_pause) = &mut focus_pause {
+                            focus_pause.manual();
+                        }
+                    }
+                    Hotkey::Breakpoint => {
+                        // The snapshot only follows the core while one of these is shown
+                        if !status.debug.load(Ordering::Relaxed) {
+                            osd.push(
+                                "Show the registers or disassembly to set breakpoints",
+                                Instant::now(),
+                            );
+                            continue;
+                        }
+                        let pc = status.snapshot.lock().unwrap().pc;
+                        let text = if status.clear_breakpoint(pc) {
+                            format!("Removed breakpoint at {pc:03X}")
+                        } else {
+                            status.set_breakpoint(pc, false);
+                            format!("Breakpoint at {pc:03X}")
+                        };
+                        osd.push(text, Instant::now());
+                    }
+                    Hotkey::Browse => {
+                        let Some(dir) = &rom_dir else {
+                            osd.push("Set --rom-dir or rom-dir to browse ROMs", Instant::now());
+                            continue;
+                        };
+                        match browser::Browser::open(dir) {
+                            Ok(list) if list.is_empty() => {
+                                osd.push(format!("No ROMs in {}", dir.display()), Instant::now());
+                            }
+                            Ok(list) => {
+                                input.release_all();
+                                let mut paused = paused.lock().unwrap();
+                                rom_browser = Some((list, *paused));
+                                *paused = true;
+                            }
+                            Err(err) => {
+                                warn!("{err}");
+                                osd.push(err, Instant::now());
+                            }
+                        }
+                    }
+                    Hotkey::CycleKeys => {
+                        input.release_all();
+                        keymaps.cycle();
+                        keymap = keymaps.current().clone();
+                        info!("Using {}", keymaps.describe());
+                        osd.push(format!("Keys: {}", keymaps.describe()), Instant::now());
+                        let paused = *paused.lock().unwrap();
+                        let title = title::format(
+                            &rom_name,
+                            &keymaps.describe(),
+                            paused,
+                            status.muted.load(Ordering::Relaxed),
+                            status.speed(),
+                            None,
+                        );
+                        set_title(&mut canvas, &title)?;
+                    }
+                    Hotkey::Cheats => osd.push(status.toggle_cheats(), Instant::now()),
+                    Hotkey::RecordGif => capture.toggle_gif(&rom_name, &mut osd),
+                }
+                status.debug.store(shown.debug(), Ordering::Relaxed);
+                continue;
+            }
             match event {
-                Event::Quit { .. }
-                | Event::KeyDown {
-                    keycode: Some(Keycode::Escape | Keycode::Q),
+                Event::Quit { .. } => {
+                    info!("Recieved quit. Shutting down");
+                    break 'running;
+                }
+                Event::Window {
+                    win_event: WindowEvent::SizeChanged(..),
                     ..
                 } => {
-                    info!("Recieved quit. Shutting down");
-                    return;
+                    viewport = fit_layout(&canvas, virtual_keypad, scaling, rotation)?;
+                    debug!("Resized, drawing to {viewport:?}");
+                    if remember_window
+                        && canvas.window().fullscreen_state() == FullscreenType::Off
+                        && !borderless_window.covering()
+                    {
+                        windowed = geometry::Geometry::of(canvas.window()).or(windowed);
+                    }
+                }
+                Event::Window {
+                    win_event:
+                        win_event @ (WindowEvent::Minimized
+                        | WindowEvent::Restored
+                        | WindowEvent::Maximized
+                        | WindowEvent::Hidden
+                        | WindowEvent::Shown
+                        | WindowEvent::Exposed),
+                    ..
+                } => {
+                    visibility.event(&win_event);
+                    debug!("Window {win_event:?}, visible: {}", visibility.visible());
+                }
+                Event::Window {
+                    win_event: WindowEvent::Moved(..),
+                    ..
+                } if remember_window
+                    && canvas.window().fullscreen_state() == FullscreenType::Off
+                    && !borderless_window.covering() =>
+                {
+                    windowed = geometry::Geometry::of(canvas.window()).or(windowed);
+                }
+                Event::AudioDeviceRemoved {
+                    iscapture: false, ..
+                } if audio_output
+                    .as_ref()
+                    .is_some_and(|device| device.status() == AudioStatus::Stopped) =>
+                {
+                    // SDL stops a device that was disconnected, but keeps it open
+                    audio_output = None;
+                    match open_audio(None) {
+                        Ok(device) => {
+                            warn!("The audio device was disconnected, using the default one");
+                            osd.push("Audio device lost, using the default", Instant::now());
+                            audio_output = Some(device);
+                        }
+                        Err(err) => {
+                            warn!("The audio device was disconnected, and the default one can't be opened: {err}");
+                            osd.push("Audio device lost, sound is off", Instant::now());
+                        }
+                    }
+                }
+                event if textures_lost(&event) => {
+                    // The renderer lost its textures along with the device, which
+                    // happens with Direct3D when the display changes
+                    warn!("The renderer was reset, creating its textures again");
+                    (tex, crt_tex) = create_textures(&texcreator, texture_filtering)?;
+                }
+                Event::DropFile { filename, .. } => {
+                    let path = PathBuf::from(filename);
+                    if confirm_drop && pending_drop.as_ref() != Some(&path) {
+                        osd.push(
+                            format!("Drop {} again to load it", rom::name(&path)),
+                            Instant::now(),
+                        );
+                        pending_drop = Some(path);
+                        continue;
+                    }
+                    pending_drop = None;
+                    match rom::check_extension(&path) {
+                        Ok(()) => load_path = Some(path),
+                        Err(err) => {
+                            warn!("{err}");
+                            osd.push(err, Instant::now());
+                        }
+                    }
+                }
+                Event::Window {
+                    win_event: WindowEvent::FocusLost,
+                    ..
+                } => {
+                    // Key and button releases go to whichever window has focus now
+                    debug!("Lost focus, releasing keys");
+                    input.release_all();
+                    status.fast_forward.store(false, Ordering::Relaxed);
+                    mouse_key = None;
+                    touches = touch::Touches::default();
+                    if let Some(focus_pause) = &mut focus_pause {
+                        let mut paused = paused.lock().unwrap();
+                        if focus_pause.lost(*paused) {
+                            info!("Paused while in the background");
+                            *paused = true;
+                            osd.push("Paused in the background", Instant::now());
+                        }
+                    }
+                }
+                Event::Window {
+                    win_event: WindowEvent::FocusGained,
+                    ..
+                } => {
+                    if let Some(focus_pause) = &mut focus_pause {
+                        focus_pause.gained(Instant::now());
+                    }
                 }
-                #[rustfmt::skip]
                 Event::KeyDown {
-                    keycode:
-                        keycode @ Some(
-                            | Num4 | Num5 | Num6 | Num7
-                            | R    | T    | Y    | U
-                            | F    | G    | H    | J
-                            | V    | B    | N    | M
-                        ),
+                    keycode,
+                    scancode,
                     repeat: false,
                     ..
                 } => {
-                    let keycode = keycode.unwrap();
-                    info!("Recieved keydown: {keycode}");
-                    keypad.lock().unwrap().press(keycode);
-
+                    if let Some(key) = keymap.key(scancode, keycode).map(direction) {
+                        info!("Recieved keydown: {key:X}");
+                        input.press(key);
+                    }
                 }
-                #[rustfmt::skip]
                 Event::KeyUp {
-                    keycode:
-                        keycode @ Some(
-                            | Num4 | Num5 | Num6 | Num7
-                            | R    | T    | Y    | U
-                            | F    | G    | H    | J
-                            | V    | B    | N    | M
-                        ),
+                    keycode,
+                    scancode,
                     repeat: false,
                     ..
                 } => {
-                    let keycode = keycode.unwrap();
-                    info!("Recieved keyup: {keycode}");
-                    keypad.lock().unwrap().release(keycode);
-
+                    if let Some(key) = keymap.key(scancode, keycode).map(direction) {
+                        info!("Recieved keyup: {key:X}");
+                        input.release(key);
+                    }
+                }
+                Event::MouseButtonDown {
+                    which,
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
+                    ..
+                } if virtual_keypad && which != touch::TOUCH_MOUSE_ID => {
+                    mouse_key = viewport
+                        .logical_point(Point::new(x, y))
+                        .and_then(|point| overlay::keypad_cell(keypad_area, point));
+                    if let Some(key) = mouse_key {
+                        debug!("Clicked key {key:X}");
+                        input.press(key);
+                    }
+                }
+                Event::MouseMotion {
+                    which,
+                    mousestate,
+                    x,
+                    y,
+                    ..
+                } if virtual_keypad && mousestate.left() && which != touch::TOUCH_MOUSE_ID => {
+                    let key = viewport
+                        .logical_point(Point::new(x, y))
+                        .and_then(|point| overlay::keypad_cell(keypad_area, point));
+                    if key != mouse_key {
+                        if let Some(old) = mouse_key {
+                            input.release(old);
+                        }
+                        if let Some(new) = key {
+                            input.press(new);
+                        }
+                        mouse_key = key;
+                    }
+                }
+                Event::MouseButtonUp {
+                    mouse_btn: MouseButton::Left,
+                    ..
+                } => {
+                    if let Some(key) = mouse_key.take() {
+                        input.release(key);
+                    }
+                }
+                Event::FingerDown {
+                    touch_id,
+                    finger_id,
+                    x,
+                    y,
+                    ..
+                }
+                | Event::FingerMotion {
+                    touch_id,
+                    finger_id,
+                    x,
+                    y,
+                    ..
+                } => {
+                    let area = if virtual_keypad {
+                        Some(keypad_area)
+                    } else {
+                        touch_grid.then_some(game_area)
+                    };
+                    let key = touch::touched_key(&viewport, canvas.window().size(), (x, y), area);
+                    let (released, pressed) = touches.update((touch_id, finger_id), key);
+                    if let Some(key) = released {
+                        input.release(key);
+                    }
+                    if let Some(key) = pressed {
+                        debug!("Touched key {key:X}");
+                        input.press(key);
+                    }
+                }
+                Event::FingerUp {
+                    touch_id,
+                    finger_id,
+                    ..
+                } => {
+                    if let Some(key) = touches.lift((touch_id, finger_id)) {
+                        input.release(key);
+                    }
+                }
+                Event::ControllerDeviceAdded { .. }
+                | Event::ControllerDeviceRemoved { .. }
+                | Event::ControllerButtonDown { .. }
+                | Event::ControllerButtonUp { .. }
+                | Event::ControllerAxisMotion { .. } => {
+                    for (action, pressed) in controllers.handle_event(&event) {
+                        match action {
+                            Action::Key(key) if pressed => input.press(direction(key)),
+                            Action::Key(key) => input.release(direction(key)),
+                            Action::Pause if pressed => {
+                                let mut paused = paused.lock().unwrap();
+                                *paused = !*paused;
+                                info!("Paused: {paused}");
+                                if let Some(focus_pause) = &mut focus_pause {
+                                    focus_pause.manual();
+                                }
+                                osd.push(
+                                    if *paused { "Paused" } else { "Resumed" },
+                                    Instant::now(),
+                                );
+                            }
+                            Action::Pause => {}
+                        }
+                    }
                 }
                 _ => {}
             }
         }
+        if let Some(path) = load_path.take() {
+            match rom::read(&path) {
+                Ok(rom) => {
+                    info!("Loading {}", path.display());
+                    crate::recent::remember(&path);
+                    rom_name = rom::name(&path);
+                    input.release_all();
+                    let keys = keymaps.describe();
+                    keymaps.select(&rom, &rom_name);
+                    keymap = keymaps.current().clone();
+                    let suggested = match crate::metadata::Metadata::load(&path) {
+                        Ok(metadata) => metadata.map(|metadata| metadata.config_values()),
+                        Err(err) => {
+                            warn!("{err}");
+                            None
+                        }
+                    };
+                    // Only for saving settings changed from here on, the rest were
+                    // already applied
+                    config.select_rom(&rom, suggested.unwrap_or_default());
+                    status.reload(rom);
+                    osd.push(format!("Loaded {rom_name}"), Instant::now());
+                    if keymaps.describe() != keys {
+                        osd.push(format!("Keys: {}", keymaps.describe()), Instant::now());
+                    }
+                    let paused = *paused.lock().unwrap();
+                    let title = title::format(
+                        &rom_name,
+                        &keymaps.describe(),
+                        paused,
+                        status.muted.load(Ordering::Relaxed),
+                        status.speed(),
+                        None,
+                    );
+                    set_title(&mut canvas, &title)?;
+                }
+                Err(err) => {
+                    warn!("{err}");
+                    osd.push(err, Instant::now());
+                }
+            }
+        }
+
         // The rest of the game loop goes here...
+        input.update();
 
-        let beep = *sound_timer.lock().unwrap() > 1;
-        if beep {
-            audio_device.resume();
-        } else {
-            audio_device.pause();
+        let sounding = status.sounding.load(Ordering::Relaxed);
+        let bell_lit = match &mut bell {
+            Some(bell) => bell.update(sounding, Instant::now()),
+            None => false,
+        };
+
+        let vram = *vram.lock().unwrap();
+        if let Some(script) = &mut script {
+            let frame = status.frames.load(Ordering::Relaxed);
+            if let ControlFlow::Break(played) = play_script(script, frame, &vram, &mut input) {
+                // Returned once the window is put away, for main to report
+                outcome = played;
+                break 'running;
+            }
+        }
+        let colors = match &mut phosphor {
+            Some(phosphor) => {
+                phosphor.update(&vram, frame_time);
+                phosphor.colors(&palette)
+            }
+            None => color::pixel_colors(&vram, &palette),
+        };
+        if let Some(activity) = &mut activity {
+            activity.update(&vram, &status.heatmap, frame_time);
+        }
+        capture.frame(
+            status.frames.load(Ordering::Relaxed),
+            &vram,
+            &colors,
+            &palette,
+            &rom_name,
+            &mut osd,
+        );
+        let fast_forward = status.fast_forward.load(Ordering::Relaxed)
+            && !status
+                .frames
+                .load(Ordering::Relaxed)
+                .is_multiple_of(FAST_FORWARD_DRAW_EVERY);
+        let skipping = visibility.visible()
+            && (fast_forward
+                || frame_skip
+                    .as_mut()
+                    .is_some_and(|frame_skip| frame_skip.skip(pacer.late(start))));
+        if skipping {
+            trace!("Behind, not drawing this frame");
         }
+        // Nobody would see it, so only the things that don't draw carry on
+        if visibility.visible() && !skipping {
+            let tex = match filter {
+                Filter::None => {
+                    let pixels = color::to_rgb24(&colors);
+                    tex.update(None, &pixels, 64 * 3).map_err(Error::render)?;
+                    &mut tex
+                }
+                Filter::Crt => {
+                    let pixels = filter::crt(&colors, palette.background());
+                    let pitch = 64 * filter::CRT_SCALE as usize * 3;
+                    crt_tex
+                        .update(None, &pixels, pitch)
+                        .map_err(Error::render)?;
+                    &mut crt_tex
+                }
+            };
 
-        let vram = vram.lock().unwrap().map(|pix| pix as u8 * 255);
-        tex.update(None, &vram, 64).unwrap();
+            trace!("Drawing frame");
+            #[cfg(feature = "shader")]
+            let drawn = match &shader {
+                Some(shader) => {
+                    let output_height = canvas.output_size().map_err(Error::Render)?.1;
+                    shader.draw(tex, viewport.rect(game_area), output_height);
+                    true
+                }
+                None => false,
+            };
+            #[cfg(not(feature = "shader"))]
+            let drawn = false;
+            if !drawn {
+                canvas
+                    .copy_ex(
+                        &*tex,
+                        None,
+                        viewport.texture_rect(game_area),
+                        viewport.angle(),
+                        None,
+                        false,
+                        false,
+                    )
+                    .map_err(Error::Render)?;
+            }
+            if let Some(activity) = &activity {
+                activity
+                    .draw_screen(&mut canvas, &viewport, game_area)
+                    .map_err(Error::Render)?;
+            }
+            if bell_lit {
+                bell::draw_border(&mut canvas, viewport.rect(game_area), palette.0[1])
+                    .map_err(Error::Render)?;
+            }
+            if let Some(binding) = &rebind {
+                overlay::draw_keypad(&mut canvas, &viewport, game_area, |key| {
+                    key == binding.current()
+                })
+                .map_err(Error::Render)?;
+            }
+            if quit_confirm
+                .as_ref()
+                .is_some_and(|confirm| confirm.pending(Instant::now()))
+            {
+                canvas.set_draw_color(Color::RED);
+                canvas
+                    .draw_rect(viewport.rect(game_area))
+                    .map_err(Error::Render)?;
+                canvas.set_draw_color(Color::BLACK);
+            }
+            if virtual_keypad {
+                let pressed = *keypad.lock().unwrap();
+                overlay::draw_keypad(&mut canvas, &viewport, keypad_area, |key| {
+                    pressed.is_pressed(key)
+                })
+                .map_err(Error::Render)?;
+            }
+
+            let (output_width, output_height) = canvas.output_size().map_err(Error::Render)?;
+            let scale = (output_height / 240).max(1);
+            if shown.key_state {
+                let area =
+                    overlay::key_state_area((output_width, output_height), scale, key_state_labels);
+                let pressed = *keypad.lock().unwrap();
+                let label = |key| keymap.label(key);
+                overlay::draw_key_state(
+                    &mut canvas,
+                    area,
+                    scale,
+                    |key| pressed.is_pressed(key),
+                    key_state_labels.then_some(&label as &dyn Fn(u8) -> String),
+                )
+                .map_err(Error::Render)?;
+            }
+            if shown.console {
+                // Keeps showing the last copy if a message is being added right now
+                logging::console_lines(&mut console_lines);
+                console_scroll = console_scroll.min(console_lines.len());
+                overlay::draw_console(&mut canvas, &console_lines, console_scroll, scale)
+                    .map_err(Error::Render)?;
+            }
+            let messages = osd.visible(Instant::now()).collect::<Vec<_>>();
+            overlay::draw_messages(&mut canvas, &messages, scale).map_err(Error::Render)?;
+            if shown.stats {
+                let text = format!(
+                "FPS {fps:.0}\nIPS {ips:.0}\nSPEED {}X\nDT {:02X} ST {:02X}\nTONE {tone_hz:.0}HZ VOL {}%\nDRIFT {:.1}MS\nDROPPED {} FRAMES {} TICKS",
+                status.speed(),
+                *status.delay_timer.lock().unwrap(),
+                *status.sound_timer.lock().unwrap(),
+                status.volume.load(Ordering::Relaxed),
+                pacer.drift().as_secs_f64() * 1000.0,
+                pacer.dropped(),
+                status.dropped_ticks.load(Ordering::Relaxed),
+            );
+                let origin = Point::new(4 * scale as i32, 4 * scale as i32);
+                font::draw_text(&mut canvas, &text, origin, scale, Color::WHITE)
+                    .map_err(Error::Render)?;
+            }
+            // Debug panels are stacked down the right edge
+            let mut column = overlay::Column::new(output_width, scale);
+            if shown.registers {
+                let text = status.snapshot.lock().unwrap().to_string();
+                column
+                    .draw_text(&mut canvas, &text)
+                    .map_err(Error::Render)?;
+            }
+            if shown.disassembly {
+                // Refreshed at 10Hz so it can be read while the game runs
+                if disassembly.0.elapsed() >= Duration::from_millis(100) {
+                    disassembly = (
+                        Instant::now(),
+                        status.snapshot.lock().unwrap().disassembly(),
+                    );
+                }
+                column
+                    .draw_text(&mut canvas, &disassembly.1)
+                    .map_err(Error::Render)?;
+            }
+            if shown.memory {
+                let snapshot = status.snapshot.lock().unwrap();
+                overlay::draw_memory(&mut canvas, &mut column, &memory_view, &snapshot)
+                    .map_err(Error::Render)?;
+            }
+            if shown.sprite {
+                let snapshot = status.snapshot.lock().unwrap();
+                overlay::draw_sprite_panel(&mut canvas, &mut column, &snapshot)
+                    .map_err(Error::Render)?;
+            }
+            if let Some(activity) = &activity {
+                let origin = column.place(activity.panel_size(scale));
+                activity
+                    .draw_panel(&mut canvas, origin, scale)
+                    .map_err(Error::Render)?;
+            }
 
-        trace!("Drawing frame");
-        canvas.copy(&tex, None, None).unwrap();
+            if let Some((list, _)) = &mut rom_browser {
+                // Dims everything else, the game included
+                overlay::draw_browser(&mut canvas, list, scale).map_err(Error::Render)?;
+            }
 
-        canvas.present();
-        Timer::after(Duration::from_secs_f64(1f64 / 60f64).saturating_sub(start.elapsed())).await;
+            canvas.present();
+            present_timing.presented();
+        }
+        let frame = status.frames.fetch_add(1, Ordering::Relaxed) + 1;
+        if frame_hash_every.is_some_and(|every| frame.is_multiple_of(every)) {
+            println!(
+                "Frame {frame} {:016x}",
+                crate::hash::framebuffer_hash(&vram, 64, 32)
+            );
+        }
+        if exit_after_frames.is_some_and(|limit| frame >= limit) {
+            // Left to main, which can still get at the core's state
+            status.bound_reached.store(true, Ordering::Relaxed);
+            break 'running;
+        }
+        drop(frame_span);
+        let visible = visibility.visible();
+        let counting_frames = replaying
+            || script.is_some()
+            || capture.recording()
+            || exit_after_frames.is_some()
+            || frame_hash_every.is_some();
+        let next_frame = match vsync {
+            Vsync::On | Vsync::Adaptive if visible && !skipping && present_timing.pacing() => {
+                // Presenting keeps the pace, frames are only counted to tell late ones
+                if frame_skip.is_some() {
+                    pacer.next(Instant::now());
+                } else {
+                    pacer.restart();
+                }
+                None
+            }
+            // Frames that come late are made up for by waiting less after the next ones
+            _ if visible || counting_frames => {
+                if !visible {
+                    if let Some(frame_skip) = &mut frame_skip {
+                        frame_skip.restart();
+                    }
+                }
+                Some(pacer.next(Instant::now()))
+            }
+            _ => {
+                if let Some(frame_skip) = &mut frame_skip {
+                    frame_skip.restart();
+                }
+                pacer.restart();
+                present_timing.restart();
+                Some(start + visibility::HIDDEN_INTERVAL)
+            }
+        };
+        match next_frame {
+            Some(deadline) => {
+                // Keys mean something else while rebinding, browsing or typing
+                let keys =
+                    rebind.is_none() && rom_browser.is_none() && memory_view.prompt().is_none();
+                // A frame early would throw off anything counting them
+                let early = !counting_frames;
+                poller
+                    .wait(
+                        &mut event_pump,
+                        deadline,
+                        keys,
+                        early,
+                        |scancode, keycode, down| {
+                            let Some(key) = keymap.key(scancode, keycode).map(direction) else {
+                                return false;
+                            };
+                            if down {
+                                info!("Recieved keydown: {key:X}");
+                                input.press(key);
+                            } else {
+                                info!("Recieved keyup: {key:X}");
+                                input.release(key);
+                            }
+                            true
+                        },
+                    )
+                    .await;
+            }
+            // Presenting already waited for the display, just let the core catch up
+            None => smol::future::yield_now().await,
+        }
         let diff = start.elapsed().as_micros() as f64;
         trace!("FPS: {:.1}", 1f64 / (diff / 1000000.0));
+        if let Some(frame_rate) = frame_counter.tick(Instant::now()) {
+            fps = frame_rate;
+            let now = Instant::now();
+            let count = status.instructions.load(Ordering::Relaxed);
+            ips = (count - last_count.1) as f32 / now.duration_since(last_count.0).as_secs_f32();
+            last_count = (now, count);
+            let paused = *paused.lock().unwrap();
+            let title = title::format(
+                &rom_name,
+                &keymaps.describe(),
+                paused,
+                status.muted.load(Ordering::Relaxed),
+                status.speed(),
+                title_fps.then_some(fps),
+            );
+            if canvas.window().title() != title {
+                set_title(&mut canvas, &title)?;
+            }
+        }
     }
+    if let Some(mut geometry) = windowed {
+        geometry.fullscreen = canvas.window().fullscreen_state() != FullscreenType::Off;
+        if let Err(err) = geometry.save() {
+            warn!("Could not save the window geometry: {err}");
+        }
+    }
+    outcome
 }
 
-#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
-pub struct Keypad(pub [bool; 16]);
+/// What stopped the window from running.
+#[derive(Debug)]
+pub enum Error {
+    /// SDL itself couldn't start
+    Sdl(String),
+    /// The window or its renderer couldn't be created
+    Window(String),
+    /// Drawing failed for good
+    Render(String),
+    /// A setting couldn't be put into effect
+    Setting(String),
+    /// An expectation of the input script didn't hold
+    Script(String),
+}
 
-impl Keypad {
-    pub fn press(&mut self, keycode: Keycode) {
-        use Keycode::*;
-        match keycode {
-            Num4 => self.0[0x1] = true,
-            Num5 => self.0[0x2] = true,
-            Num6 => self.0[0x3] = true,
-            Num7 => self.0[0xC] = true,
-            R => self.0[0x4] = true,
-            T => self.0[0x5] = true,
-            Y => self.0[0x6] = true,
-            U => self.0[0xD] = true,
-            F => self.0[0x7] = true,
-            G => self.0[0x8] = true,
-            H => self.0[0x9] = true,
-            J => self.0[0xE] = true,
-            V => self.0[0xA] = true,
-            B => self.0[0x0] = true,
-            N => self.0[0xB] = true,
-            M => self.0[0xF] = true,
-            _ => {}
-        };
-    }
-    pub fn release(&mut self, keycode: Keycode) {
-        use Keycode::*;
-        match keycode {
-            Num4 => self.0[0x1] = false,
-            Num5 => self.0[0x2] = false,
-            Num6 => self.0[0x3] = false,
-            Num7 => self.0[0xC] = false,
-            R => self.0[0x4] = false,
-            T => self.0[0x5] = false,
-            Y => self.0[0x6] = false,
-            U => self.0[0xD] = false,
-            F => self.0[0x7] = false,
-            G => self.0[0x8] = false,
-            H => self.0[0x9] = false,
-            J => self.0[0xE] = false,
-            V => self.0[0xA] = false,
-            B => self.0[0x0] = false,
-            N => self.0[0xB] = false,
-            M => self.0[0xF] = false,
-            _ => {}
+impl Error {
+    fn render(err: impl fmt::Display) -> Error {
+        Error::Render(err.to_string())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Sdl(message) => write!(f, "Could not start SDL: {message}"),
+            Error::Window(message) => write!(f, "Could not open the window: {message}"),
+            Error::Render(message) => write!(f, "Could not draw the screen: {message}"),
+            Error::Setting(message) | Error::Script(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+pub const MAX_SCALE: u32 = 64;
+/// Lines the console scrolls by with PageUp and PageDown.
+const CONSOLE_PAGE: usize = 10;
+/// How long after getting focus back a game paused in the background resumes.
+const FOCUS_GRACE: Duration = Duration::from_millis(300);
+
+/// Set to anything, [`check_video`] fails without trying, to check what happens
+/// without a display server on a machine that has one.
+pub const NO_VIDEO_VAR: &str = "CHIP8_NO_VIDEO";
+
+/// Whether SDL can open a window here, which it can't over SSH without a display
+/// server, for instance.
+pub fn check_video() -> Result<(), String> {
+    if std::env::var_os(NO_VIDEO_VAR).is_some() {
+        return Err(format!("{NO_VIDEO_VAR} is set"));
+    }
+    sdl2::hint::set("SDL_NO_SIGNAL_HANDLERS", "1");
+    sdl2::init()?.video().map(drop)
+}
+
+/// Plays the script up to `frame` through `input`, breaking with how the run ends
+/// once it quits or an expectation doesn't hold.
+fn play_script(
+    script: &mut Script,
+    frame: u64,
+    vram: &[bool],
+    input: &mut Input,
+) -> ControlFlow<Result<(), Error>> {
+    let played = script.play(frame, vram, |key, down| match down {
+        true => input.press(key),
+        false => input.release(key),
+    });
+    match played {
+        Ok(ControlFlow::Continue(())) => ControlFlow::Continue(()),
+        Ok(ControlFlow::Break(())) => ControlFlow::Break(Ok(())),
+        Err(err) => ControlFlow::Break(Err(Error::Script(err))),
+    }
+}
+
+/// Keeps the frames coming without a window, for when there is none to open: counted,
+/// hashed and stopped after as asked for, with replays and scripts played into
+/// `keypad`. The screen can still be seen through the ASCII mirror, remote viewers or
+/// the stream, and played through remote keypads.
+pub async fn windowless(
+    vram: Arc<Mutex<[bool; 64 * 32]>>,
+    keypad: Arc<Mutex<Keypad>>,
+    status: Arc<Status>,
+    settings: Settings,
+) -> Result<(), Error> {
+    let Settings {
+        frame_hash_every,
+        exit_after_frames,
+        replay,
+        mut script,
+        merge_replay,
+        ..
+    } = settings;
+    let mut input = input::Input::new(keypad, None);
+    if let Some(replay) = replay {
+        input.replay(replay, merge_replay);
+    }
+    let mut pacer = Pacer::new(60.0, pace::MAX_BEHIND);
+    loop {
+        Timer::at(pacer.next(Instant::now())).await;
+        if status.interrupted.load(Ordering::Relaxed) != 0 {
+            return Ok(());
+        }
+        input.update();
+        let vram = *vram.lock().unwrap();
+        if let Some(script) = &mut script {
+            let frame = status.frames.load(Ordering::Relaxed);
+            if let ControlFlow::Break(played) = play_script(script, frame, &vram, &mut input) {
+                return played;
+            }
+        }
+        let frame = status.frames.fetch_add(1, Ordering::Relaxed) + 1;
+        if frame_hash_every.is_some_and(|every| frame.is_multiple_of(every)) {
+            println!(
+                "Frame {frame} {:016x}",
+                crate::hash::framebuffer_hash(&vram, 64, 32)
+            );
+        }
+        if exit_after_frames.is_some_and(|limit| frame >= limit) {
+            status.bound_reached.store(true, Ordering::Relaxed);
+            return Ok(());
+        }
+    }
+}
+
+/// The audio subsystem, unless `enabled` is false or SDL can't start it, in which
+/// case the game goes on without sound. The sound timer runs in the core either way.
+fn start_audio(sdl_context: &sdl2::Sdl, enabled: bool) -> Option<sdl2::AudioSubsystem> {
+    if !enabled {
+        info!("Audio is off");
+        return None;
+    }
+    sdl_context
+        .audio()
+        .map_err(|err| warn!("Could not start audio, carrying on without sound: {err}"))
+        .ok()
+}
+
+/// Prints the names of the devices `--audio-device` picks from.
+pub fn list_audio_devices() -> Result<(), String> {
+    let audio = sdl2::init()?.audio()?;
+    for name in audio_devices::playback_devices(&audio) {
+        println!("{name}");
+    }
+    Ok(())
+}
+
+/// Shrinks `scale` until a layout of `size` fits on the desktop.
+fn fit_scale(size: (u32, u32), scale: u32, desktop: (u32, u32)) -> u32 {
+    let largest = (desktop.0 / size.0).min(desktop.1 / size.1).max(1);
+    if scale > largest {
+        warn!("A scale of {scale} doesn't fit on the desktop, using {largest}");
+        largest
+    } else {
+        scale
+    }
+}
+
+/// Size of the logical layout: the game, and the on-screen keypad below it when shown.
+fn layout_size(virtual_keypad: bool) -> (u32, u32) {
+    if virtual_keypad {
+        (64, 64)
+    } else {
+        (64, 32)
+    }
+}
+
+/// Resizes the window to make room for the on-screen keypad, keeping its width.
+/// In fullscreen or `covering` the display the layout is letterboxed into it instead.
+fn set_layout(
+    canvas: &mut Canvas<Window>,
+    covering: bool,
+    virtual_keypad: bool,
+    scaling: Scaling,
+    rotation: Rotation,
+) -> Result<Viewport, Error> {
+    if canvas.window().fullscreen_state() == FullscreenType::Off && !covering {
+        let (width, height) = rotation.apply(layout_size(virtual_keypad));
+        let window_width = canvas.window().size().0;
+        canvas
+            .window_mut()
+            .set_size(window_width, window_width * height / width)
+            .map_err(|err| Error::Setting(format!("Could not resize the window: {err}")))?;
+    }
+    fit_layout(canvas, virtual_keypad, scaling, rotation)
+}
+
+/// Switches between a window and desktop fullscreen, hiding the cursor in fullscreen.
+fn set_fullscreen(canvas: &mut Canvas<Window>, mouse: &MouseUtil, fullscreen: bool) {
+    let mode = if fullscreen {
+        FullscreenType::Desktop
+    } else {
+        FullscreenType::Off
+    };
+    if let Err(err) = canvas.window_mut().set_fullscreen(mode) {
+        warn!("Could not change fullscreen mode: {err}");
+    }
+    mouse.show_cursor(!fullscreen);
+}
+
+/// The textures for the screen without and with the CRT filter, stretched to the
+/// window with `filtering`.
+fn create_textures(
+    texcreator: &TextureCreator<WindowContext>,
+    filtering: Filtering,
+) -> Result<(Texture<'_>, Texture<'_>), Error> {
+    debug!("Creating textures with {filtering:?} filtering");
+    sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", filtering.hint());
+    let tex = texcreator
+        .create_texture_streaming(PixelFormatEnum::RGB24, 64, 32)
+        .map_err(Error::render)?;
+    let crt_tex = texcreator
+        .create_texture_streaming(
+            PixelFormatEnum::RGB24,
+            64 * filter::CRT_SCALE,
+            32 * filter::CRT_SCALE,
+        )
+        .map_err(Error::render)?;
+    Ok((tex, crt_tex))
+}
+
+/// Compiles the shader at `path` for `canvas`, or says why the game is drawn the
+/// usual way instead.
+#[cfg(feature = "shader")]
+fn load_shader(
+    canvas: &Canvas<Window>,
+    video_subsystem: &sdl2::VideoSubsystem,
+    path: &Path,
+    rotation: Rotation,
+    status: &Status,
+) -> Option<shader::Shader> {
+    let renderer = canvas.info().name;
+    let loaded = if renderer != "opengl" {
+        Err(format!("Shaders need the OpenGL renderer, not {renderer}"))
+    } else if rotation != Rotation::None {
+        Err("Shaders can't be used with a rotated screen".into())
+    } else {
+        shader::Shader::load(video_subsystem, path)
+    };
+    shader_or_fallback(loaded, path, status)
+}
+
+/// The shader, or nothing after saying why the game is drawn the usual way.
+#[cfg(feature = "shader")]
+fn shader_or_fallback(
+    loaded: Result<shader::Shader, String>,
+    path: &Path,
+    status: &Status,
+) -> Option<shader::Shader> {
+    match loaded {
+        Ok(shader) => {
+            info!("Drawing through {}", path.display());
+            Some(shader)
+        }
+        Err(err) => {
+            warn!("{err}");
+            // The first frame shows it on screen
+            *status.notice.lock().unwrap() = Some("Shader failed, drawing without it".into());
+            None
+        }
+    }
+}
+
+fn fit_layout(
+    canvas: &Canvas<Window>,
+    virtual_keypad: bool,
+    scaling: Scaling,
+    rotation: Rotation,
+) -> Result<Viewport, Error> {
+    let output = canvas.output_size().map_err(Error::Render)?;
+    let window = canvas.window().size();
+    Ok(Viewport::fit(
+        layout_size(virtual_keypad),
+        output,
+        window,
+        scaling,
+        rotation,
+    ))
+}
+
+/// Whether the renderer threw away its textures along with `event`, so they have
+/// to be created again.
+fn textures_lost(event: &Event) -> bool {
+    matches!(
+        event,
+        Event::RenderTargetsReset { .. } | Event::RenderDeviceReset { .. }
+    )
+}
+
+/// Puts `title` on the window.
+fn set_title(canvas: &mut Canvas<Window>, title: &str) -> Result<(), Error> {
+    canvas
+        .window_mut()
+        .set_title(title)
+        .map_err(|err| Error::Setting(format!("Could not set the window title: {err}")))
+}
+
+/// The speeds the speed hotkeys step through.
+pub const SPEEDS: [f32; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+/// While fast-forwarding, how many instructions in a row without a draw make a frame
+/// for the timers, so games waiting on the delay timer get there too.
+pub const FAST_FORWARD_FRAME: u64 = 1000;
+/// While fast-forwarding, only one in this many frames is drawn, which leaves the
+/// rest of the thread's time to the core.
+pub const FAST_FORWARD_DRAW_EVERY: u64 = 8;
+
+/// Checks a starting speed, which has to be within the hotkeys' range.
+pub fn check_speed(speed: f32) -> Result<f32, String> {
+    let (min, max) = (SPEEDS[0], SPEEDS[SPEEDS.len() - 1]);
+    if (min..=max).contains(&speed) {
+        Ok(speed)
+    } else {
+        Err(format!(
+            "A speed of {speed}x is not between {min}x and {max}x"
+        ))
+    }
+}
+
+/// Live numbers from the core for the stats and register overlays.
+#[derive(Debug)]
+pub struct Status {
+    /// Instructions executed since startup
+    pub instructions: AtomicU64,
+    /// Frames presented since startup
+    pub frames: AtomicU64,
+    /// Ticks of either timer given up on for falling too far behind
+    pub dropped_ticks: AtomicU64,
+    /// Buzzer volume, 0-100
+    pub volume: AtomicU8,
+    pub muted: AtomicBool,
+    /// Whether the buzzer should be sounding, kept in step with the sound timer's ticks
+    pub sounding: AtomicBool,
+    /// Set when the game starts a beep, so the buzzer sounds even if the timer runs
+    /// out before the next frame
+    pub beep: AtomicBool,
+    /// XO-CHIP audio pattern, played instead of the buzzer once loaded
+    pub pattern: Mutex<Option<[u8; 16]>>,
+    pub pitch: AtomicU8,
+    pub delay_timer: Arc<Mutex<u8>>,
+    pub sound_timer: Arc<Mutex<u8>>,
+    /// Whether the core should keep `snapshot` up to date
+    pub debug: AtomicBool,
+    pub snapshot: Mutex<Snapshot>,
+    /// A new program for the core to restart with
+    pub reload: Mutex<Option<Vec<u8>>>,
+    /// Exit once this many instructions have run
+    pub exit_after_instructions: Option<u64>,
+    /// Where to save the last frame on exit
+    pub dump_frame: Option<PathBuf>,
+    pub coverage: Option<crate::coverage::Coverage>,
+    pub heatmap: crate::heatmap::Heatmap,
+    /// Video is being written to stdout, so anything else has to go to stderr
+    pub stdout_is_video: bool,
+    /// The palette the screen is currently drawn with
+    pub palette: Mutex<Palette>,
+    breakpoints: Mutex<Breakpoints>,
+    /// Whether `breakpoints` has any, so the core can skip locking it otherwise
+    any_breakpoints: AtomicBool,
+    any_watches: AtomicBool,
+    any_register_watches: AtomicBool,
+    /// The first address of the page the memory viewer shows, while it is open
+    pub memory_top: Mutex<Option<u16>>,
+    /// A message from the core for the on-screen display
+    pub notice: Mutex<Option<String>>,
+    /// How many times faster than normal the game runs, as the bits of an `f32`
+    speed: AtomicU32,
+    /// The fast-forward key is held: the core runs flat out and ticks the timers
+    /// itself, once a frame
+    pub fast_forward: AtomicBool,
+    /// The signal that asked to quit, e.g. SIGINT from Ctrl+C, or 0
+    pub interrupted: AtomicI32,
+    /// `--exit-after-frames` stopped the window
+    pub bound_reached: AtomicBool,
+    /// Where the core should save its state as JSON, between two instructions
+    export_state: Mutex<Option<PathBuf>>,
+    /// Waiting for a snapshot of the machine from the core
+    state_requests: Mutex<Vec<Sender<Dump>>>,
+    /// Whether either of the above is waiting, so the core can skip locking them
+    /// otherwise
+    any_state_requests: AtomicBool,
+    /// Run one instruction while paused, then pause again
+    pub step: AtomicBool,
+    cheats: Mutex<Cheats>,
+    /// Cheats can all be turned off for a moment without forgetting them
+    cheats_on: AtomicBool,
+    /// Whether any cheat is enabled while `cheats_on`, so the core can skip locking
+    /// `cheats` otherwise
+    any_cheats: AtomicBool,
+}
+
+impl Status {
+    pub fn new(delay_timer: Arc<Mutex<u8>>, sound_timer: Arc<Mutex<u8>>) -> Status {
+        Status {
+            instructions: AtomicU64::new(0),
+            frames: AtomicU64::new(0),
+            dropped_ticks: AtomicU64::new(0),
+            volume: AtomicU8::new(0),
+            muted: AtomicBool::new(false),
+            sounding: AtomicBool::new(false),
+            beep: AtomicBool::new(false),
+            pattern: Mutex::new(None),
+            pitch: AtomicU8::new(DEFAULT_PITCH),
+            delay_timer,
+            sound_timer,
+            debug: AtomicBool::new(false),
+            snapshot: Mutex::new(Snapshot::default()),
+            reload: Mutex::new(None),
+            exit_after_instructions: None,
+            dump_frame: None,
+            coverage: None,
+            heatmap: crate::heatmap::Heatmap::new(None),
+            stdout_is_video: false,
+            palette: Mutex::new(Palette::default()),
+            breakpoints: Mutex::new(Breakpoints::default()),
+            any_breakpoints: AtomicBool::new(false),
+            any_watches: AtomicBool::new(false),
+            any_register_watches: AtomicBool::new(false),
+            memory_top: Mutex::new(None),
+            notice: Mutex::new(None),
+            speed: AtomicU32::new(1f32.to_bits()),
+            fast_forward: AtomicBool::new(false),
+            interrupted: AtomicI32::new(0),
+            bound_reached: AtomicBool::new(false),
+            export_state: Mutex::new(None),
+            state_requests: Mutex::new(Vec::new()),
+            any_state_requests: AtomicBool::new(false),
+            step: AtomicBool::new(false),
+            cheats: Mutex::new(Cheats::default()),
+            cheats_on: AtomicBool::new(true),
+            any_cheats: AtomicBool::new(false),
+        }
+    }
+
+    /// Stops the core before it runs `addr`, every time or only the next time.
+    pub fn set_breakpoint(&self, addr: u16, temporary: bool) {
+        let mut breakpoints = self.breakpoints.lock().unwrap();
+        if temporary {
+            breakpoints.add_temporary(addr);
+        } else {
+            breakpoints.add(addr, None);
+        }
+        self.any_breakpoints.store(true, Ordering::Relaxed);
+    }
+
+    /// Stops the core before it runs `addr` while `condition` holds.
+    pub fn set_conditional_breakpoint(&self, addr: u16, condition: Condition) {
+        self.breakpoints.lock().unwrap().add(addr, Some(condition));
+        self.any_breakpoints.store(true, Ordering::Relaxed);
+    }
+
+    pub fn clear_breakpoint(&self, addr: u16) -> bool {
+        let mut breakpoints = self.breakpoints.lock().unwrap();
+        let removed = breakpoints.remove(addr);
+        self.any_breakpoints
+            .store(!breakpoints.is_empty(), Ordering::Relaxed);
+        removed
+    }
+
+    /// Whether the core should stop before running `addr`.
+    pub fn breaks_at(&self, addr: u16, registers: &crate::trace::Registers) -> bool {
+        if !self.any_breakpoints.load(Ordering::Relaxed) {
+            return false;
+        }
+        let mut breakpoints = self.breakpoints.lock().unwrap();
+        let hit = breakpoints.hit(addr, registers);
+        self.any_breakpoints
+            .store(!breakpoints.is_empty(), Ordering::Relaxed);
+        hit
+    }
+
+    /// Stops the core after anything writes to `addr` in memory.
+    pub fn watch(&self, addr: u16) {
+        self.breakpoints.lock().unwrap().watch(addr);
+        self.any_watches.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_watched(&self, addr: u16) -> bool {
+        self.any_watches.load(Ordering::Relaxed)
+            && self.breakpoints.lock().unwrap().is_watched(addr)
+    }
+
+    /// Stops the core after an instruction makes `condition` true.
+    pub fn watch_registers(&self, condition: Condition) {
+        self.breakpoints.lock().unwrap().watch_registers(condition);
+        self.any_register_watches.store(true, Ordering::Relaxed);
+    }
+
+    /// The register watch that the last instruction set off, if any.
+    pub fn check_register_watches(&self, registers: &crate::trace::Registers) -> Option<String> {
+        if !self.any_register_watches.load(Ordering::Relaxed) {
+            return None;
+        }
+        let mut breakpoints = self.breakpoints.lock().unwrap();
+        breakpoints
+            .check_registers(registers)
+            .map(ToString::to_string)
+    }
+
+    /// How many times faster than normal the game runs right now.
+    pub fn speed(&self) -> f32 {
+        f32::from_bits(self.speed.load(Ordering::Relaxed))
+    }
+
+    pub fn set_speed(&self, speed: f32) {
+        self.speed.store(speed.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Moves to the next of [`SPEEDS`] up or down, returning the new speed.
+    pub fn change_speed(&self, faster: bool) -> f32 {
+        let speed = f32::from_bits(self.speed.load(Ordering::Relaxed));
+        let next = match faster {
+            true => SPEEDS.iter().find(|next| **next > speed),
+            false => SPEEDS.iter().rev().find(|next| **next < speed),
+        };
+        let next = next.copied().unwrap_or(speed);
+        self.speed.store(next.to_bits(), Ordering::Relaxed);
+        info!("Speed: {next}x");
+        next
+    }
+
+    /// Keeps [`Status::sounding`] in step with a tick of the sound timer that left it
+    /// at `timer`.
+    pub fn sound_timer_ticked(&self, timer: u8, paused: bool) {
+        self.sounding.store(timer > 0 && !paused, Ordering::Relaxed);
+    }
+
+    /// How long a frame lasts at the current speed, which both drawing and the
+    /// timers go by, so games see time pass at the same rate as they run.
+    pub fn frame_period(&self) -> Duration {
+        Duration::from_secs_f32(1f32 / 60f32 / self.speed())
+    }
+
+    /// Replaces the cheats, e.g. with those of a newly loaded ROM.
+    pub fn set_cheats(&self, cheats: Cheats) {
+        let mut current = self.cheats.lock().unwrap();
+        *current = cheats;
+        self.update_any_cheats(&current);
+    }
+
+    /// Turns all cheats off or back on, returning what to show on screen.
+    pub fn toggle_cheats(&self) -> String {
+        let cheats = self.cheats.lock().unwrap();
+        if cheats.is_empty() {
+            return "No cheats for this ROM".into();
+        }
+        let on = !self.cheats_on.fetch_xor(true, Ordering::Relaxed);
+        self.update_any_cheats(&cheats);
+        if !on {
+            return "Cheats off".into();
+        }
+        let enabled: Vec<_> = cheats
+            .iter()
+            .filter(|cheat| cheat.enabled)
+            .map(ToString::to_string)
+            .collect();
+        format!("Cheats on: {}", enabled.join(", "))
+    }
+
+    fn update_any_cheats(&self, cheats: &Cheats) {
+        let any = self.cheats_on.load(Ordering::Relaxed) && cheats.iter().any(|c| c.enabled);
+        self.any_cheats.store(any, Ordering::Relaxed);
+    }
+
+    /// Calls `write` with the address and value of every cheat that is on.
+    pub fn apply_cheats(&self, mut write: impl FnMut(u16, u8)) {
+        if !self.any_cheats.load(Ordering::Relaxed) {
+            return;
+        }
+        for cheat in self.cheats.lock().unwrap().iter().filter(|c| c.enabled) {
+            write(cheat.addr, cheat.value);
+        }
+    }
+
+    /// Shows `text` on screen, as well as logging it.
+    pub fn notify(&self, text: String) {
+        info!("{text}");
+        *self.notice.lock().unwrap() = Some(text);
+    }
+
+    /// Asks the core to save its state as JSON to `path` after the current
+    /// instruction.
+    pub fn request_export(&self, path: PathBuf) {
+        *self.export_state.lock().unwrap() = Some(path);
+        self.any_state_requests.store(true, Ordering::Relaxed);
+    }
+
+    /// Asks the core for a snapshot of the machine after the current instruction.
+    pub fn request_state(&self) -> Receiver<Dump> {
+        let (sender, receiver) = smol::channel::bounded(1);
+        self.state_requests.lock().unwrap().push(sender);
+        self.any_state_requests.store(true, Ordering::Relaxed);
+        receiver
+    }
+
+    /// What was asked for since the last call: where to export to, and who is
+    /// waiting for a snapshot.
+    pub fn take_state_requests(&self) -> Option<(Option<PathBuf>, Vec<Sender<Dump>>)> {
+        if !self.any_state_requests.swap(false, Ordering::Relaxed) {
+            return None;
+        }
+        Some((
+            self.export_state.lock().unwrap().take(),
+            std::mem::take(&mut *self.state_requests.lock().unwrap()),
+        ))
+    }
+
+    pub fn reload(&self, rom: Vec<u8>) {
+        *self.reload.lock().unwrap() = Some(rom);
+    }
+
+    /// Saves what was asked for on exit: the last frame, the coverage report and the
+    /// heatmap.
+    pub fn finish(&self, vram: &[bool]) {
+        if let Some(coverage) = &self.coverage {
+            coverage.write();
+        }
+        self.heatmap.write();
+        self.dump_frame(vram);
+    }
+
+    /// Saves `vram` to `dump_frame`, if set, in the current palette.
+    fn dump_frame(&self, vram: &[bool]) {
+        let Some(path) = &self.dump_frame else {
+            return;
         };
+        let colors = color::pixel_colors(vram, &self.palette.lock().unwrap());
+        match screenshot::dump(path, 64, 32, &color::to_rgb24(&colors)) {
+            Ok(()) => info!("Saved the last frame to {}", path.display()),
+            Err(err) => error!("Could not save the last frame: {err}"),
+        }
+    }
+}
+
+/// `tapped` is set by sticky keys so Fx0A can finish without waiting for the key to be
+/// released.
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct Keypad {
+    keys: [bool; 16],
+    tapped: Option<u8>,
+}
+
+impl Keypad {
+    pub fn press(&mut self, key: u8) {
+        self.keys[key as usize] = true;
+    }
+    pub fn release(&mut self, key: u8) {
+        self.keys[key as usize] = false;
+    }
+    pub fn tap(&mut self, key: u8) {
+        self.tapped = Some(key);
+    }
+    pub fn take_tap(&mut self) -> Option<u8> {
+        self.tapped.take()
     }
 
     pub fn is_pressed(&self, key: u8) -> bool {
-        self.0[key as usize]
+        self.keys[key as usize]
     }
     pub fn first_pressed(&self) -> Option<u8> {
-        self.0.iter().position(|x| *x).map(|x| x as u8)
+        self.keys.iter().position(|x| *x).map(|x| x as u8)
     }
 }
 
-struct SquareWave {
-    phase_inc: f32,
-    phase: f32,
-    volume: f32,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-impl AudioCallback for SquareWave {
-    type Channel = f32;
+    fn status() -> Status {
+        Status::new(Arc::new(Mutex::new(0)), Arc::new(Mutex::new(0)))
+    }
 
-    fn callback(&mut self, out: &mut [f32]) {
-        // Generate a square wave
-        for x in out.iter_mut() {
-            *x = if self.phase <= 0.5 {
-                self.volume
-            } else {
-                -self.volume
-            };
-            self.phase = (self.phase + self.phase_inc) % 1.0;
+    #[test]
+    fn script_presses_keys_and_ends_the_run() {
+        let keypad = Arc::new(Mutex::new(Keypad::default()));
+        let mut input = Input::new(keypad.clone(), None);
+        let mut script =
+            Script::parse("frame 1 press 5\nframe 2 expect-pixel 0 0 on\nframe 3 quit\n").unwrap();
+        let mut vram = [false; 64 * 32];
+        assert!(play_script(&mut script, 1, &vram, &mut input).is_continue());
+        input.update();
+        assert!(keypad.lock().unwrap().is_pressed(5));
+        vram[0] = true;
+        assert!(play_script(&mut script, 2, &vram, &mut input).is_continue());
+        assert!(matches!(
+            play_script(&mut script, 3, &vram, &mut input),
+            ControlFlow::Break(Ok(()))
+        ));
+    }
+
+    #[test]
+    fn script_fails_on_an_expectation() {
+        let mut input = Input::new(Arc::new(Mutex::new(Keypad::default())), None);
+        let mut script = Script::parse("frame 2 expect-pixel 1 2 on\n").unwrap();
+        let played = play_script(&mut script, 9, &[false; 64 * 32], &mut input);
+        assert!(matches!(played, ControlFlow::Break(Err(Error::Script(_)))));
+    }
+
+    #[test]
+    fn speed_steps_through_the_presets() {
+        let status = status();
+        let ups: Vec<f32> = (0..4).map(|_| status.change_speed(true)).collect();
+        assert_eq!(ups, [2.0, 4.0, 8.0, 8.0]);
+        let downs: Vec<f32> = (0..7).map(|_| status.change_speed(false)).collect();
+        assert_eq!(downs, [4.0, 2.0, 1.0, 0.5, 0.25, 0.25, 0.25]);
+        // From a speed in between, to the nearest preset either way
+        status.set_speed(3.0);
+        assert_eq!(status.change_speed(true), 4.0);
+        status.set_speed(3.0);
+        assert_eq!(status.change_speed(false), 2.0);
+    }
+
+    #[test]
+    fn frames_get_shorter_with_speed() {
+        let status = status();
+        assert_eq!(status.frame_period().as_micros(), 16_666);
+        status.set_speed(2.0);
+        assert_eq!(status.frame_period().as_micros(), 8_333);
+        status.set_speed(0.25);
+        assert_eq!(status.frame_period().as_micros(), 66_666);
+    }
+
+    #[test]
+    fn starting_speed_must_be_a_hotkey_speed() {
+        assert_eq!(check_speed(0.25), Ok(0.25));
+        assert_eq!(check_speed(3.0), Ok(3.0));
+        assert!(check_speed(0.1).is_err());
+        assert!(check_speed(9.0).is_err());
+    }
+
+    /// The command line beats the ROM's own section, which beats the rest of the
+    /// config, which beats the default.
+    #[test]
+    fn command_line_wins_over_the_config() {
+        let rom = [0x12, 0x00];
+        let settings = |args: &[&str], text: &str| {
+            let options = Options::parse(args.iter().map(|arg| arg.to_string())).unwrap();
+            let mut config = Config::parse(text).unwrap();
+            config.select_rom(&rom, vec![]);
+            Settings::new(&options, &config).unwrap().filter
+        };
+        let section = format!("[rom.\"sha1:{}\"]", crate::hash::sha1_hex(&rom));
+        let both = format!("video.filter = \"none\"\n{section}\nvideo.filter = \"crt\"");
+        assert_eq!(settings(&["--filter", "none"], &both), Filter::None);
+        assert_eq!(settings(&[], &both), Filter::Crt);
+        let global = "video.filter = \"crt\"";
+        assert_eq!(settings(&[], global), Filter::Crt);
+        assert_eq!(settings(&[], ""), Filter::None);
+    }
+
+    /// `--palette` beats `video.palette`, and the single colors from either go on
+    /// top of whichever palette that picks.
+    #[test]
+    fn palette_from_the_command_line_or_config() {
+        let settings = |args: &[&str], text: &str| {
+            let options = Options::parse(args.iter().map(|arg| arg.to_string()))?;
+            Settings::new(&options, &Config::parse(text).unwrap())
+                .map(|settings| settings.palette)
+                .map_err(|err| err.to_string())
+        };
+        let named = |name: &str| name.parse::<Palette>().unwrap();
+        assert_eq!(settings(&[], ""), Ok(Palette::default()));
+        let amber = "video.palette = \"amber\"";
+        assert_eq!(settings(&[], amber), Ok(named("amber")));
+        assert_eq!(settings(&["--palette", "paper"], amber), Ok(named("paper")));
+
+        let mut expected = named("amber");
+        expected.0[1] = Color::RGB(0x33, 0xFF, 0x66);
+        assert_eq!(settings(&["--fg", "#33FF66"], amber), Ok(expected));
+        let fg = "video.palette = \"amber\"\nvideo.fg = \"red\"";
+        expected.0[1] = Color::RGB(0xFF, 0, 0);
+        assert_eq!(settings(&[], fg), Ok(expected));
+        expected.0[1] = Color::WHITE;
+        assert_eq!(settings(&["--fg", "white"], fg), Ok(expected));
+        expected.0[0] = Color::BLACK;
+        assert_eq!(
+            settings(&["--fg", "white", "--bg", "#000"], fg),
+            Ok(expected)
+        );
+
+        assert!(settings(&["--palette", "sepia"], "").is_err());
+        let err = settings(&[], "video.palette = \"sepia\"").unwrap_err();
+        assert!(err.contains("video.palette"), "{err}");
+        let err = settings(&[], "video.bg = \"#12\"").unwrap_err();
+        assert!(err.contains("video.bg"), "{err}");
+    }
+
+    /// `--layout` beats both `layout` and a `[keypad]` section in the config.
+    #[test]
+    fn layout_from_the_command_line_or_config() {
+        let settings = |args: &[&str], text: &str| {
+            let options = Options::parse(args.iter().map(|arg| arg.to_string()))?;
+            Settings::new(&options, &Config::parse(text).unwrap())
+                .map(|settings| {
+                    (
+                        settings.keymaps.current().clone(),
+                        settings.keymaps.describe(),
+                    )
+                })
+                .map_err(|err| err.to_string())
+        };
+        let layout = |layout: Layout| (layout.keymap(), format!("{layout} layout"));
+        assert_eq!(settings(&[], ""), Ok(layout(Layout::Classic)));
+        let right_hand = "layout = \"right-hand\"";
+        assert_eq!(settings(&[], right_hand), Ok(layout(Layout::RightHand)));
+        assert_eq!(
+            settings(&["--layout", "current-default"], right_hand),
+            Ok(layout(Layout::CurrentDefault))
+        );
+        let keypad: String = (0..16)
+            .map(|key| {
+                format!(
+                    "keypad.{key:X} = \"{}\"\n",
+                    Layout::RightHand.keymap().binding(key)
+                )
+            })
+            .collect();
+        assert_eq!(settings(&[], &keypad).unwrap().1, "custom layout");
+        assert_eq!(
+            settings(&["--layout=classic"], &keypad),
+            Ok(layout(Layout::Classic))
+        );
+
+        assert!(settings(&["--layout", "dvorak"], "").is_err());
+        let err = settings(&[], "layout = \"dvorak\"").unwrap_err();
+        assert!(err.contains("layout"), "{err}");
+    }
+
+    #[test]
+    fn filtering_from_the_command_line_or_config() {
+        let settings = |args: &[&str], text: &str| {
+            let options = Options::parse(args.iter().map(|arg| arg.to_string()))?;
+            Settings::new(&options, &Config::parse(text).unwrap())
+                .map(|settings| settings.filtering)
+                .map_err(|err| err.to_string())
+        };
+        assert_eq!(settings(&[], ""), Ok(Filtering::Nearest));
+        let linear = "video.filtering = \"linear\"";
+        assert_eq!(settings(&[], linear), Ok(Filtering::Linear));
+        assert_eq!(
+            settings(&["--filtering", "nearest"], linear),
+            Ok(Filtering::Nearest)
+        );
+        assert!(settings(&["--filtering", "blurry"], "").is_err());
+        let err = settings(&[], "video.filtering = \"blurry\"").unwrap_err();
+        assert!(err.contains("video.filtering"), "{err}");
+
+        // Whole multiples stay crisp whatever was asked for
+        assert_eq!(Filtering::Linear.with(Scaling::Fit), Filtering::Linear);
+        assert_eq!(Filtering::Linear.with(Scaling::Integer), Filtering::Nearest);
+        assert_eq!(Filtering::Nearest.with(Scaling::Fit).hint(), "nearest");
+    }
+
+    #[cfg(feature = "shader")]
+    #[test]
+    fn broken_shaders_fall_back_with_a_notice() {
+        let status = status();
+        let path = Path::new("tests/fixtures/shaders/broken.frag");
+        let err = Err(format!("{} didn't compile", path.display()));
+        assert!(shader_or_fallback(err, path, &status).is_none());
+        assert_eq!(
+            status.notice.lock().unwrap().as_deref(),
+            Some("Shader failed, drawing without it")
+        );
+    }
+
+    /// Borderless and fullscreen are separate settings, so both can be asked for and
+    /// fullscreen goes on top; of the borderless flags the last one counts.
+    #[test]
+    fn borderless_from_the_command_line_or_config() {
+        let settings = |args: &[&str], text: &str| {
+            let options = Options::parse(args.iter().map(|arg| arg.to_string())).unwrap();
+            let settings = Settings::new(&options, &Config::parse(text).unwrap()).unwrap();
+            (settings.borderless, settings.fullscreen)
+        };
+        assert_eq!(settings(&[], ""), (Borderless::Off, false));
+        assert_eq!(settings(&["--borderless"], ""), (Borderless::Window, false));
+        assert_eq!(
+            settings(&["--borderless", "--fullscreen"], ""),
+            (Borderless::Window, true)
+        );
+        assert_eq!(
+            settings(&["--borderless-desktop", "--borderless"], ""),
+            (Borderless::Window, false)
+        );
+        let desktop = "video.borderless = \"desktop\"\nvideo.fullscreen = true";
+        assert_eq!(settings(&[], desktop), (Borderless::Desktop, true));
+        assert_eq!(
+            settings(&["--borderless"], desktop),
+            (Borderless::Window, true)
+        );
+        let options = Options::parse([]).unwrap();
+        let broken = Config::parse("video.borderless = \"sometimes\"").unwrap();
+        assert!(Settings::new(&options, &broken).is_err());
+    }
+
+    #[test]
+    fn audio_can_be_turned_off() {
+        let settings = |args: &[&str], text: &str| {
+            let options = Options::parse(args.iter().map(|arg| arg.to_string())).unwrap();
+            let settings = Settings::new(&options, &Config::parse(text).unwrap()).unwrap();
+            (settings.audio, settings.visual_bell)
+        };
+        assert_eq!(settings(&[], ""), (true, None));
+        assert_eq!(settings(&["--no-audio"], ""), (false, None));
+        assert_eq!(settings(&[], "audio.enabled = false"), (false, None));
+        let quiet = "audio.enabled = false\nvideo.visual-bell = false";
+        assert_eq!(settings(&[], quiet), (false, Some(false)));
+        assert_eq!(settings(&["--visual-bell"], quiet), (false, Some(true)));
+    }
+
+    /// Without a device the sound timer still runs, and the bell shows it instead.
+    #[test]
+    fn sound_timer_runs_without_audio() {
+        let status = status();
+        let mut bell = bell::wanted(None, false).then(bell::VisualBell::default);
+        let start = Instant::now();
+        let mut lit = vec![];
+        for (tick, timer) in [2, 1, 0].into_iter().enumerate() {
+            status.sound_timer_ticked(timer, false);
+            let sounding = status.sounding.load(Ordering::Relaxed);
+            let now = start + bell::MIN_PHASE * tick as u32;
+            lit.push(bell.as_mut().is_some_and(|bell| bell.update(sounding, now)));
+        }
+        assert_eq!(lit, [true, true, false]);
+        // Paused, it goes quiet with time left on the timer
+        status.sound_timer_ticked(5, true);
+        assert!(!status.sounding.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn errors_say_what_failed() {
+        let cases = [
+            (
+                Error::Sdl("no driver".into()),
+                "Could not start SDL: no driver",
+            ),
+            (
+                Error::Window("too big".into()),
+                "Could not open the window: too big",
+            ),
+            (
+                Error::render("device lost"),
+                "Could not draw the screen: device lost",
+            ),
+            (
+                Error::Setting("Unknown audio device \"x\"".into()),
+                "Unknown audio device \"x\"",
+            ),
+            (
+                Error::Script("Script failed at frame 9: expected pixel 1,2 to be on".into()),
+                "Script failed at frame 9: expected pixel 1,2 to be on",
+            ),
+        ];
+        for (err, message) in cases {
+            assert_eq!(err.to_string(), message);
         }
     }
+
+    #[test]
+    fn textures_are_rebuilt_after_a_renderer_reset() {
+        assert!(textures_lost(&Event::RenderTargetsReset { timestamp: 0 }));
+        assert!(textures_lost(&Event::RenderDeviceReset { timestamp: 0 }));
+        let resized = Event::Window {
+            timestamp: 0,
+            window_id: 1,
+            win_event: WindowEvent::SizeChanged(640, 320),
+        };
+        assert!(!textures_lost(&resized));
+        assert!(!textures_lost(&Event::Quit { timestamp: 0 }));
+    }
+
+    /// Ctrl+C stops the frames without a window too, which lets main clean up.
+    #[test]
+    fn windowless_stops_when_interrupted() {
+        let status = Arc::new(status());
+        let options = Options::parse([]).unwrap();
+        let settings = Settings::new(&options, &Config::default()).unwrap();
+        let vram = Arc::new(Mutex::new([false; 64 * 32]));
+        let keypad = Arc::new(Mutex::new(Keypad::default()));
+        let interrupt = {
+            let status = status.clone();
+            async move {
+                Timer::after(Duration::from_millis(50)).await;
+                status.interrupted.store(2, Ordering::Relaxed);
+                futures::future::pending::<()>().await
+            }
+        };
+        let result = smol::block_on(futures::future::select(
+            Box::pin(windowless(vram, keypad, status.clone(), settings)),
+            Box::pin(interrupt),
+        ));
+        assert!(matches!(result, futures::future::Either::Left((Ok(()), _))));
+        assert!(status.frames.load(Ordering::Relaxed) >= 1);
+        assert!(!status.bound_reached.load(Ordering::Relaxed));
+    }
+
+    /// Scripts still play without a window, and end the run when they quit.
+    #[test]
+    fn windowless_plays_scripts() {
+        let status = Arc::new(status());
+        let options = Options::parse([]).unwrap();
+        let mut settings = Settings::new(&options, &Config::default()).unwrap();
+        settings.script = Some(Script::parse("frame 1 press A\nframe 3 quit\n").unwrap());
+        let vram = Arc::new(Mutex::new([false; 64 * 32]));
+        let keypad = Arc::new(Mutex::new(Keypad::default()));
+        let result = smol::block_on(windowless(vram, keypad.clone(), status.clone(), settings));
+        assert!(result.is_ok());
+        assert!(keypad.lock().unwrap().is_pressed(0xA));
+        assert_eq!(status.frames.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn windowless_fails_with_the_script() {
+        let status = Arc::new(status());
+        let options = Options::parse([]).unwrap();
+        let mut settings = Settings::new(&options, &Config::default()).unwrap();
+        settings.script = Some(Script::parse("frame 2 expect-pixel 0 0 on\n").unwrap());
+        let vram = Arc::new(Mutex::new([false; 64 * 32]));
+        let keypad = Arc::new(Mutex::new(Keypad::default()));
+        let result = smol::block_on(windowless(vram, keypad, status, settings));
+        assert!(matches!(result, Err(Error::Script(_))));
+    }
 }
@@ -0,0 +1,282 @@
+use super::font;
+use super::viewport::Viewport;
+use crate::heatmap;
+use crate::heatmap::Heatmap;
+use sdl2::pixels::Color;
+use sdl2::rect::Point;
+use sdl2::rect::Rect;
+use sdl2::render::BlendMode;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use std::time::Duration;
+
+/// How long a region stays lit once nothing happens there anymore.
+const DECAY: Duration = Duration::from_millis(1500);
+/// Side of the square screen regions, in CHIP-8 pixels.
+const REGION: usize = 4;
+const REGIONS_WIDE: usize = 64 / REGION;
+/// ROM bytes per cell in the panel.
+const BUCKET: usize = 16;
+/// Cells per row in the panel, so each row covers 0x100 bytes.
+const PANEL_COLUMNS: usize = 16;
+/// Most of the game stays visible through the tint.
+const TINT_ALPHA: f32 = 160.0;
+
+/// The live heatmap: which parts of the screen changed lately, and which parts of
+/// the ROM are running now. Both fade out over [`DECAY`] once nothing happens there.
+#[derive(Clone, Debug)]
+pub struct Activity {
+    last_vram: Vec<bool>,
+    /// Heat of each screen region, from 0 to 1
+    screen: Vec<f32>,
+    last_counts: Vec<u64>,
+    counts: Vec<u64>,
+    /// Heat of each [`BUCKET`] of the ROM, from 0 to 1
+    rom: Vec<f32>,
+}
+
+impl Activity {
+    /// Starts out cold, with `vram` as the last frame.
+    pub fn new(vram: &[bool], heatmap: &Heatmap) -> Activity {
+        let mut activity = Activity {
+            last_vram: vram.to_vec(),
+            screen: vec![0.0; 64 * 32 / (REGION * REGION)],
+            last_counts: Vec::new(),
+            counts: Vec::new(),
+            rom: Vec::new(),
+        };
+        activity.restart(heatmap);
+        activity
+    }
+
+    /// Takes what has run so far as the baseline, and cools the ROM down.
+    fn restart(&mut self, heatmap: &Heatmap) {
+        heatmap.sample(&mut self.last_counts);
+        self.rom = vec![0.0; self.last_counts.len().div_ceil(BUCKET)];
+    }
+
+    /// Takes in a frame that came `elapsed` after the last one.
+    pub fn update(&mut self, vram: &[bool], heatmap: &Heatmap, elapsed: Duration) {
+        let fade = elapsed.as_secs_f32() / DECAY.as_secs_f32();
+        let mut changed = vec![false; self.screen.len()];
+        for (idx, (now, before)) in vram.iter().zip(&self.last_vram).enumerate() {
+            if now != before {
+                changed[idx / 64 / REGION * REGIONS_WIDE + idx % 64 / REGION] = true;
+            }
+        }
+        self.last_vram.copy_from_slice(vram);
+        for (heat, changed) in self.screen.iter_mut().zip(changed) {
+            *heat = if changed {
+                1.0
+            } else {
+                (*heat - fade).max(0.0)
+            };
+        }
+
+        heatmap.sample(&mut self.counts);
+        if self.counts.len() != self.last_counts.len() {
+            // A new ROM, whose counts started over
+            self.restart(heatmap);
+            return;
+        }
+        let runs: Vec<u64> = self
+            .counts
+            .chunks(BUCKET)
+            .zip(self.last_counts.chunks(BUCKET))
+            .map(|(now, before)| {
+                // Reloading a ROM of the same size starts the counts over too
+                now.iter().sum::<u64>().saturating_sub(before.iter().sum())
+            })
+            .collect();
+        std::mem::swap(&mut self.counts, &mut self.last_counts);
+        let max = runs.iter().copied().max().unwrap_or(0);
+        for (heat, runs) in self.rom.iter_mut().zip(runs) {
+            // On a log scale like the exported image, relative to the busiest bucket
+            let now = if runs == 0 {
+                0.0
+            } else {
+                ((runs as f32).ln_1p() / (max as f32).ln_1p()).max(0.1)
+            };
+            *heat = now.max(*heat - fade);
+        }
+    }
+
+    /// Address of the ROM bucket that ran the most lately.
+    fn hottest(&self) -> Option<u16> {
+        let (idx, heat) = self
+            .rom
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+        (*heat > 0.0).then(|| 0x200 + (idx * BUCKET) as u16)
+    }
+
+    /// Tints the regions of the game in `area` that changed lately.
+    pub fn draw_screen(
+        &self,
+        canvas: &mut Canvas<Window>,
+        viewport: &Viewport,
+        area: Rect,
+    ) -> Result<(), String> {
+        canvas.set_blend_mode(BlendMode::Blend);
+        for (idx, heat) in self.screen.iter().enumerate() {
+            if *heat <= 0.0 {
+                continue;
+            }
+            let [r, g, b] = heatmap::ramp(f64::from(*heat));
+            canvas.set_draw_color(Color::RGBA(r, g, b, (heat * TINT_ALPHA) as u8));
+            let region = Rect::new(
+                area.x() + (idx % REGIONS_WIDE * REGION) as i32,
+                area.y() + (idx / REGIONS_WIDE * REGION) as i32,
+                REGION as u32,
+                REGION as u32,
+            );
+            canvas.fill_rect(viewport.rect(region))?;
+        }
+        canvas.set_blend_mode(BlendMode::None);
+        Ok(())
+    }
+
+    /// Size of the ROM panel drawn at `scale`.
+    pub fn panel_size(&self, scale: u32) -> (u32, u32) {
+        let cell = 4 * scale;
+        let rows = self.rom.len().div_ceil(PANEL_COLUMNS) as u32;
+        let (width, label) = font::text_size("HOT 000", scale);
+        (
+            width.max(PANEL_COLUMNS as u32 * cell),
+            label + 2 * scale + rows * cell,
+        )
+    }
+
+    /// Draws the ROM as a grid of cells colored by how hot they are, with the
+    /// hottest address above it.
+    pub fn draw_panel(
+        &self,
+        canvas: &mut Canvas<Window>,
+        origin: Point,
+        scale: u32,
+    ) -> Result<(), String> {
+        let label = match self.hottest() {
+            Some(addr) => format!("HOT {addr:03X}"),
+            None => "HOT -".into(),
+        };
+        font::draw_text(canvas, &label, origin, scale, Color::WHITE)?;
+        let cell = 4 * scale;
+        let top = origin.y() + (font::GLYPH_SIZE * scale + 2 * scale) as i32;
+        for (idx, heat) in self.rom.iter().enumerate() {
+            let [r, g, b] = if *heat > 0.0 {
+                heatmap::ramp(f64::from(*heat))
+            } else {
+                [0x20, 0x20, 0x20]
+            };
+            canvas.set_draw_color(Color::RGB(r, g, b));
+            let x = origin.x() + (idx % PANEL_COLUMNS) as i32 * cell as i32;
+            let y = top + (idx / PANEL_COLUMNS) as i32 * cell as i32;
+            // A gap of a font pixel between cells
+            let size = cell - scale;
+            canvas.fill_rect(Rect::new(x, y, size, size))?;
+        }
+        canvas.set_draw_color(Color::BLACK);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(16);
+
+    fn counting(rom_len: usize) -> Heatmap {
+        let heatmap = Heatmap::new(None);
+        heatmap.count(true);
+        heatmap.reset(&vec![0; rom_len]);
+        heatmap
+    }
+
+    fn hit(heatmap: &Heatmap, addr: u16, times: usize) {
+        for _ in 0..times {
+            heatmap.hit(addr);
+        }
+    }
+
+    #[test]
+    fn rom_buckets_heat_up_relative_to_the_busiest() {
+        let heatmap = counting(64);
+        let vram = [false; 64 * 32];
+        let mut activity = Activity::new(&vram, &heatmap);
+        assert_eq!(activity.rom.len(), 4);
+        assert_eq!(activity.hottest(), None);
+
+        hit(&heatmap, 0x200, 60);
+        hit(&heatmap, 0x20E, 40);
+        hit(&heatmap, 0x210, 1);
+        activity.update(&vram, &heatmap, FRAME);
+        assert_eq!(activity.rom[0], 1.0);
+        // A single run still shows
+        assert!((activity.rom[1] - 2f32.ln() / 101f32.ln()).abs() < 1e-6);
+        assert_eq!(activity.rom[2..], [0.0, 0.0]);
+        assert_eq!(activity.hottest(), Some(0x200));
+
+        // Only what ran since the last frame counts
+        hit(&heatmap, 0x230, 5);
+        activity.update(&vram, &heatmap, FRAME);
+        assert_eq!(activity.rom[3], 1.0);
+        assert!(activity.rom[0] < 1.0 && activity.rom[0] > 0.9);
+    }
+
+    #[test]
+    fn heat_fades_out_over_the_decay() {
+        let heatmap = counting(32);
+        let mut vram = [false; 64 * 32];
+        let mut activity = Activity::new(&vram, &heatmap);
+        hit(&heatmap, 0x200, 10);
+        // Pixel (5, 9) is in region (1, 2)
+        vram[9 * 64 + 5] = true;
+        activity.update(&vram, &heatmap, FRAME);
+        assert_eq!(activity.screen[2 * REGIONS_WIDE + 1], 1.0);
+        assert_eq!(
+            activity.screen.iter().filter(|heat| **heat > 0.0).count(),
+            1
+        );
+
+        activity.update(&vram, &heatmap, DECAY / 2);
+        assert!((activity.screen[2 * REGIONS_WIDE + 1] - 0.5).abs() < 1e-6);
+        assert!((activity.rom[0] - 0.5).abs() < 1e-6);
+        activity.update(&vram, &heatmap, DECAY / 2);
+        assert_eq!(activity.screen[2 * REGIONS_WIDE + 1], 0.0);
+        assert_eq!(activity.rom[0], 0.0);
+        assert_eq!(activity.hottest(), None);
+    }
+
+    #[test]
+    fn new_roms_start_cold() {
+        let heatmap = counting(32);
+        let vram = [false; 64 * 32];
+        let mut activity = Activity::new(&vram, &heatmap);
+        hit(&heatmap, 0x200, 10);
+        activity.update(&vram, &heatmap, FRAME);
+
+        heatmap.reset(&[0; 128]);
+        hit(&heatmap, 0x200, 10);
+        activity.update(&vram, &heatmap, FRAME);
+        assert_eq!(activity.rom, [0.0; 8]);
+        hit(&heatmap, 0x270, 3);
+        activity.update(&vram, &heatmap, FRAME);
+        assert_eq!(activity.hottest(), Some(0x270));
+
+        // One of the same size, whose counts went back to 0
+        heatmap.reset(&[0; 128]);
+        activity.update(&vram, &heatmap, FRAME);
+        assert!(activity.rom[7] < 1.0);
+    }
+
+    #[test]
+    fn panel_fits_every_bucket() {
+        let heatmap = counting(0x300);
+        let activity = Activity::new(&[false; 64 * 32], &heatmap);
+        // 48 buckets in 3 rows of 4x4 cells, under the label
+        let (_, label) = font::text_size("HOT 000", 2);
+        assert_eq!(activity.panel_size(2), (16 * 8, label + 4 + 3 * 8));
+    }
+}
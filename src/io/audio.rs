@@ -0,0 +1,638 @@
+use super::Status;
+use crate::config;
+use crate::config::Config;
+use core::f32::consts::TAU;
+use core::str::FromStr;
+use sdl2::audio::AudioCallback;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+pub const MAX_VOLUME: u8 = 100;
+/// XO-CHIP pitch at which patterns play at 4000 bits per second
+pub const DEFAULT_PITCH: u8 = 64;
+
+/// Bits per second an XO-CHIP pattern plays at.
+pub fn pattern_rate(pitch: u8) -> f32 {
+    4000.0 * 2f32.powf((f32::from(pitch) - 64.0) / 48.0)
+}
+
+/// The level of an XO-CHIP pattern at `phase` bits in, first bit being the high bit
+/// of the first byte.
+pub fn pattern_sample(pattern: &[u8; 16], phase: f32) -> f32 {
+    let bit = phase as usize % 128;
+    if pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 {
+        1.0
+    } else {
+        -1.0
+    }
+}
+
+/// Checks a buzzer frequency, in Hz.
+pub fn check_hz(hz: f32) -> Result<f32, String> {
+    if hz > 0.0 && hz <= 20_000.0 {
+        Ok(hz)
+    } else {
+        Err(format!("{hz} Hz is not between 0 and 20000"))
+    }
+}
+
+/// Checks the fraction of each period the wave is high.
+pub fn check_duty(duty: f32) -> Result<f32, String> {
+    if duty > 0.0 && duty < 1.0 {
+        Ok(duty)
+    } else {
+        Err(format!("A duty cycle of {duty} is not between 0 and 1"))
+    }
+}
+
+/// Parses a low-pass cutoff in Hz, or `off`.
+pub fn parse_lowpass(s: &str) -> Result<Option<f32>, String> {
+    match s {
+        "off" | "0" => Ok(None),
+        _ => {
+            let hz = s
+                .parse()
+                .map_err(|_| format!("Expected a cutoff in Hz or off, got {s:?}"))?;
+            check_hz(hz).map(Some)
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Waveform {
+    #[default]
+    Square,
+    Sine,
+    Triangle,
+    Sawtooth,
+    /// A new random level every half period
+    Noise,
+}
+
+impl Waveform {
+    /// The level at `phase` (0-1) of a period, from -1 to 1.
+    fn sample(self, phase: f32, duty: f32, noise: f32) -> f32 {
+        match self {
+            Waveform::Square if phase < duty => 1.0,
+            Waveform::Square => -1.0,
+            Waveform::Sine => (phase * TAU).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+            Waveform::Noise => noise,
+        }
+    }
+}
+
+impl FromStr for Waveform {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "square" => Ok(Waveform::Square),
+            "sine" => Ok(Waveform::Sine),
+            "triangle" => Ok(Waveform::Triangle),
+            "sawtooth" => Ok(Waveform::Sawtooth),
+            "noise" => Ok(Waveform::Noise),
+            _ => Err(format!(
+                "Unknown waveform {s:?}, expected square, sine, triangle, sawtooth or noise"
+            )),
+        }
+    }
+}
+
+/// Flips the mute, returning whether it's now muted. The device keeps running and
+/// the sound timer counts down as usual, so games can't tell.
+pub fn toggle_mute(status: &Status) -> bool {
+    !status.muted.fetch_xor(true, Ordering::Relaxed)
+}
+
+/// Keeps the mute as it is now for the next run.
+pub fn save_mute(config: &mut Config, muted: bool) -> Result<(), config::Error> {
+    config.set("audio.mute", &muted.to_string());
+    config.save()
+}
+
+/// How long the buzzer takes to fade in and out
+const RAMP: f32 = 0.004;
+/// Beeps sound for at least about a frame, even if the timer was only set to 1
+const MIN_BEEP: f32 = 0.016;
+
+/// Fades the buzzer in and out instead of starting and stopping the device, which pops.
+#[derive(Clone, Debug)]
+pub struct Envelope {
+    level: f32,
+    step: f32,
+    /// Samples left before a triggered beep may be released
+    hold: u32,
+    min_hold: u32,
+}
+
+impl Envelope {
+    pub fn new(sample_rate: i32) -> Envelope {
+        let sample_rate = sample_rate.max(1) as f32;
+        Envelope {
+            level: 0.0,
+            step: 1.0 / (RAMP * sample_rate),
+            hold: 0,
+            min_hold: (MIN_BEEP * sample_rate) as u32,
+        }
+    }
+
+    /// Starts a beep that lasts at least [`MIN_BEEP`], however short the timer.
+    pub fn trigger(&mut self) {
+        self.hold = self.min_hold;
+    }
+
+    /// The gain for the next sample.
+    pub fn next(&mut self, on: bool) -> f32 {
+        let target = if on || self.hold > 0 { 1.0 } else { 0.0 };
+        self.hold = self.hold.saturating_sub(1);
+        self.level = if self.level < target {
+            (self.level + self.step).min(target)
+        } else {
+            (self.level - self.step).max(target)
+        };
+        self.level
+    }
+
+    pub fn silent(&self) -> bool {
+        self.level == 0.0 && self.hold == 0
+    }
+}
+
+/// One-pole low-pass filter, which takes the edge off the square wave and its aliasing.
+#[derive(Clone, Debug)]
+pub struct LowPass {
+    alpha: f32,
+    state: f32,
+}
+
+impl LowPass {
+    pub fn new(cutoff: f32, sample_rate: i32) -> LowPass {
+        LowPass {
+            alpha: 1.0 - (-TAU * cutoff / sample_rate.max(1) as f32).exp(),
+            state: 0.0,
+        }
+    }
+
+    pub fn filter(&mut self, x: f32) -> f32 {
+        self.state += self.alpha * (x - self.state);
+        self.state
+    }
+
+    pub fn reset(&mut self) {
+        self.state = 0.0;
+    }
+}
+
+pub struct Buzzer {
+    waveform: Waveform,
+    phase_inc: f32,
+    phase: f32,
+    duty: f32,
+    /// Reads the volume (0-100) and whether to sound on every callback
+    status: Arc<Status>,
+    envelope: Envelope,
+    lowpass: Option<LowPass>,
+    sample_rate: f32,
+    /// Position in the XO-CHIP pattern, in bits
+    pattern_phase: f32,
+    /// 16-bit Fibonacci LFSR, so the noise is the same on every run
+    lfsr: u16,
+    noise: f32,
+    /// Looped instead of the waveform when set
+    sample: Option<Vec<f32>>,
+    position: usize,
+}
+
+impl Buzzer {
+    pub fn new(
+        waveform: Waveform,
+        hz: f32,
+        sample_rate: i32,
+        duty: f32,
+        status: Arc<Status>,
+        sample: Option<Vec<f32>>,
+        lowpass: Option<f32>,
+    ) -> Buzzer {
+        Buzzer {
+            waveform,
+            phase_inc: hz / sample_rate as f32,
+            phase: 0.0,
+            duty,
+            status,
+            envelope: Envelope::new(sample_rate),
+            lowpass: lowpass.map(|cutoff| LowPass::new(cutoff, sample_rate)),
+            sample_rate: sample_rate as f32,
+            pattern_phase: 0.0,
+            lfsr: 0xACE1,
+            noise: 1.0,
+            sample: sample.filter(|sample| !sample.is_empty()),
+            position: 0,
+        }
+    }
+
+    /// The unfiltered next sample of the clip or waveform.
+    fn next_sample(&mut self) -> f32 {
+        if let Some(sample) = &self.sample {
+            let x = sample[self.position];
+            self.position = (self.position + 1) % sample.len();
+            return x;
+        }
+        let x = self.waveform.sample(self.phase, self.duty, self.noise);
+        let next = self.phase + self.phase_inc;
+        // The phase carries on across callbacks, so the wave never jumps. Noise
+        // changes level every half period
+        if (self.phase < 0.5) != (next % 1.0 < 0.5) {
+            self.step_noise();
+        }
+        self.phase = next % 1.0;
+        x
+    }
+
+    fn step_noise(&mut self) {
+        let bit = (self.lfsr ^ (self.lfsr >> 2) ^ (self.lfsr >> 3) ^ (self.lfsr >> 5)) & 1;
+        self.lfsr = (self.lfsr >> 1) | (bit << 15);
+        self.noise = if self.lfsr & 1 != 0 { 1.0 } else { -1.0 };
+    }
+}
+
+impl AudioCallback for Buzzer {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        // Muting keeps the device running, so the timer and unmuting behave as usual
+        let volume = if self.status.muted.load(Ordering::Relaxed) {
+            0.0
+        } else {
+            f32::from(self.status.volume.load(Ordering::Relaxed)) / 100.0
+        };
+        let on = self.status.sounding.load(Ordering::Relaxed);
+        if self.status.beep.swap(false, Ordering::Relaxed) {
+            if self.envelope.silent() {
+                self.position = 0;
+                // Start from rest, like the filter would have if it had kept running
+                if let Some(lowpass) = &mut self.lowpass {
+                    lowpass.reset();
+                }
+            }
+            self.envelope.trigger();
+        }
+        let pattern = *self.status.pattern.lock().unwrap();
+        // Read once per callback, so pitch changes take effect smoothly at the current
+        // position in the pattern
+        let pattern_inc =
+            pattern_rate(self.status.pitch.load(Ordering::Relaxed)) / self.sample_rate;
+        for x in out.iter_mut() {
+            let gain = volume * self.envelope.next(on);
+            let wave = match &pattern {
+                Some(pattern) => {
+                    let x = pattern_sample(pattern, self.pattern_phase);
+                    self.pattern_phase = (self.pattern_phase + pattern_inc) % 128.0;
+                    x
+                }
+                None => self.next_sample(),
+            };
+            let wave = match &mut self.lowpass {
+                Some(lowpass) => lowpass.filter(wave),
+                None => wave,
+            };
+            *x = gain * wave;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    const RATE: i32 = 44100;
+
+    fn status() -> Arc<Status> {
+        let status = Status::new(Arc::new(Mutex::new(0)), Arc::new(Mutex::new(0)));
+        status.volume.store(MAX_VOLUME, Ordering::Relaxed);
+        status.sounding.store(true, Ordering::Relaxed);
+        Arc::new(status)
+    }
+
+    fn buzzer(waveform: Waveform, hz: f32, lowpass: Option<f32>) -> Buzzer {
+        Buzzer::new(waveform, hz, RATE, 0.5, status(), None, lowpass)
+    }
+
+    /// `len` samples, after the fade in.
+    fn play(buzzer: &mut Buzzer, len: usize) -> Vec<f32> {
+        let fade = (RAMP * RATE as f32) as usize + 1;
+        let mut out = vec![0.0; fade + len];
+        buzzer.callback(&mut out);
+        out.split_off(fade)
+    }
+
+    /// Where the wave goes from low to high.
+    fn rising_edges(samples: &[f32]) -> Vec<usize> {
+        samples
+            .windows(2)
+            .enumerate()
+            .filter(|(_, pair)| pair[0] < 0.0 && pair[1] >= 0.0)
+            .map(|(idx, _)| idx + 1)
+            .collect()
+    }
+
+    #[test]
+    fn checks_parameters() {
+        assert_eq!(check_hz(880.0), Ok(880.0));
+        assert!(check_hz(0.0).is_err());
+        assert!(check_hz(-1.0).is_err());
+        assert!(check_hz(20_001.0).is_err());
+        assert!(check_hz(f32::NAN).is_err());
+        assert_eq!(check_duty(0.25), Ok(0.25));
+        assert!(check_duty(0.0).is_err());
+        assert!(check_duty(1.0).is_err());
+    }
+
+    #[test]
+    fn square_wave_has_the_right_period() {
+        // 441Hz at 44.1kHz is a period of exactly 100 samples
+        let samples = play(&mut buzzer(Waveform::Square, 441.0, None), 2000);
+        let edges = rising_edges(&samples);
+        assert_eq!(edges.len(), 20);
+        for pair in edges.windows(2) {
+            assert!((99..=101).contains(&(pair[1] - pair[0])), "{edges:?}");
+        }
+        let average = (edges[19] - edges[0]) as f32 / 19.0;
+        assert!((average - 100.0).abs() < 0.1, "{average}");
+        // Half of each period high
+        let high = samples[edges[0]..edges[10]].iter().filter(|x| **x > 0.0);
+        assert!((495..=505).contains(&high.count()));
+    }
+
+    /// One period of `waveform`, 1000 samples long.
+    fn period(waveform: Waveform) -> Vec<f32> {
+        (0..1000)
+            .map(|idx| waveform.sample(idx as f32 / 1000.0, 0.5, 1.0))
+            .collect()
+    }
+
+    fn mean(samples: &[f32]) -> f32 {
+        samples.iter().sum::<f32>() / samples.len() as f32
+    }
+
+    fn sign_changes(samples: &[f32]) -> usize {
+        samples
+            .windows(2)
+            .filter(|pair| (pair[0] < 0.0) != (pair[1] < 0.0))
+            .count()
+    }
+
+    #[test]
+    fn waveforms_over_one_period() {
+        for waveform in [
+            Waveform::Square,
+            Waveform::Sine,
+            Waveform::Triangle,
+            Waveform::Sawtooth,
+        ] {
+            let samples = period(waveform);
+            let peak = samples.iter().fold(0f32, |peak, x| peak.max(x.abs()));
+            assert!((peak - 1.0).abs() < 0.01, "{waveform:?} peaks at {peak}");
+            assert!(mean(&samples).abs() < 0.01, "{waveform:?}");
+            // Counting the step from the end of the period back to its start
+            let wrapped = [&samples[..], &samples[..1]].concat();
+            assert_eq!(sign_changes(&wrapped), 2, "{waveform:?}");
+        }
+    }
+
+    #[test]
+    fn noise_is_the_same_every_time() {
+        let first = play(&mut buzzer(Waveform::Noise, 441.0, None), 44100);
+        let second = play(&mut buzzer(Waveform::Noise, 441.0, None), 44100);
+        assert_eq!(first, second);
+        assert!(first.iter().all(|x| x.abs() == 1.0));
+        assert!(mean(&first).abs() < 0.1);
+        // A new level at most every half period
+        assert!(sign_changes(&first) <= 882);
+        assert!(sign_changes(&first) > 100);
+    }
+
+    #[test]
+    fn phase_carries_across_callbacks() {
+        for waveform in [Waveform::Sine, Waveform::Triangle, Waveform::Noise] {
+            let whole = play(&mut buzzer(waveform, 300.0, None), 1000);
+            let mut split = buzzer(waveform, 300.0, None);
+            let mut pieces = play(&mut split, 333);
+            for len in [1, 250, 416] {
+                let mut piece = vec![0.0; len];
+                split.callback(&mut piece);
+                pieces.extend(piece);
+            }
+            assert_eq!(whole, pieces, "{waveform:?}");
+        }
+    }
+
+    #[test]
+    fn lowpass_step_settles() {
+        let mut lowpass = LowPass::new(1000.0, RATE);
+        let step = (0..RATE / 100)
+            .map(|_| lowpass.filter(1.0))
+            .collect::<Vec<_>>();
+        assert!(step.windows(2).all(|pair| pair[0] <= pair[1]));
+        // 1 - 1/e of the way after one time constant, and there after five
+        let tau = RATE as f32 / (TAU * 1000.0);
+        let at_tau = step[tau.round() as usize - 1];
+        assert!((at_tau - (1.0 - (-1f32).exp())).abs() < 0.02, "{at_tau}");
+        assert!(step[(5.0 * tau) as usize] > 0.99);
+        assert!((step.last().unwrap() - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn lowpass_impulse_decays() {
+        let mut lowpass = LowPass::new(2000.0, RATE);
+        let first = lowpass.filter(1.0);
+        assert!((first - lowpass.alpha).abs() < 1e-6);
+        let decay = 1.0 - lowpass.alpha;
+        let mut expected = first;
+        for _ in 0..100 {
+            expected *= decay;
+            assert!((lowpass.filter(0.0) - expected).abs() < 1e-6);
+        }
+        assert!(lowpass.filter(0.0) < 1e-3);
+    }
+
+    #[test]
+    fn lowpass_resets_to_rest() {
+        let mut lowpass = LowPass::new(500.0, RATE);
+        for _ in 0..100 {
+            lowpass.filter(1.0);
+        }
+        lowpass.reset();
+        assert_eq!(lowpass.filter(0.0), 0.0);
+    }
+
+    #[test]
+    fn filtered_square_wave_is_softer() {
+        let raw = play(&mut buzzer(Waveform::Square, 441.0, None), 1000);
+        let filtered = play(&mut buzzer(Waveform::Square, 441.0, Some(1000.0)), 1000);
+        let largest_step = |samples: &[f32]| {
+            samples
+                .windows(2)
+                .fold(0f32, |step, pair| step.max((pair[1] - pair[0]).abs()))
+        };
+        assert_eq!(largest_step(&raw), 2.0);
+        assert!(largest_step(&filtered) < 0.5);
+        // The same wave underneath, just without the edges
+        assert_eq!(rising_edges(&raw).len(), 10);
+        assert!(filtered.iter().any(|x| *x > 0.9));
+    }
+
+    #[test]
+    fn parses_lowpass() {
+        assert_eq!(parse_lowpass("off"), Ok(None));
+        assert_eq!(parse_lowpass("0"), Ok(None));
+        assert_eq!(parse_lowpass("4000"), Ok(Some(4000.0)));
+        assert!(parse_lowpass("-5").is_err());
+        assert!(parse_lowpass("soft").is_err());
+    }
+
+    #[test]
+    fn parses_waveforms() {
+        assert_eq!("triangle".parse(), Ok(Waveform::Triangle));
+        assert!("pulse".parse::<Waveform>().is_err());
+    }
+
+    /// The envelope as heard: the size of each sample of a full-volume square wave.
+    fn levels(buzzer: &mut Buzzer, len: usize) -> Vec<f32> {
+        let mut out = vec![0.0; len];
+        buzzer.callback(&mut out);
+        out.iter().map(|x| x.abs()).collect()
+    }
+
+    #[test]
+    fn fades_in_and_out_over_the_ramp() {
+        let ramp = (RAMP * RATE as f32).ceil() as usize;
+        let mut buzzer = buzzer(Waveform::Square, 441.0, None);
+        let attack = levels(&mut buzzer, 1000);
+        // Rising evenly from the first sample, then held
+        assert!((attack[0] - 1.0 / (RAMP * RATE as f32)).abs() < 1e-6);
+        assert!(attack.windows(2).all(|pair| pair[1] >= pair[0]));
+        assert!(attack[ramp - 2] < 1.0);
+        assert!(attack[ramp - 1..].iter().all(|x| *x == 1.0));
+
+        buzzer.status.sounding.store(false, Ordering::Relaxed);
+        let release = levels(&mut buzzer, 1000);
+        assert!(release[0] < 1.0 && release[0] > 0.99);
+        assert!(release.windows(2).all(|pair| pair[1] <= pair[0]));
+        assert!(release[ramp - 2] > 0.0);
+        assert!(release[ramp - 1..].iter().all(|x| *x == 0.0));
+        assert!(buzzer.envelope.silent());
+    }
+
+    /// A beep too short to hear, like the timer set to 1, is held for a frame.
+    #[test]
+    fn short_beeps_last_a_frame() {
+        let mut buzzer = buzzer(Waveform::Square, 441.0, None);
+        buzzer.status.sounding.store(false, Ordering::Relaxed);
+        assert!(levels(&mut buzzer, 100).iter().all(|x| *x == 0.0));
+        buzzer.status.beep.store(true, Ordering::Relaxed);
+        let beep = levels(&mut buzzer, 2000);
+        let hold = (MIN_BEEP * RATE as f32) as usize;
+        let heard = beep.iter().filter(|x| **x > 0.0).count();
+        let ramp = (RAMP * RATE as f32).ceil() as usize;
+        assert!((hold..=hold + ramp).contains(&heard), "{heard}");
+        assert_eq!(beep[hold - 1], 1.0);
+        assert_eq!(*beep.last().unwrap(), 0.0);
+    }
+
+    /// An XO-CHIP ROM loads a pattern with F002 and its pitch with FX3A, and the
+    /// buzzer plays it bit by bit at the rate for that pitch.
+    #[test]
+    fn plays_the_pattern_the_rom_loads() {
+        #[rustfmt::skip]
+        let mut rom = vec![
+            0xA2, 0x0E, // LD I, pattern
+            0xF0, 0x02, // LD AUDIO, [I]
+            0x60, 0x70, // LD V0, 112
+            0xF0, 0x3A, // LD PITCH, V0
+            0x60, 0x10, // LD V0, 16
+            0xF0, 0x18, // LD ST, V0
+            0x12, 0x0C, // JP 0x20C
+        ];
+        // Four bits on, four off, the whole way through
+        rom.extend([0xF0; 16]);
+        let mut state = crate::State::headless(rom, crate::quirks::Quirks::default());
+        for _ in 0..6 {
+            let _ = state.step();
+        }
+        assert_eq!(*state.status.pattern.lock().unwrap(), Some([0xF0; 16]));
+        assert_eq!(state.status.pitch.load(Ordering::Relaxed), 112);
+        assert!(state.status.sounding.load(Ordering::Relaxed));
+
+        // At 8000 samples a second, pitch 112 (8000 bits a second) is a bit a
+        // sample, and the default pitch of 64 (4000) two
+        let status = state.status.clone();
+        status.volume.store(MAX_VOLUME, Ordering::Relaxed);
+        let mut buzzer = Buzzer::new(Waveform::Square, 441.0, 8000, 0.5, status, None, None);
+        let signs = |buzzer: &mut Buzzer, len| {
+            let mut out = vec![0.0; len];
+            buzzer.callback(&mut out);
+            out.iter()
+                .map(|x| if *x > 0.0 { '+' } else { '-' })
+                .collect::<String>()
+        };
+        assert_eq!(signs(&mut buzzer, 16), "++++----++++----");
+        state.registers.0[0] = DEFAULT_PITCH;
+        let _ = state.execute(crate::instruction::DecodedInstr::SetPitch {
+            register: ux::u4::new(0),
+        });
+        // Carrying on from where it was in the pattern
+        assert_eq!(signs(&mut buzzer, 24), "++++++++--------++++++++");
+        // Back to the buzzer's own wave once the ROM loads no pattern
+        *buzzer.status.pattern.lock().unwrap() = None;
+        assert_eq!(signs(&mut buzzer, 9), "+++++++++");
+    }
+
+    #[test]
+    fn mute_toggles_without_touching_the_timer() {
+        let mut buzzer = buzzer(Waveform::Square, 441.0, None);
+        assert!(toggle_mute(&buzzer.status));
+        assert!(buzzer.status.muted.load(Ordering::Relaxed));
+        assert!(play(&mut buzzer, 200).iter().all(|x| *x == 0.0));
+        // Still sounding as far as the game and the bell are concerned
+        assert!(buzzer.status.sounding.load(Ordering::Relaxed));
+        assert!(!toggle_mute(&buzzer.status));
+        assert!(play(&mut buzzer, 200).iter().any(|x| *x != 0.0));
+    }
+
+    /// Saved mutes are picked up by the next run, and saving leaves the rest of the
+    /// config alone.
+    #[test]
+    fn mute_persists() {
+        let dir = std::env::temp_dir().join(format!("chip8-{}-mute", std::process::id()));
+        let path = dir.join("config.toml");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&path, "# Mine\naudio.volume = 40\n").unwrap();
+        let settings = || {
+            let config = Config::load(&path).unwrap();
+            let options = crate::cli::Options::parse([]).unwrap();
+            let settings = super::super::Settings::new(&options, &config).unwrap();
+            (settings.muted, config.get_as::<u8>("audio.volume").unwrap())
+        };
+        assert_eq!(settings(), (false, Some(40)));
+        save_mute(&mut Config::load(&path).unwrap(), true).unwrap();
+        assert_eq!(settings(), (true, Some(40)));
+        save_mute(&mut Config::load(&path).unwrap(), false).unwrap();
+        assert_eq!(settings(), (false, Some(40)));
+        assert!(std::fs::read_to_string(&path)
+            .unwrap()
+            .starts_with("# Mine\n"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn volume_scales_the_wave() {
+        let mut buzzer = buzzer(Waveform::Square, 441.0, None);
+        buzzer.status.volume.store(25, Ordering::Relaxed);
+        let samples = play(&mut buzzer, 200);
+        assert!(samples.iter().all(|x| (x.abs() - 0.25).abs() < 1e-6));
+        buzzer.status.muted.store(true, Ordering::Relaxed);
+        assert!(play(&mut buzzer, 200).iter().all(|x| *x == 0.0));
+    }
+}
@@ -0,0 +1,100 @@
+use sdl2::AudioSubsystem;
+use tracing::*;
+
+/// Names of the playback devices SDL knows about.
+pub fn playback_devices(audio: &AudioSubsystem) -> Vec<String> {
+    let count = audio.num_audio_playback_devices().unwrap_or(0);
+    (0..count)
+        .filter_map(|idx| {
+            audio
+                .audio_playback_device_name(idx)
+                .map_err(|err| warn!("Could not get the name of audio device {idx}: {err}"))
+                .ok()
+        })
+        .collect()
+}
+
+/// The device in `devices` that `wanted` names, ignoring case. A name matching one
+/// device exactly picks it, and otherwise it has to be part of exactly one name.
+pub fn find<'a>(devices: &'a [String], wanted: &str) -> Result<&'a str, String> {
+    let lowercase = wanted.to_lowercase();
+    if let Some(exact) = devices.iter().find(|name| name.to_lowercase() == lowercase) {
+        return Ok(exact);
+    }
+    let matching: Vec<&String> = devices
+        .iter()
+        .filter(|name| name.to_lowercase().contains(&lowercase))
+        .collect();
+    match matching[..] {
+        [name] => Ok(name),
+        [] if devices.is_empty() => Err(format!(
+            "No audio device has {wanted:?} in its name, and none were found at all"
+        )),
+        [] => Err(format!(
+            "No audio device has {wanted:?} in its name. These are available:\n{}",
+            list(devices.iter())
+        )),
+        _ => Err(format!(
+            "Several audio devices have {wanted:?} in their name, pick one of:\n{}",
+            list(matching.into_iter())
+        )),
+    }
+}
+
+fn list<'a>(names: impl Iterator<Item = &'a String>) -> String {
+    names
+        .map(|name| format!("  {name}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn devices() -> Vec<String> {
+        [
+            "Built-in Audio Analog Stereo",
+            "Built-in Audio Digital Stereo (HDMI)",
+            "USB Headset",
+            "USB Headset Mono",
+        ]
+        .map(String::from)
+        .to_vec()
+    }
+
+    #[test]
+    fn picks_the_one_device_with_the_name_in_it() {
+        let devices = devices();
+        assert_eq!(
+            find(&devices, "hdmi"),
+            Ok("Built-in Audio Digital Stereo (HDMI)")
+        );
+        assert_eq!(find(&devices, "ANALOG"), Ok("Built-in Audio Analog Stereo"));
+    }
+
+    /// A whole name wins even when it is part of another one.
+    #[test]
+    fn exact_names_win() {
+        assert_eq!(find(&devices(), "usb headset"), Ok("USB Headset"));
+    }
+
+    #[test]
+    fn says_what_there_is_to_pick_from() {
+        let devices = devices();
+        let err = find(&devices, "Built-in").unwrap_err();
+        assert!(err.starts_with("Several audio devices"), "{err}");
+        assert!(err.contains("\n  Built-in Audio Analog Stereo\n"), "{err}");
+        assert!(!err.contains("USB"), "{err}");
+
+        let err = find(&devices, "Bluetooth").unwrap_err();
+        assert!(
+            err.starts_with("No audio device has \"Bluetooth\""),
+            "{err}"
+        );
+        assert!(err.ends_with("\n  USB Headset Mono"), "{err}");
+
+        let err = find(&[], "USB").unwrap_err();
+        assert!(err.ends_with("none were found at all"), "{err}");
+    }
+}
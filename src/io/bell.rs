@@ -0,0 +1,99 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use std::time::Duration;
+use std::time::Instant;
+
+/// The border stays on or off for at least this long, so it never flashes faster
+/// than 2Hz however the game toggles the sound timer.
+pub const MIN_PHASE: Duration = Duration::from_millis(250);
+
+/// Whether to light the bell: as `setting` says if it says, and otherwise when there
+/// is no `sound` to hear.
+pub fn wanted(setting: Option<bool>, sound: bool) -> bool {
+    setting.unwrap_or(!sound)
+}
+
+/// Lights a border around the game while the sound timer runs.
+#[derive(Clone, Debug, Default)]
+pub struct VisualBell {
+    lit: bool,
+    changed: Option<Instant>,
+    /// A beep that came and went while the border had to stay off
+    pending: bool,
+}
+
+impl VisualBell {
+    /// Returns whether the border is lit this frame.
+    pub fn update(&mut self, sounding: bool, now: Instant) -> bool {
+        self.pending |= sounding && !self.lit;
+        let settled = self
+            .changed
+            .is_none_or(|changed| now.duration_since(changed) >= MIN_PHASE);
+        if settled {
+            let lit = sounding || (self.pending && !self.lit);
+            if lit != self.lit {
+                self.lit = lit;
+                self.changed = Some(now);
+                self.pending = false;
+            }
+        }
+        self.lit
+    }
+}
+
+/// Draws a translucent frame just inside `rect`.
+pub fn draw_border(canvas: &mut Canvas<Window>, rect: Rect, color: Color) -> Result<(), String> {
+    let thickness = (rect.width().min(rect.height()) / 64).max(2);
+    canvas.set_blend_mode(sdl2::render::BlendMode::Blend);
+    canvas.set_draw_color(Color::RGBA(color.r, color.g, color.b, 160));
+    let (x, y, width, height) = (rect.x(), rect.y(), rect.width(), rect.height());
+    let edges = [
+        Rect::new(x, y, width, thickness),
+        Rect::new(x, rect.bottom() - thickness as i32, width, thickness),
+        Rect::new(
+            x,
+            y + thickness as i32,
+            thickness,
+            height.saturating_sub(2 * thickness),
+        ),
+        Rect::new(
+            rect.right() - thickness as i32,
+            y + thickness as i32,
+            thickness,
+            height.saturating_sub(2 * thickness),
+        ),
+    ];
+    canvas.fill_rects(&edges)?;
+    canvas.set_blend_mode(sdl2::render::BlendMode::None);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lit_without_sound_unless_turned_off() {
+        assert!(wanted(None, false));
+        assert!(!wanted(None, true));
+        assert!(wanted(Some(true), true));
+        assert!(!wanted(Some(false), false));
+    }
+
+    #[test]
+    fn short_beeps_still_show_for_a_phase() {
+        let start = Instant::now();
+        let mut bell = VisualBell::default();
+        assert!(bell.update(true, start));
+        // Over before the border could go off, so it stays on for the phase
+        assert!(bell.update(false, start + MIN_PHASE / 2));
+        assert!(!bell.update(false, start + MIN_PHASE));
+        // A beep while it has to stay off shows once it can
+        assert!(!bell.update(true, start + MIN_PHASE + MIN_PHASE / 4));
+        assert!(!bell.update(false, start + MIN_PHASE + MIN_PHASE / 2));
+        assert!(bell.update(false, start + MIN_PHASE * 2));
+        assert!(!bell.update(false, start + MIN_PHASE * 3));
+    }
+}
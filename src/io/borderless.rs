@@ -0,0 +1,115 @@
+use core::str::FromStr;
+use sdl2::rect::Rect;
+use sdl2::video::Window;
+use sdl2::video::WindowPos;
+use tracing::*;
+
+/// A window without decorations, for streaming and kiosk setups. Unlike fullscreen it
+/// stays a regular window, so other windows can still go on top of it.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Borderless {
+    #[default]
+    Off,
+    /// At the usual size and position
+    Window,
+    /// Covering the whole display the window is on
+    Desktop,
+}
+
+impl FromStr for Borderless {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Borderless::Off),
+            "window" => Ok(Borderless::Window),
+            "desktop" => Ok(Borderless::Desktop),
+            _ => Err(format!(
+                "Unknown borderless mode {s:?}, expected off, window or desktop"
+            )),
+        }
+    }
+}
+
+impl Borderless {
+    /// The mode to toggle to from this one, with `configured` as what was asked for
+    /// on the command line or in the config: that mode if there was one, and no
+    /// decorations at the usual size otherwise.
+    pub fn toggled(self, configured: Borderless) -> Borderless {
+        match (self, configured) {
+            (Borderless::Off, Borderless::Off) => Borderless::Window,
+            (Borderless::Off, mode) => mode,
+            _ => Borderless::Off,
+        }
+    }
+}
+
+/// The borderless mode the window is in, and where it was before it covered the
+/// display, to put it back there after.
+#[derive(Clone, Debug, Default)]
+pub struct BorderlessWindow {
+    mode: Borderless,
+    restore: Option<Rect>,
+}
+
+impl BorderlessWindow {
+    pub fn mode(&self) -> Borderless {
+        self.mode
+    }
+
+    /// Whether the window covers its display, so its size follows the display's rather
+    /// than the layout and isn't worth remembering.
+    pub fn covering(&self) -> bool {
+        self.mode == Borderless::Desktop
+    }
+
+    pub fn set(&mut self, window: &mut Window, mode: Borderless) {
+        if mode == self.mode {
+            return;
+        }
+        if let Some(rect) = self.restore.take() {
+            move_to(window, rect);
+        }
+        window.set_bordered(mode == Borderless::Off);
+        if mode == Borderless::Desktop {
+            let video = window.subsystem();
+            match window
+                .display_index()
+                .and_then(|idx| video.display_bounds(idx))
+            {
+                Ok(bounds) => {
+                    let (x, y) = window.position();
+                    let (width, height) = window.size();
+                    self.restore = Some(Rect::new(x, y, width, height));
+                    move_to(window, bounds);
+                }
+                Err(err) => warn!("Could not tell where the display is, not covering it: {err}"),
+            }
+        }
+        self.mode = mode;
+    }
+}
+
+fn move_to(window: &mut Window, rect: Rect) {
+    window.set_position(
+        WindowPos::Positioned(rect.x()),
+        WindowPos::Positioned(rect.y()),
+    );
+    if let Err(err) = window.set_size(rect.width(), rect.height()) {
+        warn!("Could not resize the window: {err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toggles_to_the_configured_mode_and_back() {
+        use Borderless::*;
+        assert_eq!(Off.toggled(Off), Window);
+        assert_eq!(Off.toggled(Desktop), Desktop);
+        assert_eq!(Desktop.toggled(Desktop), Off);
+        assert_eq!(Window.toggled(Off), Off);
+    }
+}
@@ -0,0 +1,182 @@
+//! The ROM browser: the ROMs in a directory, one per line, to pick one to play.
+
+use super::rom;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// The ROMs in a directory by name, which of them is selected, and which are on
+/// screen.
+#[derive(Clone, Debug)]
+pub struct Browser {
+    roms: Vec<PathBuf>,
+    selected: usize,
+    top: usize,
+    /// How many fit on screen the last time they were drawn
+    rows: usize,
+}
+
+impl Browser {
+    /// Lists the files in `dir` with a ROM's extension, sorted by name ignoring case.
+    pub fn open(dir: &Path) -> Result<Browser, String> {
+        let entries = std::fs::read_dir(dir).map_err(|err| format!("{}: {err}", dir.display()))?;
+        let mut roms: Vec<PathBuf> = entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file() && rom::check_extension(path).is_ok())
+            .collect();
+        roms.sort_by_key(|path| rom::name(path).to_lowercase());
+        Ok(Browser {
+            roms,
+            selected: 0,
+            top: 0,
+            rows: 1,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+
+    pub fn selected(&self) -> Option<&Path> {
+        self.roms.get(self.selected).map(PathBuf::as_path)
+    }
+
+    /// Moves the selection by `lines`, stopping at either end.
+    pub fn move_by(&mut self, lines: isize) {
+        let last = self.roms.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(lines).min(last);
+    }
+
+    /// Moves the selection by `pages` screens.
+    pub fn move_pages(&mut self, pages: isize) {
+        self.move_by(pages * self.rows as isize);
+    }
+
+    pub fn first(&mut self) {
+        self.selected = 0;
+    }
+
+    pub fn last(&mut self) {
+        self.selected = self.roms.len().saturating_sub(1);
+    }
+
+    /// The names of the ROMs that fit in `rows` lines, scrolled just far enough to
+    /// keep the selected one on screen, and whether each is the selected one.
+    pub fn page(&mut self, rows: usize) -> impl Iterator<Item = (String, bool)> + '_ {
+        let rows = rows.max(1);
+        self.rows = rows;
+        if self.selected < self.top {
+            self.top = self.selected;
+        } else if self.selected >= self.top + rows {
+            self.top = self.selected + 1 - rows;
+        }
+        let selected = self.selected;
+        self.roms
+            .iter()
+            .enumerate()
+            .skip(self.top)
+            .take(rows)
+            .map(move |(idx, path)| (rom::name(path), idx == selected))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory with `files` in it, removed again when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str, files: &[&str]) -> TempDir {
+            let dir = std::env::temp_dir().join(format!("chip8-{}-{name}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            for file in files {
+                std::fs::write(dir.join(file), [0x12, 0x00]).unwrap();
+            }
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn names(browser: &mut Browser, rows: usize) -> Vec<(String, bool)> {
+        browser.page(rows).collect()
+    }
+
+    #[test]
+    fn lists_roms_sorted_ignoring_case() {
+        let dir = TempDir::new(
+            "browser-list",
+            &[
+                "pong.ch8",
+                "Breakout.C8",
+                "tetris.rom",
+                "notes.txt",
+                "README",
+            ],
+        );
+        // Directories aren't ROMs, whatever they are called
+        std::fs::create_dir(dir.0.join("games.ch8")).unwrap();
+        let mut browser = Browser::open(&dir.0).unwrap();
+        let all: Vec<_> = names(&mut browser, 10)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(all, ["Breakout.C8", "pong.ch8", "tetris.rom"]);
+        assert_eq!(
+            browser.selected(),
+            Some(dir.0.join("Breakout.C8").as_path())
+        );
+    }
+
+    #[test]
+    fn empty_and_missing_directories() {
+        let dir = TempDir::new("browser-empty", &["notes.txt"]);
+        let mut browser = Browser::open(&dir.0).unwrap();
+        assert!(browser.is_empty());
+        assert_eq!(browser.selected(), None);
+        browser.move_by(1);
+        browser.last();
+        assert_eq!(browser.selected(), None);
+        assert!(Browser::open(&dir.0.join("missing")).is_err());
+    }
+
+    #[test]
+    fn scrolls_to_keep_the_selection_on_screen() {
+        let files: Vec<_> = (0..7).map(|n| format!("{n}.ch8")).collect();
+        let files: Vec<_> = files.iter().map(String::as_str).collect();
+        let dir = TempDir::new("browser-scroll", &files);
+        let mut browser = Browser::open(&dir.0).unwrap();
+        let page = |browser: &mut Browser| -> Vec<String> {
+            names(browser, 3)
+                .into_iter()
+                .map(|(name, selected)| match selected {
+                    true => format!(">{name}"),
+                    false => name,
+                })
+                .collect()
+        };
+        assert_eq!(page(&mut browser), [">0.ch8", "1.ch8", "2.ch8"]);
+        browser.move_by(3);
+        assert_eq!(page(&mut browser), ["1.ch8", "2.ch8", ">3.ch8"]);
+        // Moving back up within the page doesn't scroll
+        browser.move_by(-2);
+        assert_eq!(page(&mut browser), [">1.ch8", "2.ch8", "3.ch8"]);
+        browser.move_pages(1);
+        assert_eq!(page(&mut browser), ["2.ch8", "3.ch8", ">4.ch8"]);
+        // Stops at either end
+        browser.move_pages(5);
+        assert_eq!(page(&mut browser), ["4.ch8", "5.ch8", ">6.ch8"]);
+        browser.move_by(-100);
+        assert_eq!(page(&mut browser), [">0.ch8", "1.ch8", "2.ch8"]);
+        browser.last();
+        assert_eq!(page(&mut browser), ["4.ch8", "5.ch8", ">6.ch8"]);
+        browser.first();
+        assert_eq!(page(&mut browser), [">0.ch8", "1.ch8", "2.ch8"]);
+    }
+}
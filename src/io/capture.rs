@@ -0,0 +1,214 @@
+//! Screenshots, GIFs and video taken of the frames as they come.
+use super::color;
+use super::gif::GifRecorder;
+use super::osd::Osd;
+use super::rom;
+use super::screenshot;
+use super::video::VideoSink;
+use super::Palette;
+use sdl2::pixels::Color;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Instant;
+use tracing::*;
+
+/// What is being kept of the frames, and what was asked for of the next one.
+pub struct Capture {
+    /// Where screenshots, GIFs and exported states go
+    dir: Option<PathBuf>,
+    screenshot: bool,
+    gif: Option<GifRecorder>,
+    /// Recording starts with the next frame, once the palette is known
+    start_gif: Option<PathBuf>,
+    stop_gif: bool,
+    /// Only every this many frames go into the GIF
+    gif_divisor: u32,
+    video: Option<VideoSink>,
+}
+
+impl Capture {
+    pub fn new(
+        dir: Option<PathBuf>,
+        record_gif: Option<PathBuf>,
+        gif_divisor: u32,
+        video: Option<VideoSink>,
+    ) -> Capture {
+        Capture {
+            dir,
+            screenshot: false,
+            gif: None,
+            start_gif: record_gif,
+            stop_gif: false,
+            gif_divisor,
+            video,
+        }
+    }
+
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    /// Saves the next frame as a screenshot.
+    pub fn screenshot(&mut self) {
+        self.screenshot = true;
+    }
+
+    /// Starts recording a GIF with the next frame, or stops the one being recorded.
+    pub fn toggle_gif(&mut self, rom_name: &str, osd: &mut Osd) {
+        match (&self.gif, &self.dir) {
+            (Some(_), _) => self.stop_gif = true,
+            (None, Some(dir)) => match screenshot::gif_path(dir, rom_name) {
+                Ok(path) => self.start_gif = Some(path),
+                Err(err) => {
+                    warn!("Could not start recording: {err}");
+                    osd.push("Could not start recording", Instant::now());
+                }
+            },
+            (None, None) => {
+                osd.push(
+                    "No screenshot directory, set screenshot.dir",
+                    Instant::now(),
+                );
+            }
+        }
+    }
+
+    /// Whether every frame is being recorded, so none can be left out.
+    pub fn recording(&self) -> bool {
+        self.gif.is_some() || self.video.is_some()
+    }
+
+    /// Takes what was asked for of `frame`, shown as `colors`.
+    pub fn frame(
+        &mut self,
+        frame: u64,
+        vram: &[bool],
+        colors: &[Color],
+        palette: &Palette,
+        rom_name: &str,
+        osd: &mut Osd,
+    ) {
+        if std::mem::take(&mut self.screenshot) {
+            let rgb = color::to_rgb24(colors);
+            let saved = match &self.dir {
+                Some(dir) => screenshot::save(dir, rom_name, 64, 32, &rgb),
+                None => Err("No screenshot directory, set screenshot.dir".into()),
+            };
+            match saved {
+                Ok(path) => {
+                    info!("Saved screenshot to {}", path.display());
+                    osd.push(format!("Saved {}", rom::name(&path)), Instant::now());
+                }
+                Err(err) => {
+                    warn!("Could not save screenshot: {err}");
+                    osd.push("Could not save screenshot", Instant::now());
+                }
+            }
+        }
+        if let Some(recording) = self.gif.take_if(|_| std::mem::take(&mut self.stop_gif)) {
+            match recording.finish() {
+                Ok(()) => osd.push("Saved GIF", Instant::now()),
+                Err(err) => {
+                    warn!("Could not save GIF: {err}");
+                    osd.push("Could not save GIF", Instant::now());
+                }
+            }
+        }
+        if let Some(sink) = &mut self.video {
+            if let Err(err) = sink.push(&color::to_rgb24(colors), Instant::now()) {
+                error!("Stopped recording video: {err}");
+                self.video = None;
+            }
+        }
+        if let Some(path) = self.start_gif.take() {
+            match GifRecorder::create(&path, 64, 32, palette) {
+                Ok(recording) => {
+                    info!("Recording to {}", path.display());
+                    osd.push("Recording GIF", Instant::now());
+                    self.gif = Some(recording);
+                }
+                Err(err) => {
+                    warn!("Could not start recording: {err}");
+                    osd.push("Could not start recording", Instant::now());
+                }
+            }
+        }
+        if let Some(gif) = &mut self.gif {
+            if frame.is_multiple_of(u64::from(self.gif_divisor)) {
+                gif.push(vram, self.gif_divisor);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(osd: &mut Osd) -> Vec<String> {
+        osd.visible(Instant::now())
+            .map(|(text, _)| text.to_string())
+            .collect()
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("chip8-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn run_frames(capture: &mut Capture, frames: std::ops::Range<u64>, osd: &mut Osd) {
+        let vram = [false; 64 * 32];
+        let palette = Palette::default();
+        let colors = color::pixel_colors(&vram, &palette);
+        for frame in frames {
+            capture.frame(frame, &vram, &colors, &palette, "test", osd);
+        }
+    }
+
+    #[test]
+    fn screenshot_is_taken_of_the_next_frame_only() {
+        let dir = temp_dir("capture-screenshot");
+        let mut capture = Capture::new(Some(dir.clone()), None, 1, None);
+        let mut osd = Osd::default();
+        capture.screenshot();
+        run_frames(&mut capture, 0..3, &mut osd);
+        // The picture at its own size and a scaled up copy
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+        let messages = messages(&mut osd);
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].starts_with("Saved "));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn gif_needs_a_directory() {
+        let mut capture = Capture::new(None, None, 1, None);
+        let mut osd = Osd::default();
+        capture.toggle_gif("test", &mut osd);
+        run_frames(&mut capture, 0..2, &mut osd);
+        assert!(!capture.recording());
+        assert_eq!(
+            messages(&mut osd),
+            ["No screenshot directory, set screenshot.dir"]
+        );
+    }
+
+    #[test]
+    fn gif_records_between_toggles() {
+        let dir = temp_dir("capture-gif");
+        let mut capture = Capture::new(Some(dir.clone()), None, 2, None);
+        let mut osd = Osd::default();
+        capture.toggle_gif("test", &mut osd);
+        assert!(!capture.recording());
+        run_frames(&mut capture, 0..4, &mut osd);
+        assert!(capture.recording());
+        capture.toggle_gif("test", &mut osd);
+        run_frames(&mut capture, 4..5, &mut osd);
+        assert!(!capture.recording());
+        assert_eq!(messages(&mut osd), ["Recording GIF", "Saved GIF"]);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,197 @@
+use core::str::FromStr;
+use sdl2::pixels::Color;
+
+/// Background followed by the colors for pixel values 1-3. Plain CHIP-8 only draws
+/// with the first of these; the others are for two-plane XO-CHIP content.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Palette(pub [Color; 4]);
+
+const fn rgb(rgb: u32) -> Color {
+    Color::RGB((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+}
+
+#[rustfmt::skip]
+const PALETTES: [(&str, Palette); 5] = [
+    ("mono",    Palette([rgb(0x000000), rgb(0xFFFFFF), rgb(0xAAAAAA), rgb(0x555555)])),
+    ("octo",    Palette([rgb(0x996600), rgb(0xFFCC00), rgb(0xFF6600), rgb(0x662200)])),
+    ("gameboy", Palette([rgb(0x9BBC0F), rgb(0x0F380F), rgb(0x306230), rgb(0x8BAC0F)])),
+    ("amber",   Palette([rgb(0x1A0F00), rgb(0xFFB000), rgb(0xB37B00), rgb(0x664600)])),
+    ("paper",   Palette([rgb(0xF4F1E8), rgb(0x222222), rgb(0x666666), rgb(0xAAAAAA)])),
+];
+
+impl Palette {
+    pub fn background(&self) -> Color {
+        self.0[0]
+    }
+
+    /// The color `intensity` of the way from the background to the first foreground color.
+    pub fn blend(&self, intensity: f32) -> Color {
+        let (from, to) = (self.0[0], self.0[1]);
+        let lerp = |a: u8, b: u8| (f32::from(a) + (f32::from(b) - f32::from(a)) * intensity) as u8;
+        Color::RGB(lerp(from.r, to.r), lerp(from.g, to.g), lerp(from.b, to.b))
+    }
+
+    /// With `inverted`, swaps the background with the first foreground color.
+    pub fn with_inversion(mut self, inverted: bool) -> Palette {
+        if inverted {
+            self.0.swap(0, 1);
+        }
+        self
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        PALETTES[0].1
+    }
+}
+
+impl FromStr for Palette {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PALETTES
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|(_, palette)| *palette)
+            .ok_or_else(|| {
+                let names = PALETTES.map(|(name, _)| name).join(", ");
+                format!("Unknown palette {s:?}, expected one of {names}")
+            })
+    }
+}
+
+const NAMED: [(&str, Color); 8] = [
+    ("black", Color::RGB(0x00, 0x00, 0x00)),
+    ("white", Color::RGB(0xFF, 0xFF, 0xFF)),
+    ("red", Color::RGB(0xFF, 0x00, 0x00)),
+    ("green", Color::RGB(0x00, 0xFF, 0x00)),
+    ("blue", Color::RGB(0x00, 0x00, 0xFF)),
+    ("amber", Color::RGB(0xFF, 0xB0, 0x00)),
+    ("grey", Color::RGB(0x80, 0x80, 0x80)),
+    ("gray", Color::RGB(0x80, 0x80, 0x80)),
+];
+
+/// Parses `#RGB`, `#RRGGBB` or one of a few color names.
+pub fn parse_color(s: &str) -> Result<Color, String> {
+    if let Some((_, color)) = NAMED.iter().find(|(name, _)| name.eq_ignore_ascii_case(s)) {
+        return Ok(*color);
+    }
+    let invalid = || {
+        let names = NAMED.map(|(name, _)| name).join(", ");
+        format!("Invalid color {s:?}, expected #RGB, #RRGGBB or one of {names}")
+    };
+    let hex = s.strip_prefix('#').ok_or_else(invalid)?;
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    let channel = |idx: usize, len: usize| {
+        let value = u8::from_str_radix(&hex[idx * len..(idx + 1) * len], 16).unwrap();
+        // #RGB is shorthand for #RRGGBB
+        if len == 1 {
+            value * 0x11
+        } else {
+            value
+        }
+    };
+    match hex.len() {
+        3 => Ok(Color::RGB(channel(0, 1), channel(1, 1), channel(2, 1))),
+        6 => Ok(Color::RGB(channel(0, 2), channel(1, 2), channel(2, 2))),
+        _ => Err(invalid()),
+    }
+}
+
+/// Looks each pixel value up in the palette.
+pub fn pixel_colors(vram: &[bool], palette: &Palette) -> Vec<Color> {
+    vram.iter()
+        .map(|pix| palette.0[usize::from(*pix)])
+        .collect()
+}
+
+pub fn to_rgb24(colors: &[Color]) -> Vec<u8> {
+    colors
+        .iter()
+        .flat_map(|color| [color.r, color.g, color.b])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_colors() {
+        assert_eq!(parse_color("#33FF66"), Ok(Color::RGB(0x33, 0xFF, 0x66)));
+        assert_eq!(parse_color("#112211"), Ok(Color::RGB(0x11, 0x22, 0x11)));
+        assert_eq!(parse_color("#abcdef"), Ok(Color::RGB(0xAB, 0xCD, 0xEF)));
+        // Each digit of the short form is doubled
+        assert_eq!(parse_color("#3F6"), Ok(Color::RGB(0x33, 0xFF, 0x66)));
+        assert_eq!(parse_color("#000"), Ok(Color::BLACK));
+    }
+
+    #[test]
+    fn named_colors() {
+        assert_eq!(parse_color("amber"), Ok(Color::RGB(0xFF, 0xB0, 0x00)));
+        assert_eq!(parse_color("White"), Ok(Color::WHITE));
+        assert_eq!(parse_color("grey"), parse_color("gray"));
+    }
+
+    #[test]
+    fn malformed_colors_are_rejected() {
+        for input in [
+            "", "#", "33FF66", "#33FF6", "#33FF666", "#GGGGGG", "#12 456", "purple",
+        ] {
+            let err = parse_color(input).unwrap_err();
+            assert!(err.contains(&format!("{input:?}")), "{err}");
+            assert!(err.contains("#RGB, #RRGGBB or one of black"), "{err}");
+        }
+        // Multibyte characters don't get sliced through
+        assert!(parse_color("#ééé").is_err());
+    }
+
+    #[test]
+    fn every_palette_resolves() {
+        for (name, palette) in PALETTES {
+            assert_eq!(name.parse(), Ok(palette));
+            // Four distinct colors, so the planes can be told apart
+            for (idx, color) in palette.0.iter().enumerate() {
+                assert!(!palette.0[idx + 1..].contains(color), "{name}");
+            }
+        }
+        assert_eq!("mono".parse(), Ok(Palette::default()));
+        let gameboy: Palette = "gameboy".parse().unwrap();
+        assert_eq!(gameboy.background(), Color::RGB(0x9B, 0xBC, 0x0F));
+        assert_eq!(gameboy.0[1], Color::RGB(0x0F, 0x38, 0x0F));
+        let err = "Octo".parse::<Palette>().unwrap_err();
+        assert_eq!(
+            err,
+            "Unknown palette \"Octo\", expected one of mono, octo, gameboy, amber, paper"
+        );
+    }
+
+    /// Inverting swaps what on and off pixels look like, and nothing else.
+    #[test]
+    fn inversion_swaps_on_and_off() {
+        let palette: Palette = "amber".parse().unwrap();
+        let vram = [true, false, false, true];
+        let bytes = |inverted| to_rgb24(&pixel_colors(&vram, &palette.with_inversion(inverted)));
+        let (off, on) = ([0x1A, 0x0F, 0x00], [0xFF, 0xB0, 0x00]);
+        assert_eq!(bytes(false), [on, off, off, on].concat());
+        assert_eq!(bytes(true), [off, on, on, off].concat());
+        let inverted = palette.with_inversion(true);
+        assert_eq!(inverted.0[2..], palette.0[2..]);
+        assert_eq!(inverted.with_inversion(true), palette);
+    }
+
+    /// Off pixels get the background and on pixels the foreground, three bytes each.
+    #[test]
+    fn pixels_take_the_configured_colors() {
+        let mut palette = Palette::default();
+        palette.0[0] = parse_color("#112211").unwrap();
+        palette.0[1] = parse_color("#33FF66").unwrap();
+        let bytes = to_rgb24(&pixel_colors(&[false, true, true, false], &palette));
+        assert_eq!(
+            bytes,
+            [0x11, 0x22, 0x11, 0x33, 0xFF, 0x66, 0x33, 0xFF, 0x66, 0x11, 0x22, 0x11]
+        );
+    }
+}
@@ -0,0 +1,248 @@
+//! `chip8 compare`: two cores side by side in one window, to see for yourself where
+//! quirks or two versions of a ROM make a difference.
+
+use super::font;
+use super::KeyMap;
+use super::KeyMaps;
+use super::Palette;
+use crate::cli::Exit;
+use crate::config;
+use crate::headless::Headless;
+use crate::quirks::Profile;
+use crate::ExitReason;
+use core::fmt;
+use core::time::Duration;
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Point;
+use sdl2::rect::Rect;
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+use std::time::Instant;
+use tracing::*;
+
+pub const USAGE: &str = "\
+Usage: chip8 compare [OPTIONS] ROM [ROM]
+
+Runs two copies of a game side by side in one window, a frame at a time so they
+stay in step, with the keyboard playing both. Given one ROM, both sides run it
+with different quirks.
+
+Options:
+    --quirks <A,B>     Profiles for the left and right side [default: chip8,schip]
+    --ipf <N>          Instructions per frame [default: 10]
+    --scale <N>        Window pixels per CHIP-8 pixel [default: 8]";
+
+/// Width of the line between the two screens, in CHIP-8 pixels.
+const DIVIDER: u32 = 2;
+
+struct Side {
+    label: String,
+    core: Headless,
+    /// Why the program stopped, once it has
+    stopped: Option<ExitReason>,
+}
+
+impl Side {
+    fn frame(&mut self, ipf: u64) {
+        if self.stopped.is_some() {
+            return;
+        }
+        for _ in 0..ipf {
+            match self.core.step() {
+                ControlFlow::Continue(()) | ControlFlow::Break(ExitReason::WaitingForKeyPress) => {}
+                ControlFlow::Break(reason) => {
+                    info!("{} stopped: {reason:?}", self.label);
+                    self.stopped = Some(reason);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+pub fn run(args: impl IntoIterator<Item = String>) -> Result<(), Exit> {
+    let mut profiles = [Profile::Chip8, Profile::Schip];
+    let mut ipf = 10;
+    let mut scale = 8;
+    let mut roms = vec![];
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_owned())),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| usage_error(format!("{flag} expects a value")))
+        };
+        match flag {
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            "--quirks" => {
+                let value = value()?;
+                let parsed: Vec<Profile> = value
+                    .split(',')
+                    .map(|name| name.trim().parse().map_err(|err: String| usage_error(err)))
+                    .collect::<Result<_, _>>()?;
+                profiles = parsed.try_into().map_err(|_| {
+                    usage_error(format!("--quirks expects two profiles, got {value:?}"))
+                })?;
+            }
+            "--ipf" => ipf = number(flag, &value()?)?.max(1),
+            "--scale" => {
+                scale = number(flag, &value()?)?.clamp(1, u64::from(super::MAX_SCALE)) as u32
+            }
+            _ if flag.starts_with('-') => {
+                return Err(usage_error(format!("Unknown option {flag}")))
+            }
+            _ if roms.len() < 2 => roms.push(PathBuf::from(&arg)),
+            _ => return Err(usage_error(format!("Unexpected argument {arg}"))),
+        }
+    }
+    let paths = match &roms[..] {
+        [] => return Err(usage_error("No ROM given")),
+        [rom] => [rom.clone(), rom.clone()],
+        [left, right] => [left.clone(), right.clone()],
+        _ => unreachable!(),
+    };
+    let config = match config::default_path() {
+        Some(path) => config::Config::load(&path),
+        None => Ok(config::Config::default()),
+    };
+    let mut keymaps = config
+        .and_then(|config| KeyMaps::from_config(&config, KeyMap::from_config(&config)?))
+        .map_err(Exit::failed)?;
+    let [left, right] = [0, 1].map(|idx| super::rom::read(&paths[idx]));
+    let roms = [left.map_err(Exit::failed)?, right.map_err(Exit::failed)?];
+    // One keyboard plays both, so the left ROM picks the keys
+    keymaps.select(&roms[0], &super::rom::name(&paths[0]));
+    let mut sides = [0, 1].map(|idx| Side {
+        label: format!("{} {}", super::rom::name(&paths[idx]), profiles[idx]),
+        core: Headless::new(roms[idx].clone(), profiles[idx].quirks(), ipf),
+        stopped: None,
+    });
+
+    let sdl_context = sdl2::init().unwrap();
+    let video_subsystem = sdl_context.video().unwrap();
+    // A row of text above each screen for its label
+    let label_height = font::GLYPH_SIZE + 4;
+    let window = video_subsystem
+        .window(
+            &format!("{} vs {} - chip8", sides[0].label, sides[1].label),
+            (64 * 2 + DIVIDER) * scale,
+            32 * scale + label_height * scale / 2,
+        )
+        .position_centered()
+        .allow_highdpi()
+        .build()
+        .unwrap();
+    let mut canvas = window.into_canvas().build().unwrap();
+    let keymap = keymaps.current();
+    let mut event_pump = sdl_context.event_pump().unwrap();
+    let palette = Palette::default();
+    let (background, foreground) = (palette.0[0], palette.0[1]);
+    let text_scale = (scale / 2).max(1);
+    let top = (label_height * text_scale) as i32;
+    let mut deadline = Instant::now();
+    loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => return Ok(()),
+                Event::KeyDown {
+                    keycode,
+                    scancode,
+                    repeat: false,
+                    ..
+                } => {
+                    if let Some(key) = keymap.key(scancode, keycode) {
+                        for side in &sides {
+                            side.core.state.keypad.lock().unwrap().press(key);
+                        }
+                    }
+                }
+                Event::KeyUp {
+                    keycode,
+                    scancode,
+                    repeat: false,
+                    ..
+                } => {
+                    if let Some(key) = keymap.key(scancode, keycode) {
+                        for side in &mut sides {
+                            side.core.state.keypad.lock().unwrap().release(key);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        for side in &mut sides {
+            side.frame(ipf);
+        }
+
+        canvas.set_draw_color(Color::RGB(0x40, 0x40, 0x40));
+        canvas.clear();
+        for (idx, side) in sides.iter().enumerate() {
+            let left = (idx as u32 * (64 + DIVIDER) * scale) as i32;
+            canvas.set_draw_color(background);
+            canvas
+                .fill_rect(Rect::new(left, top, 64 * scale, 32 * scale))
+                .unwrap();
+            let lit: Vec<Rect> = side
+                .core
+                .state
+                .vram
+                .lock()
+                .unwrap()
+                .iter()
+                .enumerate()
+                .filter(|(_, on)| **on)
+                .map(|(pixel, _)| {
+                    let (x, y) = ((pixel % 64) as u32, (pixel / 64) as u32);
+                    Rect::new(
+                        left + (x * scale) as i32,
+                        top + (y * scale) as i32,
+                        scale,
+                        scale,
+                    )
+                })
+                .collect();
+            canvas.set_draw_color(foreground);
+            canvas.fill_rects(&lit).unwrap();
+            let label = match side.stopped {
+                Some(reason) => format!("{} ({reason:?})", side.label),
+                None => side.label.clone(),
+            };
+            let origin = Point::new(left + 2 * text_scale as i32, 2 * text_scale as i32);
+            font::draw_text(&mut canvas, &label, origin, text_scale, Color::WHITE).unwrap();
+        }
+        canvas.present();
+
+        deadline += Duration::from_secs_f32(1f32 / 60f32);
+        // Don't try to catch up after falling behind, e.g. while the window was moved
+        let now = Instant::now();
+        match deadline.checked_duration_since(now) {
+            Some(wait) => std::thread::sleep(wait),
+            None => deadline = now,
+        }
+    }
+}
+
+fn number(flag: &str, value: &str) -> Result<u64, Exit> {
+    value
+        .parse()
+        .map_err(|_| usage_error(format!("{flag} expects a number, got {value:?}")))
+}
+
+fn usage_error(message: impl fmt::Display) -> Exit {
+    Exit::usage(message, USAGE)
+}
@@ -0,0 +1,439 @@
+use crate::config;
+use crate::config::Config;
+use sdl2::controller::Axis;
+use sdl2::controller::Button;
+use sdl2::controller::GameController;
+use sdl2::event::Event;
+use sdl2::GameControllerSubsystem;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use tracing::*;
+
+/// The names SDL gives buttons and axes in mappings, which the config uses too. Kept
+/// as tables so looking them up doesn't need SDL.
+const BUTTONS: &[(&str, Button)] = &[
+    ("a", Button::A),
+    ("b", Button::B),
+    ("x", Button::X),
+    ("y", Button::Y),
+    ("back", Button::Back),
+    ("guide", Button::Guide),
+    ("start", Button::Start),
+    ("leftstick", Button::LeftStick),
+    ("rightstick", Button::RightStick),
+    ("leftshoulder", Button::LeftShoulder),
+    ("rightshoulder", Button::RightShoulder),
+    ("dpup", Button::DPadUp),
+    ("dpdown", Button::DPadDown),
+    ("dpleft", Button::DPadLeft),
+    ("dpright", Button::DPadRight),
+    ("misc1", Button::Misc1),
+    ("paddle1", Button::Paddle1),
+    ("paddle2", Button::Paddle2),
+    ("paddle3", Button::Paddle3),
+    ("paddle4", Button::Paddle4),
+    ("touchpad", Button::Touchpad),
+];
+
+const AXES: &[(&str, Axis)] = &[
+    ("leftx", Axis::LeftX),
+    ("lefty", Axis::LeftY),
+    ("rightx", Axis::RightX),
+    ("righty", Axis::RightY),
+    ("lefttrigger", Axis::TriggerLeft),
+    ("righttrigger", Axis::TriggerRight),
+];
+
+/// Looks a name up in one of the tables above, ignoring case like SDL does.
+fn lookup<T: Copy>(table: &[(&str, T)], name: &str) -> Option<T> {
+    table
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(name))
+        .map(|(_, value)| *value)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    Key(u8),
+    Pause,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Negative,
+    Positive,
+}
+
+impl Direction {
+    fn of(value: i16, threshold: i16) -> Option<Direction> {
+        if value <= -threshold {
+            Some(Direction::Negative)
+        } else if value >= threshold {
+            Some(Direction::Positive)
+        } else {
+            None
+        }
+    }
+
+    fn magnitude(self, value: i16) -> i32 {
+        match self {
+            Direction::Negative => -i32::from(value),
+            Direction::Positive => i32::from(value),
+        }
+    }
+}
+
+/// Controller bindings on top of the defaults, read from the config as
+/// `controller.<SDL button name> = "<hex key>" | "pause" | "none"` and
+/// `axis.<SDL axis name>.<negative|positive>` with the same values.
+/// An axis presses its key once it passes `axis.threshold` and only releases it again
+/// when it falls back below `axis.release`, so a stick resting near the threshold
+/// doesn't chatter.
+#[derive(Clone, Debug)]
+pub struct ControllerMap {
+    buttons: HashMap<Button, Action>,
+    axes: HashMap<(Axis, Direction), Action>,
+    threshold: i16,
+    release: i16,
+}
+
+impl Default for ControllerMap {
+    fn default() -> Self {
+        let buttons = HashMap::from([
+            (Button::DPadUp, Action::Key(0x2)),
+            (Button::DPadLeft, Action::Key(0x4)),
+            (Button::DPadRight, Action::Key(0x6)),
+            (Button::DPadDown, Action::Key(0x8)),
+            (Button::A, Action::Key(0x5)),
+            (Button::Start, Action::Pause),
+        ]);
+        let axes = HashMap::from([
+            ((Axis::LeftX, Direction::Negative), Action::Key(0x4)),
+            ((Axis::LeftX, Direction::Positive), Action::Key(0x6)),
+            ((Axis::LeftY, Direction::Negative), Action::Key(0x2)),
+            ((Axis::LeftY, Direction::Positive), Action::Key(0x8)),
+        ]);
+        ControllerMap {
+            buttons,
+            axes,
+            threshold: 16000,
+            release: 8000,
+        }
+    }
+}
+
+impl ControllerMap {
+    pub fn from_config(config: &Config) -> Result<ControllerMap, config::Error> {
+        let mut map = ControllerMap::default();
+        let mut bound = HashSet::new();
+        let mut bound_axes = HashSet::new();
+        for (key, value) in config.section("controller") {
+            let invalid = |message: String| config::Error::Invalid {
+                key: format!("controller.{key}"),
+                message,
+            };
+            let button = lookup(BUTTONS, key).ok_or_else(|| invalid("Unknown button".into()))?;
+            // The config only catches the same spelling twice
+            if !bound.insert(button) {
+                return Err(invalid("Button bound more than once".into()));
+            }
+            match parse_action(value).map_err(invalid)? {
+                Some(action) => map.buttons.insert(button, action),
+                None => map.buttons.remove(&button),
+            };
+        }
+        for (key, value) in config.section("axis") {
+            let invalid = |message: String| config::Error::Invalid {
+                key: format!("axis.{key}"),
+                message,
+            };
+            let parse_threshold = || {
+                value
+                    .parse()
+                    .ok()
+                    .filter(|threshold| *threshold >= 0)
+                    .ok_or_else(|| invalid("Expected a number between 0 and 32767".into()))
+            };
+            match key {
+                "threshold" => map.threshold = parse_threshold()?,
+                "release" => map.release = parse_threshold()?,
+                _ => {
+                    let (axis, direction) = key
+                        .split_once('.')
+                        .ok_or_else(|| invalid("Expected axis.<axis>.<direction>".into()))?;
+                    let axis = lookup(AXES, axis).ok_or_else(|| invalid("Unknown axis".into()))?;
+                    let direction = match direction {
+                        "negative" => Direction::Negative,
+                        "positive" => Direction::Positive,
+                        _ => return Err(invalid("Expected negative or positive".into())),
+                    };
+                    if !bound_axes.insert((axis, direction)) {
+                        return Err(invalid("Axis bound more than once".into()));
+                    }
+                    match parse_action(value).map_err(invalid)? {
+                        Some(action) => map.axes.insert((axis, direction), action),
+                        None => map.axes.remove(&(axis, direction)),
+                    };
+                }
+            }
+        }
+        if map.release > map.threshold {
+            return Err(config::Error::Invalid {
+                key: "axis.release".into(),
+                message: format!("Must not be above axis.threshold ({})", map.threshold),
+            });
+        }
+        Ok(map)
+    }
+
+    pub fn button(&self, button: Button) -> Option<Action> {
+        self.buttons.get(&button).copied()
+    }
+
+    /// Where an axis is held after moving to `value`, given where it was held before.
+    pub fn axis_direction(&self, held: Option<Direction>, value: i16) -> Option<Direction> {
+        match held {
+            Some(direction) if direction.magnitude(value) >= i32::from(self.release) => held,
+            _ => Direction::of(value, self.threshold.max(1)),
+        }
+    }
+}
+
+fn parse_action(value: &str) -> Result<Option<Action>, String> {
+    match value {
+        "none" => Ok(None),
+        "pause" => Ok(Some(Action::Pause)),
+        _ => u8::from_str_radix(value, 16)
+            .ok()
+            .filter(|key| *key < 16)
+            .map(|key| Some(Action::Key(key)))
+            .ok_or_else(|| {
+                format!(
+                    "Expected a keypad key between 0 and F, \"pause\" or \"none\", got {value:?}"
+                )
+            }),
+    }
+}
+
+/// Open controllers and the directions their axes are currently held in.
+/// All controllers feed the same keypad.
+pub struct Controllers {
+    subsystem: GameControllerSubsystem,
+    map: ControllerMap,
+    open: HashMap<u32, GameController>,
+    axes: HashMap<(u32, Axis), Direction>,
+}
+
+impl Controllers {
+    pub fn new(subsystem: GameControllerSubsystem, map: ControllerMap) -> Controllers {
+        Controllers {
+            subsystem,
+            map,
+            open: HashMap::new(),
+            axes: HashMap::new(),
+        }
+    }
+
+    /// Translates a controller event into actions, paired with whether they were
+    /// pressed or released.
+    pub fn handle_event(&mut self, event: &Event) -> Vec<(Action, bool)> {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } => {
+                match self.subsystem.open(which) {
+                    Ok(controller) => {
+                        info!("Connected controller: {}", controller.name());
+                        self.open.insert(controller.instance_id(), controller);
+                    }
+                    Err(err) => warn!("Could not open controller {which}: {err}"),
+                }
+                vec![]
+            }
+            Event::ControllerDeviceRemoved { which, .. } => {
+                if let Some(controller) = self.open.remove(&which) {
+                    info!("Disconnected controller: {}", controller.name());
+                }
+                let held = self
+                    .axes
+                    .iter()
+                    .filter(|((id, _), _)| *id == which)
+                    .filter_map(|((_, axis), direction)| self.map.axes.get(&(*axis, *direction)))
+                    .map(|action| (*action, false))
+                    .collect();
+                self.axes.retain(|(id, _), _| *id != which);
+                held
+            }
+            Event::ControllerButtonDown { button, .. } => self
+                .map
+                .button(button)
+                .map(|a| (a, true))
+                .into_iter()
+                .collect(),
+            Event::ControllerButtonUp { button, .. } => self
+                .map
+                .button(button)
+                .map(|a| (a, false))
+                .into_iter()
+                .collect(),
+            Event::ControllerAxisMotion {
+                which, axis, value, ..
+            } => {
+                let held = self.axes.get(&(which, axis)).copied();
+                let direction = self.map.axis_direction(held, value);
+                if direction == held {
+                    return vec![];
+                }
+                match direction {
+                    Some(direction) => self.axes.insert((which, axis), direction),
+                    None => self.axes.remove(&(which, axis)),
+                };
+                let action = |direction| self.map.axes.get(&(axis, direction)).copied();
+                let released = held.and_then(action).map(|a| (a, false));
+                let pressed = direction.and_then(action).map(|a| (a, true));
+                released.into_iter().chain(pressed).collect()
+            }
+            _ => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_config(text: &str) -> Result<ControllerMap, config::Error> {
+        ControllerMap::from_config(&Config::parse(text).unwrap())
+    }
+
+    /// Where the axis is held after each of `values`, starting from the middle.
+    fn follow(map: &ControllerMap, values: &[i16]) -> Vec<Option<Direction>> {
+        let mut held = None;
+        values
+            .iter()
+            .map(|value| {
+                held = map.axis_direction(held, *value);
+                held
+            })
+            .collect()
+    }
+
+    #[test]
+    fn presses_past_the_threshold() {
+        let map = ControllerMap::default();
+        let positive = Some(Direction::Positive);
+        let negative = Some(Direction::Negative);
+        assert_eq!(
+            follow(&map, &[0, 15999, 16000, 32767, -15999, -16000, i16::MIN]),
+            [None, None, positive, positive, None, negative, negative]
+        );
+    }
+
+    #[test]
+    fn releases_below_the_release_point() {
+        let map = ControllerMap::default();
+        let held = Some(Direction::Positive);
+        // Wobbling around the threshold keeps the key down until it drops below 8000
+        assert_eq!(
+            follow(&map, &[16000, 15000, 16500, 9000, 8000, 7999, 15999, 16000]),
+            [held, held, held, held, held, None, None, held]
+        );
+    }
+
+    #[test]
+    fn flipping_straight_across_switches_direction() {
+        let map = ControllerMap::default();
+        assert_eq!(
+            follow(&map, &[20000, -20000, -7999, 7999]),
+            [
+                Some(Direction::Positive),
+                Some(Direction::Negative),
+                None,
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn thresholds_from_the_config() {
+        let map = from_config("[axis]\nthreshold = 100\nrelease = 50\n").unwrap();
+        let held = Some(Direction::Negative);
+        assert_eq!(
+            follow(&map, &[-99, -100, -60, -50, -49]),
+            [None, held, held, held, None]
+        );
+        // No hysteresis at all when both are the same
+        let equal = from_config("[axis]\nthreshold = 300\nrelease = 300\n").unwrap();
+        assert_eq!(
+            follow(&equal, &[300, 299, 300]),
+            [Some(Direction::Positive), None, Some(Direction::Positive)]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_thresholds() {
+        let err = from_config("[axis]\nthreshold = 100\nrelease = 200\n").unwrap_err();
+        assert!(err.to_string().contains("axis.release"), "{err}");
+        assert!(from_config("[axis]\nthreshold = -1\n").is_err());
+        assert!(from_config("[axis]\nrelease = 40000\n").is_err());
+    }
+
+    #[test]
+    fn buttons_from_the_config() {
+        let map = from_config(
+            "[controller]\nb = \"pause\"\nA = \"c\"\nstart = \"none\"\nleftshoulder = \"0\"\n",
+        )
+        .unwrap();
+        assert_eq!(map.button(Button::A), Some(Action::Key(0xC)));
+        assert_eq!(map.button(Button::B), Some(Action::Pause));
+        assert_eq!(map.button(Button::Start), None);
+        assert_eq!(map.button(Button::LeftShoulder), Some(Action::Key(0x0)));
+        // The rest keep their defaults
+        assert_eq!(map.button(Button::DPadUp), Some(Action::Key(0x2)));
+        assert_eq!(map.button(Button::Y), None);
+    }
+
+    #[test]
+    fn axes_from_the_config() {
+        let map = from_config("[axis]\nrighttrigger.positive = \"5\"\nleftx.negative = \"none\"\n")
+            .unwrap();
+        assert_eq!(
+            map.axes.get(&(Axis::TriggerRight, Direction::Positive)),
+            Some(&Action::Key(0x5))
+        );
+        assert_eq!(map.axes.get(&(Axis::LeftX, Direction::Negative)), None);
+        assert_eq!(
+            map.axes.get(&(Axis::LeftX, Direction::Positive)),
+            Some(&Action::Key(0x6))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_bindings() {
+        let message = |text: &str| from_config(text).unwrap_err().to_string();
+        let err = message("[controller]\nturbo = \"5\"\n");
+        assert!(
+            err.contains("controller.turbo") && err.contains("Unknown button"),
+            "{err}"
+        );
+        let err = message("[controller]\na = \"10\"\n");
+        assert!(err.contains("between 0 and F"), "{err}");
+        let err = message("[controller]\na = \"jump\"\n");
+        assert!(err.contains("\"jump\""), "{err}");
+        let err = message("[axis]\nleftz.positive = \"5\"\n");
+        assert!(err.contains("Unknown axis"), "{err}");
+        let err = message("[axis]\nleftx.up = \"5\"\n");
+        assert!(err.contains("negative or positive"), "{err}");
+        let err = message("[axis]\nleftx = \"5\"\n");
+        assert!(err.contains("axis.<axis>.<direction>"), "{err}");
+    }
+
+    #[test]
+    fn rejects_duplicate_bindings() {
+        // The config itself rejects the same key twice
+        assert!(Config::parse("[controller]\na = \"5\"\na = \"6\"\n").is_err());
+        let err = from_config("[controller]\na = \"5\"\nA = \"6\"\n").unwrap_err();
+        assert!(err.to_string().contains("more than once"), "{err}");
+        let err =
+            from_config("[axis]\nleftx.positive = \"5\"\nLeftX.positive = \"6\"\n").unwrap_err();
+        assert!(err.to_string().contains("more than once"), "{err}");
+    }
+}
@@ -0,0 +1,115 @@
+use core::fmt;
+
+/// The core's registers after the last instruction, published while the register
+/// overlay is shown.
+#[derive(Clone, Debug, Default)]
+pub struct Snapshot {
+    pub registers: [u8; 16],
+    pub i: u16,
+    pub pc: u16,
+    pub stack_depth: usize,
+    /// Return addresses, innermost last
+    pub stack: Vec<u16>,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    /// Address, opcode and mnemonic of the last instruction
+    pub last: Option<(u16, u16, String)>,
+    /// Memory around `pc`, starting at `code_start`
+    pub code: Vec<u8>,
+    pub code_start: u16,
+    /// The page the memory viewer shows, starting at `memory_start`
+    pub memory: Vec<u8>,
+    pub memory_start: u16,
+    /// The bytes at `i`, for the sprite viewer
+    pub sprite: Vec<u8>,
+}
+
+impl Snapshot {
+    /// The instructions in `code`, marking the one at `pc`:
+    /// `> 0202 6120 LD V1, 20`
+    pub fn disassembly(&self) -> String {
+        self.code
+            .chunks_exact(2)
+            .enumerate()
+            .map(|(idx, bytes)| {
+                let addr = self.code_start + 2 * idx as u16;
+                let opcode = u16::from_be_bytes([bytes[0], bytes[1]]);
+                let marker = if addr == self.pc { '>' } else { ' ' };
+                let mnemonic = crate::instruction::disassemble(opcode);
+                format!("{marker} {addr:04X} {opcode:04X} {mnemonic}")
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (row, values) in self.registers.chunks(4).enumerate() {
+            for (col, value) in values.iter().enumerate() {
+                if col > 0 {
+                    write!(f, " ")?;
+                }
+                write!(f, "V{:X} {value:02X}", row * 4 + col)?;
+            }
+            writeln!(f)?;
+        }
+        writeln!(f, "I {:04X} PC {:04X}", self.i, self.pc)?;
+        writeln!(
+            f,
+            "SP {} DT {:02X} ST {:02X}",
+            self.stack_depth, self.delay_timer, self.sound_timer
+        )?;
+        // Each return address is just past the call that pushed it
+        for ret in self.stack.iter().rev() {
+            writeln!(f, "CALLED FROM {:04X}", ret.wrapping_sub(2))?;
+        }
+        match &self.last {
+            Some((addr, opcode, mnemonic)) => write!(f, "{addr:04X} {opcode:04X} {mnemonic}"),
+            None => write!(f, "-"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disassembly_marks_pc() {
+        let snapshot = Snapshot {
+            pc: 0x202,
+            code: vec![0x61, 0x20, 0x12, 0x00, 0x00, 0xE0, 0xFF],
+            code_start: 0x200,
+            ..Default::default()
+        };
+        assert_eq!(
+            snapshot.disassembly(),
+            "  0200 6120 LD V1, 20\n> 0202 1200 JP 200\n  0204 00E0 CLS"
+        );
+    }
+
+    #[test]
+    fn registers_and_stack() {
+        let snapshot = Snapshot {
+            registers: core::array::from_fn(|idx| idx as u8),
+            i: 0x123,
+            pc: 0x2A0,
+            stack_depth: 1,
+            stack: vec![0x204],
+            last: Some((0x29E, 0x22A0, "CALL 2A0".into())),
+            ..Default::default()
+        };
+        assert_eq!(
+            snapshot.to_string(),
+            "V0 00 V1 01 V2 02 V3 03\n\
+             V4 04 V5 05 V6 06 V7 07\n\
+             V8 08 V9 09 VA 0A VB 0B\n\
+             VC 0C VD 0D VE 0E VF 0F\n\
+             I 0123 PC 02A0\n\
+             SP 1 DT 00 ST 00\n\
+             CALLED FROM 0202\n\
+             029E 22A0 CALL 2A0"
+        );
+    }
+}
@@ -0,0 +1,112 @@
+//! Native file picker, by way of the tools each desktop already ships with so no GUI
+//! toolkit has to be linked in.
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Asks for a ROM. `Ok(None)` means the dialog was cancelled.
+pub fn pick_rom() -> Result<Option<PathBuf>, String> {
+    for mut command in commands() {
+        let program = command.get_program().to_string_lossy().into_owned();
+        let output = command
+            .output()
+            .map(|output| (output.status.success(), output.stdout));
+        if let Some(picked) = picked(&program, output) {
+            return picked;
+        }
+    }
+    Err("No file dialog available, install zenity or kdialog".into())
+}
+
+/// What running `program` came to, given whether it succeeded and what it printed:
+/// the path picked if any, or `None` to try the next tool as this one isn't
+/// installed.
+fn picked(
+    program: &str,
+    output: std::io::Result<(bool, Vec<u8>)>,
+) -> Option<Result<Option<PathBuf>, String>> {
+    let (success, stdout) = match output {
+        Ok(output) => output,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(err) => return Some(Err(format!("Could not run {program}: {err}"))),
+    };
+    let path = String::from_utf8_lossy(&stdout).trim().to_owned();
+    Some(Ok(
+        (success && !path.is_empty()).then(|| PathBuf::from(path))
+    ))
+}
+
+#[cfg(target_os = "windows")]
+fn commands() -> Vec<Command> {
+    let script = "Add-Type -AssemblyName System.Windows.Forms; \
+        $dialog = New-Object System.Windows.Forms.OpenFileDialog; \
+        $dialog.Title = 'Open ROM'; \
+        $dialog.Filter = 'CHIP-8 ROMs|*.ch8;*.c8;*.rom|All files|*.*'; \
+        if ($dialog.ShowDialog() -eq 'OK') { $dialog.FileName }";
+    let mut command = Command::new("powershell");
+    command.args(["-NoProfile", "-Command", script]);
+    vec![command]
+}
+
+#[cfg(target_os = "macos")]
+fn commands() -> Vec<Command> {
+    let script = r#"POSIX path of (choose file with prompt "Open ROM")"#;
+    let mut command = Command::new("osascript");
+    command.args(["-e", script]);
+    vec![command]
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn commands() -> Vec<Command> {
+    let mut zenity = Command::new("zenity");
+    zenity.args([
+        "--file-selection",
+        "--title=Open ROM",
+        "--file-filter=CHIP-8 ROMs | *.ch8 *.c8 *.rom",
+        "--file-filter=All files | *",
+    ]);
+    let mut kdialog = Command::new("kdialog");
+    kdialog.args([
+        "--title",
+        "Open ROM",
+        "--getopenfilename",
+        ".",
+        "*.ch8 *.c8 *.rom|CHIP-8 ROMs",
+    ]);
+    vec![zenity, kdialog]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Error;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn the_picked_path_is_what_the_tool_prints() {
+        let output = Ok((true, b"/home/me/roms/pong.ch8\n".to_vec()));
+        assert_eq!(
+            picked("zenity", output),
+            Some(Ok(Some(PathBuf::from("/home/me/roms/pong.ch8"))))
+        );
+    }
+
+    /// Cancelling makes the tools exit with an error, or print nothing.
+    #[test]
+    fn cancelling_picks_nothing() {
+        assert_eq!(picked("zenity", Ok((false, vec![]))), Some(Ok(None)));
+        assert_eq!(
+            picked("kdialog", Ok((true, b"\n".to_vec()))),
+            Some(Ok(None))
+        );
+    }
+
+    #[test]
+    fn missing_tools_make_way_for_the_next() {
+        assert_eq!(picked("zenity", Err(ErrorKind::NotFound.into())), None);
+        let denied = Error::new(ErrorKind::PermissionDenied, "denied");
+        assert_eq!(
+            picked("zenity", Err(denied)),
+            Some(Err("Could not run zenity: denied".into()))
+        );
+    }
+}
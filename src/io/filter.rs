@@ -0,0 +1,168 @@
+use super::viewport::Scaling;
+use core::fmt;
+use core::str::FromStr;
+use sdl2::pixels::Color;
+
+/// Each CHIP-8 pixel becomes a block of this many texture pixels per side when
+/// filtering, leaving room for the scanline and the rounded corners.
+pub const CRT_SCALE: u32 = 4;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Filter {
+    #[default]
+    None,
+    /// Dark scanlines between rows and softened pixel corners
+    Crt,
+}
+
+impl FromStr for Filter {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Filter::None),
+            "crt" => Ok(Filter::Crt),
+            _ => Err(format!("Unknown filter {s:?}, expected none or crt")),
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Filter::None => write!(f, "none"),
+            Filter::Crt => write!(f, "crt"),
+        }
+    }
+}
+
+/// How the screen is stretched to the window at sizes that aren't whole multiples.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Filtering {
+    /// Crisp pixels, some a window pixel wider than others
+    #[default]
+    Nearest,
+    /// Even pixels with blurred edges
+    Linear,
+}
+
+impl Filtering {
+    /// The value for SDL's scale quality hint, which applies to textures created
+    /// after setting it.
+    pub fn hint(self) -> &'static str {
+        match self {
+            Filtering::Nearest => "nearest",
+            Filtering::Linear => "linear",
+        }
+    }
+
+    /// What to use with `scaling`: whole multiples are always crisp, as that is
+    /// what they are for.
+    pub fn with(self, scaling: Scaling) -> Filtering {
+        match scaling {
+            Scaling::Fit => self,
+            Scaling::Integer => Filtering::Nearest,
+        }
+    }
+}
+
+impl FromStr for Filtering {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest" => Ok(Filtering::Nearest),
+            "linear" => Ok(Filtering::Linear),
+            _ => Err(format!(
+                "Unknown filtering {s:?}, expected nearest or linear"
+            )),
+        }
+    }
+}
+
+/// Converts the screen to RGB24 texture data of `CRT_SCALE` times the size.
+pub fn crt(colors: &[Color], background: Color) -> Vec<u8> {
+    let scale = CRT_SCALE as usize;
+    let mut out = Vec::with_capacity(colors.len() * scale * scale * 3);
+    for y in 0..32 * scale {
+        let (row, sub_y) = (y / scale, y % scale);
+        for x in 0..64 * scale {
+            let (col, sub_x) = (x / scale, x % scale);
+            let mut color = colors[row * 64 + col];
+            let edge_x = sub_x == 0 || sub_x == scale - 1;
+            let edge_y = sub_y == 0 || sub_y == scale - 2;
+            if edge_x && edge_y {
+                color = mix(color, background);
+            }
+            if sub_y == scale - 1 {
+                color = mix(color, Color::BLACK);
+            }
+            out.extend([color.r, color.g, color.b]);
+        }
+    }
+    out
+}
+
+/// Halfway between two colors.
+fn mix(a: Color, b: Color) -> Color {
+    let avg = |a: u8, b: u8| ((u16::from(a) + u16::from(b)) / 2) as u8;
+    Color::RGB(avg(a.r, b.r), avg(a.g, b.g), avg(a.b, b.b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The top left 8x8 texture pixels, as grey levels, for a screen with (0,0) and
+    /// (1,1) lit in white on black: rounded corners, then a dimmed scanline.
+    #[rustfmt::skip]
+    const GOLDEN: [[u8; 8]; 8] = [
+        [0x7F, 0xFF, 0xFF, 0x7F, 0x00, 0x00, 0x00, 0x00],
+        [0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x00],
+        [0x7F, 0xFF, 0xFF, 0x7F, 0x00, 0x00, 0x00, 0x00],
+        [0x7F, 0x7F, 0x7F, 0x7F, 0x00, 0x00, 0x00, 0x00],
+        [0x00, 0x00, 0x00, 0x00, 0x7F, 0xFF, 0xFF, 0x7F],
+        [0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF],
+        [0x00, 0x00, 0x00, 0x00, 0x7F, 0xFF, 0xFF, 0x7F],
+        [0x00, 0x00, 0x00, 0x00, 0x7F, 0x7F, 0x7F, 0x7F],
+    ];
+
+    fn screen(lit: &[(usize, usize)], on: Color, off: Color) -> Vec<Color> {
+        let mut colors = vec![off; 64 * 32];
+        for (x, y) in lit {
+            colors[y * 64 + x] = on;
+        }
+        colors
+    }
+
+    /// The texture pixel at `x`, `y`.
+    fn pixel(out: &[u8], x: usize, y: usize) -> [u8; 3] {
+        let idx = (y * 64 * CRT_SCALE as usize + x) * 3;
+        out[idx..idx + 3].try_into().unwrap()
+    }
+
+    #[test]
+    fn crt_matches_the_golden_buffer() {
+        let colors = screen(&[(0, 0), (1, 1)], Color::WHITE, Color::BLACK);
+        let out = crt(&colors, Color::BLACK);
+        assert_eq!(out.len(), 64 * 32 * 16 * 3);
+        for (y, row) in GOLDEN.iter().enumerate() {
+            for (x, grey) in row.iter().enumerate() {
+                assert_eq!(pixel(&out, x, y), [*grey; 3], "at {x},{y}");
+            }
+        }
+        // Everything else is unlit
+        assert!(out[8 * 64 * 4 * 3..].iter().all(|byte| *byte == 0));
+    }
+
+    /// Corners blend into the palette's background, and scanlines darken towards
+    /// black whatever the palette.
+    #[test]
+    fn crt_blends_with_the_palette() {
+        let (on, off) = (Color::RGB(0xFF, 0xB0, 0x00), Color::RGB(0x1A, 0x0F, 0x00));
+        let out = crt(&screen(&[(0, 0)], on, off), off);
+        assert_eq!(pixel(&out, 1, 1), [0xFF, 0xB0, 0x00]);
+        assert_eq!(pixel(&out, 0, 0), [0x8C, 0x5F, 0x00]);
+        assert_eq!(pixel(&out, 1, 3), [0x7F, 0x58, 0x00]);
+        assert_eq!(pixel(&out, 4, 0), [0x1A, 0x0F, 0x00]);
+        assert_eq!(pixel(&out, 5, 3), [0x0D, 0x07, 0x00]);
+    }
+}
@@ -0,0 +1,143 @@
+use std::time::Duration;
+use std::time::Instant;
+
+/// Pausing while the window is in the background. Only undoes its own pause: a game
+/// that was paused by hand before losing focus stays paused, and pausing or resuming
+/// by hand in between takes over from it.
+#[derive(Clone, Debug)]
+pub struct FocusPause {
+    /// How long after getting focus back the game resumes, so a key pressed to switch
+    /// windows doesn't reach it
+    grace: Duration,
+    state: State,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State {
+    /// Nothing to undo
+    Idle,
+    /// Paused because the window lost focus
+    Paused,
+    /// Focus is back, and the game resumes at the given time
+    Resuming(Instant),
+}
+
+impl FocusPause {
+    pub fn new(grace: Duration) -> FocusPause {
+        FocusPause {
+            grace,
+            state: State::Idle,
+        }
+    }
+
+    /// Returns whether to pause, given whether the game is paused already.
+    pub fn lost(&mut self, paused: bool) -> bool {
+        match self.state {
+            State::Idle if !paused => {
+                self.state = State::Paused;
+                true
+            }
+            State::Idle => false,
+            // Still paused from last time
+            State::Paused | State::Resuming(_) => {
+                self.state = State::Paused;
+                false
+            }
+        }
+    }
+
+    pub fn gained(&mut self, now: Instant) {
+        if self.state == State::Paused {
+            self.state = State::Resuming(now + self.grace);
+        }
+    }
+
+    /// The game was paused or resumed by hand, which wins.
+    pub fn manual(&mut self) {
+        self.state = State::Idle;
+    }
+
+    /// Returns whether to resume now, given whether the game is still paused.
+    pub fn resume(&mut self, now: Instant, paused: bool) -> bool {
+        match self.state {
+            // Something else resumed it meanwhile
+            State::Paused | State::Resuming(_) if !paused => {
+                self.state = State::Idle;
+                false
+            }
+            State::Resuming(at) if now >= at => {
+                self.state = State::Idle;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRACE: Duration = Duration::from_millis(300);
+
+    #[test]
+    fn resumes_after_the_grace_period() {
+        let mut focus = FocusPause::new(GRACE);
+        let start = Instant::now();
+        assert!(focus.lost(false));
+        // Still in the background
+        assert!(!focus.resume(start + GRACE * 10, true));
+        focus.gained(start);
+        assert!(!focus.resume(start + GRACE / 2, true));
+        assert!(focus.resume(start + GRACE, true));
+        // Only once
+        assert!(!focus.resume(start + GRACE * 2, false));
+    }
+
+    #[test]
+    fn leaves_a_manual_pause_alone() {
+        let mut focus = FocusPause::new(GRACE);
+        let start = Instant::now();
+        assert!(!focus.lost(true));
+        focus.gained(start);
+        assert!(!focus.resume(start + GRACE, true));
+    }
+
+    #[test]
+    fn pausing_or_resuming_by_hand_wins() {
+        let start = Instant::now();
+        // Resumed by hand in the background, then paused by hand again
+        let mut focus = FocusPause::new(GRACE);
+        assert!(focus.lost(false));
+        focus.manual();
+        focus.gained(start);
+        assert!(!focus.resume(start + GRACE, true));
+
+        // Paused by hand during the grace period
+        let mut focus = FocusPause::new(GRACE);
+        assert!(focus.lost(false));
+        focus.gained(start);
+        focus.manual();
+        assert!(!focus.resume(start + GRACE, true));
+
+        // Resumed by something else, e.g. the debug server, before focus came back
+        let mut focus = FocusPause::new(GRACE);
+        assert!(focus.lost(false));
+        assert!(!focus.resume(start, false));
+        focus.gained(start);
+        assert!(!focus.resume(start + GRACE, true));
+    }
+
+    #[test]
+    fn losing_focus_again_waits_for_it_again() {
+        let mut focus = FocusPause::new(GRACE);
+        let start = Instant::now();
+        assert!(focus.lost(false));
+        focus.gained(start);
+        // Already paused by us, so nothing to do but keep waiting
+        assert!(!focus.lost(true));
+        assert!(!focus.resume(start + GRACE, true));
+        focus.gained(start + GRACE);
+        assert!(focus.resume(start + GRACE * 2, true));
+    }
+}
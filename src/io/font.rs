@@ -0,0 +1,220 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Point;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+/// Width and height of a glyph, in font pixels.
+pub const GLYPH_SIZE: u32 = 8;
+
+/// 8x8 glyphs for ASCII 0x20-0x5F, one byte per row with the leftmost pixel in the high
+/// bit. Lowercase letters use the uppercase glyphs.
+#[rustfmt::skip]
+const GLYPHS: [[u8; 8]; 64] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // space
+    [0x10, 0x10, 0x10, 0x10, 0x10, 0x00, 0x10, 0x00], // !
+    [0x28, 0x28, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // "
+    [0x28, 0x28, 0x7C, 0x28, 0x7C, 0x28, 0x28, 0x00], // #
+    [0x38, 0x44, 0x04, 0x08, 0x10, 0x00, 0x10, 0x00], // $ (drawn as ?)
+    [0x60, 0x64, 0x08, 0x10, 0x20, 0x4C, 0x0C, 0x00], // %
+    [0x38, 0x44, 0x04, 0x08, 0x10, 0x00, 0x10, 0x00], // & (drawn as ?)
+    [0x10, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '
+    [0x08, 0x10, 0x20, 0x20, 0x20, 0x10, 0x08, 0x00], // (
+    [0x20, 0x10, 0x08, 0x08, 0x08, 0x10, 0x20, 0x00], // )
+    [0x00, 0x10, 0x54, 0x38, 0x54, 0x10, 0x00, 0x00], // *
+    [0x00, 0x10, 0x10, 0x7C, 0x10, 0x10, 0x00, 0x00], // +
+    [0x00, 0x00, 0x00, 0x00, 0x30, 0x10, 0x20, 0x00], // ,
+    [0x00, 0x00, 0x00, 0x7C, 0x00, 0x00, 0x00, 0x00], // -
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x30, 0x30, 0x00], // .
+    [0x04, 0x04, 0x08, 0x10, 0x20, 0x40, 0x40, 0x00], // /
+    [0x38, 0x44, 0x4C, 0x54, 0x64, 0x44, 0x38, 0x00], // 0
+    [0x10, 0x30, 0x10, 0x10, 0x10, 0x10, 0x38, 0x00], // 1
+    [0x38, 0x44, 0x04, 0x08, 0x10, 0x20, 0x7C, 0x00], // 2
+    [0x38, 0x44, 0x04, 0x18, 0x04, 0x44, 0x38, 0x00], // 3
+    [0x08, 0x18, 0x28, 0x48, 0x7C, 0x08, 0x08, 0x00], // 4
+    [0x7C, 0x40, 0x78, 0x04, 0x04, 0x44, 0x38, 0x00], // 5
+    [0x18, 0x20, 0x40, 0x78, 0x44, 0x44, 0x38, 0x00], // 6
+    [0x7C, 0x04, 0x08, 0x10, 0x20, 0x20, 0x20, 0x00], // 7
+    [0x38, 0x44, 0x44, 0x38, 0x44, 0x44, 0x38, 0x00], // 8
+    [0x38, 0x44, 0x44, 0x3C, 0x04, 0x08, 0x30, 0x00], // 9
+    [0x00, 0x30, 0x30, 0x00, 0x30, 0x30, 0x00, 0x00], // :
+    [0x38, 0x44, 0x04, 0x08, 0x10, 0x00, 0x10, 0x00], // ; (drawn as ?)
+    [0x08, 0x10, 0x20, 0x40, 0x20, 0x10, 0x08, 0x00], // <
+    [0x00, 0x00, 0x7C, 0x00, 0x7C, 0x00, 0x00, 0x00], // =
+    [0x20, 0x10, 0x08, 0x04, 0x08, 0x10, 0x20, 0x00], // >
+    [0x38, 0x44, 0x04, 0x08, 0x10, 0x00, 0x10, 0x00], // ?
+    [0x38, 0x44, 0x04, 0x08, 0x10, 0x00, 0x10, 0x00], // @ (drawn as ?)
+    [0x38, 0x44, 0x44, 0x7C, 0x44, 0x44, 0x44, 0x00], // A
+    [0x78, 0x44, 0x44, 0x78, 0x44, 0x44, 0x78, 0x00], // B
+    [0x38, 0x44, 0x40, 0x40, 0x40, 0x44, 0x38, 0x00], // C
+    [0x78, 0x44, 0x44, 0x44, 0x44, 0x44, 0x78, 0x00], // D
+    [0x7C, 0x40, 0x40, 0x78, 0x40, 0x40, 0x7C, 0x00], // E
+    [0x7C, 0x40, 0x40, 0x78, 0x40, 0x40, 0x40, 0x00], // F
+    [0x38, 0x44, 0x40, 0x5C, 0x44, 0x44, 0x3C, 0x00], // G
+    [0x44, 0x44, 0x44, 0x7C, 0x44, 0x44, 0x44, 0x00], // H
+    [0x38, 0x10, 0x10, 0x10, 0x10, 0x10, 0x38, 0x00], // I
+    [0x1C, 0x08, 0x08, 0x08, 0x08, 0x48, 0x30, 0x00], // J
+    [0x44, 0x48, 0x50, 0x60, 0x50, 0x48, 0x44, 0x00], // K
+    [0x40, 0x40, 0x40, 0x40, 0x40, 0x40, 0x7C, 0x00], // L
+    [0x44, 0x6C, 0x54, 0x54, 0x44, 0x44, 0x44, 0x00], // M
+    [0x44, 0x44, 0x64, 0x54, 0x4C, 0x44, 0x44, 0x00], // N
+    [0x38, 0x44, 0x44, 0x44, 0x44, 0x44, 0x38, 0x00], // O
+    [0x78, 0x44, 0x44, 0x78, 0x40, 0x40, 0x40, 0x00], // P
+    [0x38, 0x44, 0x44, 0x44, 0x54, 0x48, 0x34, 0x00], // Q
+    [0x78, 0x44, 0x44, 0x78, 0x50, 0x48, 0x44, 0x00], // R
+    [0x3C, 0x40, 0x40, 0x38, 0x04, 0x04, 0x78, 0x00], // S
+    [0x7C, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x00], // T
+    [0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x38, 0x00], // U
+    [0x44, 0x44, 0x44, 0x44, 0x44, 0x28, 0x10, 0x00], // V
+    [0x44, 0x44, 0x44, 0x54, 0x54, 0x54, 0x28, 0x00], // W
+    [0x44, 0x44, 0x28, 0x10, 0x28, 0x44, 0x44, 0x00], // X
+    [0x44, 0x44, 0x28, 0x10, 0x10, 0x10, 0x10, 0x00], // Y
+    [0x7C, 0x04, 0x08, 0x10, 0x20, 0x40, 0x7C, 0x00], // Z
+    [0x38, 0x20, 0x20, 0x20, 0x20, 0x20, 0x38, 0x00], // [
+    [0x38, 0x44, 0x04, 0x08, 0x10, 0x00, 0x10, 0x00], // \ (drawn as ?)
+    [0x38, 0x08, 0x08, 0x08, 0x08, 0x08, 0x38, 0x00], // ]
+    [0x38, 0x44, 0x04, 0x08, 0x10, 0x00, 0x10, 0x00], // ^ (drawn as ?)
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7C, 0x00], // _
+];
+
+fn glyph(c: char) -> [u8; 8] {
+    let c = c.to_ascii_uppercase();
+    match u32::from(c) {
+        code @ 0x20..=0x5F => GLYPHS[(code - 0x20) as usize],
+        _ => GLYPHS[usize::from(b'?' - 0x20)],
+    }
+}
+
+/// The lit pixels of `text` with its top left corner at `origin`, each font pixel
+/// `scale` window pixels wide. Lines are separated by `\n`.
+pub fn text_rects(text: &str, origin: Point, scale: u32) -> Vec<Rect> {
+    let step = (GLYPH_SIZE * scale) as i32;
+    let mut rects = vec![];
+    for (line_no, line) in text.lines().enumerate() {
+        for (col, c) in line.chars().enumerate() {
+            let x = origin.x() + col as i32 * step;
+            let y = origin.y() + line_no as i32 * step;
+            for (row, bits) in glyph(c).iter().enumerate() {
+                for bit in 0..8 {
+                    if bits & (0x80 >> bit) != 0 {
+                        rects.push(Rect::new(
+                            x + bit * scale as i32,
+                            y + row as i32 * scale as i32,
+                            scale,
+                            scale,
+                        ));
+                    }
+                }
+            }
+        }
+    }
+    rects
+}
+
+/// Size of the box `text` takes up when drawn at `scale`.
+pub fn text_size(text: &str, scale: u32) -> (u32, u32) {
+    let columns = text
+        .lines()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0);
+    let lines = text.lines().count();
+    (
+        columns as u32 * GLYPH_SIZE * scale,
+        lines as u32 * GLYPH_SIZE * scale,
+    )
+}
+
+/// Draws `text` on a translucent black box so it stays readable over the game. The
+/// alpha of `color` fades out the box as well.
+pub fn draw_text(
+    canvas: &mut Canvas<Window>,
+    text: &str,
+    origin: Point,
+    scale: u32,
+    color: Color,
+) -> Result<(), String> {
+    let (width, height) = text_size(text, scale);
+    let padding = scale as i32 * 2;
+    let background = Rect::new(
+        origin.x() - padding,
+        origin.y() - padding,
+        width + 2 * padding as u32,
+        height + 2 * padding as u32,
+    );
+    let blend = canvas.blend_mode();
+    canvas.set_blend_mode(sdl2::render::BlendMode::Blend);
+    let alpha = (u16::from(color.a) * 0xC0 / 0xFF) as u8;
+    canvas.set_draw_color(Color::RGBA(0, 0, 0, alpha));
+    canvas.fill_rect(background)?;
+    canvas.set_draw_color(color);
+    canvas.fill_rects(&text_rects(text, origin, scale))?;
+    canvas.set_blend_mode(blend);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `text` drawn at scale 1 from the top left, one string per pixel row.
+    fn render(text: &str) -> Vec<String> {
+        let (width, height) = text_size(text, 1);
+        let mut rows = vec![vec!['.'; width as usize]; height as usize];
+        for rect in text_rects(text, Point::new(0, 0), 1) {
+            rows[rect.y() as usize][rect.x() as usize] = '#';
+        }
+        rows.into_iter().map(String::from_iter).collect()
+    }
+
+    #[test]
+    fn glyphs_go_side_by_side_and_lines_below() {
+        assert_eq!(
+            render("Hi\n!"),
+            [
+                ".#...#....###...",
+                ".#...#.....#....",
+                ".#...#.....#....",
+                ".#####.....#....",
+                ".#...#.....#....",
+                ".#...#.....#....",
+                ".#...#....###...",
+                "................",
+                "...#............",
+                "...#............",
+                "...#............",
+                "...#............",
+                "...#............",
+                "................",
+                "...#............",
+                "................",
+            ]
+        );
+    }
+
+    #[test]
+    fn scale_and_origin_move_every_pixel() {
+        let rects = text_rects("!", Point::new(10, 20), 3);
+        let expected: Vec<_> = [0, 1, 2, 3, 4, 6]
+            .into_iter()
+            .map(|row| Rect::new(10 + 3 * 3, 20 + row * 3, 3, 3))
+            .collect();
+        assert_eq!(rects, expected);
+        assert_eq!(text_size("!", 3), (24, 24));
+        let second = text_rects(" !", Point::new(10, 20), 3);
+        assert_eq!(second[0].x(), 10 + 8 * 3 + 3 * 3);
+    }
+
+    #[test]
+    fn sizes_fit_the_longest_line() {
+        assert_eq!(text_size("FPS 60\nIPS 700", 2), (7 * 16, 2 * 16));
+        assert_eq!(text_size("", 2), (0, 0));
+    }
+
+    /// Lowercase takes the uppercase glyphs, and anything else a question mark.
+    #[test]
+    fn missing_glyphs_are_stood_in_for() {
+        assert_eq!(render("fps"), render("FPS"));
+        assert_eq!(render("é~"), render("??"));
+    }
+}
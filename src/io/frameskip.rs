@@ -0,0 +1,116 @@
+use std::time::Duration;
+
+const FRAME: Duration = Duration::from_micros(1_000_000 / 60);
+
+/// Skipping the drawing of frames on hosts too slow to draw 60 a second, so the rest
+/// of the loop and the core sharing its thread keep their pace. A frame that starts
+/// more than a frame late isn't drawn, up to `max` in a row so the screen still
+/// changes now and then.
+#[derive(Clone, Debug)]
+pub struct FrameSkip {
+    max: u32,
+    skipped: u32,
+}
+
+impl FrameSkip {
+    pub fn new(max: u32) -> FrameSkip {
+        FrameSkip { max, skipped: 0 }
+    }
+
+    /// How many frames the loop can fall behind by before it had better start
+    /// counting again than try to catch up even by skipping.
+    pub fn behind(&self) -> u32 {
+        self.max + 1
+    }
+
+    /// Returns whether to skip drawing a frame starting `late` past when it was due.
+    pub fn skip(&mut self, late: Duration) -> bool {
+        if late > FRAME && self.skipped < self.max {
+            self.skipped += 1;
+            true
+        } else {
+            self.skipped = 0;
+            false
+        }
+    }
+
+    /// Forgets about the frames so far, for when the loop runs at another pace for a
+    /// while.
+    pub fn restart(&mut self) {
+        self.skipped = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pace;
+    use crate::pace::Pacer;
+    use std::time::Instant;
+
+    /// Simulated seconds of the frontend's loop, where running the core for a frame
+    /// takes a millisecond and presenting one takes `present`. Returns how many frames
+    /// the core ran and how many were presented.
+    fn simulate(max_skip: u32, present: Duration, seconds: u64) -> (u32, u32) {
+        let mut frame_skip = (max_skip > 0).then(|| FrameSkip::new(max_skip));
+        let mut pacer = Pacer::new(
+            60.0,
+            frame_skip
+                .as_ref()
+                .map_or(pace::MAX_BEHIND, FrameSkip::behind),
+        );
+        let start = Instant::now();
+        let end = start + Duration::from_secs(seconds);
+        let (mut frames, mut presents) = (0, 0);
+        let mut now = start;
+        while now < end {
+            let skipping = frame_skip
+                .as_mut()
+                .is_some_and(|frame_skip| frame_skip.skip(pacer.late(now)));
+            frames += 1;
+            now += Duration::from_millis(1);
+            if !skipping {
+                presents += 1;
+                now += present;
+            }
+            now = now.max(pacer.next(now));
+        }
+        (frames, presents)
+    }
+
+    #[test]
+    fn skips_up_to_max_in_a_row() {
+        let mut frame_skip = FrameSkip::new(2);
+        let late = FRAME * 2;
+        assert!(!frame_skip.skip(FRAME));
+        assert!(frame_skip.skip(late));
+        assert!(frame_skip.skip(late));
+        assert!(!frame_skip.skip(late));
+        assert!(frame_skip.skip(late));
+        frame_skip.restart();
+        assert!(frame_skip.skip(late));
+        assert!(frame_skip.skip(late));
+        assert_eq!(frame_skip.behind(), 3);
+    }
+
+    #[test]
+    fn fast_hosts_draw_every_frame() {
+        assert_eq!(simulate(3, Duration::from_millis(5), 1), (60, 60));
+    }
+
+    /// Presenting takes nearly two frames, so without skipping the game runs at half
+    /// speed, and with it the core keeps up with 60 frames a second, never more than
+    /// the frames it can skip behind, while about every other one is drawn.
+    #[test]
+    fn slow_presents_keep_the_game_at_full_speed() {
+        let slow = Duration::from_millis(30);
+        let (frames, presents) = simulate(0, slow, 3);
+        assert!(frames < 100, "{frames}");
+        assert_eq!(frames, presents);
+
+        let (frames, presents) = simulate(3, slow, 3);
+        assert!(frames >= 180 - FrameSkip::new(3).behind(), "{frames}");
+        assert!(frames <= 180, "{frames}");
+        assert!((85..=95).contains(&presents), "{presents}");
+    }
+}
@@ -0,0 +1,302 @@
+//! The window's size, position, display and fullscreen state, saved on quitting so
+//! the next run opens the window where the last one left it. Kept in `window.toml`
+//! next to the config file rather than in it, as it changes on nearly every run.
+
+use crate::config;
+use crate::config::Config;
+use sdl2::rect::Rect;
+use sdl2::video::Window;
+use sdl2::VideoSubsystem;
+use std::path::PathBuf;
+use tracing::*;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Geometry {
+    /// Name of the display, as display numbers change when monitors come and go
+    pub display: String,
+    /// The window while not fullscreen, in desktop coordinates
+    pub rect: Rect,
+    pub fullscreen: bool,
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    Some(config::default_path()?.with_file_name("window.toml"))
+}
+
+impl Geometry {
+    /// Reads the saved geometry. A missing, incomplete or unreadable file is ignored.
+    pub fn load() -> Option<Geometry> {
+        let path = default_path()?;
+        let saved = Config::load(&path).and_then(|state| Geometry::parse(&state));
+        match saved {
+            Ok(geometry) => geometry,
+            Err(err) => {
+                warn!("Ignoring the saved window geometry: {err}");
+                None
+            }
+        }
+    }
+
+    fn parse(state: &Config) -> Result<Option<Geometry>, config::Error> {
+        let (Some(display), Some(x), Some(y), Some(width), Some(height)) = (
+            state.get("display"),
+            state.get_as("x")?,
+            state.get_as("y")?,
+            state.get_as("width")?,
+            state.get_as("height")?,
+        ) else {
+            return Ok(None);
+        };
+        Ok(Some(Geometry {
+            display: display.to_owned(),
+            rect: Rect::new(x, y, width, height),
+            fullscreen: state.get_as("fullscreen")?.unwrap_or(false),
+        }))
+    }
+
+    /// Where `window` is now, or None if SDL can't tell which display it is on.
+    pub fn of(window: &Window) -> Option<Geometry> {
+        let video = window.subsystem();
+        let display = window
+            .display_index()
+            .and_then(|idx| video.display_name(idx))
+            .map_err(|err| warn!("Could not tell which display the window is on: {err}"))
+            .ok()?;
+        let (x, y) = window.position();
+        let (width, height) = window.size();
+        Some(Geometry {
+            display,
+            rect: Rect::new(x, y, width, height),
+            fullscreen: false,
+        })
+    }
+
+    pub fn save(&self) -> Result<(), config::Error> {
+        let Some(path) = default_path() else {
+            return Ok(());
+        };
+        let mut state = Config::load(&path)?;
+        state.set("display", &self.display);
+        state.set("x", &self.rect.x().to_string());
+        state.set("y", &self.rect.y().to_string());
+        state.set("width", &self.rect.width().to_string());
+        state.set("height", &self.rect.height().to_string());
+        state.set("fullscreen", &self.fullscreen.to_string());
+        state.save()
+    }
+
+    /// Where to open the window now: on the saved display, with the saved size unless
+    /// `size` is given, kept entirely on the display and at least `min` large. None if
+    /// that display isn't connected anymore.
+    pub fn restore(
+        &self,
+        video: &VideoSubsystem,
+        min: (u32, u32),
+        size: Option<(u32, u32)>,
+    ) -> Option<Rect> {
+        let displays = video.num_video_displays().unwrap_or(0);
+        let displays = (0..displays).filter_map(|idx| {
+            let name = video.display_name(idx).ok()?;
+            Some((name, video.display_usable_bounds(idx).ok()?))
+        });
+        self.restore_on(displays, min, size)
+    }
+
+    /// [`Geometry::restore`] given the names and usable bounds of the displays.
+    fn restore_on(
+        &self,
+        mut displays: impl Iterator<Item = (String, Rect)>,
+        min: (u32, u32),
+        size: Option<(u32, u32)>,
+    ) -> Option<Rect> {
+        let Some((_, bounds)) = displays.find(|(name, _)| *name == self.display) else {
+            info!(
+                "Display {:?} is gone, not restoring the window there",
+                self.display
+            );
+            return None;
+        };
+        let mut rect = self.rect;
+        if let Some((width, height)) = size {
+            rect.resize(width, height);
+        }
+        Some(fit(rect, bounds, min))
+    }
+}
+
+/// The display to open the window on: `monitor` if it is connected, and the primary
+/// display otherwise.
+pub fn choose_display(video: &VideoSubsystem, monitor: Option<i32>) -> i32 {
+    let Some(monitor) = monitor else {
+        return 0;
+    };
+    pick_display(monitor, video.num_video_displays().unwrap_or(0))
+}
+
+fn pick_display(monitor: i32, displays: i32) -> i32 {
+    if (0..displays).contains(&monitor) {
+        monitor
+    } else {
+        warn!("Display {monitor} isn't connected ({displays} are), using display 0");
+        0
+    }
+}
+
+/// Where to open a window of `size` on display `idx`: at `position` from its top left, or
+/// centered on it, kept on it either way. None if SDL can't tell where the display is.
+pub fn place(
+    video: &VideoSubsystem,
+    idx: i32,
+    position: Option<(i32, i32)>,
+    size: (u32, u32),
+) -> Option<Rect> {
+    let bounds = video
+        .display_usable_bounds(idx)
+        .map_err(|err| warn!("Could not tell where display {idx} is: {err}"))
+        .ok()?;
+    Some(place_in(bounds, position, size))
+}
+
+fn place_in(bounds: Rect, position: Option<(i32, i32)>, size: (u32, u32)) -> Rect {
+    let (x, y) = match position {
+        Some((x, y)) => (bounds.x() + x, bounds.y() + y),
+        None => (
+            bounds.x() + (bounds.width() as i32 - size.0 as i32) / 2,
+            bounds.y() + (bounds.height() as i32 - size.1 as i32) / 2,
+        ),
+    };
+    fit(Rect::new(x, y, size.0, size.1), bounds, size)
+}
+
+/// Parses a window position given as `X,Y`.
+pub fn parse_position(s: &str) -> Result<(i32, i32), String> {
+    s.split_once(',')
+        .and_then(|(x, y)| Some((x.trim().parse().ok()?, y.trim().parse().ok()?)))
+        .ok_or_else(|| format!("Expected a window position as X,Y, got {s:?}"))
+}
+
+/// Shrinks `rect` to fit in `bounds`, but not below `min`, and moves it inside them.
+fn fit(rect: Rect, bounds: Rect, min: (u32, u32)) -> Rect {
+    let width = rect
+        .width()
+        .clamp(min.0.min(bounds.width()), bounds.width());
+    let height = rect
+        .height()
+        .clamp(min.1.min(bounds.height()), bounds.height());
+    let x = rect.x().clamp(bounds.x(), bounds.right() - width as i32);
+    let y = rect.y().clamp(bounds.y(), bounds.bottom() - height as i32);
+    Rect::new(x, y, width, height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 1920x1080 display to the left of the primary one, less a 40 pixel taskbar.
+    fn bounds() -> Rect {
+        Rect::new(-1920, 0, 1920, 1040)
+    }
+
+    #[test]
+    fn fits_oversized_windows_on_the_display() {
+        let rect = fit(Rect::new(-1920, 0, 4000, 3000), bounds(), (64, 32));
+        assert_eq!(rect, bounds());
+    }
+
+    #[test]
+    fn moves_windows_back_onto_the_display() {
+        // Past the right edge, onto the primary display
+        let rect = fit(Rect::new(-100, 500, 640, 320), bounds(), (64, 32));
+        assert_eq!(rect, Rect::new(-640, 500, 640, 320));
+        // Above and left of it
+        let rect = fit(Rect::new(-5000, -200, 640, 320), bounds(), (64, 32));
+        assert_eq!(rect, Rect::new(-1920, 0, 640, 320));
+        // Past the bottom
+        let rect = fit(Rect::new(-1000, 1000, 640, 320), bounds(), (64, 32));
+        assert_eq!(rect, Rect::new(-1000, 720, 640, 320));
+    }
+
+    #[test]
+    fn tiny_windows_grow_to_the_minimum() {
+        let rect = fit(Rect::new(-1000, 100, 10, 5), bounds(), (128, 64));
+        assert_eq!(rect, Rect::new(-1000, 100, 128, 64));
+        // Unless the display itself is smaller
+        let small = Rect::new(0, 0, 100, 50);
+        assert_eq!(fit(Rect::new(0, 0, 10, 5), small, (128, 64)), small);
+    }
+
+    #[test]
+    fn places_windows_centered_or_at_a_position() {
+        let rect = place_in(bounds(), None, (640, 320));
+        assert_eq!(rect, Rect::new(-1920 + 640, 360, 640, 320));
+        // Positions count from the display's corner
+        let rect = place_in(bounds(), Some((10, 20)), (640, 320));
+        assert_eq!(rect, Rect::new(-1910, 20, 640, 320));
+        let rect = place_in(bounds(), Some((5000, 5000)), (640, 320));
+        assert_eq!(rect, Rect::new(-640, 720, 640, 320));
+    }
+
+    #[test]
+    fn falls_back_to_the_primary_display() {
+        assert_eq!(pick_display(1, 2), 1);
+        assert_eq!(pick_display(2, 2), 0);
+        assert_eq!(pick_display(-1, 2), 0);
+        // SDL couldn't count them
+        assert_eq!(pick_display(1, 0), 0);
+    }
+
+    #[test]
+    fn restores_on_the_display_of_the_same_name() {
+        let saved = Geometry {
+            display: "DELL U2415".into(),
+            rect: Rect::new(-1900, 40, 640, 320),
+            fullscreen: false,
+        };
+        let primary = ("Built-in".to_owned(), Rect::new(0, 0, 1280, 800));
+        let dell = ("DELL U2415".to_owned(), bounds());
+        let displays = || [primary.clone(), dell.clone()].into_iter();
+        assert_eq!(
+            saved.restore_on(displays(), (64, 32), None),
+            Some(saved.rect)
+        );
+        // The size given on the command line wins, still kept on the display
+        assert_eq!(
+            saved.restore_on(displays(), (64, 32), Some((4000, 100))),
+            Some(Rect::new(-1920, 40, 1920, 100))
+        );
+        // Unplugged, so the window goes where it would without a saved geometry
+        assert_eq!(
+            saved.restore_on([primary.clone()].into_iter(), (64, 32), None),
+            None
+        );
+    }
+
+    #[test]
+    fn parses_positions() {
+        assert_eq!(parse_position("10,20"), Ok((10, 20)));
+        assert_eq!(parse_position(" -5 , 7 "), Ok((-5, 7)));
+        for bad in ["10", "10,", "a,b", "1,2,3", ""] {
+            assert!(parse_position(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn reads_what_was_saved() {
+        let saved = "display = \"DELL U2415\"\nx = -1900\ny = 40\nwidth = 640\nheight = 320\nfullscreen = true\n";
+        let geometry = Geometry::parse(&Config::parse(saved).unwrap()).unwrap();
+        assert_eq!(
+            geometry,
+            Some(Geometry {
+                display: "DELL U2415".into(),
+                rect: Rect::new(-1900, 40, 640, 320),
+                fullscreen: true,
+            })
+        );
+        // Incomplete files are ignored, and broken values are errors
+        let incomplete = Config::parse("display = \"A\"\nx = 1\ny = 2\nwidth = 3").unwrap();
+        assert_eq!(Geometry::parse(&incomplete).unwrap(), None);
+        let broken =
+            Config::parse("display = \"A\"\nx = 1\ny = 2\nwidth = wide\nheight = 4").unwrap();
+        assert!(Geometry::parse(&broken).is_err());
+    }
+}
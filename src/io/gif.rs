@@ -0,0 +1,369 @@
+//! Streams gameplay to an animated GIF, a frame at a time.
+use super::Palette;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+
+/// Each CHIP-8 pixel is this many pixels wide and high in the GIF.
+const SCALE: usize = 4;
+
+pub struct GifRecorder {
+    file: BufWriter<File>,
+    width: usize,
+    height: usize,
+    /// A frame waiting to see how long it lasts, as indices into the palette, and
+    /// how many 60Hz ticks it has lasted so far
+    pending: Option<(Vec<u8>, u32)>,
+    /// Ticks and centiseconds written so far, to round delays without drifting
+    ticks: u64,
+    centis: u64,
+    error: bool,
+}
+
+impl GifRecorder {
+    pub fn create(
+        path: &Path,
+        width: usize,
+        height: usize,
+        palette: &Palette,
+    ) -> Result<GifRecorder, String> {
+        let err = |err: std::io::Error| format!("{}: {err}", path.display());
+        let mut file = BufWriter::new(File::create(path).map_err(err)?);
+        let (gif_width, gif_height) = ((width * SCALE) as u16, (height * SCALE) as u16);
+        file.write_all(b"GIF89a").map_err(err)?;
+        file.write_all(&gif_width.to_le_bytes()).map_err(err)?;
+        file.write_all(&gif_height.to_le_bytes()).map_err(err)?;
+        // Global color table of 4 entries, background color 0, square pixels
+        file.write_all(&[0x91, 0, 0]).map_err(err)?;
+        for color in palette.0 {
+            file.write_all(&[color.r, color.g, color.b]).map_err(err)?;
+        }
+        // Loop forever
+        file.write_all(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00")
+            .map_err(err)?;
+        Ok(GifRecorder {
+            file,
+            width,
+            height,
+            pending: None,
+            ticks: 0,
+            centis: 0,
+            error: false,
+        })
+    }
+
+    /// Adds a frame that is shown for `ticks` 60ths of a second. Repeats of the
+    /// previous frame only lengthen its delay.
+    pub fn push(&mut self, vram: &[bool], ticks: u32) {
+        match &mut self.pending {
+            Some((pixels, count)) if pixels.iter().zip(vram).all(|(a, b)| (*a != 0) == *b) => {
+                *count += ticks;
+            }
+            _ => {
+                self.flush();
+                let pixels = vram.iter().map(|on| u8::from(*on)).collect();
+                self.pending = Some((pixels, ticks));
+            }
+        }
+    }
+
+    /// Writes the last frame and the trailer.
+    pub fn finish(mut self) -> Result<(), String> {
+        self.flush();
+        self.file
+            .write_all(&[0x3B])
+            .and_then(|()| self.file.flush())
+            .map_err(|err| err.to_string())?;
+        if self.error {
+            return Err("Could not write every frame".into());
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) {
+        let Some((pixels, ticks)) = self.pending.take() else {
+            return;
+        };
+        self.ticks += u64::from(ticks);
+        let end = (self.ticks * 100 + 30) / 60;
+        let delay = (end - self.centis).min(u64::from(u16::MAX)) as u16;
+        self.centis = end;
+        if let Err(err) = self.write_frame(&pixels, delay) {
+            tracing::error!("Could not write GIF frame: {err}");
+            self.error = true;
+        }
+    }
+
+    fn write_frame(&mut self, pixels: &[u8], delay: u16) -> std::io::Result<()> {
+        let (width, height) = (self.width * SCALE, self.height * SCALE);
+        let mut scaled = Vec::with_capacity(width * height);
+        for row in pixels.chunks(self.width) {
+            let row: Vec<u8> = row.iter().flat_map(|p| [*p; SCALE]).collect();
+            for _ in 0..SCALE {
+                scaled.extend(&row);
+            }
+        }
+        let [delay_lo, delay_hi] = delay.to_le_bytes();
+        // Graphic control extension with the delay
+        self.file
+            .write_all(&[0x21, 0xF9, 0x04, 0x00, delay_lo, delay_hi, 0x00, 0x00])?;
+        self.file.write_all(&[0x2C, 0, 0, 0, 0])?;
+        self.file.write_all(&(width as u16).to_le_bytes())?;
+        self.file.write_all(&(height as u16).to_le_bytes())?;
+        self.file.write_all(&[0x00, MIN_CODE_SIZE])?;
+        for block in lzw(&scaled).chunks(255) {
+            self.file.write_all(&[block.len() as u8])?;
+            self.file.write_all(block)?;
+        }
+        self.file.write_all(&[0x00])
+    }
+}
+
+impl Drop for GifRecorder {
+    /// Still leaves a valid file when the recorder isn't finished explicitly.
+    fn drop(&mut self) {
+        if self.pending.is_some() {
+            self.flush();
+            let _ = self.file.write_all(&[0x3B]);
+        }
+    }
+}
+
+/// Two bits per pixel for the four palette colors, the smallest GIF allows.
+const MIN_CODE_SIZE: u8 = 2;
+
+/// GIF flavored LZW: variable width codes up to 12 bits, packed from the low bit.
+fn lzw(pixels: &[u8]) -> Vec<u8> {
+    let clear = 1u16 << MIN_CODE_SIZE;
+    let end = clear + 1;
+    let mut out = Vec::new();
+    let (mut acc, mut bits) = (0u32, 0u32);
+    let mut emit = |code: u16, width: u32| {
+        acc |= u32::from(code) << bits;
+        bits += width;
+        while bits >= 8 {
+            out.push(acc as u8);
+            acc >>= 8;
+            bits -= 8;
+        }
+    };
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next = end + 1;
+    let mut width = u32::from(MIN_CODE_SIZE) + 1;
+    emit(clear, width);
+    let mut prefix: Option<u16> = None;
+    for &pixel in pixels {
+        let Some(current) = prefix else {
+            prefix = Some(u16::from(pixel));
+            continue;
+        };
+        if let Some(&code) = table.get(&(current, pixel)) {
+            prefix = Some(code);
+            continue;
+        }
+        emit(current, width);
+        if next < 4096 {
+            table.insert((current, pixel), next);
+            if next == 1 << width && width < 12 {
+                width += 1;
+            }
+            next += 1;
+        } else {
+            emit(clear, width);
+            table.clear();
+            next = end + 1;
+            width = u32::from(MIN_CODE_SIZE) + 1;
+        }
+        prefix = Some(u16::from(pixel));
+    }
+    if let Some(current) = prefix {
+        emit(current, width);
+    }
+    emit(end, width);
+    if bits > 0 {
+        out.push(acc as u8);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Reads GIF flavored LZW back, the way a viewer would.
+    fn unlzw(data: &[u8]) -> Vec<u8> {
+        let clear = 1u16 << MIN_CODE_SIZE;
+        let end = clear + 1;
+        let reset = || -> Vec<Vec<u8>> { (0..=end).map(|code| vec![code as u8]).collect() };
+        let mut table = reset();
+        let mut width = u32::from(MIN_CODE_SIZE) + 1;
+        let (mut acc, mut bits, mut bytes) = (0u32, 0u32, data.iter());
+        let mut out = vec![];
+        let mut previous: Option<Vec<u8>> = None;
+        loop {
+            while bits < width {
+                acc |= u32::from(*bytes.next().expect("ran out before the end code")) << bits;
+                bits += 8;
+            }
+            let code = (acc & ((1 << width) - 1)) as u16;
+            acc >>= width;
+            bits -= width;
+            if code == clear {
+                table = reset();
+                width = u32::from(MIN_CODE_SIZE) + 1;
+                previous = None;
+                continue;
+            }
+            if code == end {
+                return out;
+            }
+            let entry = match (table.get(usize::from(code)), &previous) {
+                (Some(entry), _) => entry.clone(),
+                // The code being defined right now
+                (None, Some(previous)) => [&previous[..], &previous[..1]].concat(),
+                (None, None) => panic!("code {code} before it was defined"),
+            };
+            if let Some(previous) = previous {
+                if table.len() < 4096 {
+                    table.push([&previous[..], &entry[..1]].concat());
+                }
+            }
+            if table.len() == 1 << width && width < 12 {
+                width += 1;
+            }
+            out.extend(&entry);
+            previous = Some(entry);
+        }
+    }
+
+    /// The frames of a GIF written by the recorder, as their delay and pixels at
+    /// the recorder's scale.
+    fn frames(gif: &[u8]) -> Vec<(u16, Vec<u8>)> {
+        assert_eq!(&gif[..6], b"GIF89a");
+        let mut rest = &gif[13 + 4 * 3..];
+        let mut frames = vec![];
+        let mut delay = 0;
+        loop {
+            match rest[0] {
+                0x21 => {
+                    if rest[1] == 0xF9 {
+                        delay = u16::from_le_bytes([rest[4], rest[5]]);
+                    }
+                    rest = &rest[2..];
+                    while rest[0] != 0 {
+                        rest = &rest[1 + usize::from(rest[0])..];
+                    }
+                    rest = &rest[1..];
+                }
+                0x2C => {
+                    assert_eq!(rest[9], 0x00, "no local color table");
+                    rest = &rest[10..];
+                    assert_eq!(rest[0], MIN_CODE_SIZE);
+                    rest = &rest[1..];
+                    let mut data = vec![];
+                    while rest[0] != 0 {
+                        let len = usize::from(rest[0]);
+                        data.extend(&rest[1..1 + len]);
+                        rest = &rest[1 + len..];
+                    }
+                    rest = &rest[1..];
+                    frames.push((delay, unlzw(&data)));
+                }
+                0x3B => return frames,
+                other => panic!("unexpected block {other:#x}"),
+            }
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("chip8-{}-{name}", std::process::id()))
+    }
+
+    /// A 2x2 screen with pixel `lit` on.
+    fn screen(lit: usize) -> Vec<bool> {
+        (0..4).map(|idx| idx == lit).collect()
+    }
+
+    /// `screen(lit)` at the recorder's scale, as palette indices.
+    fn scaled(lit: usize) -> Vec<u8> {
+        let mut pixels = vec![0; 4 * SCALE * SCALE];
+        let (x, y) = (lit % 2 * SCALE, lit / 2 * SCALE);
+        for row in y..y + SCALE {
+            pixels[row * 2 * SCALE + x..][..SCALE].fill(1);
+        }
+        pixels
+    }
+
+    fn record(name: &str, pushes: &[(usize, u32)]) -> Vec<u8> {
+        let path = temp_path(name);
+        let mut gif = GifRecorder::create(&path, 2, 2, &Palette::default()).unwrap();
+        for (lit, ticks) in pushes {
+            gif.push(&screen(*lit), *ticks);
+        }
+        gif.finish().unwrap();
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        data
+    }
+
+    #[test]
+    fn repeated_frames_lengthen_the_delay() {
+        let gif = record("dedup.gif", &[(0, 1), (0, 2), (3, 1), (3, 1), (0, 1)]);
+        let frames = frames(&gif);
+        let delays: Vec<_> = frames.iter().map(|(delay, _)| *delay).collect();
+        // 3, 5 and 6 ticks in, rounded to hundredths of a second
+        assert_eq!(delays, [5, 3, 2]);
+        let pixels: Vec<_> = frames.into_iter().map(|(_, pixels)| pixels).collect();
+        assert_eq!(pixels, [scaled(0), scaled(3), scaled(0)]);
+    }
+
+    /// 60ths of a second don't come to whole hundredths, but the delays add up to
+    /// the right time anyway.
+    #[test]
+    fn delays_dont_drift() {
+        let pushes: Vec<_> = (0..600).map(|n| (n % 2, 1)).collect();
+        let frames = frames(&record("drift.gif", &pushes));
+        assert_eq!(frames.len(), 600);
+        let delays: Vec<_> = frames.iter().map(|(delay, _)| *delay).collect();
+        assert!(
+            delays.iter().all(|delay| (1..=2).contains(delay)),
+            "{delays:?}"
+        );
+        assert_eq!(
+            delays.iter().map(|delay| u32::from(*delay)).sum::<u32>(),
+            1000
+        );
+    }
+
+    /// Long runs and busy pictures go through every code width and a clear.
+    #[test]
+    fn lzw_round_trips() {
+        let mut rng = fastrand::Rng::with_seed(7);
+        let cases = [
+            vec![],
+            vec![2],
+            vec![0; 10_000],
+            (0..20_000).map(|_| rng.u8(0..4)).collect(),
+        ];
+        for pixels in cases {
+            assert_eq!(unlzw(&lzw(&pixels)), pixels);
+        }
+    }
+
+    /// tests/fixtures/gif/tiny.gif, a 2x2 screen lit in one corner after another,
+    /// the last held for a second. `BLESS=1` rewrites it.
+    #[test]
+    fn matches_the_fixture() {
+        let gif = record("fixture.gif", &[(0, 6), (1, 6), (3, 6), (2, 60)]);
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/gif/tiny.gif");
+        if std::env::var_os("BLESS").is_some() {
+            std::fs::write(&path, &gif).unwrap();
+        }
+        assert_eq!(gif, std::fs::read(path).unwrap());
+        let delays: Vec<_> = frames(&gif).iter().map(|(delay, _)| *delay).collect();
+        assert_eq!(delays, [10, 10, 10, 100]);
+    }
+}
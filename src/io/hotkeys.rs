@@ -0,0 +1,408 @@
+use super::keymap::Binding;
+use crate::config;
+use crate::config::Config;
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::keyboard::Mod;
+use sdl2::keyboard::Scancode;
+use std::time::Duration;
+use std::time::Instant;
+
+/// What a key does when it isn't playing the game, carried out by the frame.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Hotkey {
+    Quit,
+    Fullscreen,
+    /// The fullscreen key with Shift
+    Borderless,
+    Mute,
+    Invert,
+    Filter,
+    Stats,
+    KeyState,
+    Console,
+    Heatmap,
+    Export,
+    Registers,
+    Screenshot,
+    Disassembly,
+    Memory,
+    Slower,
+    Faster,
+    /// Held down, so letting go of it counts too
+    FastForward,
+    Rebind,
+    VirtualKeypad,
+    Scaling,
+    PageUp,
+    PageDown,
+    Home,
+    Sprite,
+    GoTo,
+    OpenRom,
+    Pause,
+    Breakpoint,
+    Browse,
+    CycleKeys,
+    Cheats,
+    RecordGif,
+}
+
+impl Hotkey {
+    /// Whether holding the key down does it again.
+    fn repeats(self) -> bool {
+        matches!(
+            self,
+            Hotkey::Slower | Hotkey::Faster | Hotkey::PageUp | Hotkey::PageDown
+        )
+    }
+}
+
+/// The hotkeys that can be rebound, with their config key and default, in the order
+/// they are tried.
+const CONFIGURABLE: [(Hotkey, &str, Scancode); 17] = [
+    (Hotkey::Quit, "hotkeys.quit", Scancode::Escape),
+    (Hotkey::Fullscreen, "hotkeys.fullscreen", Scancode::F11),
+    (Hotkey::Mute, "hotkeys.mute", Scancode::M),
+    (Hotkey::Invert, "hotkeys.invert", Scancode::F7),
+    (Hotkey::Filter, "hotkeys.filter", Scancode::F8),
+    (Hotkey::Stats, "hotkeys.stats", Scancode::F3),
+    (Hotkey::KeyState, "hotkeys.key-state", Scancode::F10),
+    (Hotkey::Console, "hotkeys.console", Scancode::Grave),
+    (Hotkey::Heatmap, "hotkeys.heatmap", Scancode::End),
+    (Hotkey::Export, "hotkeys.export-state", Scancode::Insert),
+    (Hotkey::Registers, "hotkeys.registers", Scancode::F9),
+    (Hotkey::Screenshot, "hotkeys.screenshot", Scancode::F12),
+    (Hotkey::Disassembly, "hotkeys.disassembly", Scancode::F5),
+    (Hotkey::Memory, "hotkeys.memory", Scancode::F1),
+    (Hotkey::Slower, "hotkeys.slower", Scancode::LeftBracket),
+    (Hotkey::Faster, "hotkeys.faster", Scancode::RightBracket),
+    (Hotkey::FastForward, "hotkeys.fast-forward", Scancode::Tab),
+];
+
+/// Keys that do something else no matter the layout, so they are left to the frame
+/// even when the keypad uses them. The paging keys only do while the memory view or
+/// the console is open.
+const FIXED: [(Keycode, Hotkey); 6] = [
+    (Keycode::F2, Hotkey::Rebind),
+    (Keycode::F4, Hotkey::VirtualKeypad),
+    (Keycode::F6, Hotkey::Scaling),
+    (Keycode::PageUp, Hotkey::PageUp),
+    (Keycode::PageDown, Hotkey::PageDown),
+    (Keycode::Home, Hotkey::Home),
+];
+
+/// Shortcuts with Ctrl held. Ctrl+O only opens a file dialog in builds that have one.
+const CTRL: [(Keycode, Hotkey); 9] = [
+    (Keycode::I, Hotkey::Sprite),
+    (Keycode::L, Hotkey::GoTo),
+    (Keycode::O, Hotkey::OpenRom),
+    (Keycode::P, Hotkey::Pause),
+    (Keycode::B, Hotkey::Breakpoint),
+    (Keycode::R, Hotkey::Browse),
+    (Keycode::K, Hotkey::CycleKeys),
+    (Keycode::H, Hotkey::Cheats),
+    (Keycode::G, Hotkey::RecordGif),
+];
+
+/// Every hotkey, the configurable ones as bound in the config.
+#[derive(Clone, Debug)]
+pub struct Hotkeys {
+    bound: Vec<(Hotkey, Binding)>,
+}
+
+impl Hotkeys {
+    pub fn from_config(config: &Config) -> Result<Hotkeys, config::Error> {
+        let bound = CONFIGURABLE
+            .iter()
+            .map(|&(hotkey, key, default)| {
+                let binding = match config.get(key) {
+                    Some(name) => Binding::parse(name).ok_or_else(|| config::Error::Invalid {
+                        key: key.to_owned(),
+                        message: format!("Unknown key {name:?}"),
+                    })?,
+                    None => Binding::Scancode(default),
+                };
+                Ok((hotkey, binding))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Hotkeys { bound })
+    }
+
+    /// The key bound to one of the configurable hotkeys.
+    pub fn binding(&self, hotkey: Hotkey) -> Binding {
+        self.bound
+            .iter()
+            .find(|(bound, _)| *bound == hotkey)
+            .map(|(_, binding)| *binding)
+            .expect("only configurable hotkeys are bound")
+    }
+
+    /// The keys bound to configurable hotkeys, which the keypad never gets.
+    pub fn bindings(&self) -> Vec<Binding> {
+        self.bound.iter().map(|(_, binding)| *binding).collect()
+    }
+
+    /// The hotkey `event` presses, or lets go of in the case of fast forward.
+    pub fn find(&self, event: &Event) -> Option<Hotkey> {
+        let (scancode, keycode, keymod, repeat) = match *event {
+            Event::KeyDown {
+                scancode,
+                keycode,
+                keymod,
+                repeat,
+                ..
+            } => (scancode, keycode, keymod, repeat),
+            Event::KeyUp {
+                scancode, keycode, ..
+            } => {
+                let fast_forward = self.binding(Hotkey::FastForward);
+                return fast_forward
+                    .matches(scancode, keycode)
+                    .then_some(Hotkey::FastForward);
+            }
+            _ => return None,
+        };
+        let bound = self
+            .bound
+            .iter()
+            .find(|(_, binding)| binding.matches(scancode, keycode))
+            .map(|(hotkey, _)| match hotkey {
+                Hotkey::Fullscreen if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) => {
+                    Hotkey::Borderless
+                }
+                hotkey => *hotkey,
+            });
+        let ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+        let hotkey = bound.or_else(|| {
+            let keycode = keycode?;
+            let shortcuts = if ctrl { &CTRL[..] } else { &[] };
+            shortcuts
+                .iter()
+                .chain(&FIXED)
+                .find(|(key, _)| *key == keycode)
+                .map(|(_, hotkey)| *hotkey)
+        })?;
+        (!repeat || hotkey.repeats()).then_some(hotkey)
+    }
+}
+
+/// Whether `keycode` always means a hotkey, whatever the config binds.
+pub fn is_fixed(keycode: Keycode) -> bool {
+    FIXED.iter().any(|(key, _)| *key == keycode)
+}
+
+/// Quitting with confirmation: the quit key has to be pressed twice within `window`.
+#[derive(Clone, Debug)]
+pub struct QuitConfirm {
+    window: Duration,
+    armed: Option<Instant>,
+}
+
+impl QuitConfirm {
+    pub fn new(window: Duration) -> QuitConfirm {
+        QuitConfirm {
+            window,
+            armed: None,
+        }
+    }
+
+    /// Returns whether this press confirms the quit.
+    pub fn press(&mut self, now: Instant) -> bool {
+        if self.pending(now) {
+            return true;
+        }
+        self.armed = Some(now);
+        false
+    }
+
+    /// Forgets a first press, e.g. because another key was pressed after it.
+    pub fn cancel(&mut self) {
+        self.armed = None;
+    }
+
+    /// Whether a first press is waiting to be confirmed.
+    pub fn pending(&self, now: Instant) -> bool {
+        self.armed
+            .is_some_and(|armed| now.duration_since(armed) < self.window)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_secs(1);
+
+    fn down(scancode: Scancode, keycode: Keycode, keymod: Mod, repeat: bool) -> Event {
+        Event::KeyDown {
+            timestamp: 0,
+            window_id: 0,
+            keycode: Some(keycode),
+            scancode: Some(scancode),
+            keymod,
+            repeat,
+        }
+    }
+
+    fn up(scancode: Scancode, keycode: Keycode) -> Event {
+        Event::KeyUp {
+            timestamp: 0,
+            window_id: 0,
+            keycode: Some(keycode),
+            scancode: Some(scancode),
+            keymod: Mod::NOMOD,
+            repeat: false,
+        }
+    }
+
+    fn press(
+        hotkeys: &Hotkeys,
+        scancode: Scancode,
+        keycode: Keycode,
+        keymod: Mod,
+    ) -> Option<Hotkey> {
+        hotkeys.find(&down(scancode, keycode, keymod, false))
+    }
+
+    #[test]
+    fn finds_bound_fixed_and_ctrl_hotkeys() {
+        let hotkeys = Hotkeys::from_config(&Config::default()).unwrap();
+        let cases = [
+            (
+                Scancode::Escape,
+                Keycode::Escape,
+                Mod::NOMOD,
+                Some(Hotkey::Quit),
+            ),
+            (
+                Scancode::F11,
+                Keycode::F11,
+                Mod::NOMOD,
+                Some(Hotkey::Fullscreen),
+            ),
+            (
+                Scancode::F11,
+                Keycode::F11,
+                Mod::RSHIFTMOD,
+                Some(Hotkey::Borderless),
+            ),
+            // Bound keys don't care about modifiers
+            (
+                Scancode::F3,
+                Keycode::F3,
+                Mod::LCTRLMOD,
+                Some(Hotkey::Stats),
+            ),
+            (Scancode::F2, Keycode::F2, Mod::NOMOD, Some(Hotkey::Rebind)),
+            (
+                Scancode::Home,
+                Keycode::Home,
+                Mod::LCTRLMOD,
+                Some(Hotkey::Home),
+            ),
+            (Scancode::P, Keycode::P, Mod::LCTRLMOD, Some(Hotkey::Pause)),
+            (Scancode::P, Keycode::P, Mod::NOMOD, None),
+            (Scancode::X, Keycode::X, Mod::RCTRLMOD, None),
+        ];
+        for (scancode, keycode, keymod, expected) in cases {
+            assert_eq!(
+                press(&hotkeys, scancode, keycode, keymod),
+                expected,
+                "{keymod:?} {keycode:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn binds_hotkeys_from_the_config() {
+        let config = Config::parse("[hotkeys]\nquit = \"Q\"\nmute = \"keycode:N\"\n").unwrap();
+        let hotkeys = Hotkeys::from_config(&config).unwrap();
+        assert_eq!(
+            hotkeys.binding(Hotkey::Quit),
+            Binding::Scancode(Scancode::Q)
+        );
+        assert_eq!(
+            press(&hotkeys, Scancode::Q, Keycode::Q, Mod::NOMOD),
+            Some(Hotkey::Quit)
+        );
+        assert_eq!(
+            press(&hotkeys, Scancode::Escape, Keycode::Escape, Mod::NOMOD),
+            None
+        );
+        // Found by the symbol wherever the layout puts it
+        assert_eq!(
+            press(&hotkeys, Scancode::B, Keycode::N, Mod::NOMOD),
+            Some(Hotkey::Mute)
+        );
+        assert_eq!(hotkeys.bindings().len(), CONFIGURABLE.len());
+        let config = Config::parse("[hotkeys]\nstats = \"Nope\"\n").unwrap();
+        assert!(Hotkeys::from_config(&config).is_err());
+    }
+
+    #[test]
+    fn only_some_hotkeys_repeat() {
+        let hotkeys = Hotkeys::from_config(&Config::default()).unwrap();
+        let held = |scancode, keycode| hotkeys.find(&down(scancode, keycode, Mod::NOMOD, true));
+        assert_eq!(
+            held(Scancode::RightBracket, Keycode::RightBracket),
+            Some(Hotkey::Faster)
+        );
+        assert_eq!(
+            held(Scancode::PageDown, Keycode::PageDown),
+            Some(Hotkey::PageDown)
+        );
+        assert_eq!(held(Scancode::F11, Keycode::F11), None);
+        assert_eq!(held(Scancode::Tab, Keycode::Tab), None);
+    }
+
+    #[test]
+    fn letting_go_only_counts_for_fast_forward() {
+        let hotkeys = Hotkeys::from_config(&Config::default()).unwrap();
+        assert_eq!(
+            hotkeys.find(&up(Scancode::Tab, Keycode::Tab)),
+            Some(Hotkey::FastForward)
+        );
+        assert_eq!(hotkeys.find(&up(Scancode::F3, Keycode::F3)), None);
+    }
+
+    #[test]
+    fn fixed_keys_are_the_function_and_paging_keys() {
+        assert!(is_fixed(Keycode::F4));
+        assert!(is_fixed(Keycode::PageUp));
+        assert!(!is_fixed(Keycode::F11));
+        assert!(!is_fixed(Keycode::P));
+    }
+
+    #[test]
+    fn a_second_press_inside_the_window_quits() {
+        let start = Instant::now();
+        let mut confirm = QuitConfirm::new(WINDOW);
+        assert!(!confirm.pending(start));
+        assert!(!confirm.press(start));
+        assert!(confirm.pending(start + Duration::from_millis(999)));
+        assert!(confirm.press(start + Duration::from_millis(999)));
+    }
+
+    #[test]
+    fn a_second_press_outside_the_window_starts_over() {
+        let start = Instant::now();
+        let mut confirm = QuitConfirm::new(WINDOW);
+        assert!(!confirm.press(start));
+        assert!(!confirm.pending(start + WINDOW));
+        assert!(!confirm.press(start + WINDOW));
+        // Which counts as a first press again
+        assert!(confirm.press(start + WINDOW + Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn cancelling_needs_two_presses_again() {
+        let start = Instant::now();
+        let mut confirm = QuitConfirm::new(WINDOW);
+        assert!(!confirm.press(start));
+        confirm.cancel();
+        assert!(!confirm.pending(start));
+        assert!(!confirm.press(start + Duration::from_millis(100)));
+        assert!(confirm.press(start + Duration::from_millis(200)));
+    }
+}
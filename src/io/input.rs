@@ -0,0 +1,293 @@
+use super::replay::Recorder;
+use super::replay::Replay;
+use super::vsync::Clock;
+use super::vsync::SystemClock;
+use super::Keypad;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+use tracing::*;
+
+/// Accessibility mode where a press toggles a key instead of holding it. A held key
+/// is released by tapping it again or after `timeout`, if there is one.
+#[derive(Clone, Debug)]
+pub struct StickyKeys {
+    timeout: Option<Duration>,
+    held: [Option<Instant>; 16],
+}
+
+impl StickyKeys {
+    pub fn new(timeout: Option<Duration>) -> StickyKeys {
+        StickyKeys {
+            timeout,
+            held: [None; 16],
+        }
+    }
+
+    /// Returns whether the key is held after the toggle.
+    pub fn toggle(&mut self, key: u8, now: Instant) -> bool {
+        let held = &mut self.held[usize::from(key)];
+        *held = match held {
+            Some(_) => None,
+            None => Some(now),
+        };
+        held.is_some()
+    }
+
+    /// Releases the keys that have been held for longer than the timeout.
+    pub fn expire(&mut self, now: Instant) -> Vec<u8> {
+        let Some(timeout) = self.timeout else {
+            return vec![];
+        };
+        let mut expired = vec![];
+        for (key, held) in self.held.iter_mut().enumerate() {
+            if held.is_some_and(|since| now.duration_since(since) >= timeout) {
+                *held = None;
+                expired.push(key as u8);
+            }
+        }
+        expired
+    }
+}
+
+/// Sits between the input sources of the frontend and the keypad seen by the core.
+pub struct Input<C = SystemClock> {
+    clock: C,
+    keypad: Arc<Mutex<Keypad>>,
+    sticky: Option<StickyKeys>,
+    /// Frames since startup, which recorded input is timed by
+    frame: u64,
+    recorder: Option<Recorder>,
+    replay: Option<Replay>,
+    /// Whether live input still counts during a replay
+    merge_replay: bool,
+}
+
+impl Input {
+    pub fn new(keypad: Arc<Mutex<Keypad>>, sticky: Option<StickyKeys>) -> Input {
+        Input::with_clock(keypad, sticky, SystemClock)
+    }
+}
+
+impl<C: Clock> Input<C> {
+    /// Times sticky keys by `clock`.
+    pub fn with_clock(
+        keypad: Arc<Mutex<Keypad>>,
+        sticky: Option<StickyKeys>,
+        clock: C,
+    ) -> Input<C> {
+        Input {
+            clock,
+            keypad,
+            sticky,
+            frame: 0,
+            recorder: None,
+            replay: None,
+            merge_replay: false,
+        }
+    }
+
+    pub fn record(&mut self, recorder: Recorder) {
+        self.recorder = Some(recorder);
+    }
+
+    pub fn replay(&mut self, replay: Replay, merge: bool) {
+        self.replay = Some(replay);
+        self.merge_replay = merge;
+    }
+
+    /// Whether live input is being ignored for a replay.
+    fn replaying(&self) -> bool {
+        self.replay.is_some() && !self.merge_replay
+    }
+
+    fn set(&mut self, key: u8, pressed: bool, keypad: &mut Keypad) {
+        if keypad.is_pressed(key) == pressed {
+            return;
+        }
+        if pressed {
+            debug!(key, "Key pressed");
+            keypad.press(key);
+        } else {
+            debug!(key, "Key released");
+            keypad.release(key);
+        }
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(self.frame, key, pressed);
+        }
+    }
+
+    pub fn press(&mut self, key: u8) {
+        if self.replaying() {
+            return;
+        }
+        let keypad = self.keypad.clone();
+        let mut keypad = keypad.lock().unwrap();
+        let Some(sticky) = &mut self.sticky else {
+            self.set(key, true, &mut keypad);
+            return;
+        };
+        if sticky.toggle(key, self.clock.now()) {
+            self.set(key, true, &mut keypad);
+            keypad.tap(key);
+        } else {
+            self.set(key, false, &mut keypad);
+        }
+    }
+
+    pub fn release(&mut self, key: u8) {
+        if self.sticky.is_none() && !self.replaying() {
+            let keypad = self.keypad.clone();
+            self.set(key, false, &mut keypad.lock().unwrap());
+        }
+    }
+
+    pub fn release_all(&mut self) {
+        if self.replaying() {
+            return;
+        }
+        if let Some(sticky) = &mut self.sticky {
+            *sticky = StickyKeys::new(sticky.timeout);
+        }
+        let keypad = self.keypad.clone();
+        let mut keypad = keypad.lock().unwrap();
+        for key in 0..16 {
+            self.set(key, false, &mut keypad);
+        }
+        keypad.take_tap();
+    }
+
+    /// Called once a frame. Releases sticky keys whose timeout has run out and plays
+    /// back recorded input.
+    pub fn update(&mut self) {
+        let keypad = self.keypad.clone();
+        let mut keypad = keypad.lock().unwrap();
+        if let Some(sticky) = &mut self.sticky {
+            for key in sticky.expire(self.clock.now()) {
+                self.set(key, false, &mut keypad);
+            }
+        }
+        if let Some(replay) = &mut self.replay {
+            for (key, pressed) in replay.due(self.frame) {
+                if pressed {
+                    keypad.press(key);
+                } else {
+                    keypad.release(key);
+                }
+            }
+            if replay.finished() {
+                info!("Replay finished");
+                self.replay = None;
+            }
+        }
+        self.frame += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[derive(Clone)]
+    struct MockClock(Rc<Cell<Instant>>);
+
+    impl MockClock {
+        fn advance(&self, by: Duration) {
+            self.0.set(self.0.get() + by);
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> Instant {
+            self.0.get()
+        }
+    }
+
+    fn sticky(timeout: Option<Duration>) -> (Input<MockClock>, Arc<Mutex<Keypad>>, MockClock) {
+        let keypad = Arc::new(Mutex::new(Keypad::default()));
+        let clock = MockClock(Rc::new(Cell::new(Instant::now())));
+        let input = Input::with_clock(
+            keypad.clone(),
+            Some(StickyKeys::new(timeout)),
+            clock.clone(),
+        );
+        (input, keypad, clock)
+    }
+
+    #[test]
+    fn presses_toggle_keys() {
+        let (mut input, keypad, _) = sticky(None);
+        input.press(0x5);
+        assert!(keypad.lock().unwrap().is_pressed(0x5));
+        // FX0A takes a toggle on as a press
+        assert_eq!(keypad.lock().unwrap().take_tap(), Some(0x5));
+        // Letting go of the key is what sticky keys are there to ignore
+        input.release(0x5);
+        assert!(keypad.lock().unwrap().is_pressed(0x5));
+        // Two keys at once, one press each
+        input.press(0x6);
+        assert!(keypad.lock().unwrap().is_pressed(0x5));
+        assert!(keypad.lock().unwrap().is_pressed(0x6));
+        input.press(0x5);
+        assert!(!keypad.lock().unwrap().is_pressed(0x5));
+        assert!(keypad.lock().unwrap().is_pressed(0x6));
+        assert_eq!(keypad.lock().unwrap().take_tap(), Some(0x6));
+    }
+
+    #[test]
+    fn held_keys_time_out() {
+        let timeout = Duration::from_secs(2);
+        let (mut input, keypad, clock) = sticky(Some(timeout));
+        input.press(0x5);
+        clock.advance(Duration::from_secs(1));
+        input.press(0x6);
+        clock.advance(Duration::from_millis(999));
+        input.update();
+        assert!(keypad.lock().unwrap().is_pressed(0x5));
+        clock.advance(Duration::from_millis(1));
+        input.update();
+        assert!(!keypad.lock().unwrap().is_pressed(0x5));
+        assert!(keypad.lock().unwrap().is_pressed(0x6));
+        clock.advance(Duration::from_secs(1));
+        input.update();
+        assert!(!keypad.lock().unwrap().is_pressed(0x6));
+        // Pressing again after a timeout holds the key again, rather than toggling
+        // it off
+        input.press(0x5);
+        assert!(keypad.lock().unwrap().is_pressed(0x5));
+    }
+
+    #[test]
+    fn without_a_timeout_keys_stay_held() {
+        let (mut input, keypad, clock) = sticky(None);
+        input.press(0x5);
+        clock.advance(Duration::from_secs(3600));
+        input.update();
+        assert!(keypad.lock().unwrap().is_pressed(0x5));
+    }
+
+    #[test]
+    fn releasing_everything_forgets_toggles() {
+        let (mut input, keypad, _) = sticky(None);
+        input.press(0x5);
+        input.release_all();
+        assert!(!keypad.lock().unwrap().is_pressed(0x5));
+        assert_eq!(keypad.lock().unwrap().take_tap(), None);
+        input.press(0x5);
+        assert!(keypad.lock().unwrap().is_pressed(0x5));
+    }
+
+    #[test]
+    fn keys_follow_presses_without_sticky_keys() {
+        let keypad = Arc::new(Mutex::new(Keypad::default()));
+        let mut input = Input::new(keypad.clone(), None);
+        input.press(0x5);
+        input.press(0x5);
+        assert!(keypad.lock().unwrap().is_pressed(0x5));
+        input.release(0x5);
+        assert!(!keypad.lock().unwrap().is_pressed(0x5));
+    }
+}
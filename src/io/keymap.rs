@@ -0,0 +1,860 @@
+use super::keynames::KEYCODES;
+use super::keynames::SCANCODES;
+use super::overlay::KEYPAD_LAYOUT;
+use crate::config;
+use crate::config::Config;
+use crate::hash;
+use core::fmt;
+use core::str::FromStr;
+use sdl2::keyboard::Keycode;
+use sdl2::keyboard::Scancode;
+use std::collections::BTreeMap;
+use tracing::*;
+
+/// A physical key position, or a symbol for people who prefer layout-dependent bindings.
+/// In the config file scancodes are written by name (`"Num4"`) and keycodes with a
+/// `keycode:` prefix (`"keycode:Num4"`).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Binding {
+    Scancode(Scancode),
+    Keycode(Keycode),
+}
+
+impl Binding {
+    pub fn parse(name: &str) -> Option<Binding> {
+        if let Some(name) = name.strip_prefix("keycode:") {
+            parse_keycode(name).map(Binding::Keycode)
+        } else {
+            let name = name.strip_prefix("scancode:").unwrap_or(name);
+            parse_scancode(name).map(Binding::Scancode)
+        }
+    }
+
+    pub fn matches(self, scancode: Option<Scancode>, keycode: Option<Keycode>) -> bool {
+        match self {
+            Binding::Scancode(s) => scancode == Some(s),
+            Binding::Keycode(k) => keycode == Some(k),
+        }
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Binding::Scancode(scancode) => write!(f, "{scancode:?}"),
+            Binding::Keycode(keycode) => write!(f, "keycode:{keycode:?}"),
+        }
+    }
+}
+
+/// Built-in binding presets, selected with `--layout` or `layout = "..."` in the config.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Layout {
+    /// 1234/QWER/ASDF/ZXCV, as used by most other emulators
+    #[default]
+    Classic,
+    /// 7890/UIOP/JKL;/M,./
+    RightHand,
+    /// 4567/RTYU/FGHJ/VBNM, the original bindings of this emulator
+    CurrentDefault,
+}
+
+impl Layout {
+    pub fn keymap(self) -> KeyMap {
+        use Scancode::*;
+        // Listed in the order of the COSMAC pad: 123C/456D/789E/A0BF
+        #[rustfmt::skip]
+        let pad = match self {
+            Layout::Classic => [
+                Num1, Num2, Num3, Num4,
+                Q,    W,    E,    R,
+                A,    S,    D,    F,
+                Z,    X,    C,    V,
+            ],
+            Layout::RightHand => [
+                Num7, Num8,  Num9,   Num0,
+                U,    I,     O,      P,
+                J,    K,     L,      Semicolon,
+                M,    Comma, Period, Slash,
+            ],
+            Layout::CurrentDefault => [
+                Num4, Num5, Num6, Num7,
+                R,    T,    Y,    U,
+                F,    G,    H,    J,
+                V,    B,    N,    M,
+            ],
+        };
+        let mut keys = [Binding::Scancode(Num0); 16];
+        for (key, scancode) in KEYPAD_LAYOUT.into_iter().zip(pad) {
+            keys[usize::from(key)] = Binding::Scancode(scancode);
+        }
+        KeyMap {
+            layout: Some(self),
+            keys,
+        }
+    }
+}
+
+impl FromStr for Layout {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "classic" => Ok(Layout::Classic),
+            "right-hand" => Ok(Layout::RightHand),
+            "current-default" => Ok(Layout::CurrentDefault),
+            _ => Err(format!(
+                "Unknown layout {s:?}, expected classic, right-hand or current-default"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Layout::Classic => write!(f, "classic"),
+            Layout::RightHand => write!(f, "right-hand"),
+            Layout::CurrentDefault => write!(f, "current-default"),
+        }
+    }
+}
+
+/// Key bound to each of the 16 CHIP-8 keys, indexed by key value. `layout` is the
+/// preset the bindings came from, if any.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyMap {
+    layout: Option<Layout>,
+    keys: [Binding; 16],
+}
+
+impl KeyMap {
+    /// Reads `keypad.<hex digit> = "<Binding>"` entries. Without any entries the preset
+    /// named by `layout` is used, otherwise all 16 keys have to be bound to distinct keys.
+    pub fn from_config(config: &Config) -> Result<KeyMap, config::Error> {
+        let mut keys = [None; 16];
+        for (key, value) in config.section("keypad") {
+            let invalid = |message: String| config::Error::Invalid {
+                key: format!("keypad.{key}"),
+                message,
+            };
+            let idx = u8::from_str_radix(key, 16)
+                .ok()
+                .filter(|idx| *idx < 16)
+                .ok_or_else(|| invalid("Expected a keypad key between 0 and F".into()))?;
+            let binding =
+                Binding::parse(value).ok_or_else(|| invalid(format!("Unknown key {value:?}")))?;
+            keys[usize::from(idx)] = Some(binding);
+        }
+        if keys.iter().all(Option::is_none) {
+            let layout = match config.get("layout") {
+                Some(layout) => layout.parse().map_err(|message| config::Error::Invalid {
+                    key: "layout".into(),
+                    message,
+                })?,
+                None => Layout::default(),
+            };
+            return Ok(layout.keymap());
+        }
+
+        let missing = (0..16)
+            .filter(|idx| keys[*idx].is_none())
+            .map(|idx| format!("{idx:X}"))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            return Err(config::Error::Invalid {
+                key: "keypad".into(),
+                message: format!("Keys {} are not bound", missing.join(", ")),
+            });
+        }
+        let keys = keys.map(Option::unwrap);
+        for (idx, binding) in keys.iter().enumerate() {
+            if let Some(other) = keys[..idx].iter().position(|k| k == binding) {
+                return Err(config::Error::Invalid {
+                    key: format!("keypad.{idx:X}"),
+                    message: format!("{binding} is already bound to key {other:X}"),
+                });
+            }
+        }
+        Ok(KeyMap { layout: None, keys })
+    }
+
+    pub fn binding(&self, key: u8) -> Binding {
+        self.keys[usize::from(key)]
+    }
+
+    /// A short name for the binding of `key`, e.g. `1` for `Num1`.
+    pub fn label(&self, key: u8) -> String {
+        let name = match self.binding(key) {
+            Binding::Scancode(scancode) => format!("{scancode:?}"),
+            Binding::Keycode(keycode) => format!("{keycode:?}"),
+        };
+        match name.strip_prefix("Num") {
+            Some(digit) if !digit.is_empty() => digit.to_owned(),
+            _ => name,
+        }
+    }
+
+    pub fn key(&self, scancode: Option<Scancode>, keycode: Option<Keycode>) -> Option<u8> {
+        self.keys
+            .iter()
+            .position(|k| k.matches(scancode, keycode))
+            .map(|x| x as u8)
+    }
+
+    /// Writes all 16 bindings below `section`, e.g. `keypad`.
+    pub fn save_to(&self, config: &mut Config, section: &str) {
+        for (idx, binding) in self.keys.iter().enumerate() {
+            config.set(&format!("{section}.{idx:X}"), &binding.to_string());
+        }
+    }
+
+    /// Binds `key` to `binding`. Whichever key had `binding` before gets the old
+    /// binding of `key` in exchange, so every key stays bound to a different key.
+    fn bind(&mut self, key: u8, binding: Binding) {
+        let key = usize::from(key);
+        if let Some(other) = self.keys.iter().position(|k| *k == binding) {
+            self.keys[other] = self.keys[key];
+        }
+        self.keys[key] = binding;
+        self.layout = None;
+    }
+}
+
+/// Which ROMs a profile is for: a hash of the whole ROM, or a pattern for its file name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum RomMatch {
+    Hash(u64),
+    Pattern(String),
+}
+
+impl RomMatch {
+    /// 16 hex digits are a hash, the same one the cheats file is named after, anything
+    /// else a file name where `*` and `?` are wildcards.
+    fn parse(s: &str) -> RomMatch {
+        match u64::from_str_radix(s, 16) {
+            Ok(hash) if s.len() == 16 => RomMatch::Hash(hash),
+            _ => RomMatch::Pattern(s.to_lowercase()),
+        }
+    }
+}
+
+/// `[keymaps.<name>]` from the config: keys to bind differently from the default
+/// ones, plus `roms`, the ROMs to use them for.
+#[derive(Clone, Debug)]
+struct Profile {
+    name: String,
+    keymap: KeyMap,
+    roms: Vec<RomMatch>,
+}
+
+/// The default bindings along with the named profiles, and which of them is in use.
+///
+/// Loading a ROM picks the first of:
+/// 1. the profile whose `roms` has the ROM's hash,
+/// 2. the first profile, by name, whose `roms` has a pattern matching the file name,
+/// 3. the keys suggested by the ROM's own settings, see [`KeyMaps::suggest`],
+/// 4. the profile named by `input.keymap`,
+/// 5. the default bindings from `--layout`, `[keypad]` or `layout`.
+#[derive(Clone, Debug)]
+pub struct KeyMaps {
+    default: KeyMap,
+    profiles: Vec<Profile>,
+    /// `input.keymap`
+    preferred: Option<usize>,
+    /// Hash of the ROM that suggested a profile, and the profile
+    suggested: Option<(u64, usize)>,
+    /// Index into `profiles`, `None` for the default bindings
+    active: Option<usize>,
+}
+
+impl KeyMaps {
+    /// Reads the profiles, each of which starts out from `default`.
+    pub fn from_config(config: &Config, default: KeyMap) -> Result<KeyMaps, config::Error> {
+        let mut sections: BTreeMap<&str, Vec<(&str, &str)>> = BTreeMap::new();
+        for (key, value) in config.section("keymaps") {
+            let Some((name, key)) = key.rsplit_once('.') else {
+                return Err(config::Error::Invalid {
+                    key: format!("keymaps.{key}"),
+                    message: "Expected a key inside a [keymaps.<name>] section".into(),
+                });
+            };
+            sections.entry(name).or_default().push((key, value));
+        }
+        let mut profiles = vec![];
+        for (name, entries) in sections {
+            let mut profile = Profile {
+                name: name.to_owned(),
+                keymap: default.clone(),
+                roms: vec![],
+            };
+            let mut bound: Vec<(u8, Binding)> = vec![];
+            for (key, value) in entries {
+                let invalid = |message: String| config::Error::Invalid {
+                    key: format!("keymaps.{name}.{key}"),
+                    message,
+                };
+                if key == "roms" {
+                    profile.roms = value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|rom| !rom.is_empty())
+                        .map(RomMatch::parse)
+                        .collect();
+                    continue;
+                }
+                let idx = u8::from_str_radix(key, 16)
+                    .ok()
+                    .filter(|idx| *idx < 16)
+                    .ok_or_else(|| {
+                        invalid("Expected roms or a keypad key between 0 and F".into())
+                    })?;
+                let binding = Binding::parse(value)
+                    .ok_or_else(|| invalid(format!("Unknown key {value:?}")))?;
+                if let Some((other, _)) = bound.iter().find(|(_, b)| *b == binding) {
+                    return Err(invalid(format!(
+                        "{binding} is already bound to key {other:X}"
+                    )));
+                }
+                bound.push((idx, binding));
+                profile.keymap.bind(idx, binding);
+            }
+            profiles.push(profile);
+        }
+        let preferred = match config.get("input.keymap") {
+            Some(name) => Some(
+                profiles
+                    .iter()
+                    .position(|profile| profile.name == name)
+                    .ok_or_else(|| config::Error::Invalid {
+                        key: "input.keymap".into(),
+                        message: format!("There is no [keymaps.{name}] section"),
+                    })?,
+            ),
+            None => None,
+        };
+        Ok(KeyMaps {
+            default,
+            profiles,
+            preferred,
+            suggested: None,
+            active: preferred,
+        })
+    }
+
+    /// Adds the bindings a ROM's sidecar file suggests as a profile of their own, used
+    /// for that ROM unless the config has a profile for it.
+    pub fn suggest(&mut self, rom: &[u8], keys: &[(u8, Binding)]) {
+        if keys.is_empty() {
+            return;
+        }
+        let mut keymap = self.default.clone();
+        for (key, binding) in keys {
+            keymap.bind(*key, *binding);
+        }
+        self.profiles.push(Profile {
+            name: "suggested".into(),
+            keymap,
+            roms: vec![],
+        });
+        self.suggested = Some((hash::fnv1a(rom), self.profiles.len() - 1));
+    }
+
+    /// Switches to the bindings for this ROM, in the order described on [`KeyMaps`].
+    pub fn select(&mut self, rom: &[u8], file_name: &str) {
+        let rom_hash = hash::fnv1a(rom);
+        let file_name = file_name.to_lowercase();
+        let by_hash = self
+            .profiles
+            .iter()
+            .position(|profile| profile.roms.contains(&RomMatch::Hash(rom_hash)));
+        let by_name = || {
+            self.profiles.iter().position(|profile| {
+                profile.roms.iter().any(|rom| match rom {
+                    RomMatch::Pattern(pattern) => glob(pattern, &file_name),
+                    RomMatch::Hash(_) => false,
+                })
+            })
+        };
+        let suggested = self
+            .suggested
+            .filter(|(hash, _)| *hash == rom_hash)
+            .map(|(_, idx)| idx);
+        self.active = by_hash.or_else(by_name).or(suggested).or(self.preferred);
+        info!("ROM hash {rom_hash:016x}, using {}", self.describe());
+    }
+
+    /// Moves on to the next profile, going back to the default bindings after the last.
+    pub fn cycle(&mut self) {
+        self.active = match self.active {
+            None if self.profiles.is_empty() => None,
+            None => Some(0),
+            Some(idx) => Some(idx + 1).filter(|idx| *idx < self.profiles.len()),
+        };
+    }
+
+    pub fn current(&self) -> &KeyMap {
+        match self.active {
+            Some(idx) => &self.profiles[idx].keymap,
+            None => &self.default,
+        }
+    }
+
+    /// Replaces the bindings in use, e.g. after rebinding, and saves them to where they
+    /// came from in `config`.
+    pub fn replace_current(&mut self, keymap: KeyMap, config: &mut Config) {
+        match self.active {
+            Some(idx) => {
+                let profile = &mut self.profiles[idx];
+                keymap.save_to(config, &format!("keymaps.{}", profile.name));
+                profile.keymap = keymap;
+            }
+            None => {
+                keymap.save_to(config, "keypad");
+                self.default = keymap;
+            }
+        }
+    }
+
+    /// e.g. `classic layout` or `arrows keys`, for the title and the on-screen display.
+    pub fn describe(&self) -> String {
+        match self.active {
+            Some(idx) => format!("{} keys", self.profiles[idx].name),
+            None => match self.default.layout {
+                Some(layout) => format!("{layout} layout"),
+                None => "custom layout".into(),
+            },
+        }
+    }
+}
+
+/// Matches `text` against `pattern`, where `*` stands for any run of characters and `?`
+/// for any one character.
+fn glob(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Where the last `*` was, and how much of the text it has taken so far
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Press-to-bind state: captures one physical key for each keypad key 0-F in turn.
+#[derive(Clone, Debug, Default)]
+pub struct Rebind(Vec<Binding>);
+
+impl Rebind {
+    pub fn current(&self) -> u8 {
+        self.0.len() as u8
+    }
+
+    /// Binds the current key, returning the finished map after key F.
+    pub fn bind(&mut self, scancode: Scancode) -> Option<KeyMap> {
+        let binding = Binding::Scancode(scancode);
+        if let Some(other) = self.0.iter().position(|k| *k == binding) {
+            warn!("{binding} is already bound to key {other:X}");
+            return None;
+        }
+        info!("Bound key {:X} to {binding}", self.current());
+        self.0.push(binding);
+        let keys = self.0.clone().try_into().ok()?;
+        Some(KeyMap { layout: None, keys })
+    }
+}
+
+/// Looks a keycode up by its variant name (`Num4`, `Kp1`, `LShift`, ...).
+fn parse_keycode(name: &str) -> Option<Keycode> {
+    KEYCODES
+        .iter()
+        .find(|(variant, _)| *variant == name)
+        .map(|(_, keycode)| *keycode)
+}
+
+fn parse_scancode(name: &str) -> Option<Scancode> {
+    SCANCODES
+        .iter()
+        .find(|(variant, _)| *variant == name)
+        .map(|(_, scancode)| *scancode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keymap(text: &str) -> Result<KeyMap, config::Error> {
+        KeyMap::from_config(&Config::parse(text).unwrap())
+    }
+
+    /// A `[keypad]` section binding key `n` to the nth of `names`.
+    fn keypad(names: &[&str]) -> String {
+        let mut text = "[keypad]\n".to_owned();
+        for (key, name) in names.iter().enumerate() {
+            text += &format!("{key:X} = \"{name}\"\n");
+        }
+        text
+    }
+
+    const KEYCODE_NAMES: [&str; 16] = [
+        "keycode:Num0",
+        "keycode:Num1",
+        "keycode:Num2",
+        "keycode:Num3",
+        "keycode:Num4",
+        "keycode:Num5",
+        "keycode:Num6",
+        "keycode:Num7",
+        "keycode:Num8",
+        "keycode:Num9",
+        "keycode:A",
+        "keycode:B",
+        "keycode:C",
+        "keycode:D",
+        "keycode:E",
+        "keycode:F",
+    ];
+
+    #[test]
+    fn keycode_names_round_trip() {
+        for (name, keycode) in KEYCODES {
+            let binding = Binding::Keycode(*keycode);
+            assert_eq!(
+                Binding::parse(&binding.to_string()),
+                Some(binding),
+                "{name}"
+            );
+        }
+        assert_eq!(
+            Binding::parse("keycode:LShift"),
+            Some(Binding::Keycode(Keycode::LShift))
+        );
+        assert_eq!(Binding::parse("keycode:Shift"), None);
+        assert_eq!(Binding::parse("keycode:"), None);
+    }
+
+    #[test]
+    fn scancode_names_round_trip() {
+        for (name, scancode) in SCANCODES {
+            let binding = Binding::Scancode(*scancode);
+            assert_eq!(
+                Binding::parse(&binding.to_string()),
+                Some(binding),
+                "{name}"
+            );
+        }
+        assert_eq!(
+            Binding::parse("Semicolon"),
+            Some(Binding::Scancode(Scancode::Semicolon))
+        );
+        assert_eq!(
+            Binding::parse("scancode:Kp1"),
+            Some(Binding::Scancode(Scancode::Kp1))
+        );
+        assert_eq!(Binding::parse(";"), None);
+        assert_eq!(Binding::parse(""), None);
+    }
+
+    /// Scancodes follow the key's position, whatever symbol the layout puts on it
+    #[test]
+    fn scancodes_ignore_the_symbol() {
+        let keymap = Layout::Classic.keymap();
+        // Q on QWERTY is A on AZERTY
+        assert_eq!(keymap.key(Some(Scancode::Q), Some(Keycode::A)), Some(0x4));
+        assert_eq!(keymap.key(Some(Scancode::A), Some(Keycode::Q)), Some(0x7));
+        assert_eq!(keymap.key(None, Some(Keycode::Q)), None);
+        assert_eq!(keymap.key(Some(Scancode::G), None), None);
+    }
+
+    #[test]
+    fn keycode_overrides_follow_the_symbol() {
+        let classic = Layout::Classic.keymap();
+        let mut names = (0..16)
+            .map(|key| classic.binding(key).to_string())
+            .collect::<Vec<_>>();
+        names[0] = "keycode:A".into();
+        let names = names.iter().map(String::as_str).collect::<Vec<_>>();
+        let keymap = keymap(&keypad(&names)).unwrap();
+        // The key marked A, wherever the layout puts it
+        assert_eq!(keymap.key(Some(Scancode::Q), Some(Keycode::A)), Some(0x0));
+        assert_eq!(keymap.key(Some(Scancode::A), Some(Keycode::Q)), Some(0x7));
+        assert_eq!(keymap.label(0x0), "A");
+        assert_eq!(keymap.label(0x1), "1");
+    }
+
+    #[test]
+    fn keypad_section() {
+        let keymap = keymap(&keypad(&KEYCODE_NAMES)).unwrap();
+        assert_eq!(keymap.binding(0xA), Binding::Keycode(Keycode::A));
+        assert_eq!(keymap.key(None, Some(Keycode::Num7)), Some(0x7));
+        assert_eq!(keymap.key(None, Some(Keycode::G)), None);
+        assert_eq!(keymap.label(0x3), "3");
+    }
+
+    #[test]
+    fn keypad_needs_every_key() {
+        let err = keymap(&keypad(&KEYCODE_NAMES[..14])).unwrap_err();
+        assert!(err.to_string().contains("Keys E, F are not bound"), "{err}");
+    }
+
+    #[test]
+    fn keypad_rejects_a_key_bound_twice() {
+        let mut names = KEYCODE_NAMES;
+        names[0xC] = "keycode:Num2";
+        let err = keymap(&keypad(&names)).unwrap_err();
+        assert!(err.to_string().contains("keypad.C"), "{err}");
+        assert!(err.to_string().contains("already bound to key 2"), "{err}");
+    }
+
+    #[test]
+    fn keypad_rejects_unknown_keys_and_indices() {
+        let mut names = KEYCODE_NAMES;
+        names[5] = "keycode:Nope";
+        let err = keymap(&keypad(&names)).unwrap_err();
+        assert!(err.to_string().contains("Unknown key"), "{err}");
+        let err = keymap("[keypad]\n10 = \"keycode:A\"\n").unwrap_err();
+        assert!(err.to_string().contains("keypad.10"), "{err}");
+    }
+
+    #[test]
+    fn no_keypad_section_uses_the_layout() {
+        assert_eq!(keymap("").unwrap(), Layout::Classic.keymap());
+        assert_eq!(
+            keymap("layout = \"right-hand\"\n").unwrap(),
+            Layout::RightHand.keymap()
+        );
+        assert!(keymap("layout = \"dvorak\"\n").is_err());
+    }
+
+    #[test]
+    fn presets_bind_every_key_once() {
+        for layout in [Layout::Classic, Layout::RightHand, Layout::CurrentDefault] {
+            let keymap = layout.keymap();
+            let mut scancodes = scancodes(&keymap);
+            scancodes.sort_by_key(|scancode| *scancode as i32);
+            scancodes.dedup();
+            assert_eq!(scancodes.len(), 16, "{layout}");
+            for key in 0..16 {
+                let Binding::Scancode(scancode) = keymap.binding(key) else {
+                    unreachable!();
+                };
+                assert_eq!(keymap.key(Some(scancode), None), Some(key), "{layout}");
+            }
+            assert_eq!(layout.to_string().parse(), Ok(layout));
+        }
+        assert_eq!(Layout::default(), Layout::Classic);
+    }
+
+    /// The keys hard-coded in `Keypad::press` before bindings could be changed.
+    #[test]
+    fn current_default_matches_the_old_bindings() {
+        let old = [
+            (Scancode::Num4, 0x1),
+            (Scancode::Num5, 0x2),
+            (Scancode::Num6, 0x3),
+            (Scancode::Num7, 0xC),
+            (Scancode::R, 0x4),
+            (Scancode::T, 0x5),
+            (Scancode::Y, 0x6),
+            (Scancode::U, 0xD),
+            (Scancode::F, 0x7),
+            (Scancode::G, 0x8),
+            (Scancode::H, 0x9),
+            (Scancode::J, 0xE),
+            (Scancode::V, 0xA),
+            (Scancode::B, 0x0),
+            (Scancode::N, 0xB),
+            (Scancode::M, 0xF),
+        ];
+        let keymap = Layout::CurrentDefault.keymap();
+        for (scancode, key) in old {
+            assert_eq!(keymap.binding(key), Binding::Scancode(scancode));
+        }
+    }
+
+    /// The scancodes of a map, in key order.
+    fn scancodes(keymap: &KeyMap) -> Vec<Scancode> {
+        (0..16)
+            .map(|key| match keymap.binding(key) {
+                Binding::Scancode(scancode) => scancode,
+                Binding::Keycode(keycode) => panic!("{keycode:?} is a keycode"),
+            })
+            .collect()
+    }
+
+    /// The classic layout's keys in key order, 0-F.
+    fn classic_order() -> Vec<Scancode> {
+        scancodes(&Layout::Classic.keymap())
+    }
+
+    #[test]
+    fn binding_one_key_swaps_with_its_old_owner() {
+        let mut keymap = Layout::Classic.keymap();
+        // X is key 0 in the classic layout
+        keymap.bind(0x5, Binding::Scancode(Scancode::X));
+        assert_eq!(keymap.binding(0x5), Binding::Scancode(Scancode::X));
+        assert_eq!(keymap.binding(0x0), Binding::Scancode(Scancode::W));
+        assert_eq!(keymap.layout, None);
+        // A key nothing had before just replaces the old one
+        keymap.bind(0x5, Binding::Scancode(Scancode::G));
+        assert_eq!(keymap.binding(0x5), Binding::Scancode(Scancode::G));
+        assert_eq!(keymap.key(Some(Scancode::X), None), None);
+        let mut distinct = scancodes(&keymap);
+        distinct.sort_by_key(|scancode| *scancode as i32);
+        distinct.dedup();
+        assert_eq!(distinct.len(), 16);
+    }
+
+    #[test]
+    fn rebinding_takes_a_key_for_each_in_turn() {
+        let mut rebind = Rebind::default();
+        let keys = classic_order();
+        for (idx, scancode) in keys[..15].iter().enumerate() {
+            assert_eq!(rebind.current(), idx as u8);
+            assert_eq!(rebind.bind(*scancode), None);
+        }
+        assert_eq!(rebind.current(), 0xF);
+        assert_eq!(
+            rebind.bind(keys[0xF]),
+            Some(KeyMap {
+                layout: None,
+                ..Layout::Classic.keymap()
+            })
+        );
+    }
+
+    #[test]
+    fn rebinding_skips_a_key_already_taken() {
+        let mut rebind = Rebind::default();
+        assert_eq!(rebind.bind(Scancode::X), None);
+        assert_eq!(rebind.bind(Scancode::Num1), None);
+        // Warned about and not taken, so key 2 is still the one being bound
+        assert_eq!(rebind.bind(Scancode::X), None);
+        assert_eq!(rebind.current(), 0x2);
+        assert_eq!(rebind.bind(Scancode::Num2), None);
+        assert_eq!(rebind.current(), 0x3);
+    }
+
+    /// Rebinding, saving to the file and loading it again gives the same bindings,
+    /// whether they are the default ones or a profile's.
+    #[test]
+    fn rebound_keys_round_trip_through_the_config_file() {
+        let path = std::env::temp_dir().join(format!("chip8-{}-rebind.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "# mine\nlayout = \"classic\"\n\n[keymaps.arrows]\n5 = \"Up\"\n",
+        )
+        .unwrap();
+        let mut config = Config::load(&path).unwrap();
+        let mut keymaps =
+            KeyMaps::from_config(&config, KeyMap::from_config(&config).unwrap()).unwrap();
+
+        let mut rebind = Rebind::default();
+        let mut keys = classic_order();
+        keys.swap(0x1, 0x2);
+        let rebound = keys
+            .iter()
+            .find_map(|scancode| rebind.bind(*scancode))
+            .unwrap();
+        keymaps.replace_current(rebound.clone(), &mut config);
+        config.save().unwrap();
+        let config = Config::load(&path).unwrap();
+        assert_eq!(KeyMap::from_config(&config).unwrap(), rebound);
+        assert_eq!(config.get("keypad.1"), Some("Num2"));
+
+        // A profile's keys go to its own section
+        let mut keymaps = KeyMaps::from_config(&config, rebound.clone()).unwrap();
+        keymaps.cycle();
+        assert_eq!(keymaps.describe(), "arrows keys");
+        let mut profile = keymaps.current().clone();
+        profile.bind(0x8, Binding::Scancode(Scancode::Down));
+        let mut config = Config::load(&path).unwrap();
+        keymaps.replace_current(profile.clone(), &mut config);
+        config.save().unwrap();
+        let config = Config::load(&path).unwrap();
+        let mut keymaps =
+            KeyMaps::from_config(&config, KeyMap::from_config(&config).unwrap()).unwrap();
+        assert_eq!(keymaps.current(), &rebound);
+        keymaps.cycle();
+        assert_eq!(keymaps.current(), &profile);
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(text.starts_with("# mine\n"), "{text}");
+    }
+
+    /// Each step of the order on [`KeyMaps`] wins over the ones after it.
+    #[test]
+    fn selects_profiles_in_order() {
+        let (hashed, suggested, other) = ([0x12, 0x00], [0x12, 0x02], [0x12, 0x04]);
+        let text = format!(
+            "input.keymap = \"preferred\"\n\
+             [keymaps.hashed]\nroms = \"{:016x}\"\n\
+             [keymaps.named]\nroms = \"pong*.ch8, *.c8\"\n\
+             [keymaps.second]\nroms = \"pong?.ch8\"\n\
+             [keymaps.preferred]\n5 = \"Up\"\n",
+            hash::fnv1a(&hashed)
+        );
+        let config = Config::parse(&text).unwrap();
+        let mut keymaps = KeyMaps::from_config(&config, Layout::Classic.keymap()).unwrap();
+        keymaps.suggest(&suggested, &[(0x5, Binding::Scancode(Scancode::Down))]);
+        let without_preferred = Config::parse(text.trim_start_matches(|c| c != '\n')).unwrap();
+        let mut fallback =
+            KeyMaps::from_config(&without_preferred, Layout::Classic.keymap()).unwrap();
+        fallback.suggest(&suggested, &[(0x5, Binding::Scancode(Scancode::Down))]);
+
+        #[rustfmt::skip]
+        let cases: &[(&KeyMaps, &[u8], &str, &str)] = &[
+            // 1. The hash beats a matching file name
+            (&keymaps, &hashed, "PONG1.ch8", "hashed keys"),
+            // 2. File names, case aside, with the first profile by name winning
+            (&keymaps, &suggested, "Pong1.ch8", "named keys"),
+            (&keymaps, &other, "breakout.c8", "named keys"),
+            // 3. The keys the ROM suggests, only for that ROM
+            (&keymaps, &suggested, "breakout.ch8", "suggested keys"),
+            // 4. input.keymap
+            (&keymaps, &other, "breakout.ch8", "preferred keys"),
+            // 5. The default bindings
+            (&fallback, &other, "breakout.ch8", "classic layout"),
+            (&fallback, &suggested, "breakout.ch8", "suggested keys"),
+        ];
+        for (idx, (keymaps, rom, file_name, expected)) in cases.iter().enumerate() {
+            let mut keymaps = (*keymaps).clone();
+            // Starting from another profile, so nothing is left over from before
+            keymaps.cycle();
+            keymaps.select(rom, file_name);
+            assert_eq!(keymaps.describe(), *expected, "case {idx}: {file_name}");
+        }
+        keymaps.select(&suggested, "breakout.ch8");
+        assert_eq!(
+            keymaps.current().binding(0x5),
+            Binding::Scancode(Scancode::Down)
+        );
+        keymaps.select(&other, "breakout.ch8");
+        assert_eq!(
+            keymaps.current().binding(0x5),
+            Binding::Scancode(Scancode::Up)
+        );
+        // Keys the profile doesn't mention keep their default bindings
+        assert_eq!(
+            keymaps.current().binding(0x8),
+            Binding::Scancode(Scancode::S)
+        );
+    }
+}
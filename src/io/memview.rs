@@ -0,0 +1,247 @@
+//! The memory viewer: a page of memory as hex and ASCII, 16 bytes a row.
+
+/// Bytes on each row.
+pub const ROW: u16 = 16;
+/// Rows on a page.
+pub const ROWS: u16 = 16;
+pub const PAGE: u16 = ROW * ROWS;
+/// The last page that still ends inside the 4K of memory.
+const LAST_TOP: u16 = 0x1000 - PAGE;
+
+/// Which page is shown, and the address being typed in to jump to.
+#[derive(Debug)]
+pub struct MemoryView {
+    top: u16,
+    prompt: Option<String>,
+}
+
+impl Default for MemoryView {
+    fn default() -> Self {
+        MemoryView {
+            top: 0x200,
+            prompt: None,
+        }
+    }
+}
+
+/// What a byte is highlighted as.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Highlight {
+    /// One of the two bytes of the next instruction
+    Pc,
+    /// The byte I points to
+    I,
+}
+
+impl MemoryView {
+    /// The first address shown.
+    pub fn top(&self) -> u16 {
+        self.top
+    }
+
+    /// Moves by whole pages, stopping at either end of memory.
+    pub fn scroll(&mut self, pages: i32) {
+        let top = i32::from(self.top) + pages * i32::from(PAGE);
+        self.top = top.clamp(0, i32::from(LAST_TOP)) as u16;
+    }
+
+    /// Shows the row with `addr` at the top, or the last page if that is past it.
+    pub fn go_to(&mut self, addr: u16) {
+        self.top = (addr - addr % ROW).min(LAST_TOP);
+    }
+
+    pub fn prompt(&self) -> Option<&str> {
+        self.prompt.as_deref()
+    }
+
+    pub fn start_prompt(&mut self) {
+        self.prompt = Some(String::new());
+    }
+
+    pub fn cancel_prompt(&mut self) {
+        self.prompt = None;
+    }
+
+    /// Adds a hex digit to the address being typed, ignoring anything else and
+    /// anything past three digits.
+    pub fn type_char(&mut self, c: char) {
+        if let Some(prompt) = &mut self.prompt {
+            if c.is_ascii_hexdigit() && prompt.len() < 3 {
+                prompt.push(c.to_ascii_uppercase());
+            }
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        if let Some(prompt) = &mut self.prompt {
+            prompt.pop();
+        }
+    }
+
+    /// Jumps to the typed address, if one was typed.
+    pub fn finish_prompt(&mut self) {
+        let typed = self.prompt.take().unwrap_or_default();
+        if let Ok(addr) = u16::from_str_radix(&typed, 16) {
+            self.go_to(addr);
+        }
+    }
+
+    /// How `addr` is highlighted when the next instruction is at `pc`.
+    pub fn highlight(addr: u16, pc: u16, i: u16) -> Option<Highlight> {
+        if addr == pc || addr == pc.wrapping_add(1) {
+            Some(Highlight::Pc)
+        } else if addr == i {
+            Some(Highlight::I)
+        } else {
+            None
+        }
+    }
+
+    /// Row and column, in characters, of the hex digits of `addr` in [`MemoryView::text`].
+    pub fn position(&self, addr: u16) -> Option<(u16, u16)> {
+        let offset = addr.checked_sub(self.top).filter(|offset| *offset < PAGE)?;
+        Some((offset / ROW, 5 + 3 * (offset % ROW)))
+    }
+
+    /// The page as text, from `memory` which starts at `start`. Bytes that weren't
+    /// copied show as `--`.
+    pub fn text(&self, memory: &[u8], start: u16) -> String {
+        let byte = |addr: u16| {
+            addr.checked_sub(start)
+                .and_then(|offset| memory.get(usize::from(offset)))
+                .copied()
+        };
+        (0..ROWS)
+            .map(|row| {
+                let addr = self.top + row * ROW;
+                let mut hex = format!("{addr:04X}");
+                let mut ascii = String::new();
+                for addr in addr..addr + ROW {
+                    match byte(addr) {
+                        Some(value) => {
+                            hex.push_str(&format!(" {value:02X}"));
+                            ascii.push(match value {
+                                0x20..=0x7E => char::from(value),
+                                _ => '.',
+                            });
+                        }
+                        None => {
+                            hex.push_str(" --");
+                            ascii.push(' ');
+                        }
+                    }
+                }
+                format!("{hex}  {ascii}")
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scrolling_stops_at_either_end() {
+        let mut view = MemoryView::default();
+        view.scroll(1);
+        assert_eq!(view.top(), 0x300);
+        view.scroll(-4);
+        assert_eq!(view.top(), 0);
+        view.scroll(100);
+        assert_eq!(view.top(), 0xF00);
+        view.scroll(1);
+        assert_eq!(view.top(), 0xF00);
+    }
+
+    #[test]
+    fn going_to_an_address_shows_its_row() {
+        let mut view = MemoryView::default();
+        view.go_to(0x345);
+        assert_eq!(view.top(), 0x340);
+        view.go_to(0xFF0);
+        assert_eq!(view.top(), 0xF00);
+    }
+
+    #[test]
+    fn prompt_takes_up_to_three_hex_digits() {
+        let mut view = MemoryView::default();
+        // Typing without a prompt does nothing
+        view.type_char('4');
+        assert_eq!(view.prompt(), None);
+        view.start_prompt();
+        for c in "4g5a6".chars() {
+            view.type_char(c);
+        }
+        assert_eq!(view.prompt(), Some("45A"));
+        view.backspace();
+        view.type_char('b');
+        assert_eq!(view.prompt(), Some("45B"));
+        view.finish_prompt();
+        assert_eq!(view.prompt(), None);
+        assert_eq!(view.top(), 0x450);
+
+        view.start_prompt();
+        view.type_char('8');
+        view.cancel_prompt();
+        assert_eq!(view.top(), 0x450);
+        // Nothing typed stays put
+        view.start_prompt();
+        view.finish_prompt();
+        assert_eq!(view.top(), 0x450);
+    }
+
+    #[test]
+    fn highlights_pc_before_i() {
+        assert_eq!(
+            MemoryView::highlight(0x200, 0x200, 0x300),
+            Some(Highlight::Pc)
+        );
+        assert_eq!(
+            MemoryView::highlight(0x201, 0x200, 0x201),
+            Some(Highlight::Pc)
+        );
+        assert_eq!(
+            MemoryView::highlight(0x300, 0x200, 0x300),
+            Some(Highlight::I)
+        );
+        assert_eq!(MemoryView::highlight(0x202, 0x200, 0x300), None);
+    }
+
+    #[test]
+    fn positions_bytes_on_the_page() {
+        let view = MemoryView::default();
+        assert_eq!(view.position(0x200), Some((0, 5)));
+        assert_eq!(view.position(0x21F), Some((1, 5 + 3 * 15)));
+        assert_eq!(view.position(0x1FF), None);
+        assert_eq!(view.position(0x300), None);
+    }
+
+    #[test]
+    fn formats_hex_rows_with_ascii() {
+        let view = MemoryView::default();
+        let mut memory: Vec<u8> = b"Hi!\x00".to_vec();
+        memory.extend(0xF0..=0xFB);
+        // Only the first row and a half were copied
+        memory.extend([0x7E; 8]);
+        let text = view.text(&memory, 0x200);
+        let rows: Vec<_> = text.lines().collect();
+        assert_eq!(rows.len(), usize::from(ROWS));
+        assert_eq!(
+            rows[0],
+            "0200 48 69 21 00 F0 F1 F2 F3 F4 F5 F6 F7 F8 F9 FA FB  Hi!............."
+        );
+        assert_eq!(
+            rows[1],
+            "0210 7E 7E 7E 7E 7E 7E 7E 7E -- -- -- -- -- -- -- --  ~~~~~~~~        "
+        );
+        assert_eq!(
+            rows[15],
+            format!("02F0{}  {}", " --".repeat(16), " ".repeat(16))
+        );
+        // Each byte's hex digits are where `position` says
+        let (row, column) = view.position(0x202).unwrap();
+        assert_eq!(&rows[usize::from(row)][usize::from(column)..][..2], "21");
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+use std::time::Instant;
+
+/// How long a message stays up, including the fade out.
+const DURATION: Duration = Duration::from_secs(2);
+const FADE: Duration = Duration::from_millis(500);
+/// Older messages are dropped once there are this many.
+const MAX_MESSAGES: usize = 4;
+
+/// Short status messages shown on top of the game, oldest first.
+#[derive(Clone, Debug, Default)]
+pub struct Osd {
+    messages: VecDeque<(String, Instant)>,
+}
+
+impl Osd {
+    /// Shows `text`, replacing an identical message that is still up.
+    pub fn push(&mut self, text: impl Into<String>, now: Instant) {
+        let text = text.into();
+        self.messages.retain(|(other, _)| *other != text);
+        self.messages.push_back((text, now));
+        while self.messages.len() > MAX_MESSAGES {
+            self.messages.pop_front();
+        }
+    }
+
+    /// The messages still up, with their opacity from 0 to 255.
+    pub fn visible(&mut self, now: Instant) -> impl Iterator<Item = (&str, u8)> {
+        self.messages
+            .retain(|(_, shown)| now.duration_since(*shown) < DURATION);
+        self.messages.iter().map(move |(text, shown)| {
+            let left = DURATION.saturating_sub(now.duration_since(*shown));
+            let alpha = (left.as_secs_f32() / FADE.as_secs_f32()).min(1.0);
+            (text.as_str(), (alpha * 255.0) as u8)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shown(osd: &mut Osd, now: Instant) -> Vec<(String, u8)> {
+        osd.visible(now)
+            .map(|(text, alpha)| (text.to_owned(), alpha))
+            .collect()
+    }
+
+    #[test]
+    fn messages_fade_out_and_expire() {
+        let start = Instant::now();
+        let mut osd = Osd::default();
+        osd.push("Paused", start);
+        let at = |ms| start + Duration::from_millis(ms);
+        assert_eq!(shown(&mut osd, at(0)), [("Paused".into(), 255)]);
+        assert_eq!(shown(&mut osd, at(1500)), [("Paused".into(), 255)]);
+        // Halfway through the fade
+        assert_eq!(shown(&mut osd, at(1750)), [("Paused".into(), 127)]);
+        assert!(shown(&mut osd, at(2000)).is_empty());
+        // Gone for good, not just hidden
+        assert!(shown(&mut osd, at(1000)).is_empty());
+    }
+
+    /// The same message again replaces the one up, moving it to the end with its
+    /// time starting over.
+    #[test]
+    fn repeats_are_coalesced() {
+        let start = Instant::now();
+        let mut osd = Osd::default();
+        osd.push("Speed 2x", start);
+        osd.push("Muted", start);
+        osd.push("Speed 2x", start + Duration::from_millis(1000));
+        let texts = |osd: &mut Osd, ms| -> Vec<String> {
+            shown(osd, start + Duration::from_millis(ms))
+                .into_iter()
+                .map(|(text, _)| text)
+                .collect()
+        };
+        assert_eq!(texts(&mut osd, 1000), ["Muted", "Speed 2x"]);
+        assert_eq!(texts(&mut osd, 2500), ["Speed 2x"]);
+    }
+
+    #[test]
+    fn only_the_newest_few_stay() {
+        let start = Instant::now();
+        let mut osd = Osd::default();
+        for n in 0..6 {
+            osd.push(format!("Slot {n}"), start);
+        }
+        let texts: Vec<_> = shown(&mut osd, start)
+            .into_iter()
+            .map(|(text, _)| text)
+            .collect();
+        assert_eq!(texts, ["Slot 2", "Slot 3", "Slot 4", "Slot 5"]);
+    }
+}
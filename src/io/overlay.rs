@@ -0,0 +1,453 @@
+use super::browser::Browser;
+use super::debug::Snapshot;
+use super::font;
+use super::memview;
+use super::memview::MemoryView;
+use super::viewport::Viewport;
+use sdl2::pixels::Color;
+use sdl2::rect::Point;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+/// Keys in the order they appear on the COSMAC VIP pad.
+#[rustfmt::skip]
+pub const KEYPAD_LAYOUT: [u8; 16] = [
+    0x1, 0x2, 0x3, 0xC,
+    0x4, 0x5, 0x6, 0xD,
+    0x7, 0x8, 0x9, 0xE,
+    0xA, 0x0, 0xB, 0xF,
+];
+
+fn keypad_cells(area: Rect) -> impl Iterator<Item = (u8, Rect)> {
+    let (width, height) = (area.width() / 4, area.height() / 4);
+    KEYPAD_LAYOUT
+        .into_iter()
+        .enumerate()
+        .map(move |(idx, key)| {
+            let x = area.x() + (idx % 4) as i32 * width as i32;
+            let y = area.y() + (idx / 4) as i32 * height as i32;
+            (key, Rect::new(x, y, width, height))
+        })
+}
+
+/// The key drawn at `point` by [`draw_keypad`] in the same area.
+pub fn keypad_cell(area: Rect, point: Point) -> Option<u8> {
+    keypad_cells(area)
+        .find(|(_, cell)| cell.contains_point(point))
+        .map(|(key, _)| key)
+}
+
+/// Draws the 4x4 pad over `area`, filling the highlighted keys.
+pub fn draw_keypad(
+    canvas: &mut Canvas<Window>,
+    viewport: &Viewport,
+    area: Rect,
+    highlight: impl Fn(u8) -> bool,
+) -> Result<(), String> {
+    for (key, cell) in keypad_cells(area) {
+        let (background, foreground) = if highlight(key) {
+            (Color::WHITE, Color::BLACK)
+        } else {
+            (Color::BLACK, Color::WHITE)
+        };
+        canvas.set_draw_color(background);
+        canvas.fill_rect(viewport.rect(cell))?;
+        canvas.set_draw_color(Color::GREY);
+        canvas.draw_rect(viewport.rect(cell))?;
+        canvas.set_draw_color(foreground);
+        let (x, y) = (cell.center().x() - 2, cell.center().y() - 2);
+        draw_digit(canvas, viewport, key, x, y)?;
+    }
+    canvas.set_draw_color(Color::BLACK);
+    Ok(())
+}
+
+/// Draws a hex digit with the built-in 4x5 CHIP-8 font.
+fn draw_digit(
+    canvas: &mut Canvas<Window>,
+    viewport: &Viewport,
+    digit: u8,
+    x: i32,
+    y: i32,
+) -> Result<(), String> {
+    for (row, bits) in crate::FONTS[usize::from(digit)].iter().enumerate() {
+        for col in 0..4 {
+            if bits & (0x80 >> col) != 0 {
+                let pixel = Rect::new(x + col, y + row as i32, 1, 1);
+                canvas.fill_rect(viewport.rect(pixel))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Size of the key state panel drawn by [`draw_key_state`]: 4x4 cells wide enough
+/// for a four character label under each key.
+pub fn key_state_size(scale: u32, labels: bool) -> (u32, u32) {
+    let glyph = font::GLYPH_SIZE * scale;
+    let lines = if labels { 2 } else { 1 };
+    (4 * (4 * glyph + 2 * scale), 4 * (lines * glyph + 4 * scale))
+}
+
+/// Where the key state panel goes in an `output` sized window: the bottom right,
+/// out of the way of the messages on the left. Pinned to the top left instead when
+/// the window is too small for it.
+pub fn key_state_area(output: (u32, u32), scale: u32, labels: bool) -> Rect {
+    let (width, height) = key_state_size(scale, labels);
+    let margin = 4 * scale;
+    Rect::new(
+        output.0.saturating_sub(width + margin) as i32,
+        output.1.saturating_sub(height + margin) as i32,
+        width,
+        height,
+    )
+}
+
+/// Draws the keypad in window pixels with the pressed keys filled in, and
+/// optionally each key's binding under it.
+pub fn draw_key_state(
+    canvas: &mut Canvas<Window>,
+    area: Rect,
+    scale: u32,
+    pressed: impl Fn(u8) -> bool,
+    label: Option<&dyn Fn(u8) -> String>,
+) -> Result<(), String> {
+    let glyph = (font::GLYPH_SIZE * scale) as i32;
+    for (key, cell) in keypad_cells(area) {
+        let (background, foreground) = if pressed(key) {
+            (Color::WHITE, Color::BLACK)
+        } else {
+            (Color::RGBA(0, 0, 0, 0xC0), Color::WHITE)
+        };
+        canvas.set_blend_mode(sdl2::render::BlendMode::Blend);
+        canvas.set_draw_color(background);
+        canvas.fill_rect(cell)?;
+        canvas.set_blend_mode(sdl2::render::BlendMode::None);
+        canvas.set_draw_color(Color::GREY);
+        canvas.draw_rect(cell)?;
+        canvas.set_draw_color(foreground);
+        let mut lines = vec![format!("{key:X}")];
+        lines.extend(label.map(|label| label(key).chars().take(4).collect()));
+        let mut y = cell.y() + 2 * scale as i32;
+        for line in lines {
+            let (width, _) = font::text_size(&line, scale);
+            let x = cell.center().x() - width as i32 / 2;
+            let rects = font::text_rects(&line, Point::new(x, y), scale);
+            canvas.fill_rects(&rects)?;
+            y += glyph;
+        }
+    }
+    canvas.set_draw_color(Color::BLACK);
+    Ok(())
+}
+
+/// Rows shown by the sprite viewer, as many as DXYN can draw.
+pub const SPRITE_ROWS: u16 = 15;
+
+/// The lit pixels of `bytes` drawn as a sprite, as (x, y) with x counting from the
+/// high bit of each byte.
+pub fn sprite_pixels(bytes: &[u8]) -> Vec<(u8, u8)> {
+    (0..)
+        .zip(bytes)
+        .flat_map(|(y, byte)| {
+            (0..8)
+                .filter(move |x| byte & (0x80 >> x) != 0)
+                .map(move |x| (x, y))
+        })
+        .collect()
+}
+
+/// Draws `bytes` as a sprite with its top left corner at `origin`, each sprite pixel
+/// `pixel` window pixels square, on a box showing the 8 pixel wide area.
+pub fn draw_sprite(
+    canvas: &mut Canvas<Window>,
+    bytes: &[u8],
+    origin: Point,
+    pixel: u32,
+) -> Result<(), String> {
+    let rows = bytes.len() as u32;
+    canvas.set_blend_mode(sdl2::render::BlendMode::Blend);
+    canvas.set_draw_color(Color::RGBA(0, 0, 0, 0xC0));
+    canvas.fill_rect(Rect::new(origin.x(), origin.y(), 8 * pixel, rows * pixel))?;
+    canvas.set_blend_mode(sdl2::render::BlendMode::None);
+    canvas.set_draw_color(Color::WHITE);
+    let rects: Vec<_> = sprite_pixels(bytes)
+        .into_iter()
+        .map(|(x, y)| {
+            Rect::new(
+                origin.x() + i32::from(x) * pixel as i32,
+                origin.y() + i32::from(y) * pixel as i32,
+                pixel,
+                pixel,
+            )
+        })
+        .collect();
+    canvas.fill_rects(&rects)?;
+    canvas.set_draw_color(Color::GREY);
+    canvas.draw_rect(Rect::new(origin.x(), origin.y(), 8 * pixel, rows * pixel))?;
+    canvas.set_draw_color(Color::BLACK);
+    Ok(())
+}
+
+/// The panels drawn over the game, each toggled by its own hotkey.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Shown {
+    pub stats: bool,
+    pub key_state: bool,
+    pub console: bool,
+    pub registers: bool,
+    pub disassembly: bool,
+    pub memory: bool,
+    pub sprite: bool,
+}
+
+impl Shown {
+    /// Whether one of them shows the core's state, so its snapshot has to follow it.
+    pub fn debug(&self) -> bool {
+        self.registers || self.disassembly || self.memory || self.sprite
+    }
+}
+
+/// The height of a line of text at `scale`, with room between lines.
+pub fn line_height(scale: u32) -> i32 {
+    ((font::GLYPH_SIZE + 6) * scale) as i32
+}
+
+/// Draws `lines` over the bottom half of the window, the newest at the bottom and
+/// `scroll` lines up from it, with errors and warnings in their own colors.
+pub fn draw_console(
+    canvas: &mut Canvas<Window>,
+    lines: &[String],
+    scroll: usize,
+    scale: u32,
+) -> Result<(), String> {
+    let (width, height) = canvas.output_size()?;
+    let line_height = line_height(scale);
+    let top = height as i32 / 2;
+    canvas.set_blend_mode(sdl2::render::BlendMode::Blend);
+    canvas.set_draw_color(Color::RGBA(0, 0, 0, 0xC0));
+    canvas.fill_rect(Rect::new(0, top, width, height - top as u32))?;
+    canvas.set_blend_mode(sdl2::render::BlendMode::None);
+    let mut y = height as i32 - line_height;
+    let rows = ((height as i32 - top) / line_height).max(0) as usize;
+    for line in lines.iter().rev().skip(scroll).take(rows) {
+        let color = if line.starts_with("ERROR") {
+            Color::RGB(0xFF, 0x55, 0x55)
+        } else if line.starts_with("WARN") {
+            Color::RGB(0xFF, 0xFF, 0x55)
+        } else {
+            Color::WHITE
+        };
+        let origin = Point::new(4 * scale as i32, y + 2 * scale as i32);
+        font::draw_text(canvas, line, origin, scale, color)?;
+        y -= line_height;
+    }
+    if lines.is_empty() {
+        let origin = Point::new(4 * scale as i32, y + 2 * scale as i32);
+        font::draw_text(canvas, "NO MESSAGES", origin, scale, Color::WHITE)?;
+    }
+    Ok(())
+}
+
+/// Draws the on-screen messages up from the bottom left corner, the newest lowest,
+/// each with how opaque it still is.
+pub fn draw_messages(
+    canvas: &mut Canvas<Window>,
+    messages: &[(&str, u8)],
+    scale: u32,
+) -> Result<(), String> {
+    let line_height = line_height(scale);
+    let mut y = canvas.output_size()?.1 as i32 - line_height;
+    for (text, alpha) in messages.iter().rev() {
+        let origin = Point::new(4 * scale as i32, y + 2 * scale as i32);
+        let color = Color::RGBA(0xFF, 0xFF, 0xFF, *alpha);
+        font::draw_text(canvas, text, origin, scale, color)?;
+        y -= line_height;
+    }
+    Ok(())
+}
+
+/// Panels stacked down the right edge of the window, each under the last.
+pub struct Column {
+    right: i32,
+    y: i32,
+    scale: u32,
+}
+
+impl Column {
+    pub fn new(output_width: u32, scale: u32) -> Column {
+        Column {
+            right: output_width as i32 - 4 * scale as i32,
+            y: 4 * scale as i32,
+            scale,
+        }
+    }
+
+    /// The top left corner of the next panel, which is `size` big, kept on screen.
+    pub fn place(&mut self, (width, height): (u32, u32)) -> Point {
+        let x = (self.right - width as i32).max(4 * self.scale as i32);
+        let origin = Point::new(x, self.y);
+        self.y += (height + 6 * self.scale) as i32;
+        origin
+    }
+
+    /// Draws `text` as the next panel.
+    pub fn draw_text(&mut self, canvas: &mut Canvas<Window>, text: &str) -> Result<(), String> {
+        let origin = self.place(font::text_size(text, self.scale));
+        font::draw_text(canvas, text, origin, self.scale, Color::WHITE)
+    }
+}
+
+/// Draws a page of memory as the next panel in `column`, with the bytes at the
+/// program counter and at I in their own colors, or the address being typed in.
+pub fn draw_memory(
+    canvas: &mut Canvas<Window>,
+    column: &mut Column,
+    view: &MemoryView,
+    snapshot: &Snapshot,
+) -> Result<(), String> {
+    let scale = column.scale;
+    let header = match view.prompt() {
+        Some(typed) => format!("GO TO {typed}_"),
+        None => "PGUP/PGDN, HOME: PC, CTRL+L: GO TO".into(),
+    };
+    let page = view.text(&snapshot.memory, snapshot.memory_start);
+    let text = format!("{header}\n{page}");
+    let origin = column.place(font::text_size(&text, scale));
+    font::draw_text(canvas, &text, origin, scale, Color::WHITE)?;
+    // Drawn again over the page in their own colors
+    let step = (font::GLYPH_SIZE * scale) as i32;
+    let (pc, i) = (snapshot.pc, snapshot.i);
+    for addr in [i, pc, pc.wrapping_add(1)] {
+        let (Some(highlight), Some((row, col))) =
+            (MemoryView::highlight(addr, pc, i), view.position(addr))
+        else {
+            continue;
+        };
+        let Some(value) = addr
+            .checked_sub(snapshot.memory_start)
+            .and_then(|offset| snapshot.memory.get(usize::from(offset)))
+        else {
+            continue;
+        };
+        let color = match highlight {
+            memview::Highlight::Pc => Color::RGB(0x55, 0xFF, 0x55),
+            memview::Highlight::I => Color::RGB(0xFF, 0xB0, 0x00),
+        };
+        let origin = Point::new(
+            origin.x() + i32::from(col) * step,
+            origin.y() + (i32::from(row) + 1) * step,
+        );
+        font::draw_text(canvas, &format!("{value:02X}"), origin, scale, color)?;
+    }
+    Ok(())
+}
+
+/// Draws the bytes at I as the next panel in `column`, with the sprite they make
+/// to the left of them.
+pub fn draw_sprite_panel(
+    canvas: &mut Canvas<Window>,
+    column: &mut Column,
+    snapshot: &Snapshot,
+) -> Result<(), String> {
+    let scale = column.scale;
+    // Each sprite row lines up with the line of text showing its byte
+    let step = font::GLYPH_SIZE * scale;
+    let mut text = format!("I {:04X}", snapshot.i);
+    for (offset, byte) in (0..).zip(&snapshot.sprite) {
+        let addr = snapshot.i.wrapping_add(offset);
+        text.push_str(&format!("\n{addr:04X} {byte:02X}"));
+    }
+    let origin = column.place(font::text_size(&text, scale));
+    font::draw_text(canvas, &text, origin, scale, Color::WHITE)?;
+    let sprite = Point::new(
+        origin.x() - (8 * step + 4 * scale) as i32,
+        origin.y() + step as i32,
+    );
+    draw_sprite(canvas, &snapshot.sprite, sprite, step)
+}
+
+/// Draws the ROM list over everything else, dimmed, with the selected one marked.
+pub fn draw_browser(
+    canvas: &mut Canvas<Window>,
+    list: &mut Browser,
+    scale: u32,
+) -> Result<(), String> {
+    canvas.set_blend_mode(sdl2::render::BlendMode::Blend);
+    canvas.set_draw_color(Color::RGBA(0, 0, 0, 0xC0));
+    canvas.fill_rect(None)?;
+    canvas.set_blend_mode(sdl2::render::BlendMode::None);
+    let margin = 4 * scale as i32;
+    let line_height = line_height(scale);
+    let header = "UP/DOWN, ENTER: PLAY, ESC: BACK";
+    font::draw_text(
+        canvas,
+        header,
+        Point::new(margin, margin),
+        scale,
+        Color::WHITE,
+    )?;
+    let rows = (canvas.output_size()?.1 as i32 - 2 * margin) / line_height - 1;
+    let mut y = margin + line_height;
+    for (name, selected) in list.page(rows.max(1) as usize) {
+        let (text, color) = match selected {
+            true => (format!("> {name}"), Color::RGB(0x55, 0xFF, 0x55)),
+            false => (format!("  {name}"), Color::WHITE),
+        };
+        font::draw_text(canvas, &text, Point::new(margin, y), scale, color)?;
+        y += line_height;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_state_sits_in_the_bottom_right() {
+        let (width, height) = key_state_size(2, true);
+        let area = key_state_area((640, 480), 2, true);
+        assert_eq!(area.x(), (640 - width - 8) as i32);
+        assert_eq!(area.y(), (480 - height - 8) as i32);
+        assert_eq!((area.width(), area.height()), (width, height));
+    }
+
+    /// A window smaller than the panel used to wrap around to far off screen.
+    #[test]
+    fn key_state_stays_on_a_small_window() {
+        for labels in [false, true] {
+            let area = key_state_area((20, 10), 1, labels);
+            assert_eq!((area.x(), area.y()), (0, 0));
+        }
+        let (width, _) = key_state_size(1, false);
+        let area = key_state_area((width + 2, 1000), 1, false);
+        assert_eq!(area.x(), 0);
+        assert!(area.y() > 0);
+    }
+
+    #[test]
+    fn panels_stack_down_the_right_edge() {
+        let mut column = Column::new(640, 2);
+        assert_eq!(column.place((100, 40)), Point::new(640 - 8 - 100, 8));
+        assert_eq!(
+            column.place((50, 10)),
+            Point::new(640 - 8 - 50, 8 + 40 + 12)
+        );
+        // Too wide for the window, it starts at the left margin instead
+        assert_eq!(column.place((700, 10)).x(), 8);
+    }
+
+    #[test]
+    fn debug_panels_need_the_snapshot() {
+        let mut shown = Shown {
+            stats: true,
+            console: true,
+            ..Shown::default()
+        };
+        assert!(!shown.debug());
+        shown.sprite = true;
+        assert!(shown.debug());
+    }
+}
@@ -0,0 +1,95 @@
+use super::color::Palette;
+use sdl2::pixels::Color;
+use std::time::Duration;
+
+/// Emulates the afterglow of a CRT to hide the flicker of XOR-drawn sprites: lit
+/// pixels are at full brightness and fade out over `decay` once they turn off.
+#[derive(Clone, Debug)]
+pub struct Phosphor {
+    decay: Duration,
+    intensity: Vec<f32>,
+}
+
+impl Phosphor {
+    pub fn new(decay: Duration) -> Phosphor {
+        Phosphor {
+            decay,
+            intensity: vec![0.0; 64 * 32],
+        }
+    }
+
+    pub fn update(&mut self, vram: &[bool], elapsed: Duration) {
+        let fade = elapsed.as_secs_f32() / self.decay.as_secs_f32();
+        for (intensity, pix) in self.intensity.iter_mut().zip(vram) {
+            *intensity = if *pix {
+                1.0
+            } else {
+                (*intensity - fade).max(0.0)
+            };
+        }
+    }
+
+    pub fn colors(&self, palette: &Palette) -> Vec<Color> {
+        self.intensity
+            .iter()
+            .map(|intensity| palette.blend(*intensity))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(10);
+
+    /// A pixel lit for a frame, then off for `frames` frames of `FRAME` each:
+    /// its intensity after each of them.
+    fn fading(decay: Duration, frames: usize) -> Vec<f32> {
+        let mut phosphor = Phosphor::new(decay);
+        let mut vram = [false; 64 * 32];
+        vram[0] = true;
+        phosphor.update(&vram, FRAME);
+        vram[0] = false;
+        (0..frames)
+            .map(|_| {
+                phosphor.update(&vram, FRAME);
+                phosphor.intensity[0]
+            })
+            .collect()
+    }
+
+    /// Fades linearly, reaching the background once `decay` has passed and staying
+    /// there.
+    #[test]
+    fn fades_to_the_background_over_the_decay() {
+        let intensities = fading(Duration::from_millis(50), 7);
+        let expected = [0.8, 0.6, 0.4, 0.2, 0.0, 0.0, 0.0];
+        for (frame, (got, want)) in intensities.iter().zip(expected).enumerate() {
+            assert!((got - want).abs() < 1e-4, "frame {frame}: {intensities:?}");
+        }
+        // Well before it's gone, still more than halfway lit
+        assert!(fading(Duration::from_secs(1), 40)[39] > 0.5);
+        // Without any decay, it's gone the first frame
+        assert_eq!(fading(Duration::ZERO, 1), [0.0]);
+    }
+
+    #[test]
+    fn lit_pixels_are_at_full_brightness() {
+        let palette: Palette = "amber".parse().unwrap();
+        let mut phosphor = Phosphor::new(Duration::from_millis(50));
+        let mut vram = [false; 64 * 32];
+        vram[1] = true;
+        phosphor.update(&vram, FRAME);
+        phosphor.update(&vram, FRAME);
+        vram[1] = false;
+        phosphor.update(&vram, Duration::from_millis(25));
+        vram[0] = true;
+        phosphor.update(&vram, FRAME);
+        let colors = phosphor.colors(&palette);
+        assert_eq!(colors[0], palette.0[1]);
+        // Off for 35ms of the 50, so 30% of the way from the background
+        assert_eq!(colors[1], palette.blend(0.3));
+        assert_eq!(colors[2], palette.background());
+    }
+}
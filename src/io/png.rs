@@ -0,0 +1,181 @@
+//! Just enough of PNG to write screenshots: 8-bit RGB, with the image data in
+//! uncompressed deflate blocks.
+
+pub fn encode_rgb(width: u32, height: u32, rgb: &[u8]) -> Vec<u8> {
+    assert_eq!(rgb.len(), (width * height * 3) as usize);
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+
+    let mut header = vec![];
+    header.extend(width.to_be_bytes());
+    header.extend(height.to_be_bytes());
+    // 8 bits per channel, RGB, deflate, default filtering, no interlacing
+    header.extend([8, 2, 0, 0, 0]);
+    chunk(&mut png, b"IHDR", &header);
+
+    // Every row starts with its filter type, 0 for none
+    let mut raw = vec![];
+    for row in rgb.chunks(width as usize * 3) {
+        raw.push(0);
+        raw.extend(row);
+    }
+    chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend(kind);
+    png.extend(data);
+    let crc = crc32(&png[start..]);
+    png.extend(crc.to_be_bytes());
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // Deflate with a 32K window, no preset dictionary
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        out.extend([1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        out.push(u8::from(last));
+        let len = block.len() as u16;
+        out.extend(len.to_le_bytes());
+        out.extend((!len).to_le_bytes());
+        out.extend(block);
+    }
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + u32::from(*byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+/// Reads back what [`encode_rgb`] writes, checking the signature, every CRC and the
+/// Adler-32 on the way, into the width, height and RGB bytes.
+#[cfg(test)]
+pub fn decode_rgb(png: &[u8]) -> Result<(u32, u32, Vec<u8>), String> {
+    let mut rest = png.strip_prefix(b"\x89PNG\r\n\x1a\n").ok_or("Not a PNG")?;
+    let u32_at = |bytes: &[u8]| u32::from_be_bytes(bytes[..4].try_into().unwrap());
+    let (mut header, mut data) = (None, vec![]);
+    loop {
+        let len = u32_at(rest) as usize;
+        let (kind, body) = (&rest[4..8], &rest[8..8 + len]);
+        if crc32(&rest[4..8 + len]) != u32_at(&rest[8 + len..]) {
+            return Err(format!("Bad CRC on {}", String::from_utf8_lossy(kind)));
+        }
+        rest = &rest[12 + len..];
+        match kind {
+            b"IHDR" => header = Some(body.to_vec()),
+            b"IDAT" => data.extend(body),
+            b"IEND" => break,
+            _ => return Err("Unexpected chunk".into()),
+        }
+    }
+    let header = header.ok_or("No IHDR")?;
+    let (width, height) = (u32_at(&header), u32_at(&header[4..]));
+    if header[8..] != [8, 2, 0, 0, 0] {
+        return Err(format!("Unsupported format {:?}", &header[8..]));
+    }
+    // Stored blocks only: a header byte, then the length and its complement
+    let mut raw = vec![];
+    let mut blocks = &data[2..];
+    loop {
+        let len = u16::from_le_bytes([blocks[1], blocks[2]]);
+        if !len != u16::from_le_bytes([blocks[3], blocks[4]]) {
+            return Err("Bad block length".into());
+        }
+        raw.extend(&blocks[5..5 + usize::from(len)]);
+        let last = blocks[0] & 1 == 1;
+        blocks = &blocks[5 + usize::from(len)..];
+        if last {
+            break;
+        }
+    }
+    if adler32(&raw) != u32_at(blocks) {
+        return Err("Bad Adler-32".into());
+    }
+    let mut rgb = vec![];
+    for row in raw.chunks(width as usize * 3 + 1) {
+        if row[0] != 0 {
+            return Err(format!("Unsupported filter {}", row[0]));
+        }
+        rgb.extend(&row[1..]);
+    }
+    if rgb.len() != (width * height * 3) as usize {
+        return Err("Wrong amount of image data".into());
+    }
+    Ok((width, height, rgb))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checksums of known input, from the PNG and zlib specs.
+    #[test]
+    fn checksums_match_the_references() {
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    /// The smallest image, byte for byte.
+    #[test]
+    fn single_pixel_golden_bytes() {
+        let png = encode_rgb(1, 1, &[0xFF, 0x00, 0x80]);
+        let idat = [
+            0x78, 0x01, 0x01, 0x04, 0x00, 0xFB, 0xFF, 0x00, 0xFF, 0x00, 0x80, 0x03, 0x81, 0x01,
+            0x80,
+        ];
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[8..16], b"\x00\x00\x00\x0dIHDR");
+        assert_eq!(&png[16..29], [0, 0, 0, 1, 0, 0, 0, 1, 8, 2, 0, 0, 0]);
+        assert_eq!(&png[33..41], b"\x00\x00\x00\x0fIDAT");
+        assert_eq!(&png[41..56], idat);
+        assert_eq!(&png[60..], b"\x00\x00\x00\x00IEND\xae\x42\x60\x82");
+    }
+
+    #[test]
+    fn round_trips() {
+        // A checkerboard in two colors, like a frame of the game
+        let rgb: Vec<u8> = (0..64 * 32)
+            .flat_map(|n| match (n % 64 + n / 64) % 2 {
+                0 => [0x11, 0x22, 0x11],
+                _ => [0x33, 0xFF, 0x66],
+            })
+            .collect();
+        assert_eq!(decode_rgb(&encode_rgb(64, 32, &rgb)), Ok((64, 32, rgb)));
+    }
+
+    /// Images over 64K of data need several deflate blocks.
+    #[test]
+    fn large_images_round_trip() {
+        let rgb: Vec<u8> = (0..512 * 256 * 3).map(|n| (n % 251) as u8).collect();
+        assert_eq!(decode_rgb(&encode_rgb(512, 256, &rgb)), Ok((512, 256, rgb)));
+    }
+}
@@ -0,0 +1,304 @@
+use super::hotkeys;
+use super::keymap::Binding;
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::keyboard::Mod;
+use sdl2::keyboard::Scancode;
+use sdl2::EventPump;
+use smol::Timer;
+use std::time::Duration;
+use std::time::Instant;
+
+/// How often events are picked up while waiting for the next frame.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(4);
+
+/// Where events come from: SDL's event pump, outside of tests.
+pub trait Events {
+    /// Adds the events that came in since the last call to `events`.
+    fn poll(&mut self, events: &mut Vec<Event>);
+}
+
+impl Events for EventPump {
+    fn poll(&mut self, events: &mut Vec<Event>) {
+        events.extend(self.poll_iter());
+    }
+}
+
+/// Picking up events in between frames, so a short tap of the keypad reaches the game
+/// within [`POLL_INTERVAL`] instead of at the next frame, and hotkeys don't wait for it
+/// either. Everything but the keypad is still handled by the frame, in order, from
+/// [`Poller::take`], as SDL has to be pumped on the thread drawing the window anyway.
+pub struct Poller {
+    hotkeys: Vec<Binding>,
+    pending: Vec<Event>,
+    /// Whether a key event is among the pending ones, so later ones have to wait
+    /// for it to keep their order
+    keys_pending: bool,
+}
+
+impl Poller {
+    /// Keys bound to `hotkeys` are always left to the frame.
+    pub fn new(hotkeys: Vec<Binding>) -> Poller {
+        Poller {
+            hotkeys,
+            pending: Vec::new(),
+            keys_pending: false,
+        }
+    }
+
+    /// The events picked up since the last frame, oldest first.
+    pub fn take(&mut self) -> Vec<Event> {
+        self.keys_pending = false;
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Waits until `deadline`, picking up events every [`POLL_INTERVAL`]. With `keys`,
+    /// key presses and releases that can only be meant for the keypad are passed to
+    /// `key` with whether the key is down, which returns whether it was a keypad key.
+    /// Everything else is kept for the frame, and with `early` a key press, quitting or
+    /// the window coming back into view among it ends the wait so the frame comes
+    /// right away.
+    pub async fn wait(
+        &mut self,
+        events: &mut impl Events,
+        deadline: Instant,
+        keys: bool,
+        early: bool,
+        mut key: impl FnMut(Option<Scancode>, Option<Keycode>, bool) -> bool,
+    ) {
+        let mut polled = vec![];
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return;
+            }
+            Timer::at(deadline.min(now + POLL_INTERVAL)).await;
+            let mut urgent = false;
+            events.poll(&mut polled);
+            for event in polled.drain(..) {
+                if keys && !self.keys_pending {
+                    if let Some((scancode, keycode, down)) = self.keypad_event(&event) {
+                        if key(scancode, keycode, down) {
+                            continue;
+                        }
+                    }
+                }
+                self.keys_pending |= matches!(event, Event::KeyDown { .. } | Event::KeyUp { .. });
+                urgent |= match &event {
+                    Event::Quit { .. } | Event::KeyDown { repeat: false, .. } => true,
+                    Event::Window { win_event, .. } => super::visibility::reveals(win_event),
+                    _ => false,
+                };
+                self.pending.push(event);
+            }
+            if early && urgent {
+                return;
+            }
+        }
+    }
+
+    /// The key and whether it is down, for key events the frame would only ever pass
+    /// on to the keypad.
+    fn keypad_event(&self, event: &Event) -> Option<(Option<Scancode>, Option<Keycode>, bool)> {
+        let (scancode, keycode, keymod, down) = match *event {
+            Event::KeyDown {
+                scancode,
+                keycode,
+                keymod,
+                repeat: false,
+                ..
+            } => (scancode, keycode, keymod, true),
+            Event::KeyUp {
+                scancode,
+                keycode,
+                keymod,
+                repeat: false,
+                ..
+            } => (scancode, keycode, keymod, false),
+            _ => return None,
+        };
+        // Anything held with a modifier might be a shortcut
+        let modifiers = Mod::LCTRLMOD
+            | Mod::RCTRLMOD
+            | Mod::LSHIFTMOD
+            | Mod::RSHIFTMOD
+            | Mod::LALTMOD
+            | Mod::RALTMOD
+            | Mod::LGUIMOD
+            | Mod::RGUIMOD;
+        if (down && keymod.intersects(modifiers))
+            || keycode.is_some_and(hotkeys::is_fixed)
+            || self
+                .hotkeys
+                .iter()
+                .any(|hotkey| hotkey.matches(scancode, keycode))
+        {
+            return None;
+        }
+        Some((scancode, keycode, down))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdl2::event::WindowEvent;
+
+    /// Events that come in at set times after the start.
+    struct Pushed {
+        start: Instant,
+        events: Vec<(Duration, Event)>,
+    }
+
+    impl Events for Pushed {
+        fn poll(&mut self, events: &mut Vec<Event>) {
+            let now = self.start.elapsed();
+            let due = self.events.iter().take_while(|(at, _)| *at <= now).count();
+            events.extend(self.events.drain(..due).map(|(_, event)| event));
+        }
+    }
+
+    /// Both codes are given, asking SDL to map one to the other would need it running.
+    fn key(scancode: Scancode, keycode: Keycode, keymod: Mod, down: bool) -> Event {
+        let keycode = Some(keycode);
+        if down {
+            Event::KeyDown {
+                timestamp: 0,
+                window_id: 0,
+                keycode,
+                scancode: Some(scancode),
+                keymod,
+                repeat: false,
+            }
+        } else {
+            Event::KeyUp {
+                timestamp: 0,
+                window_id: 0,
+                keycode,
+                scancode: Some(scancode),
+                keymod,
+                repeat: false,
+            }
+        }
+    }
+
+    /// Waits `frame` with `events` pushed along the way, returning when each keypad
+    /// key went down or up since the start, and how long the wait took.
+    fn wait(
+        poller: &mut Poller,
+        frame: Duration,
+        events: Vec<(Duration, Event)>,
+    ) -> (Vec<(Duration, Scancode, bool)>, Duration) {
+        let start = Instant::now();
+        let mut pushed = Pushed { start, events };
+        let mut keys = vec![];
+        smol::block_on(poller.wait(
+            &mut pushed,
+            start + frame,
+            true,
+            true,
+            |scancode, _, down| {
+                keys.push((start.elapsed(), scancode.unwrap(), down));
+                true
+            },
+        ));
+        (keys, start.elapsed())
+    }
+
+    /// A tap shorter than a frame reaches the keypad within a few polls of each
+    /// half, without ending the wait for the frame.
+    #[test]
+    fn taps_reach_the_keypad_between_frames() {
+        let mut poller = Poller::new(vec![]);
+        let frame = Duration::from_millis(120);
+        let (keys, waited) = wait(
+            &mut poller,
+            frame,
+            vec![
+                (
+                    Duration::from_millis(20),
+                    key(Scancode::X, Keycode::X, Mod::NOMOD, true),
+                ),
+                (
+                    Duration::from_millis(40),
+                    key(Scancode::X, Keycode::X, Mod::NOMOD, false),
+                ),
+            ],
+        );
+        assert_eq!(keys.len(), 2, "{keys:?}");
+        for ((at, scancode, down), (pushed, was_down)) in keys.iter().zip([(20, true), (40, false)])
+        {
+            assert_eq!((*scancode, *down), (Scancode::X, was_down));
+            let latency = at.saturating_sub(Duration::from_millis(pushed));
+            assert!(latency < POLL_INTERVAL * 5, "{latency:?}");
+        }
+        assert!(waited >= frame, "{waited:?}");
+        assert!(poller.take().is_empty());
+    }
+
+    #[test]
+    fn hotkeys_and_quitting_end_the_wait() {
+        let frame = Duration::from_millis(500);
+        let early = Duration::from_millis(250);
+        let f11 = Binding::Scancode(Scancode::F11);
+        for event in [
+            key(Scancode::F11, Keycode::F11, Mod::NOMOD, true),
+            // Held with a modifier, it might be a shortcut
+            key(Scancode::X, Keycode::X, Mod::LCTRLMOD, true),
+            Event::Quit { timestamp: 0 },
+            Event::Window {
+                timestamp: 0,
+                window_id: 0,
+                win_event: WindowEvent::Restored,
+            },
+        ] {
+            let mut poller = Poller::new(vec![f11]);
+            let pushed = vec![(Duration::from_millis(10), event.clone())];
+            let (keys, waited) = wait(&mut poller, frame, pushed);
+            assert!(keys.is_empty());
+            assert!(waited < early, "{event:?} took {waited:?}");
+            assert_eq!(poller.take().len(), 1);
+        }
+    }
+
+    /// Once a key event is left to the frame, later ones wait for it too so they
+    /// stay in order.
+    #[test]
+    fn keys_stay_in_order_behind_one_left_to_the_frame() {
+        let mut poller = Poller::new(vec![]);
+        let pushed = vec![
+            (
+                Duration::ZERO,
+                key(Scancode::Home, Keycode::Home, Mod::NOMOD, false),
+            ),
+            (
+                Duration::ZERO,
+                key(Scancode::X, Keycode::X, Mod::NOMOD, true),
+            ),
+        ];
+        let (keys, _) = wait(&mut poller, Duration::from_millis(20), pushed);
+        assert!(keys.is_empty());
+        let taken = poller.take();
+        assert!(matches!(
+            taken[0],
+            Event::KeyUp {
+                scancode: Some(Scancode::Home),
+                ..
+            }
+        ));
+        assert!(matches!(
+            taken[1],
+            Event::KeyDown {
+                scancode: Some(Scancode::X),
+                ..
+            }
+        ));
+        // Taking them lets the keypad have keys again
+        let pushed = vec![(
+            Duration::ZERO,
+            key(Scancode::X, Keycode::X, Mod::NOMOD, false),
+        )];
+        let (keys, _) = wait(&mut poller, Duration::from_millis(20), pushed);
+        assert_eq!(keys.len(), 1);
+    }
+}
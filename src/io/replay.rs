@@ -0,0 +1,104 @@
+//! Recording keypad input to replay it later.
+//!
+//! A recording starts with a `chip8-recording 1 <seed>` header line, followed by one
+//! `<frame> <down|up> <key>` line per press or release, with the key in hex.
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+
+const HEADER: &str = "chip8-recording";
+const VERSION: u32 = 1;
+
+pub struct Recorder {
+    file: BufWriter<File>,
+}
+
+impl Recorder {
+    /// Starts a recording, seeding the random number generator with a seed that is
+    /// saved along with it.
+    pub fn create(path: &Path) -> Result<Recorder, String> {
+        let seed = fastrand::u64(..);
+        fastrand::seed(seed);
+        let file = File::create(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        let mut file = BufWriter::new(file);
+        writeln!(file, "{HEADER} {VERSION} {seed}")
+            .map_err(|err| format!("{}: {err}", path.display()))?;
+        Ok(Recorder { file })
+    }
+
+    pub fn record(&mut self, frame: u64, key: u8, pressed: bool) {
+        let action = if pressed { "down" } else { "up" };
+        if let Err(err) = writeln!(self.file, "{frame} {action} {key:X}") {
+            tracing::error!("Could not record input: {err}");
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Replay {
+    events: VecDeque<(u64, u8, bool)>,
+}
+
+impl Replay {
+    /// Loads a recording and seeds the random number generator like it was.
+    pub fn load(path: &Path) -> Result<Replay, String> {
+        let text =
+            std::fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        let replay = Replay::parse(&text).map_err(|err| format!("{}: {err}", path.display()))?;
+        Ok(replay)
+    }
+
+    fn parse(text: &str) -> Result<Replay, String> {
+        let mut lines = text.lines();
+        let header = lines.next().unwrap_or_default();
+        let seed = match header.split_whitespace().collect::<Vec<_>>()[..] {
+            [HEADER, version, seed] => {
+                if version.parse() != Ok(VERSION) {
+                    return Err(format!("Unsupported recording version {version}"));
+                }
+                seed.parse::<u64>()
+                    .map_err(|_| format!("Invalid seed {seed:?}"))?
+            }
+            _ => return Err("Not an input recording".into()),
+        };
+        let mut events = VecDeque::new();
+        for (idx, line) in lines
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+        {
+            let invalid = || format!("Invalid event on line {}: {line:?}", idx + 2);
+            let [frame, action, key] = line.split_whitespace().collect::<Vec<_>>()[..] else {
+                return Err(invalid());
+            };
+            let frame = frame.parse().map_err(|_| invalid())?;
+            let pressed = match action {
+                "down" => true,
+                "up" => false,
+                _ => return Err(invalid()),
+            };
+            let key = u8::from_str_radix(key, 16)
+                .ok()
+                .filter(|key| *key < 16)
+                .ok_or_else(invalid)?;
+            events.push_back((frame, key, pressed));
+        }
+        fastrand::seed(seed);
+        Ok(Replay { events })
+    }
+
+    /// Takes the events up to and including `frame`.
+    pub fn due(&mut self, frame: u64) -> Vec<(u8, bool)> {
+        let mut due = Vec::new();
+        while self.events.front().is_some_and(|(at, ..)| *at <= frame) {
+            let (_, key, pressed) = self.events.pop_front().unwrap();
+            due.push((key, pressed));
+        }
+        due
+    }
+
+    pub fn finished(&self) -> bool {
+        self.events.is_empty()
+    }
+}
@@ -0,0 +1,109 @@
+use std::path::Path;
+
+/// Programs are loaded at 0x200 and can't go past the end of the 4K address space.
+pub const MAX_SIZE: usize = 0x1000 - 0x200;
+
+const EXTENSIONS: [&str; 3] = ["ch8", "c8", "rom"];
+
+/// Reads the program in a ROM file, without the settings block it may end with.
+pub fn read(path: &Path) -> Result<Vec<u8>, String> {
+    let file = std::fs::read(path).map_err(|err| format!("{}: {err}", path.display()))?;
+    let (rom, _) = crate::metadata::split_embedded(&file);
+    if rom.len() > MAX_SIZE {
+        return Err(format!(
+            "{} is too large ({} bytes, at most {MAX_SIZE})",
+            path.display(),
+            rom.len()
+        ));
+    }
+    Ok(rom.to_vec())
+}
+
+/// Only dropped files that look like programs are loaded, so dropping e.g. a
+/// screenshot by accident doesn't replace the running game.
+pub fn check_extension(path: &Path) -> Result<(), String> {
+    let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+    if EXTENSIONS
+        .iter()
+        .any(|ext| ext.eq_ignore_ascii_case(extension))
+    {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} is not a ROM, expected a .ch8, .c8 or .rom file",
+            path.display()
+        ))
+    }
+}
+
+/// The file name shown in the title.
+pub fn name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::EMBEDDED_MARKER;
+
+    fn temp_file(name: &str, contents: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("chip8-{}-{name}", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn missing_files_say_which() {
+        let path = std::env::temp_dir().join(format!("chip8-{}-missing.ch8", std::process::id()));
+        let err = read(&path).unwrap_err();
+        assert!(err.starts_with(&path.display().to_string()), "{err}");
+    }
+
+    #[test]
+    fn roms_have_to_fit_in_memory() {
+        let path = temp_file("largest.ch8", &[0x12; MAX_SIZE]);
+        assert_eq!(read(&path).unwrap().len(), MAX_SIZE);
+        std::fs::remove_file(path).unwrap();
+        let path = temp_file("oversized.ch8", &[0x12; MAX_SIZE + 1]);
+        let err = read(&path).unwrap_err();
+        assert_eq!(
+            err,
+            format!("{} is too large (3585 bytes, at most 3584)", path.display())
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    /// The settings block isn't loaded, and doesn't count towards the size.
+    #[test]
+    fn embedded_settings_are_left_out() {
+        let mut file = vec![0x12; MAX_SIZE];
+        file.extend(EMBEDDED_MARKER);
+        file.extend(b"{\"quirks\": \"chip8\"}");
+        let path = temp_file("embedded.ch8", &file);
+        assert_eq!(read(&path), Ok(vec![0x12; MAX_SIZE]));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn only_rom_extensions_are_loaded() {
+        for name in ["pong.ch8", "PONG.C8", "games/pong.rom"] {
+            assert_eq!(check_extension(Path::new(name)), Ok(()), "{name}");
+        }
+        for name in ["shot.png", "pong", "ch8"] {
+            let err = check_extension(Path::new(name)).unwrap_err();
+            assert_eq!(
+                err,
+                format!("{name} is not a ROM, expected a .ch8, .c8 or .rom file")
+            );
+        }
+    }
+
+    #[test]
+    fn names_are_the_file_name() {
+        assert_eq!(name(Path::new("/games/pong.ch8")), "pong.ch8");
+        assert_eq!(name(Path::new("pong.ch8")), "pong.ch8");
+    }
+}
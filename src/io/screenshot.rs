@@ -0,0 +1,189 @@
+use super::png;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+/// Size of each CHIP-8 pixel in the upscaled copy.
+const SCALE: usize = 8;
+
+pub fn default_dir() -> Option<PathBuf> {
+    Some(crate::config::default_path()?.with_file_name("screenshots"))
+}
+
+/// Saves the frame at 1:1 and upscaled, returning the name of the upscaled file.
+pub fn save(
+    dir: &Path,
+    rom_name: &str,
+    width: usize,
+    height: usize,
+    rgb: &[u8],
+) -> Result<PathBuf, String> {
+    std::fs::create_dir_all(dir).map_err(|err| format!("{}: {err}", dir.display()))?;
+    let name = file_stem(rom_name);
+
+    let mut scaled = Vec::with_capacity(rgb.len() * SCALE * SCALE);
+    for row in rgb.chunks(width * 3) {
+        let row: Vec<u8> = row
+            .chunks(3)
+            .flat_map(|pixel| pixel.repeat(SCALE))
+            .collect();
+        for _ in 0..SCALE {
+            scaled.extend(&row);
+        }
+    }
+    let images = [
+        (
+            dir.join(format!("{name}.png")),
+            png::encode_rgb(width as u32, height as u32, rgb),
+        ),
+        (
+            dir.join(format!("{name}-x{SCALE}.png")),
+            png::encode_rgb((width * SCALE) as u32, (height * SCALE) as u32, &scaled),
+        ),
+    ];
+    for (path, data) in &images {
+        std::fs::write(path, data).map_err(|err| format!("{}: {err}", path.display()))?;
+    }
+    Ok(images[1].0.clone())
+}
+
+/// Where to start a GIF recording of the game.
+pub fn gif_path(dir: &Path, rom_name: &str) -> Result<PathBuf, String> {
+    std::fs::create_dir_all(dir).map_err(|err| format!("{}: {err}", dir.display()))?;
+    Ok(dir.join(format!("{}.gif", file_stem(rom_name))))
+}
+
+/// Where to save the game's state as JSON.
+pub fn state_path(dir: &Path, rom_name: &str) -> PathBuf {
+    dir.join(format!("{}.json", file_stem(rom_name)))
+}
+
+/// The ROM's name followed by the time, for naming captures.
+fn file_stem(rom_name: &str) -> String {
+    let stem = Path::new(rom_name)
+        .file_stem()
+        .map_or("screenshot".into(), |stem| stem.to_string_lossy());
+    format!("{stem}-{}", timestamp(SystemTime::now()))
+}
+
+/// Writes the frame at its true size, as a PPM if the path ends in `.ppm` and a PNG
+/// otherwise.
+pub fn dump(path: &Path, width: usize, height: usize, rgb: &[u8]) -> Result<(), String> {
+    let data = if path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("ppm"))
+    {
+        let mut data = format!("P6\n{width} {height}\n255\n").into_bytes();
+        data.extend(rgb);
+        data
+    } else {
+        png::encode_rgb(width as u32, height as u32, rgb)
+    };
+    std::fs::write(path, data).map_err(|err| format!("{}: {err}", path.display()))
+}
+
+/// `YYYYMMDD-HHMMSS` in UTC.
+fn timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // Howard Hinnant's days_from_civil, backwards
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}{month:02}{day:02}-{:02}{:02}{:02}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("chip8-{}-{name}", std::process::id()))
+    }
+
+    /// A 64x32 frame with the top left and bottom right pixels lit.
+    fn frame() -> Vec<u8> {
+        let mut rgb = [0x11, 0x22, 0x11].repeat(64 * 32);
+        for pixel in [0, 64 * 32 - 1] {
+            rgb[pixel * 3..pixel * 3 + 3].copy_from_slice(&[0x33, 0xFF, 0x66]);
+        }
+        rgb
+    }
+
+    #[test]
+    fn saves_the_frame_at_both_sizes() {
+        let dir = temp_dir("screenshots");
+        let rgb = frame();
+        let scaled_path = save(&dir, "games/pong.ch8", 64, 32, &rgb).unwrap();
+        let name = scaled_path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned();
+        assert!(
+            name.starts_with("pong-") && name.ends_with("-x8.png"),
+            "{name}"
+        );
+
+        let actual_path = dir.join(name.replace("-x8", ""));
+        let actual = png::decode_rgb(&std::fs::read(actual_path).unwrap()).unwrap();
+        assert_eq!(actual, (64, 32, rgb.clone()));
+
+        let (width, height, scaled) =
+            png::decode_rgb(&std::fs::read(&scaled_path).unwrap()).unwrap();
+        assert_eq!((width, height), (512, 256));
+        let at = |x: usize, y: usize| &scaled[(y * 512 + x) * 3..][..3];
+        // Every pixel is an 8x8 block
+        for (x, y) in [(0, 0), (7, 7), (504, 248), (511, 255)] {
+            assert_eq!(at(x, y), [0x33, 0xFF, 0x66], "at {x},{y}");
+        }
+        for (x, y) in [(8, 0), (0, 8), (503, 255)] {
+            assert_eq!(at(x, y), [0x11, 0x22, 0x11], "at {x},{y}");
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn dumps_as_png_or_ppm() {
+        let dir = temp_dir("dumps");
+        std::fs::create_dir_all(&dir).unwrap();
+        let rgb = frame();
+        dump(&dir.join("frame.png"), 64, 32, &rgb).unwrap();
+        let png = std::fs::read(dir.join("frame.png")).unwrap();
+        assert_eq!(png::decode_rgb(&png), Ok((64, 32, rgb.clone())));
+        dump(&dir.join("frame.PPM"), 64, 32, &rgb).unwrap();
+        let ppm = std::fs::read(dir.join("frame.PPM")).unwrap();
+        assert_eq!(ppm, [b"P6\n64 32\n255\n".as_slice(), &rgb].concat());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn write_errors_name_the_file() {
+        let dir = temp_dir("not-a-dir");
+        std::fs::write(&dir, b"").unwrap();
+        let err = save(&dir, "pong.ch8", 64, 32, &frame()).unwrap_err();
+        assert!(err.starts_with(&dir.display().to_string()), "{err}");
+        std::fs::remove_file(dir).unwrap();
+    }
+
+    #[test]
+    fn timestamps_are_utc() {
+        assert_eq!(timestamp(UNIX_EPOCH), "19700101-000000");
+        let leap_day = UNIX_EPOCH + std::time::Duration::from_secs(1_709_217_296);
+        assert_eq!(timestamp(leap_day), "20240229-143456");
+    }
+}
@@ -0,0 +1,206 @@
+//! Input scripts for automated runs, one directive per line:
+//!
+//! ```text
+//! # Comments start with a hash
+//! frame 120 press 5
+//! frame 130 release 5
+//! frame 600 expect-pixel 10 4 on
+//! frame 601 quit
+//! ```
+use std::collections::VecDeque;
+use std::ops::ControlFlow;
+use std::path::Path;
+use tracing::*;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    Press(u8),
+    Release(u8),
+    ExpectPixel { x: usize, y: usize, on: bool },
+    Quit,
+}
+
+#[derive(Clone, Debug)]
+pub struct Script {
+    steps: VecDeque<(u64, Action)>,
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Script, String> {
+        let text =
+            std::fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        Script::parse(&text).map_err(|err| format!("{}: {err}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Script, String> {
+        let mut steps = Vec::new();
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let step = parse_line(line).map_err(|err| format!("Line {}: {err}", idx + 1))?;
+            steps.push(step);
+        }
+        // Stable, so directives for the same frame keep their order
+        steps.sort_by_key(|(frame, _)| *frame);
+        Ok(Script {
+            steps: steps.into(),
+        })
+    }
+
+    /// Takes the actions up to and including `frame`.
+    pub fn due(&mut self, frame: u64) -> Vec<Action> {
+        let mut due = Vec::new();
+        while self.steps.front().is_some_and(|(at, _)| *at <= frame) {
+            due.push(self.steps.pop_front().unwrap().1);
+        }
+        due
+    }
+
+    /// Carries out the actions up to and including `frame`, pressing and releasing
+    /// keys through `key`. Breaks once the script quits, and fails at the first
+    /// expectation `vram` doesn't meet.
+    pub fn play(
+        &mut self,
+        frame: u64,
+        vram: &[bool],
+        mut key: impl FnMut(u8, bool),
+    ) -> Result<ControlFlow<()>, String> {
+        for action in self.due(frame) {
+            debug!("Script at frame {frame}: {action:?}");
+            match action {
+                Action::Press(k) => key(k, true),
+                Action::Release(k) => key(k, false),
+                Action::ExpectPixel { x, y, on } => check_pixel(vram, x, y, on)
+                    .map_err(|err| format!("Script failed at frame {frame}: {err}"))?,
+                Action::Quit => {
+                    info!("Script finished");
+                    return Ok(ControlFlow::Break(()));
+                }
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+/// Checks an `expect-pixel` directive against the screen.
+pub fn check_pixel(vram: &[bool], x: usize, y: usize, on: bool) -> Result<(), String> {
+    if vram[y * 64 + x] == on {
+        return Ok(());
+    }
+    let state = if on { "on" } else { "off" };
+    Err(format!("expected pixel {x},{y} to be {state}"))
+}
+
+fn parse_line(line: &str) -> Result<(u64, Action), String> {
+    let words: Vec<_> = line.split_whitespace().collect();
+    let ["frame", frame, action, ref args @ ..] = words[..] else {
+        return Err(format!("Expected `frame <N> <action>`, got {line:?}"));
+    };
+    let frame = frame
+        .parse()
+        .map_err(|_| format!("Invalid frame number {frame:?}"))?;
+    let key = |key: &str| {
+        u8::from_str_radix(key, 16)
+            .ok()
+            .filter(|key| *key < 16)
+            .ok_or_else(|| format!("Invalid key {key:?}, expected 0-F"))
+    };
+    let action = match (action, args) {
+        ("press", [k]) => Action::Press(key(k)?),
+        ("release", [k]) => Action::Release(key(k)?),
+        ("expect-pixel", [x, y, state]) => {
+            let x = x
+                .parse()
+                .ok()
+                .filter(|x| *x < 64)
+                .ok_or_else(|| format!("Invalid x coordinate {x:?}, expected 0-63"))?;
+            let y = y
+                .parse()
+                .ok()
+                .filter(|y| *y < 32)
+                .ok_or_else(|| format!("Invalid y coordinate {y:?}, expected 0-31"))?;
+            let on = match *state {
+                "on" => true,
+                "off" => false,
+                _ => return Err(format!("Expected on or off, got {state:?}")),
+            };
+            Action::ExpectPixel { x, y, on }
+        }
+        ("quit", []) => Action::Quit,
+        ("press" | "release" | "expect-pixel" | "quit", _) => {
+            return Err(format!("Wrong number of arguments for {action}"))
+        }
+        _ => return Err(format!("Unknown action {action:?}")),
+    };
+    Ok((frame, action))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn actions_come_due_in_frame_order() {
+        let mut script = Script::parse(
+            "# Presses 5 for a bit\nframe 130 release 5\nframe 120 press 5\n\nframe 130 quit\n",
+        )
+        .unwrap();
+        assert_eq!(script.due(119), []);
+        assert_eq!(script.due(125), [Action::Press(5)]);
+        assert_eq!(script.due(200), [Action::Release(5), Action::Quit]);
+        assert_eq!(script.due(300), []);
+    }
+
+    #[test]
+    fn says_which_line_is_wrong() {
+        let err = Script::parse("frame 1 press 5\nframe 2 expect-pixel 64 0 on").unwrap_err();
+        assert_eq!(err, "Line 2: Invalid x coordinate \"64\", expected 0-63");
+        let err = Script::parse("frame 1 press G").unwrap_err();
+        assert_eq!(err, "Line 1: Invalid key \"G\", expected 0-F");
+    }
+
+    #[test]
+    fn plays_keys_until_it_quits() {
+        let mut script =
+            Script::parse("frame 2 press A\nframe 3 release A\nframe 3 quit\nframe 4 press 1")
+                .unwrap();
+        let vram = [false; 64 * 32];
+        let mut keys = vec![];
+        let mut play = |frame| script.play(frame, &vram, |key, down| keys.push((key, down)));
+        assert_eq!(play(1), Ok(ControlFlow::Continue(())));
+        assert_eq!(play(2), Ok(ControlFlow::Continue(())));
+        assert_eq!(play(3), Ok(ControlFlow::Break(())));
+        assert_eq!(keys, [(0xA, true), (0xA, false)]);
+    }
+
+    #[test]
+    fn playing_stops_at_an_unmet_expectation() {
+        let mut script =
+            Script::parse("frame 5 expect-pixel 0 0 on\nframe 5 press 1\nframe 6 quit").unwrap();
+        let mut pressed = false;
+        let played = script.play(7, &[false; 64 * 32], |_, _| pressed = true);
+        assert_eq!(
+            played,
+            Err("Script failed at frame 7: expected pixel 0,0 to be on".into())
+        );
+        assert!(!pressed);
+    }
+
+    #[test]
+    fn pixels_are_checked_against_the_screen() {
+        let mut vram = [false; 64 * 32];
+        vram[2 * 64 + 10] = true;
+        assert_eq!(check_pixel(&vram, 10, 2, true), Ok(()));
+        assert_eq!(check_pixel(&vram, 11, 2, false), Ok(()));
+        assert_eq!(
+            check_pixel(&vram, 10, 2, false),
+            Err("expected pixel 10,2 to be off".into())
+        );
+        assert_eq!(
+            check_pixel(&vram, 63, 31, true),
+            Err("expected pixel 63,31 to be on".into())
+        );
+    }
+}
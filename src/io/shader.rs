@@ -0,0 +1,410 @@
+//! Drawing the screen through a user-supplied GLSL fragment shader, with SDL's OpenGL
+//! renderer underneath so the overlays are still drawn the usual way on top.
+//!
+//! The shader is GLSL 1.10 and gets:
+//!
+//! - `uniform sampler2D screen`: the screen, as drawn otherwise
+//! - `varying vec2 uv`: where to sample `screen` for this fragment
+//! - `uniform vec2 source_size`: the size of `screen` in pixels
+//! - `uniform vec2 resolution`: the size of the area drawn to, in window pixels
+//! - `uniform float time`: seconds since the shader was loaded
+//!
+//! Only the few GL functions needed are loaded, through SDL, as the renderer has
+//! its own context and state that everything else relies on.
+
+use core::ffi::c_char;
+use sdl2::rect::Rect;
+use sdl2::render::Texture;
+use sdl2::VideoSubsystem;
+use std::ffi::CString;
+use std::path::Path;
+use std::time::Instant;
+
+const FRAGMENT_SHADER: u32 = 0x8B30;
+const VERTEX_SHADER: u32 = 0x8B31;
+const COMPILE_STATUS: u32 = 0x8B81;
+const LINK_STATUS: u32 = 0x8B82;
+const INFO_LOG_LENGTH: u32 = 0x8B84;
+const CURRENT_PROGRAM: u32 = 0x8B8D;
+const VIEWPORT: u32 = 0x0BA2;
+const TRIANGLE_STRIP: u32 = 0x0005;
+
+/// Passes the corners straight through, as the quad is given in clip space.
+const VERTEX_SOURCE: &str = "#version 110
+varying vec2 uv;
+void main() {
+    uv = gl_MultiTexCoord0.xy;
+    gl_Position = gl_Vertex;
+}
+";
+
+macro_rules! gl_functions {
+    ($($name:ident: fn($($arg:ty),*) $(-> $ret:ty)?;)*) => {
+        #[allow(non_snake_case)]
+        struct Gl {
+            $($name: unsafe extern "system" fn($($arg),*) $(-> $ret)?,)*
+        }
+
+        impl Gl {
+            fn load(video: &VideoSubsystem) -> Result<Gl, String> {
+                Ok(Gl {
+                    $($name: {
+                        let address = video.gl_get_proc_address(stringify!($name));
+                        if address.is_null() {
+                            return Err(format!("OpenGL has no {}", stringify!($name)));
+                        }
+                        // SAFETY: the address is of the GL function of this name, whose
+                        // signature is the one declared here
+                        unsafe {
+                            std::mem::transmute::<
+                                *const (),
+                                unsafe extern "system" fn($($arg),*) $(-> $ret)?,
+                            >(address)
+                        }
+                    },)*
+                })
+            }
+        }
+    };
+}
+
+gl_functions! {
+    glCreateShader: fn(u32) -> u32;
+    glShaderSource: fn(u32, i32, *const *const c_char, *const i32);
+    glCompileShader: fn(u32);
+    glGetShaderiv: fn(u32, u32, *mut i32);
+    glGetShaderInfoLog: fn(u32, i32, *mut i32, *mut c_char);
+    glDeleteShader: fn(u32);
+    glCreateProgram: fn() -> u32;
+    glAttachShader: fn(u32, u32);
+    glLinkProgram: fn(u32);
+    glGetProgramiv: fn(u32, u32, *mut i32);
+    glGetProgramInfoLog: fn(u32, i32, *mut i32, *mut c_char);
+    glDeleteProgram: fn(u32);
+    glUseProgram: fn(u32);
+    glGetUniformLocation: fn(u32, *const c_char) -> i32;
+    glUniform1i: fn(i32, i32);
+    glUniform1f: fn(i32, f32);
+    glUniform2f: fn(i32, f32, f32);
+    glGetIntegerv: fn(u32, *mut i32);
+    glViewport: fn(i32, i32, i32, i32);
+    glBegin: fn(u32);
+    glEnd: fn();
+    glTexCoord2f: fn(f32, f32);
+    glVertex2f: fn(f32, f32);
+}
+
+pub struct Shader {
+    gl: Gl,
+    program: u32,
+    screen: i32,
+    source_size: i32,
+    resolution: i32,
+    time: i32,
+    start: Instant,
+}
+
+impl Shader {
+    /// Compiles the fragment shader at `path` for the renderer's context, which has
+    /// to be current.
+    pub fn load(video: &VideoSubsystem, path: &Path) -> Result<Shader, String> {
+        let source =
+            std::fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        Shader::build(Gl::load(video)?, path, &source)
+    }
+
+    /// Compiles `source`, read from `path`, and links it with the vertex shader.
+    fn build(gl: Gl, path: &Path, source: &str) -> Result<Shader, String> {
+        // SAFETY: all of these only use objects created here, in the current context
+        unsafe {
+            let vertex = compile(&gl, VERTEX_SHADER, VERTEX_SOURCE)
+                .map_err(|log| format!("The built-in vertex shader didn't compile: {log}"))?;
+            let fragment = match compile(&gl, FRAGMENT_SHADER, source) {
+                Ok(fragment) => fragment,
+                Err(log) => {
+                    (gl.glDeleteShader)(vertex);
+                    return Err(format!("{} didn't compile: {log}", path.display()));
+                }
+            };
+            let program = (gl.glCreateProgram)();
+            (gl.glAttachShader)(program, vertex);
+            (gl.glAttachShader)(program, fragment);
+            (gl.glLinkProgram)(program);
+            (gl.glDeleteShader)(vertex);
+            (gl.glDeleteShader)(fragment);
+            let mut linked = 0;
+            (gl.glGetProgramiv)(program, LINK_STATUS, &mut linked);
+            if linked == 0 {
+                let log = info_log(gl.glGetProgramiv, gl.glGetProgramInfoLog, program);
+                (gl.glDeleteProgram)(program);
+                return Err(format!("{} didn't link: {log}", path.display()));
+            }
+            let location = |name: &str| {
+                let name = CString::new(name).unwrap();
+                (gl.glGetUniformLocation)(program, name.as_ptr())
+            };
+            Ok(Shader {
+                screen: location("screen"),
+                source_size: location("source_size"),
+                resolution: location("resolution"),
+                time: location("time"),
+                gl,
+                program,
+                start: Instant::now(),
+            })
+        }
+    }
+
+    /// Draws `texture` over `dest` in a drawable area `output_height` pixels high.
+    /// SDL's renderer has to have batching off, so that everything it was asked to
+    /// draw before is drawn already.
+    pub fn draw(&self, texture: &mut Texture, dest: Rect, output_height: u32) {
+        let gl = &self.gl;
+        let query = texture.query();
+        texture.gl_with_bind(|max_u, max_v| {
+            // SAFETY: only touches the viewport and program, which are put back after
+            unsafe {
+                let mut program = 0;
+                (gl.glGetIntegerv)(CURRENT_PROGRAM, &mut program);
+                let mut viewport = [0; 4];
+                (gl.glGetIntegerv)(VIEWPORT, viewport.as_mut_ptr());
+                // GL counts rows from the bottom
+                let bottom = output_height as i32 - dest.bottom();
+                (gl.glViewport)(dest.x(), bottom, dest.width() as i32, dest.height() as i32);
+                (gl.glUseProgram)(self.program);
+                (gl.glUniform1i)(self.screen, 0);
+                (gl.glUniform2f)(self.source_size, query.width as f32, query.height as f32);
+                (gl.glUniform2f)(self.resolution, dest.width() as f32, dest.height() as f32);
+                (gl.glUniform1f)(self.time, self.start.elapsed().as_secs_f32());
+                (gl.glBegin)(TRIANGLE_STRIP);
+                for (x, y) in [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)] {
+                    // The texture's first row is the top of the screen
+                    (gl.glTexCoord2f)(x * max_u, y * max_v);
+                    (gl.glVertex2f)(x * 2.0 - 1.0, 1.0 - y * 2.0);
+                }
+                (gl.glEnd)();
+                (gl.glUseProgram)(program as u32);
+                let [x, y, width, height] = viewport;
+                (gl.glViewport)(x, y, width, height);
+            }
+        });
+    }
+}
+
+impl Drop for Shader {
+    fn drop(&mut self) {
+        // SAFETY: the program was created by this shader and isn't used after
+        unsafe { (self.gl.glDeleteProgram)(self.program) }
+    }
+}
+
+/// Compiles a shader of `kind`, or returns the compiler's messages.
+unsafe fn compile(gl: &Gl, kind: u32, source: &str) -> Result<u32, String> {
+    let source = CString::new(source).map_err(|_| "it has a NUL byte in it".to_owned())?;
+    let shader = (gl.glCreateShader)(kind);
+    (gl.glShaderSource)(shader, 1, &source.as_ptr(), std::ptr::null());
+    (gl.glCompileShader)(shader);
+    let mut compiled = 0;
+    (gl.glGetShaderiv)(shader, COMPILE_STATUS, &mut compiled);
+    if compiled == 0 {
+        let log = info_log(gl.glGetShaderiv, gl.glGetShaderInfoLog, shader);
+        (gl.glDeleteShader)(shader);
+        return Err(log);
+    }
+    Ok(shader)
+}
+
+/// The info log of a shader or program, with the getters for its kind.
+unsafe fn info_log(
+    get: unsafe extern "system" fn(u32, u32, *mut i32),
+    get_log: unsafe extern "system" fn(u32, i32, *mut i32, *mut c_char),
+    object: u32,
+) -> String {
+    let mut len = 0;
+    get(object, INFO_LOG_LENGTH, &mut len);
+    let mut log = vec![0u8; len.max(1) as usize];
+    let mut written = 0;
+    get_log(object, len, &mut written, log.as_mut_ptr().cast());
+    log.truncate(written.max(0) as usize);
+    String::from_utf8_lossy(&log).trim().to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::ffi::CStr;
+
+    /// Enough of a GL driver to compile shaders: one compiles if it has a main and
+    /// its braces match.
+    #[derive(Default)]
+    struct Driver {
+        created: u32,
+        sources: HashMap<u32, String>,
+        logs: HashMap<u32, String>,
+        deleted: Vec<u32>,
+    }
+
+    thread_local! {
+        static DRIVER: RefCell<Driver> = RefCell::new(Driver::default());
+    }
+
+    unsafe extern "system" fn create_shader(_kind: u32) -> u32 {
+        DRIVER.with_borrow_mut(|driver| {
+            driver.created += 1;
+            driver.created
+        })
+    }
+
+    unsafe extern "system" fn shader_source(
+        shader: u32,
+        _count: i32,
+        sources: *const *const c_char,
+        _lengths: *const i32,
+    ) {
+        let source = CStr::from_ptr(*sources).to_string_lossy().into_owned();
+        DRIVER.with_borrow_mut(|driver| driver.sources.insert(shader, source));
+    }
+
+    unsafe extern "system" fn compile_shader(shader: u32) {
+        DRIVER.with_borrow_mut(|driver| {
+            let source = &driver.sources[&shader];
+            let lines = source.lines().count();
+            if !source.contains("void main()") {
+                driver.logs.insert(shader, "0:1(1): error: no main".into());
+            } else if source.matches('{').count() != source.matches('}').count() {
+                let log = format!("0:{lines}(1): error: syntax error, unexpected end of file");
+                driver.logs.insert(shader, log);
+            }
+        });
+    }
+
+    unsafe extern "system" fn get_shader(shader: u32, name: u32, value: *mut i32) {
+        DRIVER.with_borrow(|driver| {
+            let log = driver.logs.get(&shader);
+            *value = match name {
+                COMPILE_STATUS => i32::from(log.is_none()),
+                INFO_LOG_LENGTH => log.map_or(0, |log| log.len() as i32 + 1),
+                _ => 0,
+            };
+        });
+    }
+
+    unsafe extern "system" fn get_shader_log(
+        shader: u32,
+        max: i32,
+        written: *mut i32,
+        log: *mut c_char,
+    ) {
+        DRIVER.with_borrow(|driver| {
+            let text = driver.logs.get(&shader).map_or("", String::as_str);
+            let len = text.len().min(max as usize);
+            std::ptr::copy_nonoverlapping(text.as_ptr().cast(), log, len);
+            *written = len as i32;
+        });
+    }
+
+    unsafe extern "system" fn delete(object: u32) {
+        DRIVER.with_borrow_mut(|driver| driver.deleted.push(object));
+    }
+
+    unsafe extern "system" fn create_program() -> u32 {
+        create_shader(0)
+    }
+
+    unsafe extern "system" fn get_program(_program: u32, name: u32, value: *mut i32) {
+        *value = i32::from(name == LINK_STATUS);
+    }
+
+    unsafe extern "system" fn uniform_location(_program: u32, name: *const c_char) -> i32 {
+        match CStr::from_ptr(name).to_bytes() {
+            b"screen" => 0,
+            b"source_size" => 1,
+            b"resolution" => 2,
+            b"time" => 3,
+            _ => -1,
+        }
+    }
+
+    unsafe extern "system" fn ignore_program_log(_: u32, _: i32, _: *mut i32, _: *mut c_char) {}
+    unsafe extern "system" fn ignore_u32(_: u32) {}
+    unsafe extern "system" fn ignore_u32_u32(_: u32, _: u32) {}
+    unsafe extern "system" fn ignore_i32_i32(_: i32, _: i32) {}
+    unsafe extern "system" fn ignore_i32_f32(_: i32, _: f32) {}
+    unsafe extern "system" fn ignore_i32_f32_f32(_: i32, _: f32, _: f32) {}
+    unsafe extern "system" fn ignore_u32_ptr(_: u32, _: *mut i32) {}
+    unsafe extern "system" fn ignore_viewport(_: i32, _: i32, _: i32, _: i32) {}
+    unsafe extern "system" fn ignore() {}
+    unsafe extern "system" fn ignore_f32_f32(_: f32, _: f32) {}
+
+    fn gl() -> Gl {
+        DRIVER.with_borrow_mut(|driver| *driver = Driver::default());
+        Gl {
+            glCreateShader: create_shader,
+            glShaderSource: shader_source,
+            glCompileShader: compile_shader,
+            glGetShaderiv: get_shader,
+            glGetShaderInfoLog: get_shader_log,
+            glDeleteShader: delete,
+            glCreateProgram: create_program,
+            glAttachShader: ignore_u32_u32,
+            glLinkProgram: ignore_u32,
+            glGetProgramiv: get_program,
+            glGetProgramInfoLog: ignore_program_log,
+            glDeleteProgram: delete,
+            glUseProgram: ignore_u32,
+            glGetUniformLocation: uniform_location,
+            glUniform1i: ignore_i32_i32,
+            glUniform1f: ignore_i32_f32,
+            glUniform2f: ignore_i32_f32_f32,
+            glGetIntegerv: ignore_u32_ptr,
+            glViewport: ignore_viewport,
+            glBegin: ignore_u32,
+            glEnd: ignore,
+            glTexCoord2f: ignore_f32_f32,
+            glVertex2f: ignore_f32_f32,
+        }
+    }
+
+    fn build(path: &str) -> Result<Shader, String> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(path);
+        Shader::build(gl(), &path, &std::fs::read_to_string(&path).unwrap())
+    }
+
+    #[test]
+    fn bundled_shader_compiles() {
+        let shader = build("shaders/scanlines.frag").unwrap();
+        assert_eq!(
+            (
+                shader.screen,
+                shader.source_size,
+                shader.resolution,
+                shader.time
+            ),
+            (0, 1, 2, 3)
+        );
+        // Both shaders are freed once linked, and the program with the shader
+        let program = shader.program;
+        assert_eq!(DRIVER.with_borrow(|driver| driver.deleted.clone()), [1, 2]);
+        drop(shader);
+        assert_eq!(
+            DRIVER.with_borrow(|driver| driver.deleted.clone()),
+            [1, 2, program]
+        );
+    }
+
+    #[test]
+    fn broken_shader_says_why() {
+        let Err(err) = build("tests/fixtures/shaders/broken.frag") else {
+            panic!("The broken shader compiled");
+        };
+        assert!(err.contains("broken.frag didn't compile"), "{err}");
+        assert!(
+            err.ends_with("0:9(1): error: syntax error, unexpected end of file"),
+            "{err}"
+        );
+        // Nothing is left behind: the fragment shader, then the vertex shader
+        assert_eq!(DRIVER.with_borrow(|driver| driver.deleted.clone()), [2, 1]);
+    }
+}
@@ -0,0 +1,113 @@
+use std::time::Duration;
+use std::time::Instant;
+
+/// How often the title (and with it the frame rate) is refreshed.
+pub const UPDATE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Counts presented frames to report the frame rate every [`UPDATE_INTERVAL`].
+#[derive(Clone, Debug)]
+pub struct FrameCounter {
+    frames: u32,
+    since: Instant,
+}
+
+impl FrameCounter {
+    pub fn new(now: Instant) -> FrameCounter {
+        FrameCounter {
+            frames: 0,
+            since: now,
+        }
+    }
+
+    pub fn tick(&mut self, now: Instant) -> Option<f32> {
+        self.frames += 1;
+        let elapsed = now.duration_since(self.since);
+        if elapsed < UPDATE_INTERVAL {
+            return None;
+        }
+        let fps = self.frames as f32 / elapsed.as_secs_f32();
+        *self = FrameCounter::new(now);
+        Some(fps)
+    }
+}
+
+/// e.g. `pong.ch8 - chip8 (classic layout) [paused] [2x] 60 FPS`, where `keys` is
+/// [`KeyMaps::describe`](super::KeyMaps::describe).
+pub fn format(
+    rom: &str,
+    keys: &str,
+    paused: bool,
+    muted: bool,
+    speed: f32,
+    fps: Option<f32>,
+) -> String {
+    // Nothing before the dash for the splash screen
+    let mut title = match rom {
+        "" => format!("chip8 ({keys})"),
+        rom => format!("{rom} - chip8 ({keys})"),
+    };
+    if paused {
+        title.push_str(" [paused]");
+    }
+    if muted {
+        title.push_str(" [muted]");
+    }
+    if speed != 1.0 {
+        title.push_str(&format!(" [{speed}x]"));
+    }
+    if let Some(fps) = fps {
+        title.push_str(&format!(" {fps:.0} FPS"));
+    }
+    title
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_the_rom_and_the_layout() {
+        let title = |rom, keys| format(rom, keys, false, false, 1.0, None);
+        assert_eq!(
+            title("pong.ch8", "classic layout"),
+            "pong.ch8 - chip8 (classic layout)"
+        );
+        assert_eq!(
+            title("tetris.ch8", "tetris keys"),
+            "tetris.ch8 - chip8 (tetris keys)"
+        );
+        assert_eq!(title("", "classic layout"), "chip8 (classic layout)");
+    }
+
+    #[test]
+    fn shows_what_is_going_on() {
+        let title = |paused, muted, speed, fps| {
+            format("pong.ch8", "classic layout", paused, muted, speed, fps)
+        };
+        let base = "pong.ch8 - chip8 (classic layout)";
+        assert_eq!(title(true, false, 1.0, None), format!("{base} [paused]"));
+        assert_eq!(title(false, true, 1.0, None), format!("{base} [muted]"));
+        assert_eq!(title(false, false, 0.25, None), format!("{base} [0.25x]"));
+        assert_eq!(
+            title(false, false, 1.0, Some(59.6)),
+            format!("{base} 60 FPS")
+        );
+        assert_eq!(
+            title(true, true, 2.0, Some(30.0)),
+            format!("{base} [paused] [muted] [2x] 30 FPS")
+        );
+    }
+
+    #[test]
+    fn frame_rate_comes_every_interval() {
+        let start = Instant::now();
+        let mut counter = FrameCounter::new(start);
+        let frame = Duration::from_millis(20);
+        let rates: Vec<_> = (1..=50).map(|n| counter.tick(start + frame * n)).collect();
+        // 25 frames in 500ms, then counting again from there
+        assert!(rates[..24].iter().all(Option::is_none));
+        assert_eq!(rates[24], Some(50.0));
+        assert!(rates[25..49].iter().all(Option::is_none));
+        assert_eq!(rates[49], Some(50.0));
+    }
+}
@@ -0,0 +1,103 @@
+use super::overlay;
+use super::viewport::Viewport;
+use sdl2::rect::Point;
+use sdl2::rect::Rect;
+use std::collections::HashMap;
+
+/// SDL also reports touches as mouse events from this pseudo mouse.
+pub const TOUCH_MOUSE_ID: u32 = u32::MAX;
+
+/// The key each finger is currently holding, so a finger sliding between cells
+/// releases the key it came from.
+#[derive(Clone, Debug, Default)]
+pub struct Touches(HashMap<(i64, i64), u8>);
+
+impl Touches {
+    /// Moves a finger onto `key` (or off the pad), returning the key to release and
+    /// the key to press.
+    pub fn update(&mut self, finger: (i64, i64), key: Option<u8>) -> (Option<u8>, Option<u8>) {
+        let old = match key {
+            Some(key) => self.0.insert(finger, key),
+            None => self.0.remove(&finger),
+        };
+        if old == key {
+            (None, None)
+        } else {
+            (old, key)
+        }
+    }
+
+    pub fn lift(&mut self, finger: (i64, i64)) -> Option<u8> {
+        self.0.remove(&finger)
+    }
+}
+
+/// The key under a finger on the keypad drawn over `area`, if there is one. SDL gives
+/// where the finger is as fractions of the window's `size`.
+pub fn touched_key(
+    viewport: &Viewport,
+    size: (u32, u32),
+    (x, y): (f32, f32),
+    area: Option<Rect>,
+) -> Option<u8> {
+    let point = Point::new((x * size.0 as f32) as i32, (y * size.1 as f32) as i32);
+    viewport
+        .logical_point(point)
+        .zip(area)
+        .and_then(|(point, area)| overlay::keypad_cell(area, point))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::viewport::Rotation;
+    use crate::io::viewport::Scaling;
+
+    fn viewport(logical: (u32, u32), window: (u32, u32)) -> Viewport {
+        Viewport::fit(logical, window, window, Scaling::Fit, Rotation::None)
+    }
+
+    #[test]
+    fn touches_the_keypad_under_the_game() {
+        let viewport = viewport((64, 64), (640, 640));
+        let keypad = Some(Rect::new(0, 32, 64, 32));
+        let key = |x, y| touched_key(&viewport, (640, 640), (x, y), keypad);
+        assert_eq!(key(0.05, 0.55), Some(0x1));
+        assert_eq!(key(0.95, 0.55), Some(0xC));
+        assert_eq!(key(0.3, 0.95), Some(0x0));
+        assert_eq!(key(0.95, 0.95), Some(0xF));
+        // The game itself isn't a key
+        assert_eq!(key(0.5, 0.2), None);
+    }
+
+    #[test]
+    fn touches_the_grid_over_the_game() {
+        // Letterboxed, with bars above and below the game
+        let viewport = viewport((64, 32), (640, 640));
+        let grid = Some(Rect::new(0, 0, 64, 32));
+        let key = |x, y| touched_key(&viewport, (640, 640), (x, y), grid);
+        assert_eq!(key(0.05, 0.3), Some(0x1));
+        assert_eq!(key(0.6, 0.5), Some(0x9));
+        assert_eq!(key(0.95, 0.7), Some(0xF));
+        assert_eq!(key(0.5, 0.1), None);
+        assert_eq!(key(0.5, 0.9), None);
+        // Nothing to touch with the grid off
+        assert_eq!(touched_key(&viewport, (640, 640), (0.05, 0.3), None), None);
+    }
+
+    #[test]
+    fn fingers_hold_keys_of_their_own() {
+        let mut touches = Touches::default();
+        assert_eq!(touches.update((1, 0), Some(0x5)), (None, Some(0x5)));
+        assert_eq!(touches.update((1, 1), Some(0x6)), (None, Some(0x6)));
+        // Moving within a cell changes nothing
+        assert_eq!(touches.update((1, 0), Some(0x5)), (None, None));
+        // Sliding onto the next cell releases the one it came from
+        assert_eq!(touches.update((1, 0), Some(0x4)), (Some(0x5), Some(0x4)));
+        // And sliding off the pad releases it too
+        assert_eq!(touches.update((1, 1), None), (Some(0x6), None));
+        assert_eq!(touches.update((1, 1), None), (None, None));
+        assert_eq!(touches.lift((1, 0)), Some(0x4));
+        assert_eq!(touches.lift((1, 0)), None);
+    }
+}
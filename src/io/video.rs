@@ -0,0 +1,150 @@
+//! Raw RGB24 frames at a steady 60fps, for piping into ffmpeg.
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::time::Instant;
+
+pub struct VideoSink {
+    out: Box<dyn Write>,
+    start: Option<Instant>,
+    /// Frames written so far
+    written: u64,
+}
+
+impl VideoSink {
+    /// `-` and `pipe:` write to stdout, anything else to that file or named pipe.
+    pub fn open(target: &Path) -> Result<VideoSink, String> {
+        let out: Box<dyn Write> = if is_stdout(target) {
+            Box::new(std::io::stdout())
+        } else {
+            let file =
+                File::create(target).map_err(|err| format!("{}: {err}", target.display()))?;
+            Box::new(BufWriter::new(file))
+        };
+        Ok(VideoSink::new(out))
+    }
+
+    pub fn new(out: Box<dyn Write>) -> VideoSink {
+        VideoSink {
+            out,
+            start: None,
+            written: 0,
+        }
+    }
+
+    /// Writes `rgb` as many times as it takes to catch up with 60fps since the first
+    /// frame, so the stream stays in sync when frames are late. Frames count from the
+    /// nearest tick, so ones that come a little early or late around it don't make
+    /// for a repeat followed by a gap.
+    pub fn push(&mut self, rgb: &[u8], now: Instant) -> std::io::Result<()> {
+        let start = *self.start.get_or_insert(now);
+        let due = (now.duration_since(start).as_secs_f64() * 60.0).round() as u64 + 1;
+        while self.written < due {
+            self.out.write_all(rgb)?;
+            self.written += 1;
+        }
+        self.out.flush()
+    }
+}
+
+pub fn is_stdout(target: &Path) -> bool {
+    target == Path::new("-") || target == Path::new("pipe:")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    /// 64x32 RGB24
+    const FRAME: usize = 64 * 32 * 3;
+
+    /// Stands in for ffmpeg, keeping everything written to it.
+    #[derive(Clone, Default)]
+    struct FakeSink(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for FakeSink {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn frame(n: u8) -> Vec<u8> {
+        vec![n; FRAME]
+    }
+
+    /// The frames written, as the value each is filled with.
+    fn frames(sink: &FakeSink) -> Vec<u8> {
+        let bytes = sink.0.borrow();
+        assert_eq!(bytes.len() % FRAME, 0, "only whole frames");
+        bytes.chunks(FRAME).map(|frame| frame[0]).collect()
+    }
+
+    #[test]
+    fn a_second_of_frames_is_sixty_of_them() {
+        let sink = FakeSink::default();
+        let mut video = VideoSink::new(Box::new(sink.clone()));
+        let start = Instant::now();
+        for n in 0..60u32 {
+            let now = start + Duration::from_secs(1) * n / 60;
+            video.push(&frame(n as u8), now).unwrap();
+        }
+        assert_eq!(sink.0.borrow().len(), 60 * FRAME);
+        assert_eq!(frames(&sink), (0..60).collect::<Vec<_>>());
+    }
+
+    /// Late frames are repeated, and early ones left out, so the stream keeps to
+    /// the clock whatever the emulator does.
+    #[test]
+    fn keeps_to_sixty_frames_a_second() {
+        let sink = FakeSink::default();
+        let mut video = VideoSink::new(Box::new(sink.clone()));
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        video.push(&frame(1), at(0)).unwrap();
+        // Three frames late: the new frame fills the gap
+        video.push(&frame(2), at(67)).unwrap();
+        assert_eq!(frames(&sink), [1, 2, 2, 2, 2]);
+        // Too soon for another
+        video.push(&frame(3), at(70)).unwrap();
+        assert_eq!(frames(&sink).len(), 5);
+        // After a stall of a second, it's caught up in one go
+        video.push(&frame(4), at(1070)).unwrap();
+        assert_eq!(frames(&sink).len(), 65);
+        assert!(frames(&sink)[5..].iter().all(|n| *n == 4));
+    }
+
+    /// A 60Hz loop that's never quite on time still gets one frame out per tick.
+    #[test]
+    fn jitter_around_the_ticks_is_ignored() {
+        let sink = FakeSink::default();
+        let mut video = VideoSink::new(Box::new(sink.clone()));
+        let start = Instant::now() + Duration::from_millis(10);
+        for n in 0..120u32 {
+            let tick = start + Duration::from_secs(1) * n / 60;
+            let now = match n % 3 {
+                0 => tick,
+                1 => tick - Duration::from_millis(2),
+                _ => tick + Duration::from_millis(3),
+            };
+            video.push(&frame(n as u8), now).unwrap();
+        }
+        assert_eq!(frames(&sink), (0..120).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn dash_and_pipe_mean_stdout() {
+        assert!(is_stdout(Path::new("-")));
+        assert!(is_stdout(Path::new("pipe:")));
+        assert!(!is_stdout(Path::new("out.rgb")));
+        assert!(!is_stdout(Path::new("./-")));
+    }
+}
@@ -0,0 +1,293 @@
+use core::str::FromStr;
+use sdl2::rect::Point;
+use sdl2::rect::Rect;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Scaling {
+    /// Use as much of the window as possible
+    #[default]
+    Fit,
+    /// Only scale by whole multiples so every CHIP-8 pixel is the same size
+    Integer,
+}
+
+/// Clockwise rotation of the whole layout on screen.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Rotation {
+    #[default]
+    None,
+    Quarter,
+    Half,
+    ThreeQuarters,
+}
+
+impl Rotation {
+    pub fn degrees(self) -> u32 {
+        self.quarter_turns() * 90
+    }
+
+    fn quarter_turns(self) -> u32 {
+        match self {
+            Rotation::None => 0,
+            Rotation::Quarter => 1,
+            Rotation::Half => 2,
+            Rotation::ThreeQuarters => 3,
+        }
+    }
+
+    /// The on-screen size of something `size` big.
+    pub fn apply(self, (width, height): (u32, u32)) -> (u32, u32) {
+        if self.quarter_turns() % 2 == 1 {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    /// Maps a direction key (2/4/6/8) pressed on screen to the key pointing the same
+    /// way in the rotated game.
+    pub fn direction_key(self, key: u8) -> u8 {
+        // Clockwise from up
+        const DIRECTIONS: [u8; 4] = [0x2, 0x6, 0x8, 0x4];
+        match DIRECTIONS.iter().position(|k| *k == key) {
+            Some(idx) => DIRECTIONS[(idx + 4 - self.quarter_turns() as usize) % 4],
+            None => key,
+        }
+    }
+}
+
+impl FromStr for Rotation {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(Rotation::None),
+            "90" => Ok(Rotation::Quarter),
+            "180" => Ok(Rotation::Half),
+            "270" => Ok(Rotation::ThreeQuarters),
+            _ => Err(format!(
+                "Unknown rotation {s:?}, expected 0, 90, 180 or 270"
+            )),
+        }
+    }
+}
+
+/// Where the logical layout (the 64x32 game plus anything drawn beside it) ends up in
+/// the window: scaled to fit and centered, with black bars on the remaining sides.
+/// `dest` is in drawable pixels, which on HiDPI displays outnumber the window
+/// coordinates that input events use.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Viewport {
+    logical: (u32, u32),
+    rotation: Rotation,
+    output: (u32, u32),
+    window: (u32, u32),
+    dest: Rect,
+}
+
+impl Viewport {
+    pub fn fit(
+        logical: (u32, u32),
+        output: (u32, u32),
+        window: (u32, u32),
+        scaling: Scaling,
+        rotation: Rotation,
+    ) -> Viewport {
+        let (lw, lh) = rotation.apply(logical);
+        let (lw, lh) = (u64::from(lw), u64::from(lh));
+        let (ow, oh) = (u64::from(output.0), u64::from(output.1));
+        let integer_scale = (ow / lw).min(oh / lh);
+        let (width, height) = match scaling {
+            // Windows smaller than the layout still get a (fractionally) scaled image
+            Scaling::Integer if integer_scale > 0 => (lw * integer_scale, lh * integer_scale),
+            // Compare ow/lw and oh/lh without going through floats
+            _ if ow * lh <= oh * lw => (ow, ow * lh / lw),
+            _ => (oh * lw / lh, oh),
+        };
+        let x = (ow - width) / 2;
+        let y = (oh - height) / 2;
+        Viewport {
+            logical,
+            rotation,
+            output,
+            window,
+            dest: Rect::new(
+                x as i32,
+                y as i32,
+                width.max(1) as u32,
+                height.max(1) as u32,
+            ),
+        }
+    }
+
+    /// Maps a rect in logical coordinates to drawable pixels. Edges are mapped
+    /// individually so neighbouring rects never leave gaps between them.
+    pub fn rect(&self, rect: Rect) -> Rect {
+        let (width, height) = (self.logical.0 as i32, self.logical.1 as i32);
+        let (left, top, right, bottom) = (rect.left(), rect.top(), rect.right(), rect.bottom());
+        let (left, top, right, bottom) = match self.rotation {
+            Rotation::None => (left, top, right, bottom),
+            Rotation::Quarter => (height - bottom, left, height - top, right),
+            Rotation::Half => (width - right, height - bottom, width - left, height - top),
+            Rotation::ThreeQuarters => (top, width - right, bottom, width - left),
+        };
+        let (left, top) = (self.x(left), self.y(top));
+        let (right, bottom) = (self.x(right), self.y(bottom));
+        Rect::new(
+            left,
+            top,
+            (right - left).max(1) as u32,
+            (bottom - top).max(1) as u32,
+        )
+    }
+
+    /// The rect to copy a texture of `rect` into so it lands on [`Viewport::rect`] after
+    /// being turned by [`Viewport::angle`] around its center.
+    pub fn texture_rect(&self, rect: Rect) -> Rect {
+        let rotated = self.rect(rect);
+        let (width, height) = self.rotation.apply(rotated.size());
+        Rect::from_center(rotated.center(), width, height)
+    }
+
+    pub fn angle(&self) -> f64 {
+        f64::from(self.rotation.degrees())
+    }
+
+    /// Maps a point in window coordinates back to logical coordinates, if it isn't in
+    /// the bars.
+    pub fn logical_point(&self, point: Point) -> Option<Point> {
+        let point = self.drawable_point(point);
+        if !self.dest.contains_point(point) {
+            return None;
+        }
+        let (width, height) = self.rotation.apply(self.logical);
+        let x =
+            i64::from(point.x() - self.dest.x()) * i64::from(width) / i64::from(self.dest.width());
+        let y = i64::from(point.y() - self.dest.y()) * i64::from(height)
+            / i64::from(self.dest.height());
+        let (x, y) = (x as i32, y as i32);
+        let (width, height) = (self.logical.0 as i32, self.logical.1 as i32);
+        let (x, y) = match self.rotation {
+            Rotation::None => (x, y),
+            Rotation::Quarter => (y, height - 1 - x),
+            Rotation::Half => (width - 1 - x, height - 1 - y),
+            Rotation::ThreeQuarters => (width - 1 - y, x),
+        };
+        Some(Point::new(x, y))
+    }
+
+    /// Maps a point in window coordinates to drawable pixels.
+    fn drawable_point(&self, point: Point) -> Point {
+        let scale = |v: i32, output: u32, window: u32| {
+            (i64::from(v) * i64::from(output) / i64::from(window.max(1))) as i32
+        };
+        Point::new(
+            scale(point.x(), self.output.0, self.window.0),
+            scale(point.y(), self.output.1, self.window.1),
+        )
+    }
+
+    /// Maps an x coordinate of the rotated layout to drawable pixels.
+    fn x(&self, x: i32) -> i32 {
+        let width = self.rotation.apply(self.logical).0;
+        self.dest.x() + (i64::from(x) * i64::from(self.dest.width()) / i64::from(width)) as i32
+    }
+
+    fn y(&self, y: i32) -> i32 {
+        let height = self.rotation.apply(self.logical).1;
+        self.dest.y() + (i64::from(y) * i64::from(self.dest.height()) / i64::from(height)) as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fit(output: (u32, u32), scaling: Scaling) -> Rect {
+        Viewport::fit((64, 32), output, output, scaling, Rotation::None).dest
+    }
+
+    #[test]
+    fn fills_windows_of_the_same_shape() {
+        assert_eq!(fit((640, 320), Scaling::Fit), Rect::new(0, 0, 640, 320));
+        assert_eq!(fit((64, 32), Scaling::Fit), Rect::new(0, 0, 64, 32));
+        assert_eq!(fit((100, 50), Scaling::Fit), Rect::new(0, 0, 100, 50));
+    }
+
+    #[test]
+    fn letterboxes_tall_windows() {
+        assert_eq!(fit((640, 480), Scaling::Fit), Rect::new(0, 80, 640, 320));
+        assert_eq!(fit((64, 4000), Scaling::Fit), Rect::new(0, 1984, 64, 32));
+    }
+
+    #[test]
+    fn pillarboxes_wide_windows() {
+        assert_eq!(fit((1920, 480), Scaling::Fit), Rect::new(480, 0, 960, 480));
+        assert_eq!(fit((8000, 32), Scaling::Fit), Rect::new(3968, 0, 64, 32));
+    }
+
+    #[test]
+    fn integer_scaling_uses_whole_multiples() {
+        assert_eq!(fit((640, 320), Scaling::Integer), Rect::new(0, 0, 640, 320));
+        assert_eq!(
+            fit((700, 500), Scaling::Integer),
+            Rect::new(30, 90, 640, 320)
+        );
+        assert_eq!(
+            fit((1920, 1080), Scaling::Integer),
+            Rect::new(0, 60, 1920, 960)
+        );
+        assert_eq!(
+            fit((127, 1000), Scaling::Integer),
+            Rect::new(31, 484, 64, 32)
+        );
+    }
+
+    #[test]
+    fn integer_scaling_still_fits_small_windows() {
+        assert_eq!(fit((63, 40), Scaling::Integer), fit((63, 40), Scaling::Fit));
+        assert_eq!(fit((50, 10), Scaling::Integer), Rect::new(15, 0, 20, 10));
+    }
+
+    #[test]
+    fn never_collapses_to_nothing() {
+        assert_eq!(fit((1, 1), Scaling::Fit), Rect::new(0, 0, 1, 1));
+    }
+
+    #[test]
+    fn rotation_turns_the_layout() {
+        let viewport = Viewport::fit(
+            (64, 32),
+            (640, 640),
+            (640, 640),
+            Scaling::Fit,
+            Rotation::Quarter,
+        );
+        assert_eq!(viewport.dest, Rect::new(160, 0, 320, 640));
+        // The top left of the game ends up at the top right
+        assert_eq!(
+            viewport.rect(Rect::new(0, 0, 1, 1)),
+            Rect::new(470, 0, 10, 10)
+        );
+        assert_eq!(
+            viewport.logical_point(Point::new(475, 5)),
+            Some(Point::new(0, 0))
+        );
+        assert_eq!(viewport.logical_point(Point::new(100, 5)), None);
+    }
+
+    #[test]
+    fn maps_window_points_on_hidpi_displays() {
+        let viewport = Viewport::fit(
+            (64, 32),
+            (1280, 640),
+            (640, 320),
+            Scaling::Fit,
+            Rotation::None,
+        );
+        assert_eq!(viewport.dest, Rect::new(0, 0, 1280, 640));
+        assert_eq!(
+            viewport.logical_point(Point::new(639, 319)),
+            Some(Point::new(63, 31))
+        );
+    }
+}
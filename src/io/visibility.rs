@@ -0,0 +1,100 @@
+use sdl2::event::WindowEvent;
+use std::time::Duration;
+
+/// How often the loop still comes around while nothing can be seen, to keep the
+/// events flowing.
+pub const HIDDEN_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Whether anyone can see the window, going by SDL's window events, so drawing can
+/// be skipped while it is minimized or hidden.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Visibility {
+    minimized: bool,
+    hidden: bool,
+}
+
+impl Visibility {
+    pub fn event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::Minimized => self.minimized = true,
+            WindowEvent::Restored | WindowEvent::Maximized => self.minimized = false,
+            WindowEvent::Hidden => self.hidden = true,
+            // Exposed comes when part of the window needs drawing again
+            WindowEvent::Shown | WindowEvent::Exposed => self.hidden = false,
+            _ => {}
+        }
+    }
+
+    pub fn visible(self) -> bool {
+        !self.minimized && !self.hidden
+    }
+}
+
+/// Whether `event` may bring the window back into view, which shouldn't wait out the
+/// slow loop of a hidden window to get a fresh frame.
+pub fn reveals(event: &WindowEvent) -> bool {
+    matches!(
+        event,
+        WindowEvent::Restored | WindowEvent::Maximized | WindowEvent::Shown | WindowEvent::Exposed
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether the window is visible after each of `events`.
+    fn visible_after(events: &[WindowEvent]) -> Vec<bool> {
+        let mut visibility = Visibility::default();
+        events
+            .iter()
+            .map(|event| {
+                visibility.event(event);
+                visibility.visible()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn minimizing_and_restoring() {
+        use WindowEvent::*;
+        assert_eq!(
+            visible_after(&[Minimized, FocusLost, Restored, Minimized, Maximized]),
+            [false, false, true, false, true]
+        );
+    }
+
+    #[test]
+    fn hidden_until_shown_or_exposed() {
+        use WindowEvent::*;
+        assert_eq!(
+            visible_after(&[Hidden, Moved(1, 1), Shown, Hidden, Exposed]),
+            [false, false, true, false, true]
+        );
+    }
+
+    /// Both have to be undone, in either order.
+    #[test]
+    fn minimized_and_hidden_at_once() {
+        use WindowEvent::*;
+        assert_eq!(
+            visible_after(&[Minimized, Hidden, Restored, Shown]),
+            [false, false, false, true]
+        );
+        assert_eq!(
+            visible_after(&[Hidden, Minimized, Shown, Restored]),
+            [false, false, false, true]
+        );
+    }
+
+    #[test]
+    fn events_that_bring_it_back_are_urgent() {
+        use WindowEvent::*;
+        for event in [Restored, Maximized, Shown, Exposed] {
+            assert!(reveals(&event), "{event:?}");
+        }
+        for event in [Minimized, Hidden, FocusGained, Moved(0, 0), Resized(64, 32)] {
+            assert!(!reveals(&event), "{event:?}");
+        }
+    }
+}
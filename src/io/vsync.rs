@@ -0,0 +1,193 @@
+use core::str::FromStr;
+use std::time::Duration;
+use std::time::Instant;
+use tracing::*;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Vsync {
+    /// Pace frames with a 60Hz timer
+    #[default]
+    Off,
+    /// Wait for the display's vertical blank on each present
+    On,
+    /// Like `On`, but late frames are shown straight away instead of waiting
+    Adaptive,
+}
+
+impl FromStr for Vsync {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Vsync::Off),
+            "on" => Ok(Vsync::On),
+            "adaptive" => Ok(Vsync::Adaptive),
+            _ => Err(format!(
+                "Unknown vsync mode {s:?}, expected on, off or adaptive"
+            )),
+        }
+    }
+}
+
+/// Presents closer together than this can't have waited for a display.
+const MIN_INTERVAL: Duration = Duration::from_millis(2);
+/// How many of them in a row give vsync up.
+const FAST_PRESENTS: u32 = 30;
+
+/// Where the time comes from, so tests can control it.
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Watches the time between presents to tell whether vsync is pacing frames. Some
+/// drivers accept it and then don't wait for the display, which would leave the
+/// loop spinning, so after enough presents that come straight back frames are
+/// paced with the 60Hz timer again.
+#[derive(Clone, Debug)]
+pub struct PresentTiming<C = SystemClock> {
+    clock: C,
+    last: Option<Instant>,
+    fast: u32,
+    pacing: bool,
+}
+
+impl PresentTiming {
+    pub fn new() -> PresentTiming {
+        PresentTiming::with_clock(SystemClock)
+    }
+}
+
+impl<C: Clock> PresentTiming<C> {
+    pub fn with_clock(clock: C) -> PresentTiming<C> {
+        PresentTiming {
+            clock,
+            last: None,
+            fast: 0,
+            pacing: true,
+        }
+    }
+
+    /// Notes a present that just returned.
+    pub fn presented(&mut self) {
+        let now = self.clock.now();
+        if let Some(last) = self.last.replace(now) {
+            if now.duration_since(last) < MIN_INTERVAL {
+                self.fast += 1;
+            } else {
+                self.fast = 0;
+            }
+        }
+        if self.pacing && self.fast >= FAST_PRESENTS {
+            warn!("Presenting doesn't wait for the display, pacing frames with a timer");
+            self.pacing = false;
+        }
+    }
+
+    /// Whether presenting keeps the pace, so frames don't need a timer.
+    pub fn pacing(&self) -> bool {
+        self.pacing
+    }
+
+    /// Forgets the last present, after a pause in presenting such as a hidden
+    /// window.
+    pub fn restart(&mut self) {
+        self.last = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    /// A clock that only moves when told to.
+    #[derive(Clone)]
+    struct MockClock(Rc<Cell<Instant>>);
+
+    impl MockClock {
+        fn advance(&self, by: Duration) {
+            self.0.set(self.0.get() + by);
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> Instant {
+            self.0.get()
+        }
+    }
+
+    /// Presents `count` frames, each taking `interval`, and returns whether vsync
+    /// was still pacing after each.
+    fn present(
+        timing: &mut PresentTiming<MockClock>,
+        clock: &MockClock,
+        interval: Duration,
+        count: usize,
+    ) -> Vec<bool> {
+        (0..count)
+            .map(|_| {
+                clock.advance(interval);
+                timing.presented();
+                timing.pacing()
+            })
+            .collect()
+    }
+
+    fn timing() -> (PresentTiming<MockClock>, MockClock) {
+        let clock = MockClock(Rc::new(Cell::new(Instant::now())));
+        (PresentTiming::with_clock(clock.clone()), clock)
+    }
+
+    #[test]
+    fn displays_of_any_rate_keep_vsync() {
+        for hz in [30, 60, 144, 360] {
+            let (mut timing, clock) = timing();
+            let interval = Duration::from_secs(1) / hz;
+            assert!(present(&mut timing, &clock, interval, 1000)
+                .iter()
+                .all(|pacing| *pacing));
+        }
+    }
+
+    #[test]
+    fn presents_that_dont_wait_fall_back_to_the_timer() {
+        let (mut timing, clock) = timing();
+        let pacing = present(&mut timing, &clock, Duration::from_micros(100), 40);
+        // The first present has nothing to go by
+        let given_up = pacing.iter().position(|pacing| !pacing).unwrap();
+        assert_eq!(given_up, FAST_PRESENTS as usize);
+        // Once given up, slower presents don't bring it back, as the timer is what
+        // makes them slower now
+        assert!(!present(&mut timing, &clock, Duration::from_millis(16), 10)[9]);
+    }
+
+    /// A few quick presents, like catching up after a stall, aren't enough.
+    #[test]
+    fn bursts_of_quick_presents_keep_vsync() {
+        let (mut timing, clock) = timing();
+        for _ in 0..10 {
+            present(
+                &mut timing,
+                &clock,
+                Duration::from_micros(100),
+                FAST_PRESENTS as usize - 1,
+            );
+            present(&mut timing, &clock, Duration::from_millis(16), 1);
+        }
+        assert!(timing.pacing());
+        // Nor is coming back from a pause
+        timing.restart();
+        clock.advance(Duration::from_millis(1));
+        timing.presented();
+        assert!(timing.pacing());
+    }
+}
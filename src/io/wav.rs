@@ -0,0 +1,217 @@
+//! Reads the short mono clips that can replace the synthesized beep.
+use std::path::Path;
+
+/// Sample rate and samples from -1 to 1 of a mono PCM or float WAV file.
+pub fn load(path: &Path) -> Result<(u32, Vec<f32>), String> {
+    let data = std::fs::read(path).map_err(|err| format!("{}: {err}", path.display()))?;
+    parse(&data).map_err(|err| format!("{}: {err}", path.display()))
+}
+
+pub fn parse(data: &[u8]) -> Result<(u32, Vec<f32>), String> {
+    if data.len() < 12 || &data[..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+        return Err("Not a WAV file".into());
+    }
+    let mut format = None;
+    let mut samples = None;
+    let mut rest = &data[12..];
+    while rest.len() >= 8 {
+        let id = &rest[..4];
+        let len = u32::from_le_bytes(rest[4..8].try_into().unwrap()) as usize;
+        let body = rest.get(8..8 + len).ok_or("Truncated chunk in WAV file")?;
+        match id {
+            b"fmt " if len >= 16 => {
+                let u16_at = |idx: usize| u16::from_le_bytes([body[idx], body[idx + 1]]);
+                let rate = u32::from_le_bytes(body[4..8].try_into().unwrap());
+                format = Some((u16_at(0), u16_at(2), rate, u16_at(14)));
+            }
+            b"data" => samples = Some(body),
+            _ => {}
+        }
+        // Chunks are padded to an even length
+        rest = rest.get(8 + len + len % 2..).unwrap_or_default();
+    }
+    let (tag, channels, rate, bits) = format.ok_or("WAV file has no format chunk")?;
+    let samples = samples.ok_or("WAV file has no data chunk")?;
+    if channels != 1 {
+        return Err(format!(
+            "Only mono WAV files are supported, this one has {channels} channels"
+        ));
+    }
+    let samples = match (tag, bits) {
+        (1, 8) => samples
+            .iter()
+            .map(|s| (f32::from(*s) - 128.0) / 128.0)
+            .collect(),
+        (1, 16) => samples
+            .chunks_exact(2)
+            .map(|s| f32::from(i16::from_le_bytes([s[0], s[1]])) / 32768.0)
+            .collect(),
+        (3, 32) => samples
+            .chunks_exact(4)
+            .map(|s| f32::from_le_bytes(s.try_into().unwrap()))
+            .collect(),
+        _ => {
+            return Err(format!(
+                "Unsupported WAV format {tag} with {bits} bit samples, expected 8 or 16 bit PCM or 32 bit float"
+            ))
+        }
+    };
+    Ok((rate, samples))
+}
+
+/// Linear interpolation from one sample rate to another.
+pub fn resample(samples: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from == to || samples.is_empty() {
+        return samples.to_vec();
+    }
+    let len = (samples.len() as u64 * u64::from(to) / u64::from(from)) as usize;
+    (0..len)
+        .map(|idx| {
+            let pos = idx as f64 * f64::from(from) / f64::from(to);
+            let (idx, frac) = (pos as usize, pos.fract() as f32);
+            let a = samples[idx];
+            let b = samples.get(idx + 1).copied().unwrap_or(a);
+            a + (b - a) * frac
+        })
+        .collect()
+}
+
+/// Trims the clip to start and end at rising zero crossings, so looping it doesn't
+/// click. Clips without two crossings are kept whole.
+pub fn loop_point(samples: &[f32]) -> &[f32] {
+    let rising = |pair: &[f32]| pair[0] < 0.0 && pair[1] >= 0.0;
+    let start = samples.windows(2).position(rising);
+    let end = samples.windows(2).rposition(rising);
+    match (start, end) {
+        (Some(start), Some(end)) if start < end => &samples[start + 1..end + 1],
+        _ => samples,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 16 samples of a 1kHz sine at 8kHz, 16 bit, with an odd-length chunk before
+    /// the data.
+    const BEEP: &[u8] = include_bytes!("../../tests/fixtures/wav/beep.wav");
+
+    /// A WAV file of the given format holding `data`.
+    fn wav(tag: u16, channels: u16, rate: u32, bits: u16, data: &[u8]) -> Vec<u8> {
+        let mut body = b"WAVEfmt ".to_vec();
+        body.extend(16u32.to_le_bytes());
+        for field in [tag, channels] {
+            body.extend(field.to_le_bytes());
+        }
+        body.extend(rate.to_le_bytes());
+        body.extend((rate * u32::from(bits / 8)).to_le_bytes());
+        body.extend((bits / 8).to_le_bytes());
+        body.extend(bits.to_le_bytes());
+        body.extend(b"data");
+        body.extend((data.len() as u32).to_le_bytes());
+        body.extend(data);
+        let mut file = b"RIFF".to_vec();
+        file.extend((body.len() as u32).to_le_bytes());
+        file.extend(body);
+        file
+    }
+
+    #[test]
+    fn reads_the_fixture() {
+        let (rate, samples) = parse(BEEP).unwrap();
+        assert_eq!(rate, 8000);
+        assert_eq!(samples.len(), 16);
+        assert_eq!(
+            samples[..8],
+            [0.0, 0.5, 32767.0 / 32768.0, 0.5, 0.0, -0.5, -1.0, -0.5]
+        );
+        assert_eq!(samples[..8], samples[8..]);
+    }
+
+    #[test]
+    fn reads_each_sample_format() {
+        assert_eq!(
+            parse(&wav(1, 1, 100, 8, &[0, 128, 255])),
+            Ok((100, vec![-1.0, 0.0, 127.0 / 128.0]))
+        );
+        let floats: Vec<u8> = [0.25f32, -0.75]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        assert_eq!(
+            parse(&wav(3, 1, 100, 32, &floats)),
+            Ok((100, vec![0.25, -0.75]))
+        );
+        // The smallest file there is
+        assert_eq!(parse(&wav(1, 1, 44100, 16, &[])), Ok((44100, vec![])));
+    }
+
+    #[test]
+    fn rejects_what_it_cant_play() {
+        let err = |data: &[u8]| parse(data).unwrap_err();
+        assert_eq!(err(b"RIFF"), "Not a WAV file");
+        assert_eq!(err(b"RIFF\0\0\0\0AVI LIST"), "Not a WAV file");
+        assert_eq!(
+            err(&wav(1, 2, 8000, 16, &[0; 4])),
+            "Only mono WAV files are supported, this one has 2 channels"
+        );
+        assert_eq!(
+            err(&wav(1, 1, 8000, 24, &[0; 3])),
+            "Unsupported WAV format 1 with 24 bit samples, expected 8 or 16 bit PCM or 32 bit float"
+        );
+        assert!(err(&wav(2, 1, 8000, 4, &[0; 4])).starts_with("Unsupported WAV format 2"));
+    }
+
+    #[test]
+    fn rejects_bad_chunks() {
+        let err = |data: &[u8]| parse(data).unwrap_err();
+        // The data chunk says it's longer than what's there
+        let mut truncated = BEEP.to_vec();
+        truncated.truncate(truncated.len() - 2);
+        assert_eq!(err(&truncated), "Truncated chunk in WAV file");
+        // Without its format chunk, renamed to something unknown
+        let mut no_format = BEEP.to_vec();
+        no_format[12..16].copy_from_slice(b"junk");
+        assert_eq!(err(&no_format), "WAV file has no format chunk");
+        // A format chunk too short to hold the format is skipped
+        let mut short_format = BEEP.to_vec();
+        short_format[16] = 8;
+        assert!(parse(&short_format).is_err());
+        let without_data = &BEEP[..BEEP.len() - 40];
+        let mut no_data = without_data.to_vec();
+        let len = no_data.len() as u32 - 8;
+        no_data[4..8].copy_from_slice(&len.to_le_bytes());
+        assert_eq!(err(&no_data), "WAV file has no data chunk");
+    }
+
+    #[test]
+    fn resampling_scales_the_length() {
+        let samples: Vec<f32> = (0..100).map(|n| n as f32).collect();
+        assert_eq!(resample(&samples, 8000, 44100).len(), 551);
+        assert_eq!(resample(&samples, 44100, 8000).len(), 18);
+        assert_eq!(resample(&samples, 8000, 8000), samples);
+        assert!(resample(&[], 8000, 48000).is_empty());
+        // Doubling the rate puts a point halfway between each pair, and holds the
+        // last one
+        assert_eq!(
+            resample(&[0.0, 1.0, -1.0], 1, 2),
+            [0.0, 0.5, 1.0, 0.0, -1.0, -1.0]
+        );
+    }
+
+    #[test]
+    fn loops_start_and_end_at_rising_zero_crossings() {
+        let (_, samples) = parse(BEEP).unwrap();
+        // Only rising through zero once, between its two periods
+        assert_eq!(loop_point(&samples), samples);
+        let twice = [samples.clone(), samples].concat();
+        let looped = loop_point(&twice);
+        // From the first crossing to the last, two whole periods
+        assert_eq!(looped.len(), 16);
+        assert_eq!(looped[0], 0.0);
+        assert_eq!(looped[7], -0.5);
+        // Too short to loop cleanly, so left alone
+        let once = [-0.5, 0.5, 0.25];
+        assert_eq!(loop_point(&once), once);
+    }
+}
@@ -0,0 +1,257 @@
+//! Just enough JSON to read ROM metadata and write it back out: objects keep their
+//! keys in order, and numbers are kept as they were written.
+
+use std::fmt::Write as _;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    /// The number as it appeared, e.g. `1.5`
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+/// Parses a whole document, which is one value with nothing but whitespace around it.
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = Parser { text, pos: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos < text.len() {
+        return Err(parser.error("Expected the end of the document"));
+    }
+    Ok(value)
+}
+
+/// `s` as a JSON string, quotes included.
+pub fn quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &str) -> String {
+        let line = self.text[..self.pos].matches('\n').count() + 1;
+        format!("JSON line {line}: {message}")
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start_matches([' ', '\t', '\n', '\r']).len();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.rest().chars().next() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => self.string().map(Value::String),
+            Some('-' | '0'..='9') => self.number(),
+            _ if self.eat("true") => Ok(Value::Bool(true)),
+            _ if self.eat("false") => Ok(Value::Bool(false)),
+            _ if self.eat("null") => Ok(Value::Null),
+            Some(_) => Err(self.error("Expected a value")),
+            None => Err(self.error("Unexpected end of the document")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.pos += 1;
+        let mut entries: Vec<(String, Value)> = vec![];
+        if self.eat("}") {
+            return Ok(Value::Object(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            if !self.rest().starts_with('"') {
+                return Err(self.error("Expected a key"));
+            }
+            let key = self.string()?;
+            if entries.iter().any(|(other, _)| *other == key) {
+                return Err(self.error(&format!("Duplicate key {}", quote(&key))));
+            }
+            if !self.eat(":") {
+                return Err(self.error("Expected `:`"));
+            }
+            entries.push((key, self.value()?));
+            if self.eat("}") {
+                return Ok(Value::Object(entries));
+            }
+            if !self.eat(",") {
+                return Err(self.error("Expected `,` or `}`"));
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.pos += 1;
+        let mut values = vec![];
+        if self.eat("]") {
+            return Ok(Value::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            if self.eat("]") {
+                return Ok(Value::Array(values));
+            }
+            if !self.eat(",") {
+                return Err(self.error("Expected `,` or `]`"));
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.pos += 1;
+        let mut out = String::new();
+        let mut chars = self.rest().char_indices();
+        while let Some((offset, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += offset + 1;
+                    return Ok(out);
+                }
+                '\\' => {
+                    let escaped = match chars.next().map(|(_, c)| c) {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => {
+                            let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                            u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .filter(|_| hex.len() == 4)
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| self.error("Invalid \\u escape"))?
+                        }
+                        _ => return Err(self.error("Invalid escape")),
+                    };
+                    out.push(escaped);
+                }
+                c if (c as u32) < 0x20 => return Err(self.error("Control character in a string")),
+                c => out.push(c),
+            }
+        }
+        Err(self.error("Unterminated string"))
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !matches!(c, '0'..='9' | '-' | '+' | '.' | 'e' | 'E'))
+            .unwrap_or(rest.len());
+        let number = &rest[..len];
+        if number.parse::<f64>().is_err() {
+            return Err(self.error(&format!("Invalid number {number}")));
+        }
+        self.pos += len;
+        Ok(Value::Number(number.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_nested_documents() {
+        let value = parse(r#" { "a": [1, -2.5e3, true, null], "b": { "c": "d" } } "#).unwrap();
+        assert_eq!(
+            value,
+            Value::Object(vec![
+                (
+                    "a".into(),
+                    Value::Array(vec![
+                        Value::Number("1".into()),
+                        Value::Number("-2.5e3".into()),
+                        Value::Bool(true),
+                        Value::Null,
+                    ])
+                ),
+                (
+                    "b".into(),
+                    Value::Object(vec![("c".into(), Value::String("d".into()))])
+                ),
+            ])
+        );
+        assert_eq!(parse("[]").unwrap(), Value::Array(vec![]));
+        assert_eq!(parse("{}").unwrap(), Value::Object(vec![]));
+    }
+
+    #[test]
+    fn strings_round_trip() {
+        for s in [
+            "plain",
+            "quote \" and \\ slash",
+            "tab\tnewline\n",
+            "\u{1}",
+            "ünï",
+        ] {
+            assert_eq!(parse(&quote(s)).unwrap(), Value::String(s.into()), "{s:?}");
+        }
+        assert_eq!(parse(r#""\u00e9\/""#).unwrap(), Value::String("é/".into()));
+    }
+
+    #[test]
+    fn rejects_broken_documents() {
+        for text in [
+            "",
+            "{",
+            "{\"a\" 1}",
+            "{\"a\": 1,}",
+            "[1 2]",
+            "\"open",
+            "\"\\x\"",
+            "1.2.3",
+            "{\"a\": 1, \"a\": 2}",
+            "{} {}",
+            "nope",
+        ] {
+            assert!(parse(text).is_err(), "{text:?}");
+        }
+        assert_eq!(
+            parse("{\n  \"a\": ?\n}").unwrap_err(),
+            "JSON line 2: Expected a value"
+        );
+    }
+}
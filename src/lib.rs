@@ -0,0 +1,1008 @@
+//! The interpreter and everything around it, shared by the `chip8` binary and the
+//! integration tests.
+
+use core::ops::Index;
+use core::ops::IndexMut;
+use core::time::Duration;
+use smol::Timer;
+use std::ops::ControlFlow;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Instant;
+use tracing::*;
+use ux::u4;
+
+pub mod ascii;
+pub mod breakpoints;
+pub mod callgraph;
+pub mod cheats;
+pub mod cli;
+pub mod condition;
+pub mod config;
+pub mod coverage;
+pub mod debug_http;
+pub mod diff_quirks;
+#[cfg(feature = "download")]
+pub mod download;
+pub mod dump;
+pub mod export;
+pub mod hash;
+pub mod headless;
+pub mod heatmap;
+pub mod instruction;
+pub mod io;
+pub mod json;
+pub mod logging;
+pub mod metadata;
+pub mod pace;
+pub mod quirks;
+pub mod recent;
+pub mod remote;
+pub mod signals;
+pub mod stream;
+pub mod trace;
+
+/// Exit status when `--exit-after-frames` or `--exit-after-instructions` stops a run,
+/// as opposed to the user quitting.
+pub const BOUND_EXIT_CODE: i32 = 3;
+
+/// Prints a summary of the run so far and exits with [`BOUND_EXIT_CODE`].
+pub fn exit_at_bound(status: &io::Status, vram: &[bool]) -> ! {
+    print_summary(status, vram);
+    status.finish(vram);
+    std::process::exit(BOUND_EXIT_CODE)
+}
+
+pub fn print_summary(status: &io::Status, vram: &[bool]) {
+    let summary = format!(
+        "Stopped after {} frames and {} instructions, frame hash {:016x}",
+        status.frames.load(Ordering::Relaxed),
+        status.instructions.load(Ordering::Relaxed),
+        hash::framebuffer_hash(vram, 64, 32),
+    );
+    if status.stdout_is_video {
+        eprintln!("{summary}");
+    } else {
+        println!("{summary}");
+    }
+}
+
+#[derive(Clone)]
+struct Memory {
+    rom: Vec<u8>,
+}
+
+const FONTS: [[u8; 5]; 16] = [
+    [0xF0, 0x90, 0x90, 0x90, 0xF0],
+    [0x20, 0x60, 0x20, 0x20, 0x70],
+    [0xF0, 0x10, 0xF0, 0x80, 0xF0],
+    [0xF0, 0x10, 0xF0, 0x10, 0xF0],
+    [0x90, 0x90, 0xF0, 0x10, 0x10],
+    [0xF0, 0x80, 0xF0, 0x10, 0xF0],
+    [0xF0, 0x80, 0xF0, 0x90, 0xF0],
+    [0xF0, 0x10, 0x20, 0x40, 0x40],
+    [0xF0, 0x90, 0xF0, 0x90, 0xF0],
+    [0xF0, 0x90, 0xF0, 0x10, 0xF0],
+    [0xF0, 0x90, 0xF0, 0x90, 0x90],
+    [0xE0, 0x90, 0xE0, 0x90, 0xE0],
+    [0xF0, 0x80, 0x80, 0x80, 0xF0],
+    [0xE0, 0x90, 0x90, 0x90, 0xE0],
+    [0xF0, 0x80, 0xF0, 0x80, 0xF0],
+    [0xF0, 0x80, 0xF0, 0x80, 0x80],
+];
+
+impl Memory {
+    /// Reads memory for looking at it from outside the program, with the unused
+    /// parts of the interpreter's area reading as zero.
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x0..0x50 => FONTS.as_flattened()[usize::from(addr)],
+            0x200.. => self
+                .rom
+                .get(usize::from(addr) - 0x200)
+                .copied()
+                .unwrap_or(0),
+            _ => 0,
+        }
+    }
+}
+
+impl Index<u16> for Memory {
+    type Output = u8;
+    fn index(&self, idx: u16) -> &Self::Output {
+        trace!("Accessing memory {idx:#X}");
+        match idx {
+            0x0..=0x50 => FONTS.iter().flatten().nth(usize::from(idx)).unwrap(),
+            0x1FF => &0,
+            0x200.. => {
+                let idx = usize::from(idx) - 0x200;
+                self.rom.get(idx).unwrap_or(&0)
+            }
+            _ => todo!(),
+        }
+    }
+}
+impl IndexMut<u16> for Memory {
+    fn index_mut(&mut self, idx: u16) -> &mut Self::Output {
+        trace!("Accessing memory {idx:#X}");
+        match idx {
+            0x200.. => {
+                let idx = usize::from(idx) - 0x200;
+                if self.rom.len() <= idx {
+                    self.rom.resize_with(idx + 1, Default::default);
+                }
+                &mut self.rom[idx]
+            }
+            _ => todo!(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum ExitReason {
+    InfiniteLoop,
+    WaitingForKeyPress,
+    IllegalInstruction,
+}
+
+#[derive(Clone)]
+struct Registers([u8; 16]);
+
+impl Index<u4> for Registers {
+    type Output = u8;
+    fn index(&self, idx: u4) -> &Self::Output {
+        trace!("Accessing register {idx:#X}");
+        &self.0[usize::from(u8::from(idx))]
+    }
+}
+
+impl IndexMut<u4> for Registers {
+    fn index_mut(&mut self, idx: u4) -> &mut Self::Output {
+        trace!("Accessing register {idx:#X}");
+        &mut self.0[usize::from(u8::from(idx))]
+    }
+}
+
+pub struct State {
+    pc: u16,
+    vram: Arc<Mutex<[bool; 64 * 32]>>,
+    memory: Memory,
+    stack: Vec<u16>,
+    registers: Registers,
+    vi: u16,
+    keypad: Arc<Mutex<io::Keypad>>,
+    delay_timer: Arc<Mutex<u8>>,
+    sound_timer: Arc<Mutex<u8>>,
+    last_key_press: Option<u8>,
+    /// The key held down while FX0A waits, which it takes once let go of
+    held_key: Option<u8>,
+    paused: Arc<Mutex<bool>>,
+    status: Arc<io::Status>,
+    pub emit_trace: Option<trace::TraceWriter>,
+    pub trace_log: Option<trace::TraceLog>,
+    pub compare_trace: Option<trace::TraceComparison>,
+    pub quirks: quirks::Quirks,
+    /// Where the core stopped at a breakpoint, so that resuming runs the instruction
+    /// there instead of stopping again
+    resume_at: Option<u16>,
+    /// The first write to a watched address by the current instruction: the address,
+    /// the old value and the new one
+    watch_hit: Option<(u16, u8, u8)>,
+    /// Nothing is watching, so drawing doesn't wait for the display
+    headless: bool,
+    /// Instructions since the last draw, for when fast-forwarding makes a frame of them
+    since_frame: u64,
+    /// With `--dump-on-error`, the recent instructions to save if the program dies
+    pub crash_dump: Option<dump::Recorder>,
+    /// Where to save the state as JSON on exit
+    pub dump_state_on_exit: Option<std::path::PathBuf>,
+}
+impl State {
+    pub fn new(
+        vram: Arc<Mutex<[bool; 64 * 32]>>,
+        keypad: Arc<Mutex<io::Keypad>>,
+        delay_timer: Arc<Mutex<u8>>,
+        sound_timer: Arc<Mutex<u8>>,
+        paused: Arc<Mutex<bool>>,
+        status: Arc<io::Status>,
+        rom: Vec<u8>,
+    ) -> State {
+        State {
+            pc: 0x200,
+            vram,
+            memory: Memory { rom },
+            stack: Vec::new(),
+            registers: Registers([0; 16]),
+            vi: 0,
+            keypad,
+            delay_timer,
+            sound_timer,
+            last_key_press: None,
+            held_key: None,
+            paused,
+            status,
+            emit_trace: None,
+            trace_log: None,
+            compare_trace: None,
+            quirks: quirks::Quirks::default(),
+            resume_at: None,
+            watch_hit: None,
+            headless: false,
+            since_frame: 0,
+            crash_dump: None,
+            dump_state_on_exit: None,
+        }
+    }
+
+    /// A core with no frontend, driven one instruction at a time with [`State::step`].
+    pub fn headless(rom: Vec<u8>, quirks: quirks::Quirks) -> State {
+        let delay_timer = Arc::new(Mutex::new(0));
+        let sound_timer = Arc::new(Mutex::new(0));
+        let status = io::Status::new(delay_timer.clone(), sound_timer.clone());
+        let mut state = State::new(
+            Arc::new(Mutex::new([false; 64 * 32])),
+            Arc::new(Mutex::new(io::Keypad::default())),
+            delay_timer,
+            sound_timer,
+            Arc::new(Mutex::new(false)),
+            Arc::new(status),
+            rom,
+        );
+        state.quirks = quirks;
+        state.headless = true;
+        state
+    }
+
+    /// Runs one instruction, leaving pc on FX0A while it waits for a key. Draws don't
+    /// wait for the frame here.
+    pub fn step(&mut self) -> ControlFlow<ExitReason> {
+        let instr = self.fetch().decode();
+        if let Some(coverage) = &self.status.coverage {
+            coverage.hit(self.pc);
+        }
+        if let instruction::DecodedInstr::DrawSprite { .. } = instr {
+            self.frame_wait();
+        }
+        let flow = self.execute(instr);
+        self.count_instruction();
+        let memory = &mut self.memory;
+        self.status.apply_cheats(|addr, value| memory[addr] = value);
+        if let ControlFlow::Break(ExitReason::WaitingForKeyPress) = flow {
+            self.pc -= 2;
+            self.key_let_go();
+        }
+        flow
+    }
+
+    /// Whether a key was let go of, or tapped, for FX0A to take. Until then notes
+    /// the key being held down.
+    fn key_let_go(&mut self) -> bool {
+        let (tapped, pressed) = {
+            let mut keypad = self.keypad.lock().unwrap();
+            (keypad.take_tap(), keypad.first_pressed())
+        };
+        if let Some(key) = tapped {
+            self.held_key = None;
+            self.last_key_press = Some(key);
+            return true;
+        }
+        if pressed.is_some() {
+            self.held_key = pressed;
+            return false;
+        }
+        self.last_key_press = self.held_key.take();
+        self.last_key_press.is_some()
+    }
+
+    /// How long to wait before a draw: a frame at the current speed. While
+    /// fast-forwarding nothing waits, and the timers tick for the frame right away
+    /// instead.
+    fn frame_wait(&mut self) -> Option<Duration> {
+        if self.headless {
+            return None;
+        }
+        self.since_frame = 0;
+        if self.status.fast_forward.load(Ordering::Relaxed) {
+            self.tick_timers();
+            None
+        } else {
+            Some(self.status.frame_period())
+        }
+    }
+
+    /// While fast-forwarding, makes a frame of a long enough run of instructions
+    /// without a draw.
+    fn count_instruction(&mut self) {
+        if self.headless || !self.status.fast_forward.load(Ordering::Relaxed) {
+            return;
+        }
+        self.since_frame += 1;
+        if self.since_frame >= io::FAST_FORWARD_FRAME {
+            self.tick_timers();
+            self.since_frame = 0;
+        }
+    }
+
+    /// Ticks both timers, as happens once a frame.
+    fn tick_timers(&self) {
+        for timer in [&self.delay_timer, &self.sound_timer] {
+            let mut timer = timer.lock().unwrap();
+            *timer = timer.saturating_sub(1);
+        }
+        let sound_timer = *self.sound_timer.lock().unwrap();
+        self.status.sound_timer_ticked(sound_timer, false);
+    }
+
+    /// Writes to memory, noting the write if `addr` is being watched.
+    fn store(&mut self, addr: u16, value: u8) {
+        let old = core::mem::replace(&mut self.memory[addr], value);
+        if self.watch_hit.is_none() && self.status.is_watched(addr) {
+            self.watch_hit = Some((addr, old, value));
+        }
+    }
+
+    fn publish_snapshot(&self, last: (u16, u16, String)) {
+        // Three instructions either side of pc, not reaching back below the program.
+        // Read with peek, as pc can be anywhere the program jumped to
+        let before = if self.pc >= 0x200 {
+            (self.pc - 0x200).min(6)
+        } else {
+            0
+        };
+        let code_start = self.pc - before;
+        let code = (code_start..self.pc.saturating_add(8))
+            .map(|addr| self.memory.peek(addr))
+            .collect();
+        let mut snapshot = io::Snapshot {
+            registers: self.registers.0,
+            i: self.vi,
+            pc: self.pc,
+            stack_depth: self.stack.len(),
+            stack: self.stack.clone(),
+            delay_timer: *self.delay_timer.lock().unwrap(),
+            sound_timer: *self.sound_timer.lock().unwrap(),
+            last: Some(last),
+            code,
+            code_start,
+            sprite: (self.vi..self.vi.saturating_add(io::SPRITE_ROWS))
+                .map(|addr| self.memory.peek(addr))
+                .collect(),
+            ..Default::default()
+        };
+        self.publish_memory(&mut snapshot);
+        *self.status.snapshot.lock().unwrap() = snapshot;
+    }
+
+    /// Where the program is and how it got there, innermost first:
+    ///
+    /// ```text
+    /// 0214 LD V2, 05
+    ///   called from 020A CALL 20E
+    ///   called from 0200 CALL 208
+    /// ```
+    fn call_stack(&self) -> String {
+        let opcode_at = |addr: u16| {
+            u16::from_be_bytes([
+                self.memory.peek(addr),
+                self.memory.peek(addr.wrapping_add(1)),
+            ])
+        };
+        let mut lines = vec![format!(
+            "{:04X} {}",
+            self.pc,
+            instruction::disassemble(opcode_at(self.pc))
+        )];
+        // The stack holds return addresses, just past each call
+        for call in self.stack.iter().rev().map(|ret| ret.wrapping_sub(2)) {
+            lines.push(format!(
+                "  called from {call:04X} {}",
+                instruction::disassemble(opcode_at(call))
+            ));
+        }
+        if self.stack.is_empty() {
+            lines.push("  not in a subroutine".into());
+        }
+        lines.join("\n")
+    }
+
+    /// Pauses for the user to have a look, saying why on screen and logging how the
+    /// program got here.
+    fn pause_at(&self, reason: String) {
+        self.status.notify(reason);
+        info!("Call stack:\n{}", self.call_stack());
+        *self.paused.lock().unwrap() = true;
+    }
+
+    /// Everything about the machine after the instruction at `fault` stopped it.
+    fn dump(&self, reason: ExitReason, fault: u16) -> dump::Dump {
+        dump::Dump {
+            reason: format!("{reason:?}"),
+            fault,
+            ..self.snapshot()
+        }
+    }
+
+    /// Everything about the machine between two instructions, for dumps and exports.
+    pub fn snapshot(&self) -> dump::Dump {
+        dump::Dump {
+            reason: String::new(),
+            fault: self.pc,
+            rom_hash: hash::fnv1a(&self.memory.rom),
+            quirks: self.quirks,
+            pc: self.pc,
+            i: self.vi,
+            registers: self.registers.0,
+            stack: self.stack.clone(),
+            delay_timer: *self.delay_timer.lock().unwrap(),
+            sound_timer: *self.sound_timer.lock().unwrap(),
+            vram: self.vram.lock().unwrap().to_vec(),
+            memory: (0..0x1000).map(|addr| self.memory.peek(addr)).collect(),
+            history: Vec::new(),
+        }
+    }
+
+    /// [`hash::framebuffer_hash`] of what's on screen.
+    pub fn framebuffer_hash(&self) -> u64 {
+        hash::framebuffer_hash(&*self.vram.lock().unwrap(), 64, 32)
+    }
+
+    /// Saves the state as JSON, or hands out snapshots, if anything asked for them.
+    fn check_export(&self) {
+        let Some((path, requests)) = self.status.take_state_requests() else {
+            return;
+        };
+        let snapshot = self.snapshot();
+        for request in requests {
+            let _ = request.try_send(snapshot.clone());
+        }
+        let Some(path) = path else {
+            return;
+        };
+        let notice = match export::save(&path, &snapshot) {
+            Ok(()) => {
+                info!("Saved the state to {}", path.display());
+                format!("Saved {}", io::rom::name(&path))
+            }
+            Err(err) => {
+                error!("Could not save the state: {err}");
+                "Could not save the state".into()
+            }
+        };
+        *self.status.notice.lock().unwrap() = Some(notice);
+    }
+
+    /// Saves the state for `--dump-state-on-exit`.
+    pub fn save_state_on_exit(&self) {
+        if let Some(path) = &self.dump_state_on_exit {
+            if let Err(err) = export::save(path, &self.snapshot()) {
+                error!("Could not save the state: {err}");
+            }
+        }
+    }
+
+    /// Copies the page the memory viewer is showing, if it is open, into `snapshot`.
+    fn publish_memory(&self, snapshot: &mut io::Snapshot) {
+        let Some(top) = *self.status.memory_top.lock().unwrap() else {
+            return;
+        };
+        snapshot.memory = (top..top.saturating_add(io::MEMORY_PAGE))
+            .map(|addr| self.memory.peek(addr))
+            .collect();
+        snapshot.memory_start = top;
+    }
+
+    fn trace(&mut self, opcode: u16) {
+        if self.emit_trace.is_none() && self.compare_trace.is_none() {
+            return;
+        }
+        let line = trace::format_line(self.pc, opcode, &self.registers.0, self.vi);
+        if let Some(writer) = &mut self.emit_trace {
+            writer.write(&line);
+        }
+        if let Some(comparison) = &mut self.compare_trace {
+            if let Err(report) = comparison.check(&line) {
+                eprintln!("{report}");
+                self.status.finish(&*self.vram.lock().unwrap());
+                std::process::exit(1);
+            }
+        }
+    }
+
+    /// Restarts with a new program if the frontend asked for one.
+    fn check_reload(&mut self) -> bool {
+        let Some(rom) = self.status.reload.lock().unwrap().take() else {
+            return false;
+        };
+        info!("Restarting with a new rom");
+        self.pc = 0x200;
+        if let Some(coverage) = &self.status.coverage {
+            coverage.reset(&rom);
+        }
+        self.status.heatmap.reset(&rom);
+        if let Some(crash_dump) = &mut self.crash_dump {
+            crash_dump.reset(&rom);
+        }
+        self.status.set_cheats(cheats::Cheats::load(&rom));
+        self.memory = Memory { rom };
+        self.stack.clear();
+        self.registers = Registers([0; 16]);
+        self.vi = 0;
+        self.last_key_press = None;
+        self.held_key = None;
+        *self.vram.lock().unwrap() = [false; 64 * 32];
+        *self.delay_timer.lock().unwrap() = 0;
+        *self.sound_timer.lock().unwrap() = 0;
+        self.status.sounding.store(false, Ordering::Relaxed);
+        *self.status.pattern.lock().unwrap() = None;
+        self.status
+            .pitch
+            .store(io::DEFAULT_PITCH, Ordering::Relaxed);
+        true
+    }
+
+    async fn run(&mut self) -> ControlFlow<ExitReason> {
+        loop {
+            let mut stepping = false;
+            while *self.paused.lock().unwrap() {
+                // Scrolling the memory viewer while paused still shows something
+                if self.status.debug.load(Ordering::Relaxed) {
+                    self.publish_memory(&mut self.status.snapshot.lock().unwrap());
+                }
+                self.check_export();
+                if self.status.step.swap(false, Ordering::Relaxed) {
+                    stepping = true;
+                    break;
+                }
+                smol::future::yield_now().await;
+            }
+            self.check_reload();
+            self.check_export();
+            let count = self.status.instructions.load(Ordering::Relaxed);
+            if self
+                .status
+                .exit_after_instructions
+                .is_some_and(|limit| count >= limit)
+            {
+                self.save_state_on_exit();
+                exit_at_bound(&self.status, &*self.vram.lock().unwrap());
+            }
+            if self.resume_at.take() != Some(self.pc)
+                && self.status.breaks_at(self.pc, &(self.registers.0, self.vi))
+            {
+                let opcode = self.fetch().opcode();
+                self.pause_at(format!(
+                    "Breakpoint at {:03X}: {}",
+                    self.pc,
+                    instruction::disassemble(opcode)
+                ));
+                self.resume_at = Some(self.pc);
+                continue;
+            }
+            let instr = self.fetch();
+            if let Some(coverage) = &self.status.coverage {
+                coverage.hit(self.pc);
+            }
+            self.status.heatmap.hit(self.pc);
+            self.trace(instr.opcode());
+            self.status.instructions.fetch_add(1, Ordering::Relaxed);
+            debug!("{:04X}: {instr:04X?}", self.pc);
+            let (addr, opcode) = (self.pc, instr.opcode());
+            let instr = instr.decode();
+            let debug = self.status.debug.load(Ordering::Relaxed);
+            let mnemonic = (debug || self.trace_log.is_some()).then(|| instr.to_string());
+            let before = (self.registers.0, self.vi);
+            if let Some(crash_dump) = &mut self.crash_dump {
+                crash_dump.record(addr, opcode, &before);
+            }
+            // Everything the instruction logs comes with where it is and what it was
+            let span = trace_span!(
+                "instruction",
+                pc = format_args!("{addr:03X}"),
+                opcode = format_args!("{opcode:04X}")
+            );
+            // Awaited rather than slept, as the frontend and the timers run on this
+            // thread too
+            if let instruction::DecodedInstr::DrawSprite { .. } = instr {
+                if let Some(period) = self.frame_wait() {
+                    Timer::after(period).await;
+                }
+            }
+            let flow = span.in_scope(|| self.execute(instr));
+            self.count_instruction();
+            // Rewriting the pinned bytes after every instruction means the program
+            // never gets to see anything else there
+            let memory = &mut self.memory;
+            self.status.apply_cheats(|addr, value| memory[addr] = value);
+            if stepping {
+                *self.paused.lock().unwrap() = true;
+            }
+            if let (Some(log), Some(mnemonic)) = (&self.trace_log, &mnemonic) {
+                log.log(
+                    addr,
+                    opcode,
+                    mnemonic,
+                    &before,
+                    &(self.registers.0, self.vi),
+                );
+            }
+            if let Some(mnemonic) = mnemonic.filter(|_| debug) {
+                self.publish_snapshot((addr, opcode, mnemonic));
+            }
+            if let Some((watched, old, new)) = self.watch_hit.take() {
+                self.pause_at(format!(
+                    "{watched:03X} changed from {old:02X} to {new:02X} by {} at {addr:03X}",
+                    instruction::disassemble(opcode)
+                ));
+            }
+            let registers = (self.registers.0, self.vi);
+            if let Some(condition) = self.status.check_register_watches(&registers) {
+                self.pause_at(format!(
+                    "{condition} after {} at {addr:03X}",
+                    instruction::disassemble(opcode)
+                ));
+            }
+            //TODO: wait for keypress / Draw sprite?
+            match flow {
+                ControlFlow::Break(ExitReason::WaitingForKeyPress) => {
+                    self.pc -= 2;
+                    //TODO Verify behavior
+                    while !self.check_reload() && !self.key_let_go() {
+                        smol::future::yield_now().await;
+                    }
+                }
+                reason => reason?,
+            };
+            smol::future::yield_now().await;
+        }
+    }
+}
+
+/// Keeps the window open after the program stops, until a new one is loaded.
+pub async fn run_core(state: &mut State) -> ! {
+    loop {
+        let reason = state.run().await;
+        error!("Core returned: {reason:?}\n{}", state.call_stack());
+        if let (ControlFlow::Break(reason @ ExitReason::IllegalInstruction), Some(crash_dump)) =
+            (reason, &state.crash_dump)
+        {
+            // pc has already moved past the instruction that stopped it
+            let dump = state.dump(reason, state.pc.wrapping_sub(2));
+            match crash_dump.save(dump) {
+                Ok(path) => error!("Crash dump written to {}", path.display()),
+                Err(err) => error!("Could not write a crash dump: {err}"),
+            }
+        }
+        while !state.check_reload() {
+            state.check_export();
+            Timer::after(Duration::from_millis(50)).await;
+        }
+    }
+}
+
+/// How often a timer checks whether fast-forward was let go of.
+const FAST_FORWARD_POLL: Duration = Duration::from_millis(4);
+
+/// `on_tick` sees the timer's value after every tick, and whether the game is paused.
+/// Ticks come at 60Hz times the current speed, late ones made up for as far as the
+/// [`pace::Pacer`] goes. While fast-forwarding the core ticks the timer instead.
+pub async fn handle_timer(
+    timer: Arc<Mutex<u8>>,
+    paused: Arc<Mutex<bool>>,
+    status: Arc<io::Status>,
+    on_tick: impl Fn(u8, bool),
+) -> ! {
+    let mut pacer = pace::Pacer::new(60.0 * status.speed(), pace::MAX_BEHIND);
+    loop {
+        let now = Instant::now();
+        if status.fast_forward.load(Ordering::Relaxed) {
+            // The core ticks the timers itself meanwhile. Counting afresh afterwards
+            // means the ticks it took over aren't made up for with a burst
+            pacer.restart();
+            Timer::after(FAST_FORWARD_POLL).await;
+            continue;
+        }
+        pacer.set_rate(60.0 * status.speed(), now);
+        let dropped = pacer.dropped();
+        let deadline = pacer.next(now);
+        status
+            .dropped_ticks
+            .fetch_add(pacer.dropped() - dropped, Ordering::Relaxed);
+        Timer::at(deadline).await;
+        if status.fast_forward.load(Ordering::Relaxed) {
+            continue;
+        }
+        let paused = *paused.lock().unwrap();
+        let mut timer = timer.lock().unwrap();
+        if !paused {
+            *timer = timer.saturating_sub(1);
+        }
+        on_tick(*timer, paused);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A ROM read from a file replaces the running one from the start, on a clean
+    /// machine.
+    #[test]
+    fn reloading_starts_the_new_rom_afresh() {
+        // LD V3, 0x42; then draw the font's 0 and jump in place
+        let mut state = State::headless(
+            vec![0x63, 0x42, 0xD0, 0x05, 0x12, 0x04],
+            quirks::Quirks::default(),
+        );
+        for _ in 0..3 {
+            let _ = state.step();
+        }
+        assert!(state.vram.lock().unwrap().iter().any(|pix| *pix));
+        assert!(!state.check_reload());
+
+        let path = std::env::temp_dir().join(format!("chip8-{}-reload.ch8", std::process::id()));
+        std::fs::write(&path, [0x61, 0x07, 0x12, 0x02]).unwrap();
+        let rom = io::rom::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        state.status.reload(rom.clone());
+        assert!(state.check_reload());
+        assert_eq!(state.memory.rom, rom);
+        assert_eq!(state.pc, 0x200);
+        assert_eq!(state.registers.0, [0; 16]);
+        assert!(state.vram.lock().unwrap().iter().all(|pix| !pix));
+        let _ = state.step();
+        assert_eq!(state.registers.0[1], 7);
+        // Taken, so only once
+        assert!(!state.check_reload());
+    }
+
+    fn snapshot_at(rom: &[u8], pc: u16) -> io::Snapshot {
+        let mut state = State::headless(rom.to_vec(), quirks::Quirks::default());
+        state.pc = pc;
+        state.publish_snapshot((pc, 0, String::new()));
+        let snapshot = state.status.snapshot.lock().unwrap().clone();
+        snapshot
+    }
+
+    #[test]
+    fn snapshot_code_surrounds_pc() {
+        let rom: Vec<u8> = (0..32).collect();
+        let snapshot = snapshot_at(&rom, 0x20A);
+        assert_eq!(snapshot.code_start, 0x204);
+        assert_eq!(snapshot.code, rom[4..18]);
+    }
+
+    #[test]
+    fn snapshot_code_stops_at_the_program_start() {
+        let snapshot = snapshot_at(&[0x12, 0x00], 0x202);
+        assert_eq!(snapshot.code_start, 0x200);
+        assert_eq!(snapshot.code, [0x12, 0x00, 0, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    /// pc in the interpreter's area, which the core itself can't read
+    #[test]
+    fn snapshot_code_below_the_program() {
+        let snapshot = snapshot_at(&[], 0x100);
+        assert_eq!(snapshot.code_start, 0x100);
+        assert_eq!(snapshot.code, [0; 8]);
+        let snapshot = snapshot_at(&[], 0x1FC);
+        assert_eq!(snapshot.code.len(), 8);
+    }
+
+    /// One simulated second with a window: the core waits out a frame before each
+    /// draw, and the delay timer ticks whenever the pacer says it's due. Returns the
+    /// instructions run after the first two and the delay timer's ticks.
+    fn simulate_a_second(speed: f32) -> (u64, u8) {
+        // LD V0, FF; LD DT, V0; then DRW V1, V1, 1 and JP back to it
+        let rom = vec![0x60, 0xFF, 0xF0, 0x15, 0xD1, 0x11, 0x12, 0x04];
+        let mut core = headless::Headless::new(rom, quirks::Quirks::default(), u64::MAX);
+        let _ = core.step();
+        let _ = core.step();
+        let status = core.state.status.clone();
+        status.set_speed(speed);
+        let period = status.frame_period();
+        let mut pacer = pace::Pacer::new(60.0 * status.speed(), pace::MAX_BEHIND);
+        let start = std::time::Instant::now();
+        let second = start + Duration::from_secs(1);
+        let mut tick = pacer.next(start);
+        let mut tick_until = |now, core: &headless::Headless| {
+            while tick <= now {
+                let mut timer = core.state.delay_timer.lock().unwrap();
+                *timer = timer.saturating_sub(1);
+                tick = pacer.next(tick);
+            }
+        };
+        let mut now = start;
+        let mut instructions = 0;
+        // The frame period is a float, so allow for it not adding up to a second
+        while now + period <= second + Duration::from_micros(1) {
+            now += period;
+            tick_until(now.min(second), &core);
+            // The draw, then up to the next one
+            loop {
+                let _ = core.step();
+                instructions += 1;
+                if core.state.fetch().opcode() & 0xF000 == 0xD000 {
+                    break;
+                }
+            }
+        }
+        tick_until(second, &core);
+        let timer = *core.state.delay_timer.lock().unwrap();
+        (instructions, 0xFF - timer)
+    }
+
+    /// Speed scales how fast the game runs and how fast its timers go alike.
+    #[test]
+    fn double_speed_doubles_instructions_and_timer_ticks() {
+        // A draw and a jump every frame
+        assert_eq!(simulate_a_second(1.0), (120, 60));
+        assert_eq!(simulate_a_second(2.0), (240, 120));
+        assert_eq!(simulate_a_second(0.5), (60, 30));
+    }
+
+    /// A core with a window: LD V0, FF; LD DT, V0; then `rest`.
+    fn windowed(rest: &[u8]) -> State {
+        let mut rom = vec![0x60, 0xFF, 0xF0, 0x15];
+        rom.extend_from_slice(rest);
+        let mut state = State::headless(rom, quirks::Quirks::default());
+        state.headless = false;
+        let _ = state.step();
+        let _ = state.step();
+        state
+    }
+
+    fn delay_ticks(state: &State) -> u8 {
+        0xFF - *state.delay_timer.lock().unwrap()
+    }
+
+    /// Fast-forwarding, draws don't wait and each one ticks the timers, and letting
+    /// go goes back to waiting out a single frame with no ticks owed.
+    #[test]
+    fn fast_forward_runs_frames_without_waiting() {
+        // DRW V1, V1, 1; JP 204
+        let mut state = windowed(&[0xD1, 0x11, 0x12, 0x04]);
+        state.status.fast_forward.store(true, Ordering::Relaxed);
+        let start = std::time::Instant::now();
+        for _ in 0..100 {
+            let _ = state.step();
+        }
+        // 50 frames at normal speed would take most of a second
+        assert!(start.elapsed() < Duration::from_millis(200));
+        assert_eq!(delay_ticks(&state), 50);
+
+        state.status.fast_forward.store(false, Ordering::Relaxed);
+        assert_eq!(state.frame_wait(), Some(state.status.frame_period()));
+        // The timer task ticks again from here on
+        assert_eq!(delay_ticks(&state), 50);
+    }
+
+    /// Runs `state` on a thread of its own, alongside the delay timer's task and
+    /// `check`, all on one executor the way main runs them with the frontend. Gives
+    /// back what `check` comes to, failing if that takes longer than `limit`.
+    fn run_alongside<T: Send + 'static>(
+        mut state: State,
+        limit: Duration,
+        check: impl std::future::Future<Output = T> + Send + 'static,
+    ) -> T {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let ticks = handle_timer(
+                state.delay_timer.clone(),
+                state.paused.clone(),
+                state.status.clone(),
+                |_, _| {},
+            );
+            smol::block_on(async {
+                futures::select! {
+                    _ = futures::FutureExt::fuse(state.run()) => {},
+                    _ = futures::FutureExt::fuse(ticks) => {},
+                    result = futures::FutureExt::fuse(check) => {
+                        let _ = sender.send(result);
+                    },
+                }
+            });
+        });
+        receiver
+            .recv_timeout(limit)
+            .expect("The core kept everything else waiting")
+    }
+
+    /// Waiting for the next frame before a draw leaves the timers and the keypad to
+    /// carry on meanwhile, on the same thread.
+    #[test]
+    fn timers_and_keys_go_on_while_the_core_waits_for_a_frame() {
+        // 204: DRW V1, V1, 1; SKP V3; JP 204
+        // 20A: LD V4, 2A; LD DT, V4
+        // 20E: DRW V1, V1, 1; JP 20E
+        let state = windowed(&[
+            0xD1, 0x11, 0xE3, 0x9E, 0x12, 0x04, 0x64, 0x2A, 0xF4, 0x15, 0xD1, 0x11, 0x12, 0x0E,
+        ]);
+        let (timer, keypad) = (state.delay_timer.clone(), state.keypad.clone());
+        let (woken, ticks, after_key) = run_alongside(state, Duration::from_secs(5), async move {
+            // Each of these would take a whole frame if the core blocked the thread
+            let start = Instant::now();
+            for _ in 0..30 {
+                Timer::after(Duration::from_millis(1)).await;
+            }
+            let woken = start.elapsed();
+            Timer::after(Duration::from_millis(500).saturating_sub(woken)).await;
+            let ticks = 0xFF - *timer.lock().unwrap();
+            keypad.lock().unwrap().press(0);
+            // Long enough for a frame and a few ticks, not for 2A of them
+            Timer::after(Duration::from_millis(200)).await;
+            (woken, ticks, *timer.lock().unwrap())
+        });
+        assert!(woken < Duration::from_millis(250), "{woken:?}");
+        // 30 at 60Hz
+        assert!((20..=40).contains(&ticks), "{ticks}");
+        assert!((0x10..0x2A).contains(&after_key), "{after_key:02X}");
+    }
+
+    /// Held, fast-forward runs the game as fast as it goes, the core ticking the
+    /// timers once a frame. Let go of, the game and the timer task are back at 60Hz
+    /// straight away, with no ticks made up for the time it was held.
+    #[test]
+    fn fast_forward_is_uncapped_and_lets_go_cleanly() {
+        // DRW V1, V1, 1; JP 204
+        let state = windowed(&[0xD1, 0x11, 0x12, 0x04]);
+        let (timer, status) = (state.delay_timer.clone(), state.status.clone());
+        status.fast_forward.store(true, Ordering::Relaxed);
+        let (held, held_ticks, frames, ticks) =
+            run_alongside(state, Duration::from_secs(5), async move {
+                Timer::after(Duration::from_millis(100)).await;
+                let held = status.instructions.load(Ordering::Relaxed);
+                let held_ticks = 0xFF - *timer.lock().unwrap();
+                *timer.lock().unwrap() = 0xFF;
+                status.fast_forward.store(false, Ordering::Relaxed);
+                let released = status.instructions.load(Ordering::Relaxed);
+                Timer::after(Duration::from_millis(500)).await;
+                let frames = (status.instructions.load(Ordering::Relaxed) - released) / 2;
+                (held, held_ticks, frames, 0xFF - *timer.lock().unwrap())
+            });
+        // 6 frames at normal speed
+        assert!(held > 2 * 255, "{held}");
+        assert_eq!(held_ticks, 0xFF);
+        // 30 frames and ticks at 60Hz
+        assert!((20..=40).contains(&frames), "{frames}");
+        assert!((20..=40).contains(&ticks), "{ticks}");
+    }
+
+    /// A game waiting on the delay timer without drawing still gets there.
+    #[test]
+    fn fast_forward_makes_frames_without_draws() {
+        // JP 204
+        let mut state = windowed(&[0x12, 0x04]);
+        state.status.fast_forward.store(true, Ordering::Relaxed);
+        for _ in 0..io::FAST_FORWARD_FRAME * 3 - 1 {
+            let _ = state.step();
+        }
+        assert_eq!(delay_ticks(&state), 2);
+        let _ = state.step();
+        assert_eq!(delay_ticks(&state), 3);
+        // Not fast-forwarding, only the timer task ticks
+        state.status.fast_forward.store(false, Ordering::Relaxed);
+        for _ in 0..io::FAST_FORWARD_FRAME * 2 {
+            let _ = state.step();
+        }
+        assert_eq!(delay_ticks(&state), 3);
+    }
+
+    /// The hotkey and debugger ask through the status; the core saves the state
+    /// between instructions and says where it went.
+    #[test]
+    fn saves_the_state_when_asked() {
+        let state = windowed(&[0x12, 0x04]);
+        let path = std::env::temp_dir().join(format!("chip8-{}-asked.json", std::process::id()));
+        state.status.request_export(path.clone());
+        let snapshot = state.status.request_state();
+        state.check_export();
+        let json = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(json, export::to_json(&snapshot.try_recv().unwrap()));
+        let notice = state.status.notice.lock().unwrap().take().unwrap();
+        assert!(notice.starts_with("Saved "), "{notice}");
+        // Nothing more to do until asked again
+        assert!(state.status.take_state_requests().is_none());
+    }
+}
@@ -0,0 +1,680 @@
+//! Log output: a small tracing subscriber that prints each message along with the
+//! spans it happened in, e.g. the frame and the instruction, as text or as one JSON
+//! object per line for tools.
+//!
+//! What gets printed is chosen like `RUST_LOG`, with a level for everything and
+//! levels for modules, e.g. `warn,chip8::io=debug`. Messages that are filtered out
+//! cost one cached check at each call site.
+//!
+//! Recent messages can also be kept for the console overlay, at their own level, so
+//! warnings show up in the window even when the terminal only gets errors.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::str::FromStr;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::sync::RwLock;
+use std::time::Instant;
+use tracing::field::Field;
+use tracing::field::Visit;
+use tracing::level_filters::LevelFilter;
+use tracing::span;
+use tracing::subscriber::Interest;
+use tracing::Event;
+use tracing::Metadata;
+use tracing::Subscriber;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("Unknown log format {s:?}, expected text or json")),
+        }
+    }
+}
+
+/// Which messages to print: a default level and more specific ones by module.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Filter {
+    default: LevelFilter,
+    /// Module paths and their levels, the longest first so the most specific wins
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter {
+            default: LevelFilter::ERROR,
+            targets: vec![],
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut filter = Filter::default();
+        for directive in s.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((target, level)) => {
+                    let level = parse_level(level)?;
+                    filter.targets.retain(|(other, _)| other != target);
+                    filter.targets.push((target.to_owned(), level));
+                }
+                // A bare module name turns everything on for it, like RUST_LOG
+                None => match parse_level(directive) {
+                    Ok(level) => filter.default = level,
+                    Err(_) => filter
+                        .targets
+                        .push((directive.to_owned(), LevelFilter::TRACE)),
+                },
+            }
+        }
+        filter
+            .targets
+            .sort_by_key(|(target, _)| std::cmp::Reverse(target.len()));
+        Ok(filter)
+    }
+}
+
+pub fn parse_level(s: &str) -> Result<LevelFilter, String> {
+    s.parse().map_err(|_| {
+        format!("Unknown log level {s:?}, expected off, error, warn, info, debug or trace")
+    })
+}
+
+impl Filter {
+    /// Everything at `level` or more severe, keeping the more specific levels.
+    pub fn with_level(mut self, level: LevelFilter) -> Filter {
+        self.default = level;
+        self
+    }
+
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.targets
+            .iter()
+            .find(|(prefix, _)| {
+                target
+                    .strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .map_or(self.default, |(_, level)| *level)
+    }
+
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        *metadata.level() <= self.level_for(metadata.target())
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.targets
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, LevelFilter::max)
+    }
+}
+
+/// The filter in `RUST_LOG`, or only errors if it is unset or doesn't parse.
+pub fn env_filter() -> Filter {
+    std::env::var("RUST_LOG")
+        .ok()
+        .and_then(|filter| filter.parse().ok())
+        .unwrap_or_default()
+}
+
+/// Starts logging as `RUST_LOG` says, until [`configure`] changes it.
+pub fn init() {
+    let logger = Logger::new(env_filter(), Box::new(std::io::stderr()));
+    if tracing::subscriber::set_global_default(logger).is_err() {
+        eprintln!("Logging was already set up");
+    }
+}
+
+/// Switches to `filter` and `format`, e.g. from the command line.
+pub fn configure(filter: Filter, format: Format) {
+    with_logger(|logger| {
+        let mut settings = logger.settings.write().unwrap();
+        settings.filter = filter.clone();
+        settings.format = format;
+    });
+    // Call sites remember whether they were enabled
+    tracing::callsite::rebuild_interest_cache();
+}
+
+/// How many messages the console keeps.
+pub const CONSOLE_LINES: usize = 100;
+/// Longer messages are cut short, as the console doesn't wrap them.
+const CONSOLE_WIDTH: usize = 160;
+
+/// Keeps the latest messages at `level` or more severe for [`console_lines`].
+pub fn capture(level: LevelFilter) {
+    with_logger(|logger| logger.settings.write().unwrap().console = level);
+    tracing::callsite::rebuild_interest_cache();
+}
+
+/// Copies the kept messages, oldest first, into `lines`. Leaves it alone and returns
+/// false rather than wait if a message is being added right then.
+pub fn console_lines(lines: &mut Vec<String>) -> bool {
+    let mut copied = false;
+    with_logger(|logger| {
+        if let Ok(console) = logger.console.try_lock() {
+            lines.clear();
+            lines.extend(console.iter().cloned());
+            copied = true;
+        }
+    });
+    copied
+}
+
+fn with_logger(mut f: impl FnMut(&Logger)) {
+    tracing::dispatcher::get_default(|dispatch| {
+        if let Some(logger) = dispatch.downcast_ref::<Logger>() {
+            f(logger);
+        }
+    });
+}
+
+struct SpanData {
+    name: &'static str,
+    fields: Fields,
+    /// Handles to the span still around, it is forgotten at 0
+    refs: usize,
+}
+
+struct Settings {
+    filter: Filter,
+    format: Format,
+    /// What the console keeps, apart from what is printed
+    console: LevelFilter,
+}
+
+struct Logger {
+    settings: RwLock<Settings>,
+    start: Instant,
+    spans: Mutex<HashMap<u64, SpanData>>,
+    next_id: AtomicU64,
+    console: Mutex<VecDeque<String>>,
+    /// Messages that couldn't be kept because the console was being copied
+    lost: AtomicUsize,
+    /// Where printed messages go, stderr outside of tests
+    out: Mutex<Box<dyn std::io::Write + Send>>,
+}
+
+impl Logger {
+    fn new(filter: Filter, out: Box<dyn std::io::Write + Send>) -> Logger {
+        Logger {
+            settings: RwLock::new(Settings {
+                filter,
+                format: Format::Text,
+                console: LevelFilter::OFF,
+            }),
+            start: Instant::now(),
+            spans: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+            console: Mutex::new(VecDeque::new()),
+            lost: AtomicUsize::new(0),
+            out: Mutex::new(out),
+        }
+    }
+
+    /// Adds a line to the console, never waiting for it, so logging can't get stuck
+    /// on a frontend that is copying the lines, even on the same thread.
+    fn keep(&self, line: String) {
+        let Ok(mut console) = self.console.try_lock() else {
+            self.lost.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+        let lost = self.lost.swap(0, Ordering::Relaxed);
+        if lost > 0 {
+            console.push_back(format!("({lost} messages missed)"));
+        }
+        console.push_back(line);
+        while console.len() > CONSOLE_LINES {
+            console.pop_front();
+        }
+    }
+}
+
+/// `line` cut to at most `width` characters, marked with `...` if it was longer.
+fn truncate(line: String, width: usize) -> String {
+    if line.chars().count() <= width {
+        return line;
+    }
+    let mut short: String = line.chars().take(width.saturating_sub(3)).collect();
+    short.push_str("...");
+    short
+}
+
+thread_local! {
+    /// The spans entered on this thread, innermost last
+    static CURRENT: RefCell<Vec<span::Id>> = const { RefCell::new(vec![]) };
+}
+
+impl Subscriber for Logger {
+    fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+        if self.enabled(metadata) {
+            Interest::always()
+        } else {
+            Interest::never()
+        }
+    }
+
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let settings = self.settings.read().unwrap();
+        settings.filter.enabled(metadata) || *metadata.level() <= settings.console
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        let settings = self.settings.read().unwrap();
+        Some(settings.filter.max_level().max(settings.console))
+    }
+
+    fn new_span(&self, span: &span::Attributes) -> span::Id {
+        let mut fields = Fields::default();
+        span.record(&mut fields);
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.spans.lock().unwrap().insert(
+            id,
+            SpanData {
+                name: span.metadata().name(),
+                fields,
+                refs: 1,
+            },
+        );
+        span::Id::from_u64(id)
+    }
+
+    fn record(&self, span: &span::Id, values: &span::Record) {
+        if let Some(data) = self.spans.lock().unwrap().get_mut(&span.into_u64()) {
+            values.record(&mut data.fields);
+        }
+    }
+
+    fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+    fn event(&self, event: &Event) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let metadata = event.metadata();
+        let time = self.start.elapsed().as_secs_f64();
+        let (format, print, keep) = {
+            let settings = self.settings.read().unwrap();
+            (
+                settings.format,
+                settings.filter.enabled(metadata),
+                *metadata.level() <= settings.console,
+            )
+        };
+        if keep {
+            let mut line = format!("{:<5} {}", metadata.level(), fields.message());
+            if !fields.values.is_empty() {
+                let _ = write!(line, " {}", fields.text());
+            }
+            self.keep(truncate(line, CONSOLE_WIDTH));
+        }
+        if !print {
+            return;
+        }
+        let spans = self.spans.lock().unwrap();
+        let current = CURRENT.with(|current| current.borrow().clone());
+        let context = current
+            .iter()
+            .filter_map(|id| spans.get(&id.into_u64()))
+            .map(|span| (span.name, &span.fields));
+        let mut line = String::new();
+        match format {
+            Format::Text => {
+                let _ = write!(
+                    line,
+                    "{time:9.3}s {:<5} {}",
+                    metadata.level(),
+                    metadata.target()
+                );
+                for (idx, (name, fields)) in context.enumerate() {
+                    let separator = if idx == 0 { ' ' } else { ':' };
+                    let _ = write!(line, "{separator}{name}{{{}}}", fields.text());
+                }
+                let _ = write!(line, ": {}", fields.message());
+                if !fields.values.is_empty() {
+                    let _ = write!(line, " {}", fields.text());
+                }
+            }
+            Format::Json => {
+                let _ = write!(
+                    line,
+                    "{{\"time\":{time:.6},\"level\":\"{}\",\"target\":{},\"spans\":[",
+                    metadata.level(),
+                    json_string(metadata.target())
+                );
+                for (idx, (name, fields)) in context.enumerate() {
+                    if idx > 0 {
+                        line.push(',');
+                    }
+                    let _ = write!(line, "{{\"name\":{}", json_string(name));
+                    fields.write_json(&mut line);
+                    line.push('}');
+                }
+                let _ = write!(line, "],\"message\":{}", json_string(&fields.message()));
+                fields.write_json(&mut line);
+                line.push('}');
+            }
+        }
+        drop(spans);
+        let _ = writeln!(self.out.lock().unwrap(), "{line}");
+    }
+
+    fn enter(&self, span: &span::Id) {
+        CURRENT.with(|current| current.borrow_mut().push(span.clone()));
+    }
+
+    fn exit(&self, span: &span::Id) {
+        CURRENT.with(|current| {
+            let mut current = current.borrow_mut();
+            if let Some(idx) = current.iter().rposition(|id| id == span) {
+                current.remove(idx);
+            }
+        });
+    }
+
+    fn clone_span(&self, span: &span::Id) -> span::Id {
+        if let Some(data) = self.spans.lock().unwrap().get_mut(&span.into_u64()) {
+            data.refs += 1;
+        }
+        span.clone()
+    }
+
+    fn try_close(&self, span: span::Id) -> bool {
+        let mut spans = self.spans.lock().unwrap();
+        let Some(data) = spans.get_mut(&span.into_u64()) else {
+            return false;
+        };
+        data.refs -= 1;
+        if data.refs > 0 {
+            return false;
+        }
+        spans.remove(&span.into_u64());
+        true
+    }
+}
+
+enum Value {
+    /// Numbers and bools, the same in text and JSON
+    Plain(String),
+    Text(String),
+}
+
+/// The fields of a span or message, in the order they were given.
+#[derive(Default)]
+struct Fields {
+    message: Option<String>,
+    values: Vec<(&'static str, Value)>,
+}
+
+impl Fields {
+    fn message(&self) -> String {
+        self.message.clone().unwrap_or_default()
+    }
+
+    fn text(&self) -> String {
+        let mut text = String::new();
+        for (idx, (name, value)) in self.values.iter().enumerate() {
+            if idx > 0 {
+                text.push(' ');
+            }
+            let (Value::Plain(value) | Value::Text(value)) = value;
+            let _ = write!(text, "{name}={value}");
+        }
+        text
+    }
+
+    /// Appends the fields to a JSON object that is still open.
+    fn write_json(&self, out: &mut String) {
+        for (name, value) in &self.values {
+            let _ = match value {
+                Value::Plain(value) => write!(out, ",{}:{value}", json_string(name)),
+                Value::Text(value) => {
+                    write!(out, ",{}:{}", json_string(name), json_string(value))
+                }
+            };
+        }
+    }
+
+    fn push(&mut self, field: &Field, value: Value) {
+        match (field.name(), value) {
+            ("message", Value::Plain(message) | Value::Text(message)) => {
+                self.message = Some(message)
+            }
+            (name, value) => self.values.push((name, value)),
+        }
+    }
+}
+
+impl Visit for Fields {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        // JSON has no NaN or infinity
+        let value = match value.is_finite() {
+            true => Value::Plain(value.to_string()),
+            false => Value::Text(value.to_string()),
+        };
+        self.push(field, value);
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.push(field, Value::Plain(value.to_string()));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.push(field, Value::Plain(value.to_string()));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.push(field, Value::Plain(value.to_string()));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.push(field, Value::Text(value.to_owned()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.push(field, Value::Text(format!("{value:?}")));
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tracing::*;
+
+    /// Collects what the logger prints.
+    #[derive(Clone, Default)]
+    struct Output(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Output {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// The lines printed while running `f` with `filter` and `format`, without the
+    /// time at the start of text lines.
+    fn printed(filter: &str, format: Format, f: impl FnOnce()) -> Vec<String> {
+        let output = Output::default();
+        let logger = Logger::new(filter.parse().unwrap(), Box::new(output.clone()));
+        tracing::subscriber::with_default(logger, || {
+            configure(filter.parse().unwrap(), format);
+            f();
+        });
+        let text = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        text.lines()
+            .map(|line| match format {
+                Format::Text => line.split_once("s ").unwrap().1.to_owned(),
+                Format::Json => line.to_owned(),
+            })
+            .collect()
+    }
+
+    /// A logger that prints nothing.
+    fn quiet() -> Logger {
+        Logger::new("off".parse().unwrap(), Box::new(Output::default()))
+    }
+
+    fn level(filter: &str, target: &str) -> LevelFilter {
+        filter.parse::<Filter>().unwrap().level_for(target)
+    }
+
+    #[test]
+    fn the_most_specific_module_wins() {
+        let filter = "warn,chip8::io=debug,chip8::io::audio=off";
+        assert_eq!(level(filter, "chip8"), LevelFilter::WARN);
+        assert_eq!(level(filter, "chip8::io"), LevelFilter::DEBUG);
+        assert_eq!(level(filter, "chip8::io::keymap"), LevelFilter::DEBUG);
+        assert_eq!(level(filter, "chip8::io::audio"), LevelFilter::OFF);
+        // Only whole module names match
+        assert_eq!(level(filter, "chip8::iox"), LevelFilter::WARN);
+        // The later of two levels for a module counts, and a bare name is everything
+        assert_eq!(level("chip8=info,chip8=debug", "chip8"), LevelFilter::DEBUG);
+        assert_eq!(level("chip8::trace", "chip8::trace"), LevelFilter::TRACE);
+        assert_eq!(level("", "chip8"), LevelFilter::ERROR);
+        assert_eq!(
+            "info,a=trace".parse::<Filter>().unwrap().max_level(),
+            LevelFilter::TRACE
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_levels_and_formats() {
+        assert!("chip8=loud".parse::<Filter>().is_err());
+        assert!(parse_level("loud").is_err());
+        assert_eq!("json".parse(), Ok(Format::Json));
+        assert!("yaml".parse::<Format>().is_err());
+    }
+
+    #[test]
+    fn prints_messages_inside_their_spans() {
+        let lines = printed("error,chip8::logging=info", Format::Text, || {
+            let frame = info_span!("frame", frame = 3);
+            let _frame = frame.enter();
+            let instruction = info_span!("instruction", pc = "200");
+            let _instruction = instruction.enter();
+            info!(vf = true, "Drawing");
+            debug!("Left out");
+        });
+        assert_eq!(
+            lines,
+            ["INFO  chip8::logging::tests frame{frame=3}:instruction{pc=200}: Drawing vf=true"]
+        );
+    }
+
+    #[test]
+    fn prints_json_lines() {
+        let lines = printed("info", Format::Json, || {
+            let _frame = info_span!("frame", frame = 3).entered();
+            warn!(key = "a \"b\"", "Odd key");
+        });
+        assert_eq!(lines.len(), 1);
+        let crate::json::Value::Object(fields) = crate::json::parse(&lines[0]).unwrap() else {
+            panic!("{}", lines[0]);
+        };
+        let names: Vec<&str> = fields.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["time", "level", "target", "spans", "message", "key"]
+        );
+        assert!(lines[0].contains(r#""spans":[{"name":"frame","frame":3}]"#));
+        assert!(lines[0].contains(r#""message":"Odd key","key":"a \"b\""}"#));
+    }
+
+    #[test]
+    fn console_keeps_the_latest_lines() {
+        let logger = quiet();
+        for i in 0..CONSOLE_LINES + 5 {
+            logger.keep(i.to_string());
+        }
+        let console = logger.console.lock().unwrap();
+        assert_eq!(console.len(), CONSOLE_LINES);
+        assert_eq!(console.front().unwrap(), "5");
+        assert_eq!(console.back().unwrap(), &(CONSOLE_LINES + 4).to_string());
+    }
+
+    #[test]
+    fn console_counts_lines_it_missed() {
+        let logger = quiet();
+        {
+            let _copying = logger.console.lock().unwrap();
+            logger.keep("first".into());
+            logger.keep("second".into());
+        }
+        logger.keep("third".into());
+        let console = logger.console.lock().unwrap();
+        assert_eq!(
+            console.iter().collect::<Vec<_>>(),
+            ["(2 messages missed)", "third"]
+        );
+    }
+
+    #[test]
+    fn console_captures_only_its_level() {
+        let logger = quiet();
+        tracing::subscriber::with_default(logger, || {
+            capture(LevelFilter::WARN);
+            warn!(pc = "200", "Unknown instruction");
+            info!("Left out");
+            error!("{}", "x".repeat(CONSOLE_WIDTH * 2));
+            let mut lines = vec!["old".to_owned()];
+            assert!(console_lines(&mut lines));
+            assert_eq!(lines.len(), 2);
+            assert_eq!(lines[0], "WARN  Unknown instruction pc=200");
+            assert_eq!(lines[1].chars().count(), CONSOLE_WIDTH);
+            assert!(lines[1].ends_with("..."));
+        });
+    }
+
+    #[test]
+    fn truncates_by_characters() {
+        assert_eq!(truncate("short".into(), 5), "short");
+        assert_eq!(truncate("ünïcödé".into(), 6), "ünï...");
+    }
+}
@@ -1,198 +1,446 @@
-use core::ops::Index;
-use core::ops::IndexMut;
+use chip8::ascii;
+use chip8::callgraph;
+use chip8::cheats;
+use chip8::cli;
+use chip8::cli::Exit;
+use chip8::config;
+use chip8::coverage;
+use chip8::debug_http;
+use chip8::diff_quirks;
+#[cfg(feature = "download")]
+use chip8::download;
+use chip8::dump;
+use chip8::handle_timer;
+use chip8::heatmap;
+use chip8::io;
+use chip8::logging;
+use chip8::metadata;
+use chip8::print_summary;
+use chip8::quirks;
+use chip8::recent;
+use chip8::remote;
+use chip8::run_core;
+use chip8::signals;
+use chip8::stream;
+use chip8::trace;
+use chip8::State;
+use chip8::BOUND_EXIT_CODE;
 use core::pin::pin;
 use core::time::Duration;
+use futures::future::Either;
 use futures::select;
 use futures::FutureExt;
-use log::*;
-use smol::Timer;
-use std::ops::ControlFlow;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::sync::Mutex;
-use ux::u4;
-
-mod instruction;
-mod io;
+use tracing::*;
 
 fn main() {
-    env_logger::init();
+    logging::init();
+    if let Err(exit) = run() {
+        match &exit {
+            Exit::Usage(message) | Exit::Failed(message) => eprintln!("{message}"),
+            Exit::Code(_) => {}
+        }
+        std::process::exit(exit.code())
+    }
+}
+
+fn run() -> Result<(), Exit> {
+    let mut args = std::env::args().skip(1).peekable();
+    if args.next_if(|arg| arg == "callgraph").is_some() {
+        return callgraph::run(args);
+    }
+    if args.next_if(|arg| arg == "cheats").is_some() {
+        return cheats::run(args);
+    }
+    if args.next_if(|arg| arg == "compare").is_some() {
+        return io::compare::run(args);
+    }
+    if args.next_if(|arg| arg == "diff-quirks").is_some() {
+        return diff_quirks::run(args);
+    }
+    if args.next_if(|arg| arg == "info").is_some() {
+        return metadata::run(args);
+    }
+    if args.next_if(|arg| arg == "inspect-dump").is_some() {
+        return dump::run(args);
+    }
+    if args.next_if(|arg| arg == "trace").is_some() {
+        return trace::run(args);
+    }
+    let mut options = cli::Options::parse(args).map_err(|err| Exit::usage(err, cli::USAGE))?;
+    if options.log_level.is_some() || options.log_filter.is_some() || options.log_format.is_some() {
+        let filter = match options.log_filter.take() {
+            Some(filter) => filter,
+            None => logging::env_filter(),
+        };
+        let filter = match options.log_level {
+            Some(level) => filter.with_level(level),
+            None => filter,
+        };
+        logging::configure(filter, options.log_format.unwrap_or_default());
+    }
+    if options.list_audio_devices {
+        return io::list_audio_devices()
+            .map_err(|err| fail(format!("Could not list the audio devices: {err}")));
+    }
+    if options.list_recent {
+        let recent = recent::Recent::load(recent::default_path());
+        for (n, rom) in recent.entries().enumerate() {
+            println!("{:>2}. {}", n + 1, rom.display());
+        }
+        return Ok(());
+    }
+    let rom_path = options
+        .rom_path(|| recent::Recent::load(recent::default_path()))
+        .map_err(Exit::Usage)?;
+    let rom_path = rom_path
+        .map(|path| fetch_if_url(path, options.no_cache))
+        .transpose()?;
+    options.rom = rom_path.clone();
+    let metadata = match rom_path.as_deref() {
+        Some(path) => metadata::Metadata::load(path).map_err(fail)?,
+        None => None,
+    };
+    let rom = match &rom_path {
+        Some(path) => {
+            info!("Opening rom");
+            let rom = io::rom::read(path).map_err(fail)?;
+            recent::remember(path);
+            rom
+        }
+        None => {
+            info!("No ROM given, showing the splash screen");
+            SPLASH.to_vec()
+        }
+    };
+    let mut config = match config::default_path() {
+        Some(path) => config::Config::load(&path),
+        None => Ok(config::Config::default()),
+    }
+    .map_err(fail)?;
+    if rom_path.is_some() {
+        let suggested = metadata
+            .as_ref()
+            .map(metadata::Metadata::config_values)
+            .unwrap_or_default();
+        config.select_rom(&rom, suggested);
+    }
+    let speed = match options.speed {
+        Some(speed) => Some(speed),
+        None => config
+            .get_as("speed")
+            .and_then(|speed| {
+                speed
+                    .map(io::check_speed)
+                    .transpose()
+                    .map_err(|message| config::Error::Invalid {
+                        key: "speed".into(),
+                        message,
+                    })
+            })
+            .map_err(fail)?,
+    };
     let vram = Arc::new(Mutex::<[bool; 64 * 32]>::new([false; 64 * 32]));
-    let keypad = Arc::new(Mutex::new(io::Keypad([false; 16])));
+    let keypad = Arc::new(Mutex::new(io::Keypad::default()));
     let delay_timer = Arc::new(Mutex::new(0));
     let sound_timer = Arc::new(Mutex::new(0));
-    let file = std::env::args()
-        .nth(1)
-        .expect("Expected rom as first arguement");
-    info!("Opening rom");
-    let rom = std::fs::read(file).unwrap();
+    // Paused before the first instruction runs, so the timers hold still too
+    let paused = Arc::new(Mutex::new(options.start_paused));
+    let mut status = io::Status::new(delay_timer.clone(), sound_timer.clone());
+    status.exit_after_instructions = options.exit_after_instructions;
+    status.dump_frame = options.dump_frame.clone();
+    status.coverage = options.coverage.as_deref().map(coverage::Coverage::new);
+    status.heatmap = heatmap::Heatmap::new(options.heatmap.as_deref());
+    if let Some(speed) = speed {
+        status.set_speed(speed);
+    }
+    if options.start_paused {
+        status.notify("Paused before the first instruction, Ctrl+P starts".into());
+    }
+    if options.break_at_start {
+        status.set_breakpoint(0x200, true);
+    }
+    for (addr, condition) in &options.breakpoints {
+        match condition {
+            Some(condition) => status.set_conditional_breakpoint(*addr, condition.clone()),
+            None => status.set_breakpoint(*addr, false),
+        }
+    }
+    for condition in &options.register_watches {
+        status.watch_registers(condition.clone());
+    }
+    for addr in &options.watches {
+        status.watch(*addr);
+    }
+    status.stdout_is_video = options.record_video.as_deref().is_some_and(io::is_stdout);
+    let state_to_stdout = options
+        .dump_state_on_exit
+        .as_deref()
+        .is_some_and(io::is_stdout);
+    if status.stdout_is_video
+        && (options.ascii_mirror || options.frame_hash_every.is_some() || state_to_stdout)
+    {
+        return Err(Exit::Usage(
+            "--ascii-mirror, --print-frame-hash-every and --dump-state-on-exit - can't share stdout with --record-video".into(),
+        ));
+    }
+    let status = Arc::new(status);
+    let mut settings = io::Settings::new(&options, &config).map_err(fail)?;
+    // Both seed the random number generator, so this has to happen before the core runs
+    if let Some(path) = &options.record {
+        settings.recorder = Some(io::Recorder::create(path).map_err(fail)?);
+    }
+    if let Some(path) = &options.script {
+        settings.script = Some(io::Script::load(path).map_err(fail)?);
+    }
+    if let Some(path) = &options.replay {
+        settings.replay = Some(io::Replay::load(path).map_err(fail)?);
+    }
+    // Without a display server, e.g. over SSH, the screen goes to the terminal instead
+    let windowed = match io::check_video() {
+        Ok(()) => true,
+        Err(err) => {
+            error!("Could not open a window, running without one: {err}");
+            false
+        }
+    };
+    let mirror_interval = if options.ascii_mirror || (!windowed && !status.stdout_is_video) {
+        let secs: f32 = config
+            .get_as("debug.ascii-interval")
+            .map_err(fail)?
+            .unwrap_or(1.0);
+        Some(Duration::from_secs_f32(secs.max(0.01)))
+    } else {
+        None
+    };
+    if let Some(metadata) = &metadata {
+        settings.keymaps.suggest(&rom, &metadata.keys);
+    }
+    let rom_name = rom_path.as_deref().map(io::rom::name).unwrap_or_default();
+    settings.keymaps.select(&rom, &rom_name);
+    let mut keys = format!("Keypad: {}", settings.keymaps.describe());
+    if let Some(title) = metadata
+        .as_ref()
+        .and_then(|metadata| metadata.title.as_ref())
+    {
+        let by = match metadata
+            .as_ref()
+            .and_then(|metadata| metadata.author.as_ref())
+        {
+            Some(author) => format!(" by {author}"),
+            None => String::new(),
+        };
+        keys = format!("{title}{by}\n{keys}");
+    }
+    if status.stdout_is_video {
+        eprintln!("{keys}");
+    } else {
+        println!("{keys}");
+    }
+    if let Some(coverage) = &status.coverage {
+        coverage.reset(&rom);
+    }
+    status.heatmap.reset(&rom);
+    let mut cheats = cheats::Cheats::load(&rom);
+    if !options.cheats.is_empty() {
+        for cheat in &options.cheats {
+            cheats.add(*cheat);
+        }
+        if let Err(err) = cheats.save() {
+            warn!("Could not save the cheats: {err}");
+        }
+    }
+    for cheat in cheats.iter().filter(|cheat| cheat.enabled) {
+        info!("Cheat: {cheat}");
+    }
+    status.set_cheats(cheats);
+    let crash_dump = options
+        .dump_on_error
+        .take()
+        .map(|dir| dump::Recorder::new(dir, &rom));
     let mut state = State::new(
         vram.clone(),
         keypad.clone(),
         delay_timer.clone(),
         sound_timer.clone(),
+        paused.clone(),
+        status.clone(),
         rom,
     );
-    let mut disp = pin!(io::sdl2(vram.clone(), keypad.clone(), sound_timer.clone()).fuse());
-    smol::block_on(async {
-        select! {
-            _ = disp => return,
-            _ = handle_timer(sound_timer).fuse() => {},
-            _ = handle_timer(delay_timer).fuse() => {},
-            reason = state.run().fuse() => error!("Core returned: {reason:?}"),
+    let profile = match options.quirks {
+        Some(profile) => profile,
+        None => match config.get("quirks.profile") {
+            Some(value) => value.parse().map_err(|message| {
+                fail(config::Error::Invalid {
+                    key: "quirks.profile".into(),
+                    message,
+                })
+            })?,
+            None => quirks::Profile::default(),
+        },
+    };
+    state.quirks = profile.quirks();
+    state.crash_dump = crash_dump;
+    state.dump_state_on_exit = options.dump_state_on_exit.clone();
+    if let Some(path) = &options.emit_trace {
+        state.emit_trace = Some(trace::TraceWriter::create(path).map_err(fail)?);
+    }
+    if let Some(path) = &options.trace_file {
+        let trace_options = || -> Result<_, config::Error> {
+            let format = match options.trace_format {
+                Some(format) => format,
+                None => match config.get("trace.format") {
+                    Some(value) => value.parse().map_err(|message| config::Error::Invalid {
+                        key: "trace.format".into(),
+                        message,
+                    })?,
+                    None => trace::TraceFormat::default(),
+                },
+            };
+            let max_size: u64 = config.get_as("trace.max-size")?.unwrap_or(100);
+            Ok((format, max_size))
         };
-        disp.await;
-    });
-}
-
-#[derive(Clone)]
-struct Memory {
-    rom: Vec<u8>,
-}
-
-const FONTS: [[u8; 5]; 16] = [
-    [0xF0, 0x90, 0x90, 0x90, 0xF0],
-    [0x20, 0x60, 0x20, 0x20, 0x70],
-    [0xF0, 0x10, 0xF0, 0x80, 0xF0],
-    [0xF0, 0x10, 0xF0, 0x10, 0xF0],
-    [0x90, 0x90, 0xF0, 0x10, 0x10],
-    [0xF0, 0x80, 0xF0, 0x10, 0xF0],
-    [0xF0, 0x80, 0xF0, 0x90, 0xF0],
-    [0xF0, 0x10, 0x20, 0x40, 0x40],
-    [0xF0, 0x90, 0xF0, 0x90, 0xF0],
-    [0xF0, 0x90, 0xF0, 0x10, 0xF0],
-    [0xF0, 0x90, 0xF0, 0x90, 0x90],
-    [0xE0, 0x90, 0xE0, 0x90, 0xE0],
-    [0xF0, 0x80, 0x80, 0x80, 0xF0],
-    [0xE0, 0x90, 0x90, 0x90, 0xE0],
-    [0xF0, 0x80, 0xF0, 0x80, 0xF0],
-    [0xF0, 0x80, 0xF0, 0x80, 0x80],
-];
-
-impl Index<u16> for Memory {
-    type Output = u8;
-    fn index(&self, idx: u16) -> &Self::Output {
-        trace!("Accessing memory {idx:#X}");
-        match idx {
-            0x0..=0x50 => &FONTS.iter().flatten().nth(usize::from(idx)).unwrap(),
-            0x1FF => &0,
-            0x200.. => {
-                let idx = usize::from(idx) - 0x200;
-                self.rom.get(idx).unwrap_or(&0)
+        let (format, max_size) = trace_options().map_err(fail)?;
+        let log = trace::TraceLog::create(path, format, max_size.saturating_mul(1 << 20));
+        state.trace_log = Some(log.map_err(fail)?);
+    }
+    if let Some(path) = &options.compare_trace {
+        state.compare_trace = Some(trace::TraceComparison::open(path).map_err(fail)?);
+    }
+    let remote_server = options
+        .listen
+        .as_deref()
+        .map(|addr| remote::Server::bind(addr, options.listen_token.take()))
+        .transpose()
+        .map_err(fail)?;
+    let mut disp = pin!(if windowed {
+        Either::Left(io::sdl2(
+            vram.clone(),
+            keypad.clone(),
+            status.clone(),
+            paused.clone(),
+            settings,
+            config,
+        ))
+    } else {
+        Either::Right(io::windowless(
+            vram.clone(),
+            keypad.clone(),
+            status.clone(),
+            settings,
+        ))
+    }
+    .fuse());
+    let buzzer_status = status.clone();
+    let update_buzzer = move |timer: u8, paused: bool| {
+        buzzer_status.sound_timer_ticked(timer, paused);
+    };
+    let stream_server = options
+        .stream
+        .as_deref()
+        .map(stream::Server::bind)
+        .transpose()
+        .map_err(fail)?;
+    let debug_server = options
+        .debug_http
+        .as_deref()
+        .map(debug_http::Server::bind)
+        .transpose()
+        .map_err(fail)?;
+    let remote = async {
+        match remote_server {
+            Some(server) => server.serve(keypad.clone(), vram.clone()).await,
+            None => futures::future::pending().await,
+        }
+    };
+    let stream = async {
+        match stream_server {
+            Some(server) => {
+                server
+                    .serve(keypad.clone(), vram.clone(), status.clone())
+                    .await
             }
-            _ => todo!(),
+            None => futures::future::pending().await,
         }
-    }
-}
-impl IndexMut<u16> for Memory {
-    fn index_mut(&mut self, idx: u16) -> &mut Self::Output {
-        trace!("Accessing memory {idx:#X}");
-        match idx {
-            0x200.. => {
-                let idx = usize::from(idx) - 0x200;
-                if self.rom.len() <= idx {
-                    self.rom.resize_with(idx + 1, Default::default);
-                }
-                &mut self.rom[idx]
+    };
+    let debug = async {
+        match debug_server {
+            Some(server) => {
+                server
+                    .serve(keypad.clone(), vram.clone(), status.clone(), paused.clone())
+                    .await
             }
-            _ => todo!(),
+            None => futures::future::pending().await,
         }
+    };
+    let ascii_mirror = async {
+        match mirror_interval {
+            Some(interval) => ascii::mirror(vram.clone(), status.clone(), interval).await,
+            None => futures::future::pending().await,
+        }
+    };
+    let frontend = smol::block_on(async {
+        select! {
+            result = disp => return result,
+            _ = handle_timer(sound_timer, paused.clone(), status.clone(), update_buzzer).fuse() => {},
+            _ = handle_timer(delay_timer, paused.clone(), status.clone(), |_, _| {}).fuse() => {},
+            _ = run_core(&mut state).fuse() => {},
+            _ = ascii_mirror.fuse() => {},
+            _ = remote.fuse() => {},
+            _ = stream.fuse() => {},
+            _ = debug.fuse() => {},
+            _ = signals::watch(status.clone()).fuse() => {},
+        };
+        disp.await
+    });
+    state.save_state_on_exit();
+    let vram = vram.lock().unwrap();
+    let signal = status.interrupted.load(Ordering::Relaxed);
+    let code = if status.bound_reached.load(Ordering::Relaxed) {
+        Some(BOUND_EXIT_CODE)
+    } else if signal != 0 {
+        Some(signals::exit_code(signal))
+    } else {
+        None
+    };
+    if code.is_some() {
+        print_summary(&status, &*vram);
     }
-}
-
-#[derive(Copy, Clone, Debug)]
-enum ExitReason {
-    InfiniteLoop,
-    WaitingForKeyPress,
-    IllegalInstruction,
-}
-
-#[derive(Clone)]
-struct Registers([u8; 16]);
-
-impl Index<u4> for Registers {
-    type Output = u8;
-    fn index(&self, idx: u4) -> &Self::Output {
-        trace!("Accessing register {idx:#X}");
-        &self.0[usize::from(u8::from(idx))]
-    }
-}
-
-impl IndexMut<u4> for Registers {
-    fn index_mut(&mut self, idx: u4) -> &mut Self::Output {
-        trace!("Accessing register {idx:#X}");
-        &mut self.0[usize::from(u8::from(idx))]
+    status.finish(&*vram);
+    if let Some(code) = code {
+        return Err(Exit::Code(code));
     }
+    // The core stopped along with the window, and its state is saved already
+    frontend.map_err(fail)
 }
 
-#[derive(Clone)]
-struct State {
-    pc: u16,
-    vram: Arc<Mutex<[bool; 64 * 32]>>,
-    memory: Memory,
-    stack: Vec<u16>,
-    registers: Registers,
-    vi: u16,
-    keypad: Arc<Mutex<io::Keypad>>,
-    delay_timer: Arc<Mutex<u8>>,
-    sound_timer: Arc<Mutex<u8>>,
-    last_key_press: Option<u8>,
+/// Logs `err`, so it follows `--log-format`, and exits with 1.
+fn fail(err: impl core::fmt::Display) -> Exit {
+    error!("{err}");
+    Exit::Code(1)
 }
-impl State {
-    fn new(
-        vram: Arc<Mutex<[bool; 64 * 32]>>,
-        keypad: Arc<Mutex<io::Keypad>>,
-        delay_timer: Arc<Mutex<u8>>,
-        sound_timer: Arc<Mutex<u8>>,
-        rom: Vec<u8>,
-    ) -> State {
-        State {
-            pc: 0x200,
-            vram,
-            memory: Memory { rom },
-            stack: Vec::new(),
-            registers: Registers([0; 16]),
-            vi: 0,
-            keypad,
-            delay_timer,
-            sound_timer,
-            last_key_press: None,
-        }
-    }
 
-    async fn run(&mut self) -> ControlFlow<ExitReason> {
-        loop {
-            let instr = self.fetch();
-            debug!("{:04X}: {instr:04X?}", self.pc);
-            let instr = instr.decode();
-            //TODO: wait for keypress / Draw sprite?
-            match self.execute(instr) {
-                ControlFlow::Break(ExitReason::WaitingForKeyPress) => {
-                    self.pc -= 2;
-                    //TODO Verify behavior
-                    loop {
-                        let key_pressed = self.keypad.lock().unwrap().first_pressed();
+/// Runs when no ROM is given: the name of the emulator and how to load a game, drawn
+/// with both sprites of its own and the built-in font.
+const SPLASH: &[u8] = include_bytes!("splash.ch8");
 
-                        if let Some(key) = key_pressed {
-                            self.last_key_press = Some(key);
-                        } else if self.last_key_press.is_some() {
-                            break;
-                        }
-                        smol::future::yield_now().await;
-                    }
-                }
-                reason => reason?,
-            };
-            smol::future::yield_now().await;
-        }
+/// Swaps a URL for the downloaded ROM.
+#[cfg(feature = "download")]
+fn fetch_if_url(path: std::path::PathBuf, refresh: bool) -> Result<std::path::PathBuf, Exit> {
+    match download::url(&path) {
+        Some(url) => download::fetch(url, refresh).map_err(fail),
+        None => Ok(path),
     }
 }
 
-async fn handle_timer(timer: Arc<Mutex<u8>>) -> ! {
-    loop {
-        {
-            let mut timer = timer.lock().unwrap();
-            *timer = timer.saturating_sub(1);
-        }
-        Timer::after(Duration::from_secs_f32(1f32 / 60f32)).await;
+#[cfg(not(feature = "download"))]
+fn fetch_if_url(path: std::path::PathBuf, _refresh: bool) -> Result<std::path::PathBuf, Exit> {
+    if path.to_str().is_some_and(|path| path.contains("://")) {
+        return Err(fail(
+            "This build can't download ROMs, it was built without the download feature",
+        ));
     }
+    Ok(path)
 }
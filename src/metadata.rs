@@ -0,0 +1,480 @@
+//! Settings that ship with a ROM, in a sidecar file next to it named after it plus
+//! `.toml`, e.g. `pong.ch8.toml`:
+//!
+//! ```toml
+//! title = "Pong"
+//! author = "Paul Vervalin"
+//! quirks = "chip8"      # a --quirks profile
+//! speed = 1.5           # like --speed
+//! palette = "octo"      # like --palette
+//!
+//! [keys]                # suggested bindings, like [keypad] in the config
+//! 1 = "Up"
+//! 4 = "Down"
+//! ```
+//!
+//! The same settings can be in `pong.ch8.json` instead, or in a JSON block at the
+//! end of the ROM file itself after [`EMBEDDED_MARKER`], which is left out when the
+//! ROM is loaded. Keys become an object:
+//!
+//! ```json
+//! {"title": "Pong", "quirks": "chip8", "speed": 1.5, "keys": {"1": "Up"}}
+//! ```
+//!
+//! Every field is optional. Command line options and the ROM's own section of the
+//! config file still win over the sidecar, which wins over the rest of the config.
+
+use crate::cli::Exit;
+use crate::config;
+use crate::config::Config;
+use crate::hash;
+use crate::io::Binding;
+use crate::io::Palette;
+use crate::json;
+use crate::quirks::Profile;
+use std::fmt;
+use std::path::Path;
+use std::path::PathBuf;
+use tracing::*;
+
+/// What comes before the JSON block at the end of a ROM file.
+pub const EMBEDDED_MARKER: &[u8] = b"CHIP8-METADATA";
+
+#[derive(Clone, Debug, Default)]
+pub struct Metadata {
+    /// Where the settings were read from
+    pub source: String,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub quirks: Option<Profile>,
+    pub speed: Option<f32>,
+    pub palette: Option<(String, Palette)>,
+    pub keys: Vec<(u8, Binding)>,
+}
+
+impl Metadata {
+    /// Reads the sidecar of `rom`, or its embedded block, if there is one.
+    pub fn load(rom: &Path) -> Result<Option<Metadata>, String> {
+        for path in [sidecar_path(rom, "toml"), sidecar_path(rom, "json")] {
+            let text = match std::fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(format!("{}: {err}", path.display())),
+            };
+            info!("Loading ROM settings from {}", path.display());
+            let metadata = match path.extension().is_some_and(|ext| ext == "json") {
+                true => Metadata::parse_json(&text),
+                false => Config::parse(&text)
+                    .map_err(|err| err.to_string())
+                    .and_then(|sidecar| {
+                        Metadata::parse(sidecar.entries()).map_err(|err| err.to_string())
+                    }),
+            };
+            let source = path.display().to_string();
+            return match metadata {
+                Ok(metadata) => Ok(Some(Metadata { source, ..metadata })),
+                Err(err) => Err(format!("{source}: {err}")),
+            };
+        }
+        let file = match std::fs::read(rom) {
+            Ok(file) => file,
+            Err(err) => return Err(format!("{}: {err}", rom.display())),
+        };
+        let Some(text) = split_embedded(&file).1 else {
+            return Ok(None);
+        };
+        info!("Loading ROM settings from the end of {}", rom.display());
+        let source = format!("{} (embedded)", rom.display());
+        Metadata::parse_json(text)
+            .map(|metadata| Some(Metadata { source, ..metadata }))
+            .map_err(|err| format!("{}: {err}", rom.display()))
+    }
+
+    /// Reads settings from JSON, an object with the same fields as a TOML sidecar.
+    pub fn parse_json(text: &str) -> Result<Metadata, String> {
+        let json::Value::Object(fields) = json::parse(text)? else {
+            return Err("Expected an object".into());
+        };
+        let mut entries = vec![];
+        flatten("", fields, &mut entries)?;
+        Metadata::parse(
+            entries
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_str())),
+        )
+        .map_err(|err| err.to_string())
+    }
+
+    /// Checks each field, warning about ones it doesn't know, e.g. from a newer version.
+    pub fn parse<'a>(
+        entries: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Result<Metadata, config::Error> {
+        let mut metadata = Metadata::default();
+        for (key, value) in entries {
+            let invalid = |message: String| config::Error::Invalid {
+                key: key.to_owned(),
+                message,
+            };
+            match key {
+                "title" => metadata.title = Some(value.to_owned()),
+                "author" => metadata.author = Some(value.to_owned()),
+                "quirks" => metadata.quirks = Some(value.parse().map_err(invalid)?),
+                "speed" => {
+                    let speed = value
+                        .parse()
+                        .map_err(|_| invalid(format!("Expected a number, got {value:?}")))?;
+                    metadata.speed = Some(crate::io::check_speed(speed).map_err(invalid)?);
+                }
+                "palette" => {
+                    metadata.palette = Some((value.to_owned(), value.parse().map_err(invalid)?))
+                }
+                _ => match key.strip_prefix("keys.") {
+                    Some(digit) => {
+                        let idx = u8::from_str_radix(digit, 16)
+                            .ok()
+                            .filter(|idx| *idx < 16)
+                            .ok_or_else(|| {
+                                invalid("Expected a keypad key between 0 and F".into())
+                            })?;
+                        let binding = Binding::parse(value)
+                            .ok_or_else(|| invalid(format!("Unknown key {value:?}")))?;
+                        if let Some((other, _)) = metadata.keys.iter().find(|(_, b)| *b == binding)
+                        {
+                            return Err(invalid(format!(
+                                "{binding} is already bound to key {other:X}"
+                            )));
+                        }
+                        metadata.keys.push((idx, binding));
+                    }
+                    None => warn!("Ignoring unknown ROM setting `{key}`"),
+                },
+            }
+        }
+        Ok(metadata)
+    }
+
+    /// The settings as JSON, in the same form [`Metadata::parse_json`] reads.
+    pub fn to_json(&self) -> String {
+        let mut fields = vec![];
+        let text = [("title", &self.title), ("author", &self.author)];
+        for (name, value) in text {
+            if let Some(value) = value {
+                fields.push(format!("\"{name}\": {}", json::quote(value)));
+            }
+        }
+        if let Some(quirks) = self.quirks {
+            fields.push(format!("\"quirks\": {}", json::quote(&quirks.to_string())));
+        }
+        if let Some(speed) = self.speed {
+            fields.push(format!("\"speed\": {speed}"));
+        }
+        if let Some((name, _)) = &self.palette {
+            fields.push(format!("\"palette\": {}", json::quote(name)));
+        }
+        if !self.keys.is_empty() {
+            let keys: Vec<String> = self
+                .keys
+                .iter()
+                .map(|(key, binding)| format!("\"{key:X}\": {}", json::quote(&binding.to_string())))
+                .collect();
+            fields.push(format!("\"keys\": {{{}}}", keys.join(", ")));
+        }
+        format!("{{{}}}", fields.join(", "))
+    }
+
+    /// The settings as config keys and values, see [`Config::select_rom`].
+    pub fn config_values(&self) -> Vec<(String, String)> {
+        let mut values = vec![];
+        if let Some(quirks) = self.quirks {
+            values.push(("quirks.profile".into(), quirks.to_string()));
+        }
+        if let Some(speed) = self.speed {
+            values.push(("speed".into(), speed.to_string()));
+        }
+        if let Some((name, _)) = &self.palette {
+            values.push(("video.palette".into(), name.clone()));
+        }
+        values
+    }
+}
+
+/// Splits a ROM file into the program and the JSON block at its end, if it has one.
+/// A marker that isn't followed by an object is taken to be part of the program.
+pub fn split_embedded(file: &[u8]) -> (&[u8], Option<&str>) {
+    let found = file
+        .windows(EMBEDDED_MARKER.len())
+        .rposition(|window| window == EMBEDDED_MARKER);
+    let Some(at) = found else {
+        return (file, None);
+    };
+    let block = std::str::from_utf8(&file[at + EMBEDDED_MARKER.len()..])
+        .map(str::trim)
+        .ok()
+        .filter(|block| block.starts_with('{') && block.ends_with('}'));
+    match block {
+        Some(block) => (&file[..at], Some(block)),
+        None => (file, None),
+    }
+}
+
+/// Turns nested objects into dotted keys, like sections in TOML.
+fn flatten(
+    prefix: &str,
+    fields: Vec<(String, json::Value)>,
+    out: &mut Vec<(String, String)>,
+) -> Result<(), String> {
+    for (key, value) in fields {
+        let key = format!("{prefix}{key}");
+        let value = match value {
+            json::Value::String(value) | json::Value::Number(value) => value,
+            json::Value::Bool(value) => value.to_string(),
+            json::Value::Object(fields) => {
+                flatten(&format!("{key}."), fields, out)?;
+                continue;
+            }
+            json::Value::Null | json::Value::Array(_) => {
+                return Err(format!("`{key}`: Expected a string, number or object"));
+            }
+        };
+        out.push((key, value));
+    }
+    Ok(())
+}
+
+fn sidecar_path(rom: &Path, extension: &str) -> PathBuf {
+    let mut path = rom.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    PathBuf::from(path)
+}
+
+pub const USAGE: &str = "\
+Usage: chip8 info [--json] ROM
+
+Shows the size and hashes of a ROM, and the settings it comes with, from a sidecar
+file like pong.ch8.toml or pong.ch8.json or from a block at the end of the ROM.
+
+Options:
+    --json    Print it all as one JSON object, with the settings under
+              \"metadata\" (null if there are none)";
+
+pub fn run(args: impl IntoIterator<Item = String>) -> Result<(), Exit> {
+    let mut path = None;
+    let mut as_json = false;
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            "--json" => as_json = true,
+            flag if flag.starts_with('-') => {
+                return Err(usage_error(format!("Unknown option {flag}")))
+            }
+            _ if path.is_some() => return Err(usage_error(format!("Unexpected argument {arg}"))),
+            _ => path = Some(PathBuf::from(arg)),
+        }
+    }
+    let path = path.ok_or_else(|| usage_error("No ROM given"))?;
+    let rom = crate::io::rom::read(&path).map_err(Exit::failed)?;
+    let metadata = Metadata::load(&path).map_err(Exit::failed)?;
+    if as_json {
+        println!(
+            "{{\"file\": {}, \"size\": {}, \"hash\": \"{:016x}\", \"sha1\": \"{}\", \"metadata\": {}}}",
+            json::quote(&crate::io::rom::name(&path)),
+            rom.len(),
+            hash::fnv1a(&rom),
+            hash::sha1_hex(&rom),
+            metadata.map_or("null".into(), |metadata| metadata.to_json())
+        );
+        return Ok(());
+    }
+    println!("file     {}", crate::io::rom::name(&path));
+    println!("size     {} bytes", rom.len());
+    println!("hash     {:016x}", hash::fnv1a(&rom));
+    println!("sha1     {}", hash::sha1_hex(&rom));
+    let Some(metadata) = metadata else {
+        println!("No settings next to it or in it");
+        return Ok(());
+    };
+    println!("from     {}", metadata.source);
+    let fields = [
+        ("title", metadata.title),
+        ("author", metadata.author),
+        ("quirks", metadata.quirks.map(|quirks| quirks.to_string())),
+        ("speed", metadata.speed.map(|speed| format!("{speed}x"))),
+        ("palette", metadata.palette.map(|(name, _)| name)),
+    ];
+    for (name, value) in fields {
+        if let Some(value) = value {
+            println!("{name:<8} {value}");
+        }
+    }
+    for (key, binding) in &metadata.keys {
+        println!("key {key:X}    {binding}");
+    }
+    Ok(())
+}
+
+fn usage_error(message: impl fmt::Display) -> Exit {
+    Exit::usage(message, USAGE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOML: &str = r#"
+title = "Pong"
+author = "Paul Vervalin"
+quirks = "chip8"
+speed = 1.5
+palette = "octo"
+
+[keys]
+1 = "Up"
+4 = "Down"
+"#;
+    const JSON: &str = r#"{
+  "title": "Pong",
+  "author": "Paul Vervalin",
+  "quirks": "chip8",
+  "speed": 1.5,
+  "palette": "octo",
+  "keys": {"1": "Up", "4": "Down"}
+}"#;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("chip8-{}-{name}", std::process::id()))
+    }
+
+    fn from_toml(text: &str) -> Result<Metadata, config::Error> {
+        Metadata::parse(Config::parse(text).unwrap().entries())
+    }
+
+    fn summary(metadata: &Metadata) -> String {
+        let keys: Vec<String> = metadata
+            .keys
+            .iter()
+            .map(|(key, binding)| format!("{key:X}={binding}"))
+            .collect();
+        format!(
+            "{:?} {:?} {:?} {:?} {:?} {}",
+            metadata.title,
+            metadata.author,
+            metadata.quirks,
+            metadata.speed,
+            metadata.palette.as_ref().map(|(name, _)| name),
+            keys.join(",")
+        )
+    }
+
+    #[test]
+    fn toml_and_json_read_the_same() {
+        let toml = from_toml(TOML).unwrap();
+        assert_eq!(toml.title.as_deref(), Some("Pong"));
+        assert_eq!(toml.quirks, Some(Profile::Chip8));
+        assert_eq!(toml.speed, Some(1.5));
+        assert_eq!(toml.keys.len(), 2);
+        let json = Metadata::parse_json(JSON).unwrap();
+        assert_eq!(summary(&json), summary(&toml));
+        // And what info --json prints reads back the same too
+        let again = Metadata::parse_json(&json.to_json()).unwrap();
+        assert_eq!(summary(&again), summary(&toml));
+    }
+
+    #[test]
+    fn unknown_fields_are_skipped_and_bad_values_rejected() {
+        let metadata = from_toml("title = \"x\"\nfuture = 1\n[extra]\na = 2").unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("x"));
+        let metadata = Metadata::parse_json(r#"{"future": {"a": true}}"#).unwrap();
+        assert_eq!(summary(&metadata), summary(&Metadata::default()));
+        for bad in [
+            "quirks = \"nope\"",
+            "speed = \"fast\"",
+            "speed = 100",
+            "palette = \"nope\"",
+            "[keys]\nG = \"Up\"",
+            "[keys]\n1 = \"NoSuchKey\"",
+            "[keys]\n1 = \"Up\"\n2 = \"Up\"",
+        ] {
+            assert!(from_toml(bad).is_err(), "{bad}");
+        }
+        for bad in [r#"[1]"#, r#"{"title": null}"#, r#"{"keys": [1]}"#, "{"] {
+            assert!(Metadata::parse_json(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn embedded_block_is_split_off() {
+        let program = [0x00, 0xE0, 0x12, 0x00];
+        let mut file = program.to_vec();
+        file.extend_from_slice(EMBEDDED_MARKER);
+        file.extend_from_slice(b"{\"title\": \"Inside\"}\n");
+        assert_eq!(
+            split_embedded(&file),
+            (&program[..], Some("{\"title\": \"Inside\"}"))
+        );
+        // Without an object after it, the marker is just bytes of the program
+        let mut plain = program.to_vec();
+        plain.extend_from_slice(EMBEDDED_MARKER);
+        plain.extend_from_slice(&[0x12, 0x00]);
+        assert_eq!(split_embedded(&plain), (&plain[..], None));
+        assert_eq!(split_embedded(&program), (&program[..], None));
+
+        let rom = temp_path("embedded.ch8");
+        std::fs::write(&rom, &file).unwrap();
+        let loaded = crate::io::rom::read(&rom);
+        let metadata = Metadata::load(&rom);
+        std::fs::remove_file(&rom).unwrap();
+        assert_eq!(loaded.unwrap(), program);
+        let metadata = metadata.unwrap().unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Inside"));
+        assert!(metadata.source.ends_with("(embedded)"));
+    }
+
+    #[test]
+    fn sidecars_are_found_next_to_the_rom() {
+        let rom = temp_path("sidecar.ch8");
+        std::fs::write(&rom, [0x12, 0x00]).unwrap();
+        let nothing = Metadata::load(&rom);
+        let json = sidecar_path(&rom, "json");
+        std::fs::write(&json, r#"{"title": "From JSON"}"#).unwrap();
+        let from_json = Metadata::load(&rom);
+        let toml = sidecar_path(&rom, "toml");
+        std::fs::write(&toml, "title = \"From TOML\"").unwrap();
+        let from_toml = Metadata::load(&rom);
+        for path in [&rom, &json, &toml] {
+            std::fs::remove_file(path).unwrap();
+        }
+        assert!(nothing.unwrap().is_none());
+        assert_eq!(
+            from_json.unwrap().unwrap().title.as_deref(),
+            Some("From JSON")
+        );
+        // The TOML file is looked for first
+        let from_toml = from_toml.unwrap().unwrap();
+        assert_eq!(from_toml.title.as_deref(), Some("From TOML"));
+        assert_eq!(from_toml.source, toml.display().to_string());
+    }
+
+    /// The sidecar goes between the ROM's own section of the config and the rest of
+    /// it. Command line options are checked before the config at all.
+    #[test]
+    fn sidecar_sits_between_the_rom_section_and_the_rest() {
+        let metadata = Metadata::parse_json(r#"{"speed": 2, "quirks": "schip"}"#).unwrap();
+        let mut config = Config::parse(&format!(
+            "speed = 1\nvideo.palette = \"amber\"\n[rom.\"sha1:{}\"]\nquirks.profile = \"chip8\"",
+            hash::sha1_hex(&[0xAA])
+        ))
+        .unwrap();
+        config.select_rom(&[0xAA], metadata.config_values());
+        assert_eq!(config.get("speed"), Some("2"));
+        assert_eq!(config.get("quirks.profile"), Some("chip8"));
+        assert_eq!(config.get("video.palette"), Some("amber"));
+        config.select_rom(&[0xBB], metadata.config_values());
+        assert_eq!(config.get("quirks.profile"), Some("schip"));
+        config.select_rom(&[0xBB], Metadata::default().config_values());
+        assert_eq!(config.get("speed"), Some("1"));
+    }
+}
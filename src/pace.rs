@@ -0,0 +1,264 @@
+use std::time::Duration;
+use std::time::Instant;
+
+/// How many ticks a [`Pacer`] falls behind by before it gives up on making them up.
+pub const MAX_BEHIND: u32 = 3;
+
+/// Nanoseconds in a second, times the thousandths rates are kept in.
+const NANOS_PER_MILLIHERTZ: u128 = 1_000_000_000_000;
+
+/// Keeping something ticking at a fixed rate. Every deadline is worked out from when
+/// counting started rather than added to the last, in whole nanoseconds, so neither a
+/// late tick nor a 60th of a second not coming to a whole number of them adds up over
+/// time. Ticks that are late are made up by waiting less for the next ones, up to
+/// `behind` of them, after which the pacer counts again from now and notes how many
+/// were dropped.
+#[derive(Clone, Debug)]
+pub struct Pacer {
+    origin: Instant,
+    /// Ticks a second, in thousandths
+    rate: u64,
+    behind: u32,
+    /// When counting started, in nanoseconds since `origin`, once it has
+    base: Option<u64>,
+    /// Ticks since `base`, up to the current one
+    ticks: u64,
+    dropped: u64,
+    /// How late ticks come, smoothed, in nanoseconds
+    drift: u64,
+}
+
+impl Pacer {
+    /// `rate` is in ticks a second.
+    pub fn new(rate: f32, behind: u32) -> Pacer {
+        Pacer {
+            origin: Instant::now(),
+            rate: millihertz(rate),
+            behind,
+            base: None,
+            ticks: 0,
+            dropped: 0,
+            drift: 0,
+        }
+    }
+
+    /// Changes the rate, counting at the new one from the current tick on. Ticks that
+    /// fell behind at the old rate aren't made up, so e.g. slowing down doesn't come
+    /// with a burst of them.
+    pub fn set_rate(&mut self, rate: f32, now: Instant) {
+        let rate = millihertz(rate);
+        if rate == self.rate {
+            return;
+        }
+        if let Some(base) = self.base {
+            self.base = Some(self.deadline(base, self.ticks).max(self.nanos(now)));
+            self.ticks = 0;
+        }
+        self.rate = rate;
+    }
+
+    /// When the next tick is due, as of `now`, moving on to it.
+    pub fn next(&mut self, now: Instant) -> Instant {
+        let now = self.nanos(now);
+        let base = *self.base.get_or_insert(now);
+        self.ticks += 1;
+        let due = self.deadline(base, self.ticks);
+        let period = self.deadline(0, 1).max(1);
+        let late = now.saturating_sub(due);
+        if late > period * u64::from(self.behind) {
+            self.dropped += late / period;
+            self.base = Some(now);
+            self.ticks = 0;
+            return self.origin + Duration::from_nanos(now);
+        }
+        self.origin + Duration::from_nanos(due)
+    }
+
+    /// How far past when it was due `now` is for the current tick.
+    pub fn late(&self, now: Instant) -> Duration {
+        match self.base {
+            Some(base) => {
+                let due = self.deadline(base, self.ticks);
+                Duration::from_nanos(self.nanos(now).saturating_sub(due))
+            }
+            None => Duration::ZERO,
+        }
+    }
+
+    /// Notes that the current tick came at `now`, for [`Pacer::drift`].
+    pub fn arrived(&mut self, now: Instant) {
+        let late = self.late(now).as_nanos() as u64;
+        self.drift = (self.drift * 7 + late) / 8;
+    }
+
+    /// Forgets about the ticks so far, for when something else sets the pace for a
+    /// while.
+    pub fn restart(&mut self) {
+        self.base = None;
+        self.ticks = 0;
+    }
+
+    /// How late ticks have been coming lately.
+    pub fn drift(&self) -> Duration {
+        Duration::from_nanos(self.drift)
+    }
+
+    /// How many ticks were given up on for being too far behind.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// When tick number `ticks` counting from `base` is due.
+    fn deadline(&self, base: u64, ticks: u64) -> u64 {
+        base + (u128::from(ticks) * NANOS_PER_MILLIHERTZ / u128::from(self.rate.max(1))) as u64
+    }
+
+    fn nanos(&self, time: Instant) -> u64 {
+        time.saturating_duration_since(self.origin).as_nanos() as u64
+    }
+}
+
+fn millihertz(rate: f32) -> u64 {
+    (f64::from(rate) * 1000.0).round() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PERIOD: Duration = Duration::from_nanos(16_666_666);
+
+    /// Counts the ticks a timer gets between `from` and `until`, the way it waits for
+    /// each deadline.
+    fn ticks_between(pacer: &mut Pacer, from: Instant, until: Instant) -> u32 {
+        let mut ticks = 0;
+        let mut now = from;
+        loop {
+            let deadline = pacer.next(now);
+            if deadline > until {
+                return ticks;
+            }
+            now = now.max(deadline);
+            ticks += 1;
+        }
+    }
+
+    /// Takes `ticks` ticks, each right when it's due, returning when the last was.
+    fn on_time(pacer: &mut Pacer, ticks: u32) -> Instant {
+        let mut now = pacer.origin;
+        for _ in 0..ticks {
+            now = now.max(pacer.next(now));
+        }
+        now
+    }
+
+    /// When tick `n` of a 60Hz pacer started at `start` is due.
+    fn due(start: Instant, n: u64) -> Instant {
+        start + Duration::from_nanos(n * 1_000_000_000 / 60)
+    }
+
+    /// An hour of waking up a little late for each tick, never by enough to drop any.
+    /// Adding up 16,666,666ns periods instead would end 144µs early.
+    #[test]
+    fn no_drift_over_an_hour() {
+        let mut pacer = Pacer::new(60.0, MAX_BEHIND);
+        let start = pacer.origin;
+        let mut now = start;
+        let mut deadline = start;
+        for tick in 0..60 * 60 * 60u64 {
+            deadline = pacer.next(now);
+            now = deadline + Duration::from_millis(tick % 7);
+        }
+        assert_eq!(deadline, start + Duration::from_secs(60 * 60));
+        assert_eq!(pacer.dropped(), 0);
+    }
+
+    /// A stall of less than `behind` ticks is made up at once, after which the ticks
+    /// are due when they were before it.
+    #[test]
+    fn catches_up_after_a_stall() {
+        let mut pacer = Pacer::new(60.0, MAX_BEHIND);
+        let start = pacer.origin;
+        let second = on_time(&mut pacer, 60);
+        assert_eq!(second, due(start, 60));
+        // Two and a bit ticks' worth
+        let now = second + Duration::from_millis(40);
+        assert_eq!(pacer.next(now), due(start, 61));
+        assert_eq!(pacer.next(now), due(start, 62));
+        assert!(due(start, 62) < now);
+        assert_eq!(pacer.next(now), due(start, 63));
+        assert!(due(start, 63) > now);
+        let end = start + Duration::from_secs(2);
+        assert_eq!(ticks_between(&mut pacer, due(start, 63), end), 57);
+        assert_eq!(pacer.dropped(), 0);
+    }
+
+    /// The drift follows how late ticks arrive, and settles back once they're on time.
+    #[test]
+    fn drift_is_smoothed() {
+        let mut pacer = Pacer::new(60.0, MAX_BEHIND);
+        let start = pacer.origin;
+        let mut now = start;
+        for _ in 0..100 {
+            now = pacer.next(now) + Duration::from_millis(4);
+            pacer.arrived(now);
+        }
+        let drift = pacer.drift().as_secs_f64() * 1000.0;
+        assert!((3.9..=4.0).contains(&drift), "{drift}");
+        for _ in 0..100 {
+            now = pacer.next(now);
+            pacer.arrived(now);
+        }
+        assert!(pacer.drift() < Duration::from_micros(1));
+    }
+
+    /// A new rate counts on from the current tick rather than from the start.
+    #[test]
+    fn rate_changes_count_from_the_current_tick() {
+        let mut pacer = Pacer::new(60.0, MAX_BEHIND);
+        let second = on_time(&mut pacer, 60);
+        pacer.set_rate(120.0, second);
+        assert_eq!(pacer.next(second), second + PERIOD / 2);
+        let end = second + Duration::from_secs(1);
+        assert_eq!(ticks_between(&mut pacer, second, end), 119);
+        assert_eq!(pacer.dropped(), 0);
+    }
+
+    /// Fast-forwarding restarts the pacer while the core ticks the timers, so once
+    /// it's let go of the ticks carry on a period apart with none made up.
+    #[test]
+    fn no_ticks_owed_after_fast_forward() {
+        let mut pacer = Pacer::new(60.0, MAX_BEHIND);
+        let start = pacer.origin;
+        let held = start + Duration::from_secs(1);
+        assert_eq!(ticks_between(&mut pacer, start, held), 60);
+        // Held for two seconds, checking every 4ms
+        let mut now = held;
+        while now < held + Duration::from_secs(2) {
+            pacer.restart();
+            now += Duration::from_millis(4);
+        }
+        let released = now;
+        assert_eq!(pacer.clone().next(released), released + PERIOD);
+        let second = released + Duration::from_secs(1);
+        assert_eq!(ticks_between(&mut pacer, released, second), 60);
+        assert_eq!(pacer.dropped(), 0);
+    }
+
+    /// Without the restart, the two seconds would be owed and the pacer would have
+    /// to drop them.
+    #[test]
+    fn falling_behind_drops_instead_of_bursting() {
+        let mut pacer = Pacer::new(60.0, MAX_BEHIND);
+        let start = pacer.origin;
+        pacer.next(start);
+        let later = start + Duration::from_secs(2);
+        assert_eq!(pacer.next(later), later);
+        assert!(
+            (118..=119).contains(&pacer.dropped()),
+            "{}",
+            pacer.dropped()
+        );
+        assert_eq!(pacer.next(later), later + PERIOD);
+    }
+}
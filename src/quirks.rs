@@ -0,0 +1,132 @@
+//! The places where CHIP-8 interpreters disagree, and the combinations of them that
+//! well known interpreters picked.
+
+use core::fmt;
+use core::str::FromStr;
+
+/// Which way each ambiguous instruction behaves.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY1, 8XY2 and 8XY3 reset VF to 0
+    pub vf_reset: bool,
+    /// FX55 and FX65 leave I pointing past the last register they touched
+    pub memory: bool,
+    /// 8XY6 and 8XYE shift VX in place instead of putting the shifted VY in VX
+    pub shift: bool,
+    /// BXNN jumps to XNN + VX instead of BNNN jumping to NNN + V0
+    pub jump: bool,
+    /// Sprites are cut off at the edges of the screen instead of wrapping around
+    pub clipping: bool,
+    /// FX1E sets VF when I goes past 0xFFF
+    pub index_overflow: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Profile::Chip8.quirks()
+    }
+}
+
+impl Quirks {
+    /// Which quirk decides what `opcode` does, if these and `other` disagree on it,
+    /// as e.g. `8XY6 shift source`.
+    pub fn attribute(&self, other: &Quirks, opcode: u16) -> Option<&'static str> {
+        let (differs, name) = match (opcode >> 12, opcode & 0xF, opcode & 0xFF) {
+            (0x8, 0x1, _) => (self.vf_reset != other.vf_reset, "8XY1 VF reset"),
+            (0x8, 0x2, _) => (self.vf_reset != other.vf_reset, "8XY2 VF reset"),
+            (0x8, 0x3, _) => (self.vf_reset != other.vf_reset, "8XY3 VF reset"),
+            (0x8, 0x6, _) => (self.shift != other.shift, "8XY6 shift source"),
+            (0x8, 0xE, _) => (self.shift != other.shift, "8XYE shift source"),
+            (0xB, _, _) => (self.jump != other.jump, "BNNN jump register"),
+            (0xD, _, _) => (self.clipping != other.clipping, "DXYN clipping"),
+            (0xF, _, 0x1E) => (
+                self.index_overflow != other.index_overflow,
+                "FX1E overflow flag",
+            ),
+            (0xF, _, 0x55) => (self.memory != other.memory, "FX55 I increment"),
+            (0xF, _, 0x65) => (self.memory != other.memory, "FX65 I increment"),
+            _ => (false, ""),
+        };
+        differs.then_some(name)
+    }
+}
+
+/// A named set of [`Quirks`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Profile {
+    /// The original COSMAC VIP interpreter
+    #[default]
+    Chip8,
+    /// SUPER-CHIP on the HP48
+    Schip,
+    /// XO-CHIP, as Octo runs it
+    XoChip,
+    /// The Amiga interpreter, which flags I overflowing
+    Amiga,
+}
+
+impl Profile {
+    pub const ALL: [Profile; 4] = [
+        Profile::Chip8,
+        Profile::Schip,
+        Profile::XoChip,
+        Profile::Amiga,
+    ];
+
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Profile::Chip8 => Quirks {
+                vf_reset: true,
+                memory: true,
+                shift: false,
+                jump: false,
+                clipping: true,
+                index_overflow: false,
+            },
+            Profile::Schip => Quirks {
+                vf_reset: false,
+                memory: false,
+                shift: true,
+                jump: true,
+                clipping: true,
+                index_overflow: false,
+            },
+            Profile::XoChip => Quirks {
+                vf_reset: false,
+                memory: true,
+                shift: false,
+                jump: false,
+                clipping: false,
+                index_overflow: false,
+            },
+            Profile::Amiga => Quirks {
+                index_overflow: true,
+                ..Profile::Chip8.quirks()
+            },
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Profile::Chip8 => "chip8",
+            Profile::Schip => "schip",
+            Profile::XoChip => "xochip",
+            Profile::Amiga => "amiga",
+        })
+    }
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Profile::ALL
+            .into_iter()
+            .find(|profile| profile.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                format!("unknown quirk profile {s:?}, expected chip8, schip, xochip or amiga")
+            })
+    }
+}
@@ -0,0 +1,208 @@
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+use tracing::*;
+
+pub const MAX_ENTRIES: usize = 10;
+
+/// Most recently loaded ROMs, newest first. Stored next to the config file as one
+/// `<unix time>\t<path>` line per entry.
+#[derive(Clone, Debug, Default)]
+pub struct Recent {
+    path: Option<PathBuf>,
+    entries: Vec<(u64, PathBuf)>,
+}
+
+impl Recent {
+    /// Reads the list, skipping lines that don't parse and files that are gone. A
+    /// missing or unreadable list is just empty.
+    pub fn load(path: Option<PathBuf>) -> Recent {
+        let text = match path.as_deref().map(std::fs::read_to_string) {
+            Some(Ok(text)) => text,
+            Some(Err(err)) if err.kind() != std::io::ErrorKind::NotFound => {
+                warn!("Could not read the recent ROMs: {err}");
+                String::new()
+            }
+            _ => String::new(),
+        };
+        let mut recent = Recent {
+            path,
+            entries: vec![],
+        };
+        for line in text.lines() {
+            let Some((time, rom)) = line.split_once('\t') else {
+                continue;
+            };
+            let Ok(time) = time.parse() else {
+                continue;
+            };
+            let rom = PathBuf::from(rom);
+            if rom.is_file() && !recent.entries.iter().any(|(_, other)| *other == rom) {
+                recent.entries.push((time, rom));
+            }
+        }
+        recent.entries.truncate(MAX_ENTRIES);
+        recent
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &Path> {
+        self.entries.iter().map(|(_, rom)| rom.as_path())
+    }
+
+    /// Entry `n`, counting from 1 like the printed list.
+    pub fn get(&self, n: usize) -> Option<&Path> {
+        self.entries().nth(n.checked_sub(1)?)
+    }
+
+    /// Moves `rom` to the top of the list.
+    pub fn add(&mut self, rom: &Path, time: u64) {
+        let rom = rom.canonicalize().unwrap_or_else(|_| rom.to_owned());
+        self.entries.retain(|(_, other)| *other != rom);
+        self.entries.insert(0, (time, rom));
+        self.entries.truncate(MAX_ENTRIES);
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let text: String = self
+            .entries
+            .iter()
+            .map(|(time, rom)| format!("{time}\t{}\n", rom.display()))
+            .collect();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, text)
+    }
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    Some(crate::config::default_path()?.with_file_name("recent.txt"))
+}
+
+/// Records a successful load in the list on disk.
+pub fn remember(rom: &Path) {
+    let mut recent = Recent::load(default_path());
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    recent.add(rom, now);
+    if let Err(err) = recent.save() {
+        warn!("Could not save the recent ROMs: {err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh temp dir holding a ROM for each of `roms`.
+    fn dir(name: &str, roms: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("chip8-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for rom in roms {
+            std::fs::write(dir.join(rom), [0x12, 0x00]).unwrap();
+        }
+        dir.canonicalize().unwrap()
+    }
+
+    fn names(recent: &Recent) -> Vec<String> {
+        recent
+            .entries()
+            .map(|rom| rom.file_name().unwrap().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn newest_first_across_saves() {
+        let dir = dir("recent-order", &["a.ch8", "b.ch8", "c.ch8"]);
+        let list = dir.join("recent.txt");
+        let mut recent = Recent::load(Some(list.clone()));
+        assert_eq!(recent.entries().count(), 0);
+        for (time, rom) in ["a.ch8", "b.ch8", "c.ch8"].into_iter().enumerate() {
+            recent.add(&dir.join(rom), time as u64);
+        }
+        recent.save().unwrap();
+        let recent = Recent::load(Some(list));
+        assert_eq!(names(&recent), ["c.ch8", "b.ch8", "a.ch8"]);
+        assert_eq!(recent.get(1), Some(dir.join("c.ch8").as_path()));
+        assert_eq!(recent.get(0), None);
+        assert_eq!(recent.get(4), None);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Loading a ROM again moves it to the top rather than listing it twice, however
+    /// its path was written.
+    #[test]
+    fn reloads_move_to_the_top() {
+        let dir = dir("recent-dedup", &["a.ch8", "b.ch8"]);
+        let mut recent = Recent::load(Some(dir.join("recent.txt")));
+        recent.add(&dir.join("a.ch8"), 1);
+        recent.add(&dir.join("b.ch8"), 2);
+        recent.add(&dir.join(".").join("a.ch8"), 3);
+        assert_eq!(names(&recent), ["a.ch8", "b.ch8"]);
+        // Duplicates written by hand are dropped on reading too
+        let a = dir.join("a.ch8").display().to_string();
+        std::fs::write(dir.join("recent.txt"), format!("2\t{a}\n1\t{a}\n")).unwrap();
+        assert_eq!(
+            names(&Recent::load(Some(dir.join("recent.txt")))),
+            ["a.ch8"]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn only_the_newest_are_kept() {
+        let roms: Vec<_> = (0..MAX_ENTRIES + 2).map(|n| format!("{n}.ch8")).collect();
+        let dir = dir(
+            "recent-limit",
+            &roms.iter().map(String::as_str).collect::<Vec<_>>(),
+        );
+        let mut recent = Recent::load(Some(dir.join("recent.txt")));
+        for (time, rom) in roms.iter().enumerate() {
+            recent.add(&dir.join(rom), time as u64);
+        }
+        let names = names(&recent);
+        assert_eq!(names.len(), MAX_ENTRIES);
+        assert_eq!(names[0], "11.ch8");
+        assert_eq!(names[MAX_ENTRIES - 1], "2.ch8");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn missing_files_are_pruned() {
+        let dir = dir("recent-prune", &["a.ch8", "b.ch8"]);
+        let list = dir.join("recent.txt");
+        let mut recent = Recent::load(Some(list.clone()));
+        recent.add(&dir.join("a.ch8"), 1);
+        recent.add(&dir.join("b.ch8"), 2);
+        recent.save().unwrap();
+        std::fs::remove_file(dir.join("b.ch8")).unwrap();
+        assert_eq!(names(&Recent::load(Some(list))), ["a.ch8"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Lines that don't parse are skipped, and a list that isn't text at all is
+    /// just empty.
+    #[test]
+    fn corrupt_lists_are_tolerated() {
+        let dir = dir("recent-corrupt", &["a.ch8"]);
+        let list = dir.join("recent.txt");
+        let a = dir.join("a.ch8").display().to_string();
+        let text = format!("garbage\nsoon\t{a}\n\n7\t{a}\n12 {a}\n");
+        std::fs::write(&list, text).unwrap();
+        assert_eq!(names(&Recent::load(Some(list.clone()))), ["a.ch8"]);
+        std::fs::write(&list, [0xFF, 0xFE, 0x00, 0x9F]).unwrap();
+        let mut recent = Recent::load(Some(list.clone()));
+        assert_eq!(recent.entries().count(), 0);
+        // And is written over with a good one
+        recent.add(&dir.join("a.ch8"), 1);
+        recent.save().unwrap();
+        assert_eq!(names(&Recent::load(Some(list))), ["a.ch8"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
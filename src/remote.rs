@@ -0,0 +1,338 @@
+//! Remote control over TCP, e.g. a phone as the keypad, along with a copy of the
+//! screen.
+//!
+//! The protocol is one line per message. The server starts with `chip8-remote 1`,
+//! and the client answers `hello 1`, or `hello 1 <token>` when the server was started
+//! with `--listen-token`. After that the client can send:
+//!
+//! - `down <key>` and `up <key>` to press and release a keypad key, in hex
+//! - `subscribe` and `unsubscribe` to start and stop receiving the screen
+//! - `quit` to hang up
+//!
+//! Anything the server doesn't understand gets an `error <message>` line back, and a
+//! failed handshake also closes the connection. Subscribers get a `frame <runs>` line
+//! whenever the screen changes, at most 30 times a second. The runs are the lengths of
+//! alternating stretches of dark and lit pixels, separated by commas, going through
+//! the 64x32 screen row by row and starting with dark, so a blank screen is
+//! `frame 2048`. Keys a client was holding are released when it goes away.
+
+use crate::io::Keypad;
+use core::time::Duration;
+use futures::select;
+use futures::FutureExt;
+use futures::StreamExt;
+use smol::io::AsyncBufReadExt;
+use smol::io::AsyncWriteExt;
+use smol::io::BufReader;
+use smol::net::TcpListener;
+use smol::net::TcpStream;
+use smol::Async;
+use smol::Timer;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use tracing::*;
+
+const GREETING: &str = "chip8-remote";
+const VERSION: u32 = 1;
+/// How often subscribers are sent the screen, if it changed.
+const FRAME_INTERVAL: Duration = Duration::from_millis(1000 / 30);
+
+pub struct Server {
+    listener: TcpListener,
+    token: Option<Arc<str>>,
+}
+
+impl Server {
+    /// Starts listening right away, so a bad address is reported before the window
+    /// opens.
+    pub fn bind(addr: &str, token: Option<String>) -> Result<Server, String> {
+        let err = |err| format!("Could not listen on {addr}: {err}");
+        let addr: SocketAddr = addr.parse().map_err(|_| {
+            format!("Expected an address like 0.0.0.0:5508 to listen on, got {addr:?}")
+        })?;
+        let listener = Async::<std::net::TcpListener>::bind(addr).map_err(err)?;
+        info!("Listening for remote control on {addr}");
+        Ok(Server {
+            listener: listener.into(),
+            token: token.map(Arc::from),
+        })
+    }
+
+    /// Serves each client that connects in a task of its own.
+    pub async fn serve(self, keypad: Arc<Mutex<Keypad>>, vram: Arc<Mutex<[bool; 64 * 32]>>) -> ! {
+        loop {
+            match self.listener.accept().await {
+                Ok((stream, peer)) => {
+                    info!("Remote control connected from {peer}");
+                    let client = Client {
+                        stream,
+                        token: self.token.clone(),
+                        keypad: keypad.clone(),
+                        vram: vram.clone(),
+                        held: [false; 16],
+                    };
+                    smol::spawn(async move {
+                        client.run().await;
+                        info!("Remote control from {peer} disconnected");
+                    })
+                    .detach();
+                }
+                Err(err) => {
+                    warn!("Could not accept a remote control: {err}");
+                    // e.g. out of file descriptors, which won't fix itself right away
+                    Timer::after(Duration::from_millis(100)).await;
+                }
+            }
+        }
+    }
+}
+
+struct Client {
+    stream: TcpStream,
+    token: Option<Arc<str>>,
+    keypad: Arc<Mutex<Keypad>>,
+    vram: Arc<Mutex<[bool; 64 * 32]>>,
+    /// Keys this client pressed and hasn't released yet
+    held: [bool; 16],
+}
+
+impl Client {
+    async fn run(mut self) {
+        if let Err(err) = self.session().await {
+            debug!("Remote control error: {err}");
+        }
+        let mut keypad = self.keypad.lock().unwrap();
+        for key in 0..16 {
+            if self.held[usize::from(key)] {
+                keypad.release(key);
+            }
+        }
+    }
+
+    async fn session(&mut self) -> std::io::Result<()> {
+        let mut lines = BufReader::new(self.stream.clone()).lines();
+        self.send(&format!("{GREETING} {VERSION}")).await?;
+        let Some(hello) = lines.next().await.transpose()? else {
+            return Ok(());
+        };
+        if let Err(err) = self.handshake(&hello) {
+            return self.send(&format!("error {err}")).await;
+        }
+        let mut subscribed = false;
+        let mut last_frame = None;
+        let mut ticks = Timer::interval(FRAME_INTERVAL);
+        loop {
+            select! {
+                line = lines.next().fuse() => {
+                    let Some(line) = line.transpose()? else {
+                        return Ok(());
+                    };
+                    let mut words = line.split_whitespace();
+                    match (words.next(), words.next(), words.next()) {
+                        (Some("down"), Some(key), None) => match parse_key(key) {
+                            Ok(key) => self.set(key, true),
+                            Err(err) => self.send(&format!("error {err}")).await?,
+                        },
+                        (Some("up"), Some(key), None) => match parse_key(key) {
+                            Ok(key) => self.set(key, false),
+                            Err(err) => self.send(&format!("error {err}")).await?,
+                        },
+                        (Some("subscribe"), None, _) => subscribed = true,
+                        (Some("unsubscribe"), None, _) => {
+                            subscribed = false;
+                            last_frame = None;
+                        }
+                        (Some("quit"), None, _) => return Ok(()),
+                        (None, _, _) => {}
+                        _ => self.send(&format!("error Unknown command {line:?}")).await?,
+                    }
+                }
+                _ = ticks.next().fuse() => {
+                    if !subscribed {
+                        continue;
+                    }
+                    let frame = encode(&*self.vram.lock().unwrap());
+                    if last_frame.as_ref() != Some(&frame) {
+                        self.send(&format!("frame {frame}")).await?;
+                        last_frame = Some(frame);
+                    }
+                }
+            }
+        }
+    }
+
+    fn handshake(&self, hello: &str) -> Result<(), String> {
+        let mut words = hello.split_whitespace();
+        if words.next() != Some("hello") {
+            return Err(format!("Expected hello {VERSION}"));
+        }
+        let version = words.next().unwrap_or_default();
+        if version != VERSION.to_string() {
+            return Err(format!(
+                "Unsupported version {version:?}, expected {VERSION}"
+            ));
+        }
+        match (&self.token, words.next()) {
+            (None, _) => Ok(()),
+            (Some(token), Some(given)) if **token == *given => Ok(()),
+            (Some(_), _) => Err("Wrong token".into()),
+        }
+    }
+
+    fn set(&mut self, key: u8, pressed: bool) {
+        let mut keypad = self.keypad.lock().unwrap();
+        if pressed {
+            keypad.press(key);
+        } else {
+            keypad.release(key);
+        }
+        self.held[usize::from(key)] = pressed;
+    }
+
+    async fn send(&mut self, line: &str) -> std::io::Result<()> {
+        self.stream.write_all(format!("{line}\n").as_bytes()).await
+    }
+}
+
+fn parse_key(key: &str) -> Result<u8, String> {
+    u8::from_str_radix(key, 16)
+        .ok()
+        .filter(|key| *key < 16)
+        .ok_or_else(|| format!("Expected a key from 0 to F, got {key:?}"))
+}
+
+/// The lengths of alternating runs of dark and lit pixels, starting with dark.
+pub fn encode(vram: &[bool]) -> String {
+    let mut runs = vec![];
+    let mut lit = false;
+    let mut run = 0;
+    for &pixel in vram {
+        if pixel != lit {
+            runs.push(run.to_string());
+            lit = pixel;
+            run = 0;
+        }
+        run += 1;
+    }
+    runs.push(run.to_string());
+    runs.join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::Headless;
+    use crate::quirks::Quirks;
+    use smol::io::Lines;
+
+    #[test]
+    fn encodes_runs_starting_dark() {
+        assert_eq!(encode(&[false; 64 * 32]), "2048");
+        let mut vram = [false; 64 * 32];
+        vram[..3].fill(true);
+        vram[64 * 32 - 1] = true;
+        assert_eq!(encode(&vram), "0,3,2044,1");
+    }
+
+    struct Connection {
+        lines: Lines<BufReader<TcpStream>>,
+        stream: TcpStream,
+    }
+
+    impl Connection {
+        async fn open(addr: SocketAddr) -> Connection {
+            let stream = TcpStream::connect(addr).await.unwrap();
+            let mut connection = Connection {
+                lines: BufReader::new(stream.clone()).lines(),
+                stream,
+            };
+            assert_eq!(connection.reply().await.as_deref(), Some("chip8-remote 1"));
+            connection
+        }
+
+        async fn send(&mut self, line: &str) {
+            self.stream
+                .write_all(format!("{line}\n").as_bytes())
+                .await
+                .unwrap();
+        }
+
+        /// The next line from the server, or None once it hangs up.
+        async fn reply(&mut self) -> Option<String> {
+            let line = self.lines.next();
+            let timeout = async {
+                Timer::after(Duration::from_secs(5)).await;
+                panic!("No reply from the server");
+            };
+            smol::future::or(line, timeout).await.transpose().unwrap()
+        }
+    }
+
+    /// Drives a server over loopback while a headless core draws to the screen it
+    /// mirrors.
+    #[test]
+    fn drives_the_protocol_over_loopback() {
+        // Draws the font's 0 at the top left, then loops
+        let rom = vec![0xA0, 0x00, 0xD0, 0x05, 0x12, 0x04];
+        let mut core = Headless::new(rom, Quirks::default(), 10);
+        let keypad = core.keypad();
+        let vram = core.state.vram.clone();
+        let server = Server::bind("127.0.0.1:0", Some("secret".into())).unwrap();
+        let addr = server.listener.local_addr().unwrap();
+        smol::spawn(server.serve(keypad.clone(), vram.clone())).detach();
+
+        smol::block_on(async {
+            let mut client = Connection::open(addr).await;
+            client.send("hello 1 guess").await;
+            assert_eq!(client.reply().await.as_deref(), Some("error Wrong token"));
+            assert_eq!(client.reply().await, None);
+
+            let mut client = Connection::open(addr).await;
+            client.send("hello 2 secret").await;
+            assert_eq!(
+                client.reply().await.as_deref(),
+                Some("error Unsupported version \"2\", expected 1")
+            );
+
+            let mut client = Connection::open(addr).await;
+            client.send("hello 1 secret").await;
+            client.send("down a").await;
+            // Replies come in order, so the key is down by the time this one arrives
+            client.send("down 10").await;
+            assert_eq!(
+                client.reply().await.as_deref(),
+                Some("error Expected a key from 0 to F, got \"10\"")
+            );
+            assert!(keypad.lock().unwrap().is_pressed(0xA));
+            client.send("jump").await;
+            assert_eq!(
+                client.reply().await.as_deref(),
+                Some("error Unknown command \"jump\"")
+            );
+
+            client.send("subscribe").await;
+            assert_eq!(client.reply().await.as_deref(), Some("frame 2048"));
+            for _ in 0..3 {
+                let _ = core.step();
+            }
+            let expected = format!("frame {}", encode(&*vram.lock().unwrap()));
+            assert_eq!(expected, "frame 0,4,60,1,2,1,60,1,2,1,60,1,2,1,60,4,1788");
+            assert_eq!(client.reply().await, Some(expected));
+
+            client.send("quit").await;
+            assert_eq!(client.reply().await, None);
+            // Hanging up lets go of the keys the client held
+            let released = async {
+                while keypad.lock().unwrap().is_pressed(0xA) {
+                    Timer::after(Duration::from_millis(1)).await;
+                }
+            };
+            let timeout = async {
+                Timer::after(Duration::from_secs(5)).await;
+                panic!("The key was never released");
+            };
+            smol::future::or(released, timeout).await;
+        });
+    }
+}
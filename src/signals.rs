@@ -0,0 +1,85 @@
+//! Ctrl+C in the terminal, or SIGTERM, quits like the quit key does, so recordings,
+//! traces and the last frame are still written out. A second one exits right away.
+
+use crate::io::Status;
+use async_signal::Signal;
+use async_signal::Signals;
+use futures::Stream;
+use futures::StreamExt;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use tracing::*;
+
+/// Exit status after quitting on `signal`, following the shell's 128 + signal number.
+pub fn exit_code(signal: i32) -> i32 {
+    128 + signal
+}
+
+/// Flags `status` for the window to quit on the first signal, and exits on the next.
+pub async fn watch(status: Arc<Status>) {
+    #[cfg(unix)]
+    let signals = Signals::new([Signal::Int, Signal::Term]);
+    #[cfg(not(unix))]
+    let signals = Signals::new([Signal::Int]);
+    match signals {
+        Ok(signals) => handle(signals, &status).await,
+        Err(err) => {
+            warn!("Could not catch Ctrl+C, it will quit without cleaning up: {err}");
+            futures::future::pending().await
+        }
+    }
+}
+
+async fn handle(mut signals: impl Stream<Item = std::io::Result<Signal>> + Unpin, status: &Status) {
+    while let Some(signal) = signals.next().await {
+        let Ok(signal) = signal else {
+            continue;
+        };
+        let number = signal as i32;
+        if status.interrupted.swap(number, Ordering::Relaxed) != 0 {
+            eprintln!("Quitting without cleaning up");
+            std::process::exit(exit_code(number));
+        }
+        info!("Received {signal:?}, shutting down. Again to quit right away");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn status() -> Status {
+        Status::new(Arc::new(Mutex::new(0)), Arc::new(Mutex::new(0)))
+    }
+
+    #[test]
+    fn first_signal_asks_to_quit() {
+        let status = status();
+        let signals =
+            futures::stream::iter([Err(std::io::ErrorKind::Other.into()), Ok(Signal::Int)]);
+        smol::block_on(handle(signals, &status));
+        assert_eq!(
+            status.interrupted.load(Ordering::Relaxed),
+            Signal::Int as i32
+        );
+        assert_eq!(exit_code(Signal::Int as i32), 130);
+    }
+
+    /// A real SIGINT sent to this process is caught rather than killing it.
+    #[cfg(unix)]
+    #[test]
+    fn catches_sigint() {
+        let status = status();
+        let mut signals = Signals::new([Signal::Int]).unwrap();
+        let sent = std::process::Command::new("kill")
+            .args(["-INT", &std::process::id().to_string()])
+            .status()
+            .unwrap();
+        assert!(sent.success());
+        let signal = smol::block_on(signals.next()).unwrap().unwrap();
+        assert_eq!(signal, Signal::Int);
+        smol::block_on(handle(futures::stream::iter([Ok(signal)]), &status));
+        assert_eq!(status.interrupted.load(Ordering::Relaxed), 2);
+    }
+}
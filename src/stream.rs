@@ -0,0 +1,419 @@
+//! Watching and playing in a browser: a page with a canvas, and a WebSocket on the
+//! same port that streams the screen to it and takes key presses back.
+//!
+//! Each binary message is one frame: the width and height in a byte each, the
+//! background and foreground colors as RGB, then the pixels packed eight to a byte
+//! with the leftmost in the high bit, row by row. Frames only go out when the screen
+//! changed, at most 60 times a second, and only the newest one: a client that can't
+//! keep up misses the frames in between instead of falling further behind. The page
+//! sends text messages like `down 5` and `up 5` for the keypad keys.
+
+use crate::hash;
+use crate::io::Keypad;
+use crate::io::Status;
+use core::time::Duration;
+use futures::select;
+use futures::FutureExt;
+use futures::StreamExt;
+use smol::channel::Receiver;
+use smol::channel::Sender;
+use smol::io::AsyncBufReadExt;
+use smol::io::AsyncReadExt;
+use smol::io::AsyncWriteExt;
+use smol::io::BufReader;
+use smol::net::TcpListener;
+use smol::net::TcpStream;
+use smol::Async;
+use smol::Timer;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use tracing::*;
+
+const FRAME_INTERVAL: Duration = Duration::from_micros(1_000_000 / 60);
+/// Key presses are a handful of bytes, anything much bigger isn't from the page.
+const MAX_MESSAGE: u64 = 1024;
+/// From RFC 6455, for answering the handshake.
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width">
+<title>chip8</title>
+<style>
+body { margin: 0; background: #111; display: flex; height: 100vh; align-items: center; justify-content: center; }
+canvas { width: 100vw; max-width: 1280px; image-rendering: pixelated; }
+</style>
+</head>
+<body>
+<canvas id="screen" width="64" height="32"></canvas>
+<script>
+const canvas = document.getElementById("screen");
+const context = canvas.getContext("2d");
+// The classic layout: 1234/QWER/ASDF/ZXCV
+const keys = { "1": 0x1, "2": 0x2, "3": 0x3, "4": 0xC, "q": 0x4, "w": 0x5, "e": 0x6, "r": 0xD,
+  "a": 0x7, "s": 0x8, "d": 0x9, "f": 0xE, "z": 0xA, "x": 0x0, "c": 0xB, "v": 0xF };
+const socket = new WebSocket(`ws://${location.host}/ws`);
+socket.binaryType = "arraybuffer";
+socket.onmessage = (event) => {
+  const data = new Uint8Array(event.data);
+  const [width, height] = data;
+  if (canvas.width !== width || canvas.height !== height) {
+    canvas.width = width;
+    canvas.height = height;
+  }
+  const background = data.subarray(2, 5), foreground = data.subarray(5, 8);
+  const image = context.createImageData(width, height);
+  for (let pixel = 0; pixel < width * height; pixel++) {
+    const lit = data[8 + (pixel >> 3)] & (0x80 >> (pixel & 7));
+    image.data.set(lit ? foreground : background, pixel * 4);
+    image.data[pixel * 4 + 3] = 255;
+  }
+  context.putImageData(image, 0, 0);
+};
+socket.onclose = () => { document.title = "chip8 (disconnected)"; };
+for (const [type, action] of [["keydown", "down"], ["keyup", "up"]]) {
+  document.addEventListener(type, (event) => {
+    const key = keys[event.key.toLowerCase()];
+    if (key !== undefined && !event.repeat && socket.readyState === WebSocket.OPEN) {
+      socket.send(`${action} ${key.toString(16)}`);
+    }
+  });
+}
+</script>
+</body>
+</html>
+"#;
+
+pub struct Server {
+    listener: TcpListener,
+}
+
+impl Server {
+    /// Starts listening on an address like `ws://0.0.0.0:8080`, so a bad one is
+    /// reported before the window opens.
+    pub fn bind(url: &str) -> Result<Server, String> {
+        let addr: SocketAddr = url
+            .strip_prefix("ws://")
+            .unwrap_or(url)
+            .trim_end_matches('/')
+            .parse()
+            .map_err(|_| format!("Expected an address like ws://0.0.0.0:8080, got {url:?}"))?;
+        let listener = Async::<std::net::TcpListener>::bind(addr)
+            .map_err(|err| format!("Could not listen on {addr}: {err}"))?;
+        info!("Serving the screen at http://{addr}/");
+        Ok(Server {
+            listener: listener.into(),
+        })
+    }
+
+    /// Where it ended up listening, which is needed for port 0.
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Answers each connection in a task of its own.
+    pub async fn serve(
+        self,
+        keypad: Arc<Mutex<Keypad>>,
+        vram: Arc<Mutex<[bool; 64 * 32]>>,
+        status: Arc<Status>,
+    ) -> ! {
+        loop {
+            match self.listener.accept().await {
+                Ok((stream, peer)) => {
+                    let (keypad, vram, status) = (keypad.clone(), vram.clone(), status.clone());
+                    smol::spawn(async move {
+                        if let Err(err) = connection(stream, peer, keypad, vram, status).await {
+                            debug!("Stream to {peer} ended: {err}");
+                        }
+                    })
+                    .detach();
+                }
+                Err(err) => {
+                    warn!("Could not accept a connection: {err}");
+                    Timer::after(Duration::from_millis(100)).await;
+                }
+            }
+        }
+    }
+}
+
+/// Serves the page, or upgrades to a WebSocket and streams until the browser goes.
+async fn connection(
+    stream: TcpStream,
+    peer: SocketAddr,
+    keypad: Arc<Mutex<Keypad>>,
+    vram: Arc<Mutex<[bool; 64 * 32]>>,
+    status: Arc<Status>,
+) -> std::io::Result<()> {
+    let mut writer = stream.clone();
+    let mut reader = BufReader::new(stream);
+    let mut request = String::new();
+    reader.read_line(&mut request).await?;
+    let mut key = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value.trim().to_owned());
+            }
+        }
+    }
+    let path = request.split_whitespace().nth(1).unwrap_or_default();
+    let response = match (path, key) {
+        ("/ws", Some(key)) => {
+            let accept = base64(&sha1(format!("{key}{WEBSOCKET_GUID}").as_bytes()));
+            writer
+                .write_all(
+                    format!(
+                        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+                         Connection: Upgrade\r\nSec-WebSocket-Accept: {accept}\r\n\r\n"
+                    )
+                    .as_bytes(),
+                )
+                .await?;
+            info!("Browser viewer connected from {peer}");
+            let (control, controls) = smol::channel::bounded(4);
+            let input = smol::spawn(read_input(reader, keypad, control));
+            let result = stream_frames(writer.clone(), vram, status, controls).await;
+            // Stops the input side too if writing failed, so it lets go of its keys
+            let _ = writer.shutdown(std::net::Shutdown::Both);
+            input.await;
+            info!("Browser viewer from {peer} disconnected");
+            return result;
+        }
+        ("/", _) => format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{PAGE}",
+            PAGE.len()
+        ),
+        _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".into(),
+    };
+    writer.write_all(response.as_bytes()).await
+}
+
+/// What the reading side needs the writing side to send.
+enum Control {
+    Pong(Vec<u8>),
+    Close,
+}
+
+/// Sends the screen whenever it changed, along with whatever `controls` asks for,
+/// until the browser or the connection closes.
+async fn stream_frames(
+    mut writer: TcpStream,
+    vram: Arc<Mutex<[bool; 64 * 32]>>,
+    status: Arc<Status>,
+    controls: Receiver<Control>,
+) -> std::io::Result<()> {
+    let mut last = None;
+    let mut ticks = Timer::interval(FRAME_INTERVAL);
+    loop {
+        select! {
+            control = controls.recv().fuse() => match control {
+                Ok(Control::Pong(payload)) => write_message(&mut writer, 0xA, &payload).await?,
+                Ok(Control::Close) | Err(_) => {
+                    // Answering a close is all a clean shutdown takes
+                    return write_message(&mut writer, 0x8, &[]).await;
+                }
+            },
+            _ = ticks.next().fuse() => {
+                let pixels = hash::pack(&*vram.lock().unwrap());
+                if last.as_ref() == Some(&pixels) {
+                    continue;
+                }
+                let palette = status.palette.lock().unwrap().0;
+                let mut frame = vec![64, 32];
+                for color in [palette[0], palette[1]] {
+                    frame.extend([color.r, color.g, color.b]);
+                }
+                frame.extend(&pixels);
+                // Only sending the newest frame once the last one is out of the way
+                // is what drops frames for slow clients
+                write_message(&mut writer, 0x2, &frame).await?;
+                last = Some(pixels);
+            }
+        }
+    }
+}
+
+/// Applies key messages from the browser, releasing whatever it held once it goes.
+async fn read_input(
+    mut reader: BufReader<TcpStream>,
+    keypad: Arc<Mutex<Keypad>>,
+    control: Sender<Control>,
+) {
+    let mut held = [false; 16];
+    loop {
+        let message = match read_message(&mut reader).await {
+            Ok(message) => message,
+            Err(err) => {
+                debug!("Browser viewer input ended: {err}");
+                break;
+            }
+        };
+        match message {
+            (0x1, text) => {
+                let text = String::from_utf8_lossy(&text);
+                let mut words = text.split_whitespace();
+                let pressed = match words.next() {
+                    Some("down") => true,
+                    Some("up") => false,
+                    _ => continue,
+                };
+                let Some(key) = words
+                    .next()
+                    .and_then(|key| u8::from_str_radix(key, 16).ok())
+                    .filter(|key| *key < 16)
+                else {
+                    continue;
+                };
+                let mut keypad = keypad.lock().unwrap();
+                if pressed {
+                    keypad.press(key);
+                } else {
+                    keypad.release(key);
+                }
+                held[usize::from(key)] = pressed;
+            }
+            (0x8, _) => break,
+            (0x9, payload) => {
+                let _ = control.send(Control::Pong(payload)).await;
+            }
+            _ => {}
+        }
+    }
+    {
+        let mut keypad = keypad.lock().unwrap();
+        for key in 0..16 {
+            if held[usize::from(key)] {
+                keypad.release(key);
+            }
+        }
+    }
+    let _ = control.send(Control::Close).await;
+}
+
+/// Reads one unfragmented message, as browsers send them, returning its opcode and
+/// unmasked payload.
+async fn read_message(reader: &mut BufReader<TcpStream>) -> std::io::Result<(u8, Vec<u8>)> {
+    let invalid = |message: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+    let mut header = [0; 2];
+    reader.read_exact(&mut header).await?;
+    let opcode = header[0] & 0x0F;
+    let len = match header[1] & 0x7F {
+        126 => {
+            let mut len = [0; 2];
+            reader.read_exact(&mut len).await?;
+            u64::from(u16::from_be_bytes(len))
+        }
+        127 => {
+            let mut len = [0; 8];
+            reader.read_exact(&mut len).await?;
+            u64::from_be_bytes(len)
+        }
+        len => u64::from(len),
+    };
+    if len > MAX_MESSAGE {
+        return Err(invalid("message too long"));
+    }
+    // Messages from browsers are always masked
+    if header[1] & 0x80 == 0 {
+        return Err(invalid("unmasked message"));
+    }
+    let mut mask = [0; 4];
+    reader.read_exact(&mut mask).await?;
+    let mut payload = vec![0; len as usize];
+    reader.read_exact(&mut payload).await?;
+    for (idx, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[idx % 4];
+    }
+    Ok((opcode, payload))
+}
+
+async fn write_message(writer: &mut TcpStream, opcode: u8, payload: &[u8]) -> std::io::Result<()> {
+    let mut message = vec![0x80 | opcode];
+    match payload.len() {
+        len @ 0..126 => message.push(len as u8),
+        len @ 126..=0xFFFF => {
+            message.push(126);
+            message.extend((len as u16).to_be_bytes());
+        }
+        len => {
+            message.push(127);
+            message.extend((len as u64).to_be_bytes());
+        }
+    }
+    message.extend(payload);
+    writer.write_all(&message).await
+}
+
+/// SHA-1, which the WebSocket handshake needs and nothing else does.
+fn sha1(bytes: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut message = bytes.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend((bytes.len() as u64 * 8).to_be_bytes());
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (idx, word) in block.chunks(4).enumerate() {
+            w[idx] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for idx in 16..80 {
+            w[idx] = (w[idx - 3] ^ w[idx - 8] ^ w[idx - 14] ^ w[idx - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (idx, word) in w.iter().enumerate() {
+            let (f, k) = match idx {
+                0..20 => ((b & c) | (!b & d), 0x5A827999),
+                20..40 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..60 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            (e, d, c, b, a) = (d, c, b.rotate_left(30), a, temp);
+        }
+        for (h, new) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(new);
+        }
+    }
+    let mut digest = [0; 20];
+    for (chunk, h) in digest.chunks_mut(4).zip(h) {
+        chunk.copy_from_slice(&h.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (idx, byte)| {
+            bits | u32::from(*byte) << (16 - 8 * idx)
+        });
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                out.push(char::from(
+                    ALPHABET[(bits >> (18 - 6 * idx)) as usize & 0x3F],
+                ));
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
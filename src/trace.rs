@@ -0,0 +1,489 @@
+//! Execution traces, for finding where two emulators (or two builds) part ways.
+//!
+//! A trace has one line per instruction, written before it runs:
+//!
+//! ```text
+//! PC   OP   V0 V1 V2 V3 V4 V5 V6 V7 V8 V9 VA VB VC VD VE VF I
+//! 0200 00E0 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 0000
+//! ```
+//!
+//! All numbers are hex. Lines starting with `PC` or `#` are skipped when comparing.
+use crate::cli::Exit;
+use crate::headless::Headless;
+use crate::quirks::Profile;
+use crate::ExitReason;
+use core::fmt;
+use core::str::FromStr;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Lines;
+use std::io::Write;
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::mpsc;
+
+pub const HEADER: &str = "PC   OP   V0 V1 V2 V3 V4 V5 V6 V7 V8 V9 VA VB VC VD VE VF I";
+/// Instructions shown before a divergence
+const CONTEXT: usize = 8;
+
+pub fn format_line(pc: u16, opcode: u16, registers: &[u8; 16], i: u16) -> String {
+    let mut line = format!("{pc:04X} {opcode:04X}");
+    for v in registers {
+        line.push_str(&format!(" {v:02X}"));
+    }
+    line.push_str(&format!(" {i:04X}"));
+    line
+}
+
+pub struct TraceWriter {
+    file: BufWriter<File>,
+}
+
+impl TraceWriter {
+    pub fn create(path: &Path) -> Result<TraceWriter, String> {
+        let err = |err: std::io::Error| format!("{}: {err}", path.display());
+        let mut file = BufWriter::new(File::create(path).map_err(err)?);
+        writeln!(file, "{HEADER}").map_err(err)?;
+        Ok(TraceWriter { file })
+    }
+
+    pub fn write(&mut self, line: &str) {
+        if let Err(err) = writeln!(self.file, "{line}") {
+            tracing::error!("Could not write trace: {err}");
+        }
+    }
+}
+
+/// Checks each instruction against a reference trace.
+pub struct TraceComparison {
+    reference: Lines<BufReader<File>>,
+    /// Line number in the reference
+    line: usize,
+    /// The last few instructions, which matched
+    history: VecDeque<String>,
+}
+
+impl TraceComparison {
+    pub fn open(path: &Path) -> Result<TraceComparison, String> {
+        let file = File::open(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        Ok(TraceComparison {
+            reference: BufReader::new(file).lines(),
+            line: 0,
+            history: VecDeque::with_capacity(CONTEXT),
+        })
+    }
+
+    /// Returns a report of the differences if `actual` doesn't match the reference.
+    /// Running past the end of the reference is fine.
+    pub fn check(&mut self, actual: &str) -> Result<(), String> {
+        let expected = loop {
+            self.line += 1;
+            match self.reference.next() {
+                None => return Ok(()),
+                Some(Err(err)) => return Err(format!("Could not read the reference trace: {err}")),
+                Some(Ok(line)) if line.starts_with("PC") || line.starts_with('#') => {}
+                Some(Ok(line)) if line.trim().is_empty() => {}
+                Some(Ok(line)) => break line,
+            }
+        };
+        if fields(&expected).eq(fields(actual)) {
+            if self.history.len() == CONTEXT {
+                self.history.pop_front();
+            }
+            self.history.push_back(expected);
+            return Ok(());
+        }
+        let mut report = format!("Diverged from the reference trace at line {}\n", self.line);
+        report.push_str(&format!("          {HEADER}\n"));
+        for line in &self.history {
+            report.push_str(&format!("          {line}\n"));
+        }
+        report.push_str(&format!("expected: {expected}\n"));
+        report.push_str(&format!("actual:   {actual}\n"));
+        // Point out which fields differ
+        let marks: Vec<_> = fields(&expected)
+            .zip(fields(actual))
+            .map(|(e, a)| {
+                let mark = if e == a { ' ' } else { '^' };
+                mark.to_string().repeat(e.len().max(a.len()))
+            })
+            .collect();
+        report.push_str(&format!("          {}", marks.join(" ")));
+        Err(report)
+    }
+}
+
+/// Fields of a trace line, normalized so case and spacing don't matter.
+fn fields(line: &str) -> impl Iterator<Item = String> + '_ {
+    line.split_whitespace().map(str::to_ascii_uppercase)
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// Address, opcode, mnemonic and whatever the instruction changed
+    #[default]
+    Compact,
+    /// Address, opcode and mnemonic followed by all registers and I afterwards
+    Full,
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "compact" => Ok(TraceFormat::Compact),
+            "full" => Ok(TraceFormat::Full),
+            _ => Err(format!(
+                "Unknown trace format {s:?}, expected compact or full"
+            )),
+        }
+    }
+}
+
+/// Registers and I, before or after an instruction.
+pub type Registers = ([u8; 16], u16);
+
+/// The registers and I that differ, like ` V3=05 I=0230`.
+pub fn changes(before: &Registers, after: &Registers) -> String {
+    let mut out = String::new();
+    for (idx, (old, new)) in before.0.iter().zip(&after.0).enumerate() {
+        if old != new {
+            out.push_str(&format!(" V{idx:X}={new:02X}"));
+        }
+    }
+    if before.1 != after.1 {
+        out.push_str(&format!(" I={:04X}", after.1));
+    }
+    out
+}
+
+impl TraceFormat {
+    /// The line for one instruction, given the registers and I around it.
+    pub fn line(
+        self,
+        pc: u16,
+        opcode: u16,
+        mnemonic: &str,
+        before: &Registers,
+        after: &Registers,
+    ) -> String {
+        match self {
+            TraceFormat::Compact => {
+                let line = format!(
+                    "{pc:04X} {opcode:04X} {mnemonic:<16}{}",
+                    changes(before, after)
+                );
+                line.trim_end().to_owned()
+            }
+            TraceFormat::Full => {
+                let state = format_line(pc, opcode, &after.0, after.1);
+                format!("{state} {mnemonic}")
+            }
+        }
+    }
+}
+
+/// Logs every instruction to a file from a separate thread, so the core doesn't wait
+/// on the disk. Once the file grows past `max_size` bytes it is moved to `<path>.1`,
+/// replacing the previous one, and a new file is started.
+pub struct TraceLog {
+    format: TraceFormat,
+    lines: mpsc::Sender<String>,
+}
+
+impl TraceLog {
+    pub fn create(path: &Path, format: TraceFormat, max_size: u64) -> Result<TraceLog, String> {
+        let file = File::create(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        let (lines, received) = mpsc::channel::<String>();
+        let path = path.to_owned();
+        std::thread::spawn(move || {
+            if let Err(err) = write_lines(file, &path, max_size, received) {
+                tracing::error!("Could not write trace log: {err}");
+            }
+        });
+        Ok(TraceLog { format, lines })
+    }
+
+    pub fn log(&self, pc: u16, opcode: u16, mnemonic: &str, before: &Registers, after: &Registers) {
+        let line = self.format.line(pc, opcode, mnemonic, before, after);
+        // The thread only stops after failing to write, which it already logged
+        let _ = self.lines.send(line);
+    }
+}
+
+fn write_lines(
+    file: File,
+    path: &PathBuf,
+    max_size: u64,
+    lines: mpsc::Receiver<String>,
+) -> std::io::Result<()> {
+    let mut file = BufWriter::new(file);
+    let mut size = 0;
+    loop {
+        let line = match lines.try_recv() {
+            Ok(line) => line,
+            // Write out what is buffered whenever the core stops producing, so little
+            // is lost if the process exits
+            Err(mpsc::TryRecvError::Empty) => {
+                file.flush()?;
+                match lines.recv() {
+                    Ok(line) => line,
+                    Err(_) => break,
+                }
+            }
+            Err(mpsc::TryRecvError::Disconnected) => break,
+        };
+        size += line.len() as u64 + 1;
+        writeln!(file, "{line}")?;
+        if size > max_size {
+            file.flush()?;
+            let mut rotated = path.clone().into_os_string();
+            rotated.push(".1");
+            std::fs::rename(path, rotated)?;
+            file = BufWriter::new(File::create(path)?);
+            size = 0;
+        }
+    }
+    file.flush()
+}
+
+pub const USAGE: &str = "\
+Usage: chip8 trace [OPTIONS] ROM
+
+Runs the ROM without a window for a fixed number of instructions, with no keys
+pressed, and prints a trace of every instruction to stdout.
+
+Options:
+    --instructions <N> How many instructions to run [default: 1000]
+    --ipf <N>          Instructions per frame, between timer ticks [default: 10]
+    --quirks <PROFILE> chip8, schip, xochip or amiga [default: chip8]
+    --format <FORMAT>  compact or full [default: compact]";
+
+pub fn run(args: impl IntoIterator<Item = String>) -> Result<(), Exit> {
+    let mut instructions = 1000;
+    let mut ipf = 10;
+    let mut profile = Profile::default();
+    let mut format = TraceFormat::default();
+    let mut rom = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_owned())),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| usage_error(format!("{flag} expects a value")))
+        };
+        match flag {
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            "--instructions" => instructions = number(flag, &value()?)?,
+            "--ipf" => ipf = number(flag, &value()?)?,
+            "--quirks" => profile = value()?.parse().map_err(|err: String| usage_error(err))?,
+            "--format" => format = value()?.parse().map_err(|err: String| usage_error(err))?,
+            _ if flag.starts_with('-') => {
+                return Err(usage_error(format!("Unknown option {flag}")))
+            }
+            _ if rom.is_none() => rom = Some(PathBuf::from(&arg)),
+            _ => return Err(usage_error(format!("Unexpected argument {arg}"))),
+        }
+    }
+    let rom = rom.ok_or_else(|| usage_error("No ROM given"))?;
+    let rom = crate::io::rom::read(&rom).map_err(Exit::failed)?;
+    let mut core = Headless::new(rom, profile.quirks(), ipf);
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    // Most likely a closed pipe, e.g. into `head`
+    let _ = write_trace(&mut core, instructions, format, &mut out).and_then(|()| out.flush());
+    Ok(())
+}
+
+/// Runs up to `instructions` instructions, writing a line for each in `format`, the
+/// same lines `--trace-file` logs. Stops early, saying why, if the program dies.
+pub fn write_trace(
+    core: &mut Headless,
+    instructions: u64,
+    format: TraceFormat,
+    out: &mut impl Write,
+) -> std::io::Result<()> {
+    for _ in 0..instructions {
+        let state = &core.state;
+        let (pc, opcode) = (state.pc, state.fetch().opcode());
+        let before = (state.registers.0, state.vi);
+        let flow = core.step();
+        let after = (core.state.registers.0, core.state.vi);
+        let mnemonic = crate::instruction::disassemble(opcode);
+        writeln!(
+            out,
+            "{}",
+            format.line(pc, opcode, &mnemonic, &before, &after)
+        )?;
+        match flow {
+            ControlFlow::Continue(()) | ControlFlow::Break(ExitReason::WaitingForKeyPress) => {}
+            ControlFlow::Break(reason) => {
+                writeln!(out, "# stopped: {reason:?}")?;
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn number(flag: &str, value: &str) -> Result<u64, Exit> {
+    value
+        .parse()
+        .map_err(|_| usage_error(format!("{flag} expects a number, got {value:?}")))
+}
+
+fn usage_error(message: impl fmt::Display) -> Exit {
+    Exit::usage(message, USAGE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::Quirks;
+
+    /// V0=5, V1=3, then 8016, whose result depends on the shift quirk
+    const SHIFT: &[u8] = &[0x60, 0x05, 0x61, 0x03, 0x80, 0x16, 0x70, 0x01, 0x12, 0x08];
+
+    fn core(shift: bool) -> Headless {
+        let quirks = Quirks {
+            shift,
+            ..Profile::Chip8.quirks()
+        };
+        Headless::new(SHIFT.to_vec(), quirks, 10)
+    }
+
+    /// The line `State` traces before running the next instruction.
+    fn next_line(core: &Headless) -> String {
+        let state = &core.state;
+        format_line(
+            state.pc,
+            state.fetch().opcode(),
+            &state.registers.0,
+            state.vi,
+        )
+    }
+
+    #[test]
+    fn reports_where_a_quirk_makes_cores_diverge() {
+        let path = std::env::temp_dir().join(format!("chip8-{}-divergence", std::process::id()));
+        let mut reference = core(false);
+        let mut writer = TraceWriter::create(&path).unwrap();
+        for _ in 0..6 {
+            writer.write(&next_line(&reference));
+            let _ = reference.step();
+        }
+        drop(writer);
+
+        let mut comparison = TraceComparison::open(&path).unwrap();
+        let mut actual = core(true);
+        let mut report = None;
+        for step in 0..6 {
+            if let Err(err) = comparison.check(&next_line(&actual)) {
+                report = Some((step, err));
+                break;
+            }
+            let _ = actual.step();
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        let (step, report) = report.expect("the traces should diverge");
+        // The shift itself traces the same, the instruction after it doesn't
+        assert_eq!(step, 3);
+        let lines: Vec<_> = report.lines().collect();
+        // Line 1 is the header
+        assert_eq!(lines[0], "Diverged from the reference trace at line 5");
+        assert_eq!(
+            lines[2],
+            "          0200 6005 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 0000"
+        );
+        assert_eq!(
+            lines[5],
+            "expected: 0206 7001 01 03 00 00 00 00 00 00 00 00 00 00 00 00 00 01 0000"
+        );
+        assert_eq!(
+            lines[6],
+            "actual:   0206 7001 02 03 00 00 00 00 00 00 00 00 00 00 00 00 00 01 0000"
+        );
+        assert_eq!(
+            lines[7].trim_end(),
+            "                    ^^",
+            "only V0 is marked"
+        );
+    }
+
+    #[test]
+    fn matching_ignores_case_spacing_and_comments() {
+        let path = std::env::temp_dir().join(format!("chip8-{}-matching", std::process::id()));
+        std::fs::write(
+            &path,
+            format!("{HEADER}\n# from another emulator\n\n0200  6005 ab\n"),
+        )
+        .unwrap();
+        let mut comparison = TraceComparison::open(&path).unwrap();
+        assert_eq!(comparison.check("0200 6005 AB"), Ok(()));
+        // Running past the end of the reference is fine
+        assert_eq!(comparison.check("0202 6103 AB"), Ok(()));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn formats_registers_and_i_in_hex() {
+        let mut registers = [0; 16];
+        registers[0xA] = 0xBC;
+        registers[0xF] = 1;
+        assert_eq!(
+            format_line(0x2FE, 0xD01F, &registers, 0x123),
+            "02FE D01F 00 00 00 00 00 00 00 00 00 00 BC 00 00 00 00 01 0123"
+        );
+    }
+
+    #[test]
+    fn detects_changed_registers() {
+        let before = ([0; 16], 0x200);
+        assert_eq!(changes(&before, &before), "");
+        let mut after = before;
+        after.0[3] = 5;
+        after.0[0xF] = 1;
+        assert_eq!(changes(&before, &after), " V3=05 VF=01");
+        after.1 = 0x230;
+        assert_eq!(changes(&before, &after), " V3=05 VF=01 I=0230");
+    }
+
+    #[test]
+    fn formats_compact_and_full_lines() {
+        let before = ([0; 16], 0);
+        let mut after = before;
+        after.0[3] = 5;
+        assert_eq!(
+            TraceFormat::Compact.line(0x200, 0x6305, "LD V3, 0x05", &before, &after),
+            "0200 6305 LD V3, 0x05      V3=05"
+        );
+        // Nothing changed, so no trailing spaces
+        assert_eq!(
+            TraceFormat::Compact.line(0x202, 0x00E0, "CLS", &after, &after),
+            "0202 00E0 CLS"
+        );
+        assert_eq!(
+            TraceFormat::Full.line(0x200, 0x6305, "LD V3, 0x05", &before, &after),
+            "0200 6305 00 00 00 05 00 00 00 00 00 00 00 00 00 00 00 00 0000 LD V3, 0x05"
+        );
+    }
+
+    #[test]
+    fn parses_formats() {
+        assert_eq!("compact".parse(), Ok(TraceFormat::Compact));
+        assert_eq!("full".parse(), Ok(TraceFormat::Full));
+        assert!("Full".parse::<TraceFormat>().is_err());
+    }
+}
@@ -0,0 +1,136 @@
+//! Conformance ROMs: each test runs on the deterministic scheduler with its input
+//! script from tests/fixtures/conformance, and the hash of its final screen has to
+//! match the one in tests/fixtures/conformance/hashes.txt.
+//!
+//! `opcodes.ch8` is checked in and always runs. Timendus' CHIP-8 test suite
+//! (<https://github.com/Timendus/chip8-test-suite>) isn't, see
+//! tests/fixtures/conformance/README.md: its test is ignored unless the
+//! `conformance` feature fetches the ROMs. A ROM that is missing or has no hash
+//! recorded fails. After checking the screens by hand,
+//! `BLESS=1 cargo test --test conformance` records their hashes.
+
+use chip8::ascii;
+use chip8::headless::Headless;
+use chip8::io::Script;
+use chip8::quirks::Profile;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// The test, the quirks it runs with and its input script.
+type Test = (&'static str, Profile, &'static str);
+
+/// Checked in: one instruction after another, each drawing its number when it
+/// does what the COSMAC VIP did. See the README for what each one checks.
+const CHECKED_IN: &[Test] = &[("opcodes", Profile::Chip8, "default.txt")];
+
+/// Timendus' test suite. The keypad test needs a person at the keyboard, so it
+/// isn't included.
+const SUITE: &[Test] = &[
+    ("1-chip8-logo", Profile::Chip8, "default.txt"),
+    ("2-ibm-logo", Profile::Chip8, "default.txt"),
+    ("3-corax+", Profile::Chip8, "default.txt"),
+    ("4-flags", Profile::Chip8, "default.txt"),
+    ("5-quirks", Profile::Chip8, "quirks.txt"),
+];
+/// Where the `conformance` feature fetches the suite's ROMs from.
+#[cfg(feature = "conformance")]
+const SUITE_URL: &str = "https://github.com/Timendus/chip8-test-suite/raw/main/bin";
+const IPF: u64 = 10;
+
+fn dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/conformance")
+}
+
+/// Test name to hash, from lines of `<test> <hash>`.
+fn load_hashes() -> BTreeMap<String, String> {
+    let text = std::fs::read_to_string(dir().join("hashes.txt")).unwrap_or_default();
+    text.lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once(' '))
+        .map(|(test, hash)| (test.to_owned(), hash.to_owned()))
+        .collect()
+}
+
+/// Runs `rom` until its script quits, returning the final screen.
+fn run(rom: Vec<u8>, profile: Profile, script: &str) -> Vec<bool> {
+    let mut script = Script::load(&dir().join(script)).unwrap();
+    let mut core = Headless::new(rom, profile.quirks(), IPF);
+    core.run_script(&mut script, 100_000).unwrap();
+    core.state.snapshot().vram
+}
+
+/// Runs every test with the ROM `load` finds for it, and checks or with `BLESS`
+/// records the hashes of their final screens.
+fn check(tests: &[Test], load: impl Fn(&str) -> Result<Vec<u8>, String>) {
+    let mut results = vec![];
+    let mut failed = vec![];
+    for (test, profile, script) in tests {
+        match load(test) {
+            Ok(rom) => {
+                let vram = run(rom, *profile, script);
+                let hash = format!("{:016x}", chip8::hash::framebuffer_hash(&vram, 64, 32));
+                results.push((test, hash, vram));
+            }
+            Err(err) => failed.push(format!("{test}: {err}")),
+        }
+    }
+    if std::env::var_os("BLESS").is_some() {
+        let mut hashes = load_hashes();
+        for (test, hash, vram) in &results {
+            eprintln!("{test}: recorded {hash}\n{}", ascii::render(vram, 64));
+            hashes.insert(test.to_string(), hash.clone());
+        }
+        let mut text = String::from("# <test> <hash of the final screen>\n");
+        for (test, hash) in hashes {
+            text += &format!("{test} {hash}\n");
+        }
+        std::fs::write(dir().join("hashes.txt"), text).unwrap();
+        assert!(failed.is_empty(), "{}", failed.join("\n"));
+        return;
+    }
+    let expected = load_hashes();
+    for (test, hash, vram) in &results {
+        match expected.get(**test) {
+            Some(expected) if expected == hash => eprintln!("{test}: ok"),
+            Some(expected) => failed.push(format!(
+                "{test}: expected {expected}, got {hash}\n{}",
+                ascii::render(vram, 64)
+            )),
+            None => failed.push(format!(
+                "{test}: no hash recorded, got {hash}\n{}",
+                ascii::render(vram, 64)
+            )),
+        }
+    }
+    assert!(failed.is_empty(), "{}", failed.join("\n"));
+}
+
+/// The ROM for `test` in tests/fixtures/conformance.
+fn checked_in(test: &str) -> Result<Vec<u8>, String> {
+    let path = dir().join(format!("{test}.ch8"));
+    std::fs::read(&path).map_err(|err| format!("{}: {err}", path.display()))
+}
+
+#[test]
+fn checked_in_screens_match() {
+    check(CHECKED_IN, checked_in);
+}
+
+#[test]
+#[cfg_attr(
+    not(feature = "conformance"),
+    ignore = "needs the suite's ROMs, which the conformance feature fetches"
+)]
+fn suite_screens_match() {
+    check(SUITE, |test| {
+        let copied = checked_in(test);
+        #[cfg(feature = "conformance")]
+        let copied = copied.or_else(|_| {
+            let url = format!("{SUITE_URL}/{test}.ch8");
+            let path = chip8::download::fetch(&url, false)?;
+            std::fs::read(&path).map_err(|err| format!("{}: {err}", path.display()))
+        });
+        copied
+    });
+}
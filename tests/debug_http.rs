@@ -0,0 +1,186 @@
+//! The debug server, against a core running on a thread of its own.
+
+use chip8::debug_http::Server;
+use chip8::io::Keypad;
+use chip8::io::Status;
+use chip8::json::Value;
+use chip8::State;
+use std::io::Read;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+// LD V0, 07; ADD V0, 01; JP 202
+const ROM: &[u8] = &[0x60, 0x07, 0x70, 0x01, 0x12, 0x02];
+
+struct Machine {
+    addr: SocketAddr,
+    keypad: Arc<Mutex<Keypad>>,
+    vram: Arc<Mutex<[bool; 64 * 32]>>,
+}
+
+/// Starts a core and a server for it on a free port on this machine.
+fn start() -> Machine {
+    let vram = Arc::new(Mutex::new([false; 64 * 32]));
+    let keypad = Arc::new(Mutex::new(Keypad::default()));
+    let delay_timer = Arc::new(Mutex::new(0));
+    let sound_timer = Arc::new(Mutex::new(0));
+    let status = Arc::new(Status::new(delay_timer.clone(), sound_timer.clone()));
+    let paused = Arc::new(Mutex::new(false));
+    let mut state = State::new(
+        vram.clone(),
+        keypad.clone(),
+        delay_timer,
+        sound_timer,
+        paused.clone(),
+        status.clone(),
+        ROM.to_vec(),
+    );
+    let server = Server::bind("0").unwrap();
+    let addr = server.local_addr().unwrap();
+    assert!(addr.ip().is_loopback());
+    let serve = server.serve(keypad.clone(), vram.clone(), status, paused);
+    std::thread::spawn(move || {
+        smol::block_on(futures::future::select(
+            Box::pin(chip8::run_core(&mut state)),
+            Box::pin(serve),
+        ))
+    });
+    Machine { addr, keypad, vram }
+}
+
+/// The status code and body of a request.
+fn request(machine: &Machine, method: &str, target: &str) -> (u16, Vec<u8>) {
+    let mut stream = TcpStream::connect(machine.addr).unwrap();
+    write!(stream, "{method} {target} HTTP/1.1\r\nHost: test\r\n\r\n").unwrap();
+    let mut response = vec![];
+    stream.read_to_end(&mut response).unwrap();
+    let split = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .unwrap();
+    let head = String::from_utf8(response[..split].to_vec()).unwrap();
+    let code = head.split(' ').nth(1).unwrap().parse().unwrap();
+    (code, response[split + 4..].to_vec())
+}
+
+fn text(machine: &Machine, method: &str, target: &str) -> (u16, String) {
+    let (code, body) = request(machine, method, target);
+    (code, String::from_utf8(body).unwrap())
+}
+
+/// pc and V0 from `/state`.
+fn pc_and_v0(machine: &Machine) -> (u64, u64) {
+    let (code, body) = text(machine, "GET", "/state");
+    assert_eq!(code, 200, "{body}");
+    let Value::Object(fields) = chip8::json::parse(&body).unwrap() else {
+        panic!("{body}");
+    };
+    let field = |name: &str| {
+        fields
+            .iter()
+            .find(|(key, _)| key == name)
+            .unwrap()
+            .1
+            .clone()
+    };
+    let Value::Number(pc) = field("pc") else {
+        panic!("{body}");
+    };
+    let Value::Array(v) = field("v") else {
+        panic!("{body}");
+    };
+    let Value::Number(v0) = &v[0] else {
+        panic!("{body}");
+    };
+    (pc.parse().unwrap(), v0.parse().unwrap())
+}
+
+#[test]
+fn state_and_disassembly() {
+    let machine = start();
+    let (pc, _) = pc_and_v0(&machine);
+    assert!([0x200, 0x202, 0x204].contains(&pc), "{pc:X}");
+    let (code, body) = text(&machine, "GET", "/disasm?addr=0x200&count=3");
+    assert_eq!(code, 200);
+    let lines: Vec<&str> = body.lines().collect();
+    assert_eq!(lines.len(), 3, "{body}");
+    for (line, start) in lines.iter().zip(["200 6007 ", "202 7001 ", "204 1202 "]) {
+        assert!(line.starts_with(start), "{line}");
+    }
+    assert_eq!(text(&machine, "GET", "/disasm?count=many").0, 400);
+}
+
+#[test]
+fn framebuffer_as_bits_and_png() {
+    let machine = start();
+    {
+        let mut vram = machine.vram.lock().unwrap();
+        vram[0] = true;
+        vram[9] = true;
+    }
+    let (code, bits) = request(&machine, "GET", "/framebuffer?format=bits");
+    assert_eq!(code, 200);
+    assert_eq!(bits.len(), 64 * 32 / 8);
+    assert_eq!(bits[..3], [0x80, 0x40, 0x00]);
+    let (code, png) = request(&machine, "GET", "/framebuffer");
+    assert_eq!(code, 200);
+    assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+    assert_eq!(text(&machine, "GET", "/framebuffer?format=gif").0, 400);
+}
+
+#[test]
+fn keys_go_down_and_up() {
+    let machine = start();
+    assert_eq!(text(&machine, "POST", "/key/a/down").0, 200);
+    assert!(machine.keypad.lock().unwrap().is_pressed(0xA));
+    assert_eq!(text(&machine, "POST", "/key/A/up").0, 200);
+    assert!(!machine.keypad.lock().unwrap().is_pressed(0xA));
+    assert_eq!(text(&machine, "POST", "/key/10/down").0, 400);
+    assert_eq!(text(&machine, "GET", "/key/1/down").0, 405);
+}
+
+#[test]
+fn pause_step_and_resume() {
+    let machine = start();
+    assert_eq!(text(&machine, "POST", "/step").0, 409);
+    assert_eq!(text(&machine, "POST", "/pause").0, 200);
+    let paused = pc_and_v0(&machine);
+    assert_eq!(pc_and_v0(&machine), paused);
+
+    assert_eq!(text(&machine, "POST", "/step").0, 200);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let stepped = loop {
+        let now = pc_and_v0(&machine);
+        if now != paused {
+            break now;
+        }
+        assert!(Instant::now() < deadline, "The step never ran");
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    // One instruction: either the ADD or the jump back to it
+    match paused.0 {
+        0x202 => assert_eq!(stepped, (0x204, paused.1 + 1)),
+        0x204 => assert_eq!(stepped, (0x202, paused.1)),
+        pc => assert_eq!((pc, stepped.0), (0x200, 0x202)),
+    }
+    assert_eq!(pc_and_v0(&machine), stepped);
+
+    assert_eq!(text(&machine, "POST", "/resume").0, 200);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while pc_and_v0(&machine).1 == stepped.1 {
+        assert!(Instant::now() < deadline, "The game never resumed");
+    }
+}
+
+#[test]
+fn unknown_requests() {
+    let machine = start();
+    assert_eq!(text(&machine, "GET", "/pause").0, 405);
+    assert_eq!(text(&machine, "GET", "/nothing").0, 404);
+    assert_eq!(text(&machine, "POST", "/loadstate").0, 501);
+}
@@ -0,0 +1,124 @@
+//! Downloading ROMs from a server on this machine, into a cache directory of its own.
+
+#![cfg(feature = "download")]
+
+use chip8::download;
+use chip8::io::rom::MAX_SIZE;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Once;
+
+const ROM: &[u8] = &[0x00, 0xE0, 0x12, 0x00];
+
+/// Points the cache somewhere of its own, the same for every test so they can run at
+/// the same time, and makes sure nothing goes through a proxy.
+fn cache_dir() -> PathBuf {
+    static SETUP: Once = Once::new();
+    let dir = std::env::temp_dir().join(format!("chip8-{}-cache", std::process::id()));
+    SETUP.call_once(|| {
+        std::env::set_var("XDG_CACHE_HOME", &dir);
+        for proxy in [
+            "HTTP_PROXY",
+            "HTTPS_PROXY",
+            "http_proxy",
+            "https_proxy",
+            "ALL_PROXY",
+        ] {
+            std::env::remove_var(proxy);
+        }
+    });
+    dir
+}
+
+/// Serves `/pong.ch8`, a `/moved.ch8` that redirects to it, a ROM that is too large
+/// and nothing else, returning its address and how many requests it has had.
+fn serve() -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counter = requests.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request = String::new();
+            reader.read_line(&mut request).unwrap();
+            // The headers, up to the blank line
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            counter.fetch_add(1, Ordering::Relaxed);
+            let path = request.split(' ').nth(1).unwrap_or("");
+            let (status, headers, body) = match path {
+                "/pong.ch8" | "/other/pong.ch8" => ("200 OK", String::new(), ROM.to_vec()),
+                "/moved.ch8" => ("302 Found", "Location: /pong.ch8\r\n".to_owned(), vec![]),
+                "/huge.ch8" => ("200 OK", String::new(), vec![0; MAX_SIZE + 1]),
+                _ => ("404 Not Found", String::new(), vec![]),
+            };
+            let head = format!(
+                "HTTP/1.1 {status}\r\n{headers}Content-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            let _ = stream.write_all(head.as_bytes());
+            let _ = stream.write_all(&body);
+        }
+    });
+    (addr, requests)
+}
+
+#[test]
+fn downloads_once_then_uses_the_cache() {
+    let cache = cache_dir();
+    let (server, requests) = serve();
+    let url = format!("{server}/pong.ch8");
+    let path = download::fetch(&url, false).unwrap();
+    assert!(path.starts_with(&cache));
+    assert_eq!(path.file_name().unwrap(), "pong.ch8");
+    assert_eq!(std::fs::read(&path).unwrap(), ROM);
+    assert_eq!(requests.load(Ordering::Relaxed), 1);
+
+    assert_eq!(download::fetch(&url, false).unwrap(), path);
+    assert_eq!(requests.load(Ordering::Relaxed), 1);
+    // Unless told to download it again
+    assert_eq!(download::fetch(&url, true).unwrap(), path);
+    assert_eq!(requests.load(Ordering::Relaxed), 2);
+
+    // Another URL with the same file name gets a copy of its own
+    let other = download::fetch(&format!("{server}/other/pong.ch8"), false).unwrap();
+    assert_ne!(other, path);
+    assert_eq!(requests.load(Ordering::Relaxed), 3);
+}
+
+#[test]
+fn follows_redirects() {
+    cache_dir();
+    let (server, requests) = serve();
+    let path = download::fetch(&format!("{server}/moved.ch8"), false).unwrap();
+    assert_eq!(path.file_name().unwrap(), "moved.ch8");
+    assert_eq!(std::fs::read(&path).unwrap(), ROM);
+    assert_eq!(requests.load(Ordering::Relaxed), 2);
+}
+
+#[test]
+fn failures_leave_nothing_in_the_cache() {
+    cache_dir();
+    let (server, _) = serve();
+    let missing = format!("{server}/missing.ch8");
+    let err = download::fetch(&missing, false).unwrap_err();
+    assert!(err.contains("404"), "{err}");
+    let huge = format!("{server}/huge.ch8");
+    let err = download::fetch(&huge, false).unwrap_err();
+    assert!(err.contains("too large"), "{err}");
+    for url in [missing, huge] {
+        let path = download::cache_path(&url).unwrap();
+        assert!(!path.exists(), "{}", path.display());
+        assert!(!path.with_extension("part").exists());
+    }
+}
@@ -0,0 +1,122 @@
+//! The JSON state export, read back and compared against the machine it came from.
+
+use chip8::dump::Dump;
+use chip8::export;
+use chip8::headless::Headless;
+use chip8::json::Value;
+use chip8::quirks::Profile;
+
+// LD V0, 12; LD V5, 34; LD F, V0; LD DT, V0; CALL 20C; JP 20A
+// 20C: DRW V5, V0, 5; JP 20E
+const ROM: &[u8] = &[
+    0x60, 0x12, 0x65, 0x34, 0xF0, 0x29, 0xF0, 0x15, 0x22, 0x0C, 0x12, 0x0A, 0xD5, 0x05, 0x12, 0x0E,
+];
+
+/// The machine after drawing, waiting in the subroutine's loop.
+fn core() -> Headless {
+    let mut core = Headless::new(ROM.to_vec(), Profile::Chip8.quirks(), u64::MAX);
+    for _ in 0..6 {
+        let _ = core.step();
+    }
+    core
+}
+
+fn field<'a>(value: &'a Value, name: &str) -> &'a Value {
+    let Value::Object(fields) = value else {
+        panic!("{value:?} isn't an object");
+    };
+    match fields.iter().find(|(key, _)| key == name) {
+        Some((_, value)) => value,
+        None => panic!("No {name} in {value:?}"),
+    }
+}
+
+fn number(value: &Value) -> u64 {
+    match value {
+        Value::Number(n) => n.parse().unwrap(),
+        _ => panic!("{value:?} isn't a number"),
+    }
+}
+
+fn string(value: &Value) -> &str {
+    match value {
+        Value::String(s) => s,
+        _ => panic!("{value:?} isn't a string"),
+    }
+}
+
+fn numbers(value: &Value) -> Vec<u64> {
+    match value {
+        Value::Array(values) => values.iter().map(number).collect(),
+        _ => panic!("{value:?} isn't an array"),
+    }
+}
+
+/// Reads `json` back and checks every field against `dump`.
+fn check(json: &str, dump: &Dump) {
+    let state = chip8::json::parse(json).unwrap();
+    assert_eq!(string(field(&state, "format")), export::FORMAT);
+    assert_eq!(number(field(&state, "version")), u64::from(export::VERSION));
+    assert_eq!(
+        string(field(&state, "rom_hash")),
+        format!("{:016x}", dump.rom_hash)
+    );
+    let quirks = field(&state, "quirks");
+    let mut expected = dump.quirks;
+    for (name, on) in chip8::dump::quirk_names(&mut expected) {
+        assert_eq!(field(quirks, name), &Value::Bool(*on), "{name}");
+    }
+    assert_eq!(number(field(&state, "pc")), u64::from(dump.pc));
+    assert_eq!(number(field(&state, "i")), u64::from(dump.i));
+    let registers: Vec<u64> = dump.registers.iter().map(|&v| v.into()).collect();
+    assert_eq!(numbers(field(&state, "v")), registers);
+    let stack: Vec<u64> = dump.stack.iter().map(|&addr| addr.into()).collect();
+    assert_eq!(numbers(field(&state, "stack")), stack);
+    let timers = field(&state, "timers");
+    assert_eq!(number(field(timers, "delay")), u64::from(dump.delay_timer));
+    assert_eq!(number(field(timers, "sound")), u64::from(dump.sound_timer));
+
+    let screen = field(&state, "screen");
+    assert_eq!(number(field(screen, "width")), 64);
+    assert_eq!(number(field(screen, "height")), 32);
+    let Value::Array(rows) = field(screen, "rows") else {
+        panic!("rows isn't an array");
+    };
+    let vram: Vec<bool> = rows
+        .iter()
+        .flat_map(|row| string(row).chars().map(|c| c == '#').collect::<Vec<_>>())
+        .collect();
+    assert_eq!(vram, dump.vram);
+
+    let memory = string(field(&state, "memory"));
+    let memory: Vec<u8> = (0..memory.len())
+        .step_by(2)
+        .map(|at| u8::from_str_radix(&memory[at..at + 2], 16).unwrap())
+        .collect();
+    assert_eq!(memory, dump.memory);
+}
+
+#[test]
+fn export_matches_the_machine() {
+    let core = core();
+    let dump = core.state.snapshot();
+    // What the ROM did, so the comparison isn't against an empty machine
+    assert_eq!(dump.pc, 0x20E);
+    assert_eq!(dump.stack, [0x20A]);
+    assert_eq!((dump.registers[0], dump.registers[5]), (0x12, 0x34));
+    assert_eq!(dump.delay_timer, 0x12);
+    assert!(dump.vram.iter().any(|&on| on));
+    check(&export::to_json(&dump), &dump);
+}
+
+#[test]
+fn dumps_the_state_on_exit() {
+    let mut core = core();
+    let dir = std::env::temp_dir().join(format!("chip8-{}-export", std::process::id()));
+    let path = dir.join("nested").join("state.json");
+    core.state.dump_state_on_exit = Some(path.clone());
+    core.state.save_state_on_exit();
+    let json = std::fs::read_to_string(&path).unwrap();
+    check(&json, &core.state.snapshot());
+    let _ = std::fs::remove_dir_all(dir);
+}
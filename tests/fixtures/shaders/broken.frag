@@ -0,0 +1,9 @@
+// Doesn't compile: main is never closed. The shader tests check that this falls back
+// to drawing without it.
+#version 110
+
+uniform sampler2D screen;
+varying vec2 uv;
+
+void main() {
+    gl_FragColor = texture2D(screen, uv);
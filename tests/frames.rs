@@ -0,0 +1,82 @@
+//! Golden frames: each ROM runs for a fixed number of frames on the deterministic
+//! scheduler, and the hash of the last frame has to match the one checked in to
+//! tests/fixtures/frames/hashes.txt.
+//!
+//! When drawing changes on purpose, regenerate the hashes with
+//! `BLESS=1 cargo test --test frames` and review the diff.
+
+use chip8::headless::Headless;
+use chip8::quirks::Profile;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// The ROM, relative to the crate, the quirks it runs with and for how many frames.
+const CASES: &[(&str, Profile, u64)] = &[
+    ("src/splash.ch8", Profile::Chip8, 60),
+    ("tests/fixtures/frames/font.ch8", Profile::Chip8, 10),
+    // Sprites past the edges are clipped by one and wrap around with the other
+    ("tests/fixtures/frames/sprites.ch8", Profile::Chip8, 10),
+    ("tests/fixtures/frames/sprites.ch8", Profile::XoChip, 10),
+];
+const IPF: u64 = 10;
+
+fn root() -> &'static Path {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn hashes_path() -> PathBuf {
+    root().join("tests/fixtures/frames/hashes.txt")
+}
+
+/// `<rom> <profile>` to the hash, from lines of `<rom> <profile> <hash>`.
+fn load_hashes() -> BTreeMap<String, String> {
+    let text = std::fs::read_to_string(hashes_path()).unwrap_or_default();
+    text.lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.rsplit_once(' '))
+        .map(|(case, hash)| (case.to_owned(), hash.to_owned()))
+        .collect()
+}
+
+fn last_frame_hash(rom: &str, profile: Profile, frames: u64) -> String {
+    let rom = std::fs::read(root().join(rom)).unwrap_or_else(|err| panic!("{rom}: {err}"));
+    let mut core = Headless::new(rom, profile.quirks(), IPF);
+    while core.frame() < frames {
+        let _ = core.step();
+    }
+    format!("{:016x}", core.state.framebuffer_hash())
+}
+
+#[test]
+fn last_frames_match() {
+    let results: Vec<(String, String)> = CASES
+        .iter()
+        .map(|(rom, profile, frames)| {
+            let hash = last_frame_hash(rom, *profile, *frames);
+            (format!("{rom} {profile}"), hash)
+        })
+        .collect();
+    if std::env::var_os("BLESS").is_some() {
+        let mut text = String::from("# <rom> <quirk profile> <hash of the last frame>\n");
+        for (case, hash) in &results {
+            text += &format!("{case} {hash}\n");
+        }
+        std::fs::write(hashes_path(), text).unwrap();
+        return;
+    }
+    let expected = load_hashes();
+    let changed: Vec<String> = results
+        .iter()
+        .filter(|(case, hash)| expected.get(case) != Some(hash))
+        .map(|(case, hash)| match expected.get(case) {
+            Some(old) => format!("{case}: {old} -> {hash}"),
+            None => format!("{case}: no hash checked in, got {hash}"),
+        })
+        .collect();
+    assert!(
+        changed.is_empty(),
+        "frames changed, rerun with BLESS=1 if that's on purpose:\n{}",
+        changed.join("\n")
+    );
+}
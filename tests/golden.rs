@@ -0,0 +1,72 @@
+//! Golden traces: the tiny ROMs in tests/fixtures/golden run for a fixed number of
+//! instructions, and their traces have to match the recorded `.trace` files. This
+//! catches changes to jumps, flags and timers that a final screen wouldn't show.
+//!
+//! After a change in behaviour that was meant to happen,
+//! `BLESS=1 cargo test --test golden` records the current traces. Review the diff
+//! before committing them.
+
+use chip8::headless::Headless;
+use chip8::quirks::Profile;
+use chip8::trace::TraceFormat;
+use std::path::Path;
+
+const INSTRUCTIONS: u64 = 500;
+const IPF: u64 = 10;
+
+fn trace(rom: &Path) -> String {
+    let rom = std::fs::read(rom).unwrap();
+    let mut core = Headless::new(rom, Profile::Chip8.quirks(), IPF);
+    let mut out = vec![];
+    chip8::trace::write_trace(&mut core, INSTRUCTIONS, TraceFormat::Compact, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+/// The first line that differs, with its number, or the first one missing.
+fn first_difference(expected: &str, actual: &str) -> Option<String> {
+    let mut expected = expected.lines();
+    let mut actual = actual.lines();
+    for line in 1.. {
+        match (expected.next(), actual.next()) {
+            (None, None) => return None,
+            (e, a) if e == a => {}
+            (e, a) => {
+                let (e, a) = (e.unwrap_or("<end>"), a.unwrap_or("<end>"));
+                return Some(format!("line {line}\n- {e}\n+ {a}"));
+            }
+        }
+    }
+    unreachable!()
+}
+
+#[test]
+fn traces_match() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/golden");
+    let mut roms: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "ch8"))
+        .collect();
+    roms.sort();
+    assert!(!roms.is_empty(), "no fixtures in {}", dir.display());
+    let bless = std::env::var_os("BLESS").is_some();
+    let mut failed = vec![];
+    for rom in roms {
+        let name = rom.file_stem().unwrap().to_string_lossy().into_owned();
+        let golden = rom.with_extension("trace");
+        let actual = trace(&rom);
+        if bless {
+            std::fs::write(&golden, &actual).unwrap();
+            continue;
+        }
+        let expected = std::fs::read_to_string(&golden).unwrap_or_default();
+        if let Some(difference) = first_difference(&expected, &actual) {
+            failed.push(format!("{name}: differs at {difference}"));
+        }
+    }
+    assert!(
+        failed.is_empty(),
+        "traces changed, rerun with BLESS=1 if that's on purpose:\n{}",
+        failed.join("\n")
+    );
+}
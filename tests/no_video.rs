@@ -0,0 +1,55 @@
+//! Without a display server the binary carries on without a window, printing the
+//! screen to the terminal instead. CHIP8_NO_VIDEO stands in for a missing display
+//! server, so this runs the same on machines that have one.
+
+use std::process::Command;
+
+/// Draws a 0 in the top left corner and stays there.
+const ROM: &[u8] = &[
+    0x00, 0xE0, // 200: CLS
+    0xA2, 0x0C, // 202: LD I, 20C
+    0x60, 0x00, // 204: LD V0, 0
+    0x61, 0x00, // 206: LD V1, 0
+    0xD0, 0x15, // 208: DRW V0, V1, 5
+    0x12, 0x0A, // 20A: JP 20A
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 20C: 0
+];
+
+#[test]
+fn falls_back_to_the_terminal() {
+    let dir = std::env::temp_dir().join(format!("chip8-{}-no-video", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let rom = dir.join("zero.ch8");
+    std::fs::write(&rom, ROM).unwrap();
+    // Nothing from the config, saved state or cache of whoever runs the tests
+    let output = Command::new(env!("CARGO_BIN_EXE_chip8"))
+        .args(["--exit-after-frames", "90", "--no-audio"])
+        .arg(&rom)
+        .env(chip8::io::NO_VIDEO_VAR, "1")
+        .env("XDG_CONFIG_HOME", &dir)
+        .env("XDG_DATA_HOME", &dir)
+        .env("XDG_CACHE_HOME", &dir)
+        .env_remove("RUST_LOG")
+        .output()
+        .unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Could not open a window, running without one: CHIP8_NO_VIDEO is set"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("panicked"), "{stderr}");
+    // Stopped by --exit-after-frames, having shown the screen along the way
+    assert_eq!(output.status.code(), Some(3), "{stderr}");
+    assert!(stdout.contains("Stopped after 90 frames"), "{stdout}");
+    let screen = stdout
+        .split("Frame ")
+        .nth(1)
+        .unwrap_or_else(|| panic!("No screen in {stdout}"));
+    let rows: Vec<&str> = screen.lines().skip(1).take(5).collect();
+    assert_eq!(
+        rows.iter().map(|row| &row[..4]).collect::<Vec<_>>(),
+        ["####", "#..#", "#..#", "#..#", "####"]
+    );
+}
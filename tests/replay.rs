@@ -0,0 +1,113 @@
+//! Replaying a recording has to end up exactly where the recorded run did.
+
+use chip8::hash;
+use chip8::headless::Headless;
+use chip8::io::Input;
+use chip8::io::Recorder;
+use chip8::io::Replay;
+use chip8::quirks::Quirks;
+use std::path::PathBuf;
+
+/// Draws the digit of every key that is down at a random height, moving right each
+/// time, so both the input and the random numbers end up on screen.
+const ROM: &[u8] = &[
+    0x62, 0x00, // 200: LD V2, 0
+    0xE2, 0xA1, // 202: SKNP V2
+    0x22, 0x10, // 204: CALL 210
+    0x72, 0x01, // 206: ADD V2, 1
+    0x32, 0x10, // 208: SE V2, 10
+    0x12, 0x02, // 20A: JP 202
+    0x12, 0x00, // 20C: JP 200
+    0x00, 0x00, // 20E
+    0xC3, 0x1F, // 210: RND V3, 1F
+    0xF2, 0x29, // 212: LD F, V2
+    0xD0, 0x35, // 214: DRW V0, V3, 5
+    0x70, 0x05, // 216: ADD V0, 5
+    0x00, 0xEE, // 218: RET
+];
+const IPF: u64 = 12;
+const FRAMES: u64 = 180;
+
+/// Presses and releases by the frame they happen on, overlapping so more than one
+/// key is down at a time.
+const SESSION: &[(u64, u8, bool)] = &[
+    (10, 0x1, true),
+    (14, 0x1, false),
+    (30, 0xA, true),
+    (33, 0x5, true),
+    (40, 0xA, false),
+    (41, 0x5, false),
+    (90, 0xF, true),
+    (91, 0xF, false),
+    (120, 0x0, true),
+    (150, 0x0, false),
+];
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("chip8-{}-{name}", std::process::id()))
+}
+
+/// Everything about the machine, from the same text a crash dump is written as.
+fn state_hash(core: &Headless) -> u64 {
+    hash::fnv1a(core.state.snapshot().to_string().as_bytes())
+}
+
+/// Runs the ROM for [`FRAMES`] frames, updating `input` at the start of each one like
+/// the frontend does, with `live` pressed and released through it by hand.
+fn run(live: &[(u64, u8, bool)], input: impl FnOnce(&Headless) -> Input) -> u64 {
+    let mut core = Headless::new(ROM.to_vec(), Quirks::default(), IPF);
+    let mut input = input(&core);
+    for frame in 0..FRAMES {
+        for (_, key, pressed) in live.iter().filter(|(at, ..)| *at == frame) {
+            if *pressed {
+                input.press(*key);
+            } else {
+                input.release(*key);
+            }
+        }
+        input.update();
+        while core.frame() == frame {
+            let _ = core.step();
+        }
+    }
+    state_hash(&core)
+}
+
+#[test]
+fn replay_ends_in_the_recorded_state() {
+    let path = temp_path("session.rec");
+    let recorded = run(SESSION, |core| {
+        let mut input = Input::new(core.keypad(), None);
+        input.record(Recorder::create(&path).unwrap());
+        input
+    });
+    let replayed = run(&[], |core| {
+        let mut input = Input::new(core.keypad(), None);
+        input.replay(Replay::load(&path).unwrap(), false);
+        input
+    });
+    let text = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(text.lines().count(), SESSION.len() + 1, "{text}");
+    assert_eq!(recorded, replayed);
+    // Or it would pass without replaying anything
+    let untouched = run(&[], |core| Input::new(core.keypad(), None));
+    assert_ne!(recorded, untouched);
+}
+
+#[test]
+fn live_input_is_ignored_while_replaying() {
+    let path = temp_path("ignored.rec");
+    let recorded = run(SESSION, |core| {
+        let mut input = Input::new(core.keypad(), None);
+        input.record(Recorder::create(&path).unwrap());
+        input
+    });
+    let replayed = run(&[(60, 0x7, true), (70, 0x7, false)], |core| {
+        let mut input = Input::new(core.keypad(), None);
+        input.replay(Replay::load(&path).unwrap(), false);
+        input
+    });
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(recorded, replayed);
+}
@@ -0,0 +1,60 @@
+//! The example input scripts in tests/scripts, each played against the ROM of the
+//! same name on the deterministic scheduler. Every `expect-pixel` has to hold.
+
+use chip8::headless::Headless;
+use chip8::io::Script;
+use chip8::quirks::Profile;
+use std::path::Path;
+use std::path::PathBuf;
+
+const IPF: u64 = 10;
+const MAX_FRAMES: u64 = 10_000;
+
+fn dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scripts")
+}
+
+/// Plays `script` against `<name>.ch8`.
+fn play(name: &str, script: &mut Script) -> Result<(), String> {
+    let rom = std::fs::read(dir().join(format!("{name}.ch8"))).unwrap();
+    let mut core = Headless::new(rom, Profile::Chip8.quirks(), IPF);
+    core.run_script(script, MAX_FRAMES)
+}
+
+#[test]
+fn example_scripts_pass() {
+    let mut played = vec![];
+    for entry in std::fs::read_dir(dir()).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_none_or(|ext| ext != "script") {
+            continue;
+        }
+        let name = path.file_stem().unwrap().to_str().unwrap().to_owned();
+        let mut script = Script::load(&path).unwrap();
+        if let Err(err) = play(&name, &mut script) {
+            panic!("{}: {err}", path.display());
+        }
+        played.push(name);
+    }
+    played.sort();
+    assert_eq!(played, ["move", "start"]);
+}
+
+#[test]
+fn unmet_expectations_fail() {
+    // Nothing is pressed, so the playfield never appears
+    let mut script = Script::parse("frame 90 expect-pixel 0 0 on\nframe 91 quit").unwrap();
+    assert_eq!(
+        play("start", &mut script),
+        Err("Script failed at frame 90: expected pixel 0,0 to be on".into())
+    );
+}
+
+#[test]
+fn scripts_have_to_quit() {
+    let mut script = Script::parse("frame 5 press 6").unwrap();
+    assert_eq!(
+        play("move", &mut script),
+        Err(format!("The script didn't quit within {MAX_FRAMES} frames"))
+    );
+}
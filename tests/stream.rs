@@ -0,0 +1,191 @@
+//! The browser stream, against a core running on a thread of its own, with just
+//! enough of a WebSocket client to check what a browser would see.
+
+use chip8::io::Keypad;
+use chip8::io::Status;
+use chip8::stream::Server;
+use chip8::State;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+// LD I, 000; DRW V0, V0, 5; ADD V0, 01; JP 204
+const ROM: &[u8] = &[0xA0, 0x00, 0xD0, 0x05, 0x70, 0x01, 0x12, 0x04];
+/// The key from the example handshake in RFC 6455
+const KEY: &str = "dGhlIHNhbXBsZSBub25jZQ==";
+
+struct Machine {
+    addr: SocketAddr,
+    keypad: Arc<Mutex<Keypad>>,
+    status: Arc<Status>,
+}
+
+/// Starts a core and a server for it on a free port on this machine.
+fn start() -> Machine {
+    let vram = Arc::new(Mutex::new([false; 64 * 32]));
+    let keypad = Arc::new(Mutex::new(Keypad::default()));
+    let delay_timer = Arc::new(Mutex::new(0));
+    let sound_timer = Arc::new(Mutex::new(0));
+    let status = Arc::new(Status::new(delay_timer.clone(), sound_timer.clone()));
+    let mut state = State::new(
+        vram.clone(),
+        keypad.clone(),
+        delay_timer,
+        sound_timer,
+        Arc::new(Mutex::new(false)),
+        status.clone(),
+        ROM.to_vec(),
+    );
+    let server = Server::bind("ws://127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap();
+    let serve = server.serve(keypad.clone(), vram, status.clone());
+    std::thread::spawn(move || {
+        smol::block_on(futures::future::select(
+            Box::pin(chip8::run_core(&mut state)),
+            Box::pin(serve),
+        ))
+    });
+    Machine {
+        addr,
+        keypad,
+        status,
+    }
+}
+
+fn connect(machine: &Machine, request: &str) -> (TcpStream, BufReader<TcpStream>) {
+    let stream = TcpStream::connect(machine.addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let mut writer = stream.try_clone().unwrap();
+    writer.write_all(request.as_bytes()).unwrap();
+    (writer, BufReader::new(stream))
+}
+
+/// The status line and headers of a response.
+fn head(reader: &mut BufReader<TcpStream>) -> Vec<String> {
+    let mut lines = vec![];
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line.trim().is_empty() {
+            return lines;
+        }
+        lines.push(line.trim_end().to_owned());
+    }
+}
+
+/// Reads one unmasked message from the server, returning its opcode and payload.
+fn read_message(reader: &mut BufReader<TcpStream>) -> (u8, Vec<u8>) {
+    let mut header = [0; 2];
+    reader.read_exact(&mut header).unwrap();
+    assert_eq!(header[0] & 0x80, 0x80, "fragmented message");
+    assert_eq!(header[1] & 0x80, 0, "servers don't mask");
+    let len = match header[1] {
+        126 => {
+            let mut len = [0; 2];
+            reader.read_exact(&mut len).unwrap();
+            usize::from(u16::from_be_bytes(len))
+        }
+        len => usize::from(len),
+    };
+    let mut payload = vec![0; len];
+    reader.read_exact(&mut payload).unwrap();
+    (header[0] & 0x0F, payload)
+}
+
+/// Sends a masked message, as browsers do.
+fn write_message(writer: &mut TcpStream, opcode: u8, payload: &[u8]) {
+    let mask = [0x12, 0x34, 0x56, 0x78];
+    let mut message = vec![
+        0x80 | opcode,
+        0x80 | payload.len() as u8,
+        0x12,
+        0x34,
+        0x56,
+        0x78,
+    ];
+    message.extend(
+        payload
+            .iter()
+            .enumerate()
+            .map(|(idx, byte)| byte ^ mask[idx % 4]),
+    );
+    writer.write_all(&message).unwrap();
+}
+
+fn wait_for(what: &str, done: impl Fn() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !done() {
+        assert!(Instant::now() < deadline, "{what} never happened");
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[test]
+fn streams_frames_and_takes_keys() {
+    let machine = start();
+    let (mut writer, mut reader) = connect(
+        &machine,
+        &format!(
+            "GET /ws HTTP/1.1\r\nHost: test\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: {KEY}\r\nSec-WebSocket-Version: 13\r\n\r\n"
+        ),
+    );
+    let head = head(&mut reader);
+    assert_eq!(head[0], "HTTP/1.1 101 Switching Protocols");
+    assert!(
+        head.contains(&"Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=".to_owned()),
+        "{head:?}"
+    );
+
+    // The screen may still be blank when streaming starts, but changes once
+    let (opcode, mut frame) = read_message(&mut reader);
+    assert_eq!(opcode, 0x2);
+    if frame[8..].iter().all(|byte| *byte == 0) {
+        frame = read_message(&mut reader).1;
+    }
+    assert_eq!(frame.len(), 8 + 64 * 32 / 8);
+    assert_eq!(frame[..2], [64, 32]);
+    let palette = machine.status.palette.lock().unwrap().0;
+    for (color, bytes) in palette.iter().zip(frame[2..8].chunks(3)) {
+        assert_eq!(bytes, [color.r, color.g, color.b]);
+    }
+    // The font's 0, in the top left
+    let rows: Vec<u8> = frame[8..].chunks(8).take(6).map(|row| row[0]).collect();
+    assert_eq!(rows, [0xF0, 0x90, 0x90, 0x90, 0xF0, 0x00]);
+
+    write_message(&mut writer, 0x1, b"down 5");
+    wait_for("Pressing 5", || {
+        machine.keypad.lock().unwrap().is_pressed(5)
+    });
+    write_message(&mut writer, 0x9, b"ping");
+    assert_eq!(read_message(&mut reader), (0xA, b"ping".to_vec()));
+
+    // Closing is answered with a close, and lets go of the keys
+    write_message(&mut writer, 0x8, &[]);
+    assert_eq!(read_message(&mut reader), (0x8, vec![]));
+    wait_for("Releasing 5", || {
+        !machine.keypad.lock().unwrap().is_pressed(5)
+    });
+}
+
+#[test]
+fn serves_the_page() {
+    let machine = start();
+    let (_writer, mut reader) = connect(&machine, "GET / HTTP/1.1\r\nHost: test\r\n\r\n");
+    assert_eq!(head(&mut reader)[0], "HTTP/1.1 200 OK");
+    let mut page = String::new();
+    reader.read_to_string(&mut page).unwrap();
+    assert!(page.contains("new WebSocket(`ws://${location.host}/ws`)"));
+
+    let (_writer, mut reader) = connect(&machine, "GET /nothing HTTP/1.1\r\n\r\n");
+    assert_eq!(head(&mut reader)[0], "HTTP/1.1 404 Not Found");
+}